@@ -0,0 +1,170 @@
+//! Helpers shared by the examples to run a cluster of nodes in a single process.
+
+use anyhow::{anyhow, Result};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use untitled::anomaly::AnomalyDetector;
+use untitled::audit::AuditLog;
+use untitled::cache_trait::{sync_data, BCache, CacheConfig, SyncContext};
+use untitled::chaos::Chaos;
+use untitled::cluster_auth::ClusterAuth;
+use untitled::events::KeyEvents;
+use untitled::gossip::{GossipNode, GossipodConfig};
+use untitled::http_server::{self, HttpServerConfig, ServerDeps};
+use untitled::limits::Limits;
+use untitled::moka_cache::MokaCache;
+use untitled::quota::Quotas;
+use untitled::session::Sessions;
+use untitled::slowlog::SlowLog;
+use untitled::staleness::Staleness;
+use untitled::tags::TagIndex;
+use untitled::topology::{NodeInfo, Topology};
+use untitled::wal::Wal;
+
+/// A node started in this process.
+///
+/// # Fields
+///
+/// - `name`: The name of the node in the cluster.
+/// - `url`: The base URL of the node's HTTP server.
+pub struct Node {
+    pub name: String,
+    pub url: String,
+}
+
+/// Starts a cluster of `size` nodes on consecutive local ports, every node joining the first.
+///
+/// Node `i` serves HTTP on port `http_port + i` and gossips on port `gossip_port + i`.
+///
+/// # Arguments
+///
+/// * `size` - The number of nodes.
+/// * `http_port` - The HTTP port of the first node.
+/// * `gossip_port` - The gossip port of the first node.
+/// * `cache_config` - The cache configuration of every node.
+///
+/// # Returns
+///
+/// * The started nodes, once every node sees the whole cluster.
+pub async fn start_cluster(
+    size: u16,
+    http_port: u16,
+    gossip_port: u16,
+    cache_config: CacheConfig,
+) -> Result<Vec<Node>> {
+    let mut nodes = Vec::new();
+    for i in 0..size {
+        let name = format!("node-{}", i + 1);
+        let seeds = (i > 0)
+            .then(|| format!("127.0.0.1:{}", gossip_port))
+            .into_iter()
+            .collect();
+        let gossip_config = GossipodConfig::new(
+            name.clone(),
+            format!("127.0.0.1:{}", gossip_port + i),
+            seeds,
+        );
+        let (gossip, gossip_receiver) = GossipNode::start(gossip_config).await?;
+        wait_for_members(&gossip, i as usize + 1).await?;
+
+        let events = KeyEvents::default();
+        let node_cache_config = CacheConfig {
+            events: Some(events.clone()),
+            ..cache_config.clone()
+        };
+        let bcache: Arc<Mutex<Box<dyn BCache>>> = Arc::new(Mutex::new(Box::new(
+            MokaCache::from_config(&node_cache_config).await,
+        )));
+        let tags = Arc::new(TagIndex::default());
+        let slowlog = Arc::new(SlowLog::new(
+            Duration::from_millis(100),
+            Duration::from_millis(50),
+            128,
+        ));
+        let audit = Arc::new(AuditLog::disabled());
+        let anomalies = Arc::new(AnomalyDetector::new(Default::default()));
+        let wal = Arc::new(Wal::disabled());
+
+        let addr = format!("127.0.0.1:{}", http_port + i);
+        let topology = Arc::new(Topology::new(
+            name.clone(),
+            NodeInfo {
+                http_addr: Some(addr.clone()),
+                ..NodeInfo::default()
+            },
+        ));
+        let sessions = Arc::new(Sessions::new(name.clone()));
+        let staleness = Arc::new(Staleness::default());
+        let deps = ServerDeps {
+            bcache: bcache.clone(),
+            tags: tags.clone(),
+            slowlog: slowlog.clone(),
+            audit: audit.clone(),
+            anomalies: anomalies.clone(),
+            wal: wal.clone(),
+            events: events.clone(),
+            chaos: Arc::new(Chaos::default()),
+            gossip_queue: gossip_receiver.counters(),
+            cluster_auth: Arc::new(ClusterAuth::default()),
+            topology: topology.clone(),
+            sessions: sessions.clone(),
+            staleness: staleness.clone(),
+            quotas: Arc::new(Quotas::new(Vec::new())),
+        };
+        let http_receiver = http_server::start(HttpServerConfig::new(addr.clone()), deps).await?;
+
+        let ctx = SyncContext {
+            bcache,
+            tags,
+            slowlog,
+            audit,
+            anomalies,
+            wal,
+            limits: Limits::default(),
+            events,
+            gossip_expirations: false,
+            topology,
+            sessions,
+            staleness,
+        };
+        tokio::spawn(sync_data(ctx, gossip, gossip_receiver, http_receiver));
+
+        nodes.push(Node {
+            name,
+            url: format!("http://{}", addr),
+        });
+    }
+
+    // Give the last node time to be seen by the others.
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    Ok(nodes)
+}
+
+/// Waits until the node sees at least `size` members, or gives up after ten seconds.
+async fn wait_for_members(gossip: &GossipNode, size: usize) -> Result<()> {
+    for _ in 0..100 {
+        if gossip.members().await.len() >= size {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    Err(anyhow!("The cluster did not converge"))
+}
+
+/// Polls `check` until it returns a value, failing after five seconds.
+///
+/// Replication is asynchronous, so reads on other nodes are retried until they observe a write.
+pub async fn eventually<T, F, Fut>(what: &str, mut check: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<Option<T>>>,
+{
+    for _ in 0..50 {
+        if let Some(value) = check().await? {
+            return Ok(value);
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    Err(anyhow!("Timed out waiting until {}", what))
+}
@@ -0,0 +1,80 @@
+//! Configuration distributed to every node of a three-node cluster.
+//!
+//! An operator publishes the configuration through one node, and services polling the other
+//! nodes pick up changes cheaply with conditional requests: an unchanged configuration is
+//! answered with `304 Not Modified`.
+//!
+//! ```sh
+//! cargo run --example config_distribution
+//! ```
+
+mod common;
+
+use anyhow::Result;
+use common::{eventually, start_cluster};
+use reqwest::header::{HeaderValue, ETAG, IF_NONE_MATCH};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use untitled::cache_trait::CacheConfig;
+
+/// The key the configuration is published under.
+const CONFIG_KEY: &str = "config:feature-flags";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let nodes = start_cluster(3, 13101, 14101, CacheConfig::new(1024)).await?;
+    let client = reqwest::Client::new();
+
+    let mut etags = vec![None; nodes.len()];
+    for version in 1..=3 {
+        let config = json!({ "version": version, "dark_mode": version % 2 == 0 });
+        client
+            .post(format!("{}/add", nodes[0].url))
+            .json(&json!({ "key": CONFIG_KEY, "value": config.to_string() }))
+            .send()
+            .await?
+            .error_for_status()?;
+        println!("{} published {}", nodes[0].name, config);
+
+        // Every other node eventually serves the new configuration.
+        for (node, etag) in nodes.iter().zip(etags.iter_mut()).skip(1) {
+            let current = etag.clone();
+            let (new_etag, value) = eventually("the configuration changes", || {
+                fetch_if_changed(&client, &node.url, current.clone())
+            })
+            .await?;
+            println!("{} picked up {}", node.name, value);
+            *etag = new_etag;
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetches the configuration from the node at `url` unless its entity tag is still `etag`.
+///
+/// # Returns
+///
+/// * The new entity tag and configuration, or `None` if the configuration did not change.
+async fn fetch_if_changed(
+    client: &reqwest::Client,
+    url: &str,
+    etag: Option<HeaderValue>,
+) -> Result<Option<(Option<HeaderValue>, String)>> {
+    let mut request = client
+        .get(format!("{}/query", url))
+        .query(&[("key", CONFIG_KEY)]);
+    if let Some(etag) = etag {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+    let response = request.send().await?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+
+    let new_etag = response.headers().get(ETAG).cloned();
+    let body: Value = response.json().await?;
+    Ok(body["data"][CONFIG_KEY]
+        .as_str()
+        .map(|value| (new_etag, value.to_string())))
+}
@@ -0,0 +1,140 @@
+//! A game leaderboard kept in counters across a three-node cluster.
+//!
+//! Players score points on whichever node their game server talks to. Each score is an `add`
+//! operation on the player's counter, replicated as the operation rather than the new total, so
+//! concurrent scores on different nodes add up instead of overwriting each other. Players join
+//! the board through a set, which every node ranks them from.
+//!
+//! ```sh
+//! cargo run --example leaderboard
+//! ```
+
+mod common;
+
+use anyhow::{anyhow, Result};
+use common::{eventually, start_cluster, Node};
+use futures::future::try_join_all;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use untitled::cache_trait::CacheConfig;
+use untitled::typed_value::TypedValue;
+
+/// The set of the players on the board.
+const PLAYERS_KEY: &str = "leaderboard:players";
+
+/// The points scored in the games, as `(node, player, points)`.
+const GAMES: [(usize, &str, i64); 9] = [
+    (0, "alice", 30),
+    (1, "bob", 25),
+    (2, "carol", 40),
+    (1, "alice", 15),
+    (2, "bob", 10),
+    (0, "carol", -5),
+    (2, "alice", 20),
+    (0, "bob", 35),
+    (1, "dave", 5),
+];
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let nodes = start_cluster(3, 13201, 14201, CacheConfig::new(1024)).await?;
+    let client = reqwest::Client::new();
+
+    // Every game reports its score at once, to the node of its game server.
+    try_join_all(
+        GAMES
+            .iter()
+            .map(|&(node, player, points)| score(&client, &nodes[node], player, points)),
+    )
+    .await?;
+
+    let mut expected = BTreeMap::new();
+    for (_, player, points) in GAMES {
+        *expected.entry(player.to_string()).or_insert(0) += points;
+    }
+
+    // Every node ends up with the same totals.
+    for node in &nodes {
+        let board = eventually("the scores are replicated", || async {
+            let board = leaderboard(&client, node).await?;
+            Ok((board == expected).then_some(board))
+        })
+        .await?;
+        println!("{} has every score", node.name);
+
+        let mut ranking: Vec<_> = board.into_iter().collect();
+        ranking.sort_by(|(a, a_points), (b, b_points)| b_points.cmp(a_points).then(a.cmp(b)));
+        for (rank, (player, points)) in ranking.iter().enumerate() {
+            println!("  {}. {} {}", rank + 1, player, points);
+        }
+    }
+
+    Ok(())
+}
+
+/// Adds points to the counter of a player through a node, and adds the player to the board.
+async fn score(client: &reqwest::Client, node: &Node, player: &str, points: i64) -> Result<()> {
+    for (key, op) in [
+        (
+            format!("score:{}", player),
+            json!({ "op": "add", "delta": points }),
+        ),
+        (
+            PLAYERS_KEY.to_string(),
+            json!({ "op": "set_add", "members": [player] }),
+        ),
+    ] {
+        client
+            .post(format!("{}/keys/{}/ops", node.url, key))
+            .json(&op)
+            .send()
+            .await?
+            .error_for_status()?;
+    }
+    println!("{} scored {} for {}", node.name, points, player);
+    Ok(())
+}
+
+/// Returns the score of every player on the board, as seen by a node.
+async fn leaderboard(client: &reqwest::Client, node: &Node) -> Result<BTreeMap<String, i64>> {
+    let Some(TypedValue::Set(players)) = query(client, node, PLAYERS_KEY).await? else {
+        return Ok(BTreeMap::new());
+    };
+    let mut board = BTreeMap::new();
+    for player in players {
+        let points = match query(client, node, &format!("score:{}", player)).await? {
+            Some(TypedValue::Integer(points)) => points,
+            Some(value) => {
+                return Err(anyhow!(
+                    "The score of {} is a {}",
+                    player,
+                    value.type_name()
+                ))
+            }
+            None => 0,
+        };
+        board.insert(player, points);
+    }
+    Ok(board)
+}
+
+/// Reads a key from a node, returning `None` if the node does not hold it yet.
+async fn query(client: &reqwest::Client, node: &Node, key: &str) -> Result<Option<TypedValue>> {
+    let response = client
+        .get(format!("{}/query", node.url))
+        .query(&[("key", key)])
+        .send()
+        .await?;
+    match response.status() {
+        StatusCode::OK => {
+            let body: Value = response.json().await?;
+            let value = body["data"][key]
+                .as_str()
+                .ok_or_else(|| anyhow!("No value for {} in {}", key, body))?;
+            Ok(Some(TypedValue::decode(value)))
+        }
+        StatusCode::NOT_FOUND => Ok(None),
+        status => Err(anyhow!("Failed to query {}: {}", key, status)),
+    }
+}
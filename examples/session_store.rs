@@ -0,0 +1,100 @@
+//! A session store replicated across a three-node cluster.
+//!
+//! Sessions are written on one node and read on another, tagged with their user so that
+//! logging a user out everywhere is a single tag invalidation, and expire once idle.
+//!
+//! ```sh
+//! cargo run --example session_store
+//! ```
+
+mod common;
+
+use anyhow::{anyhow, Result};
+use common::{eventually, start_cluster};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::time::Duration;
+use untitled::cache_trait::CacheConfig;
+
+/// How long a session may stay unread before it expires.
+const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cache_config = CacheConfig {
+        time_to_idle: Some(SESSION_IDLE_TIMEOUT),
+        ..CacheConfig::new(1024)
+    };
+    let nodes = start_cluster(3, 13001, 14001, cache_config).await?;
+    let client = reqwest::Client::new();
+
+    // Log alice in on two devices through the first node.
+    for session in ["s-1", "s-2"] {
+        let response: Value = client
+            .post(format!("{}/add", nodes[0].url))
+            .json(&json!({
+                "key": format!("session:{}", session),
+                "value": json!({ "user": "alice" }).to_string(),
+                "tags": ["user:alice"],
+            }))
+            .send()
+            .await?
+            .json()
+            .await?;
+        println!("{} stored session {}: {}", nodes[0].name, session, response);
+    }
+
+    // The sessions are replicated to the other nodes.
+    eventually("the session is replicated", || async {
+        Ok(session_exists(&client, &nodes[1].url, "s-1")
+            .await?
+            .then_some(()))
+    })
+    .await?;
+    println!("{} sees session s-1", nodes[1].name);
+
+    // Logging alice out on the third node removes her sessions everywhere.
+    client
+        .delete(format!("{}/tags/user:alice", nodes[2].url))
+        .send()
+        .await?
+        .error_for_status()?;
+    for node in &nodes {
+        for session in ["s-1", "s-2"] {
+            eventually("the session is removed", || async {
+                Ok((!session_exists(&client, &node.url, session).await?).then_some(()))
+            })
+            .await?;
+        }
+    }
+    println!("alice is logged out on every node");
+
+    // A session that is not read for the idle timeout expires.
+    client
+        .post(format!("{}/add", nodes[0].url))
+        .json(&json!({ "key": "session:s-3", "value": json!({ "user": "bob" }).to_string() }))
+        .send()
+        .await?
+        .error_for_status()?;
+    tokio::time::sleep(SESSION_IDLE_TIMEOUT * 2).await;
+    if session_exists(&client, &nodes[0].url, "s-3").await? {
+        return Err(anyhow!("The idle session did not expire"));
+    }
+    println!("bob's idle session expired");
+
+    Ok(())
+}
+
+/// Returns whether the node at `url` holds the session.
+async fn session_exists(client: &reqwest::Client, url: &str, session: &str) -> Result<bool> {
+    let response = client
+        .get(format!("{}/query", url))
+        .query(&[("key", format!("session:{}", session))])
+        .send()
+        .await?;
+    match response.status() {
+        StatusCode::OK => Ok(true),
+        StatusCode::NOT_FOUND => Ok(false),
+        status => Err(anyhow!("Failed to query session {}: {}", session, status)),
+    }
+}
@@ -0,0 +1,14 @@
+//! Feeds arbitrary gossip frames, as any peer reaching the gossip port can, to the chunk
+//! reassembler and the frame decoder, which must reject them without panicking.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::time::Duration;
+use untitled::wire::{self, Reassembler};
+
+fuzz_target!(|frame: &[u8]| {
+    let mut reassembler = Reassembler::new(Duration::from_secs(10));
+    if let Ok(Some(frame)) = reassembler.accept("127.0.0.1:4001".parse().unwrap(), frame.to_vec()) {
+        let _ = wire::decode(&frame);
+    }
+});
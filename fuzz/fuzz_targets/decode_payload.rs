@@ -0,0 +1,17 @@
+//! Feeds arbitrary payloads to every codec. Payloads that decode must encode again and decode
+//! to the same envelope.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use untitled::codec::WireCodec;
+
+fuzz_target!(|payload: &[u8]| {
+    for codec in [WireCodec::Bincode, WireCodec::Json, WireCodec::Protobuf] {
+        let Ok(envelope) = codec.codec().decode(payload) else {
+            continue;
+        };
+        let encoded = codec.codec().encode(&envelope).expect("decoded envelope encodes");
+        let decoded = codec.codec().decode(&encoded).expect("encoded envelope decodes");
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", envelope));
+    }
+});
@@ -0,0 +1,305 @@
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::error::Error;
+use anyhow::{anyhow, Result};
+
+/// The access a grant gives to the keys it covers. Each permission includes the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Permission {
+    /// Reading keys, and streaming their events.
+    Read,
+    /// Writing and removing keys.
+    Write,
+    /// The operational routes (`/admin/*`, `/debug/*`, `/cluster/*` and `/stats/*`), which act
+    /// on every key, so they need an admin grant on every key.
+    Admin,
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Permission::Read => "read",
+            Permission::Write => "write",
+            Permission::Admin => "admin",
+        })
+    }
+}
+
+/// A permission on the keys starting with a prefix, parsed from `<permission>:<prefix>`, such as
+/// `write:sessions:*`. A trailing `*` is optional, and `*` alone covers every key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grant {
+    pub permission: Permission,
+    pub prefix: String,
+}
+
+impl Grant {
+    /// Returns whether the grant gives a permission on every key starting with `prefix`.
+    fn covers(&self, permission: Permission, prefix: &str) -> bool {
+        self.permission >= permission && prefix.starts_with(&self.prefix)
+    }
+}
+
+impl FromStr for Grant {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (permission, prefix) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Expected <permission>:<prefix>, got '{}'", s))?;
+        let permission = Permission::from_str(permission, true)
+            .map_err(|_| anyhow!("Invalid permission '{}' in grant '{}'", permission, s))?;
+        Ok(Self {
+            permission,
+            prefix: prefix.strip_suffix('*').unwrap_or(prefix).to_string(),
+        })
+    }
+}
+
+/// A bearer token and its grants, parsed from `<token>=<grant>[,<grant>...]`, such as
+/// `s3cr3t=write:sessions:*,read:*`. The token is everything before the last `=`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AclToken {
+    pub token: String,
+    pub grants: Vec<Grant>,
+}
+
+impl fmt::Debug for AclToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AclToken")
+            .field("token", &"<redacted>")
+            .field("grants", &self.grants)
+            .finish()
+    }
+}
+
+impl FromStr for AclToken {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (token, grants) = s
+            .rsplit_once('=')
+            .filter(|(token, _)| !token.is_empty())
+            .ok_or_else(|| anyhow!("Expected <token>=<permission>:<prefix>[,...]"))?;
+        let grants = grants
+            .split(',')
+            .filter(|grant| !grant.is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<Grant>>>()?;
+        Ok(Self {
+            token: token.to_string(),
+            grants,
+        })
+    }
+}
+
+/// What the bearer of a request may access, checked by the handlers against the keys it
+/// reads and writes.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    /// The grants of the token, or `None` when access control is disabled.
+    grants: Option<Arc<[Grant]>>,
+}
+
+impl Principal {
+    /// Returns a principal that may access every key, for servers without access control.
+    pub fn unrestricted() -> Self {
+        Self { grants: None }
+    }
+
+    /// Checks that the principal has a permission on every key starting with `prefix`; a key
+    /// is a prefix of itself, and the empty prefix stands for every key.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Forbidden` error if no grant of the principal covers them.
+    pub fn check(&self, permission: Permission, prefix: &str) -> Result<(), Error> {
+        let Some(grants) = &self.grants else {
+            return Ok(());
+        };
+        if grants.iter().any(|grant| grant.covers(permission, prefix)) {
+            return Ok(());
+        }
+        let what = if prefix.is_empty() {
+            "every key".to_string()
+        } else {
+            prefix.to_string()
+        };
+        Err(Error::Forbidden(format!(
+            "The token has no {} access to {}",
+            permission, what
+        )))
+    }
+
+    /// Checks that the principal has a permission on every key between `start` (inclusive) and
+    /// `end` (exclusive), unbounded when `None`: a single grant must cover the whole range.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Forbidden` error if no grant of the principal covers the range.
+    pub fn check_range(
+        &self,
+        permission: Permission,
+        start: Option<&str>,
+        end: Option<&str>,
+    ) -> Result<(), Error> {
+        let Some(grants) = &self.grants else {
+            return Ok(());
+        };
+        let covered = grants.iter().any(|grant| {
+            if !grant.covers(permission, start.unwrap_or_default()) {
+                return false;
+            }
+            match (end, prefix_end(&grant.prefix)) {
+                (_, None) => true,
+                (Some(end), Some(prefix_end)) => end <= prefix_end.as_str(),
+                (None, Some(_)) => false,
+            }
+        });
+        if covered {
+            return Ok(());
+        }
+        Err(Error::Forbidden(format!(
+            "The token has no {} access to every key from {} to {}",
+            permission,
+            start.unwrap_or("the first key"),
+            end.unwrap_or("the last key")
+        )))
+    }
+}
+
+/// Returns the smallest key after every key starting with `prefix`, or `None` if there is none,
+/// as for the empty prefix.
+fn prefix_end(prefix: &str) -> Option<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+    while let Some(last) = chars.pop() {
+        if let Some(next) = (last as u32 + 1..=char::MAX as u32).find_map(char::from_u32) {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
+    }
+    None
+}
+
+/// The bearer tokens of the HTTP API and their grants.
+///
+/// Access control is disabled when no token is configured; otherwise every request must carry
+/// one of the tokens in an `Authorization: Bearer <token>` header.
+///
+/// # Example
+///
+/// ```rust
+/// let acl = Acl::new(vec!["s3cr3t=write:sessions:*".parse()?]);
+/// let principal = acl.authenticate(Some("Bearer s3cr3t"))?;
+/// principal.check(Permission::Write, "sessions:42")?;
+/// ```
+#[derive(Default)]
+pub struct Acl {
+    principals: HashMap<String, Principal>,
+}
+
+impl Acl {
+    /// Creates the access control of the given tokens. The grants of a token configured more
+    /// than once are combined.
+    pub fn new(tokens: Vec<AclToken>) -> Self {
+        let mut grants: HashMap<String, Vec<Grant>> = HashMap::new();
+        for token in tokens {
+            grants.entry(token.token).or_default().extend(token.grants);
+        }
+        Self {
+            principals: grants
+                .into_iter()
+                .map(|(token, grants)| {
+                    let principal = Principal {
+                        grants: Some(grants.into()),
+                    };
+                    (token, principal)
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns whether requests must carry a token.
+    pub fn is_enabled(&self) -> bool {
+        !self.principals.is_empty()
+    }
+
+    /// Returns the principal of a request from its `Authorization` header.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Unauthorized` error if access control is enabled and the header is missing,
+    /// is not a bearer token, or carries an unknown token.
+    pub fn authenticate(&self, authorization: Option<&str>) -> Result<Principal, Error> {
+        if !self.is_enabled() {
+            return Ok(Principal::unrestricted());
+        }
+        let token = authorization
+            .and_then(|authorization| authorization.strip_prefix("Bearer "))
+            .ok_or_else(|| Error::Unauthorized("A bearer token is required".to_string()))?;
+        self.principals
+            .get(token.trim())
+            .cloned()
+            .ok_or_else(|| Error::Unauthorized("Unknown bearer token".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit test for `Acl` and `Principal`.
+    ///
+    /// Tokens are required once configured; grants cover the keys starting with their prefix,
+    /// with their permission and the ones it includes, and ranges only when a single grant
+    /// covers them entirely.
+    #[test]
+    fn test_acl() {
+        let unrestricted = Acl::default().authenticate(None).unwrap();
+        assert!(unrestricted.check(Permission::Admin, "").is_ok());
+
+        let acl = Acl::new(vec![
+            "team=write:sessions:*,read:public:".parse().unwrap(),
+            "ops==admin:*".parse().unwrap(),
+        ]);
+        assert!(matches!(
+            acl.authenticate(None),
+            Err(Error::Unauthorized(_))
+        ));
+        assert!(acl.authenticate(Some("Bearer nope")).is_err());
+        assert!(acl.authenticate(Some("Basic team")).is_err());
+
+        let team = acl.authenticate(Some("Bearer team")).unwrap();
+        assert!(team.check(Permission::Write, "sessions:42").is_ok());
+        assert!(team.check(Permission::Read, "sessions:42").is_ok());
+        assert!(team.check(Permission::Read, "public:a").is_ok());
+        assert!(matches!(
+            team.check(Permission::Write, "public:a"),
+            Err(Error::Forbidden(_))
+        ));
+        assert!(team.check(Permission::Write, "config:db").is_err());
+        assert!(team.check(Permission::Read, "").is_err());
+        assert!(team.check(Permission::Admin, "").is_err());
+        assert!(team
+            .check_range(Permission::Read, Some("sessions:a"), Some("sessions;"))
+            .is_ok());
+        assert!(team
+            .check_range(Permission::Read, Some("sessions:a"), Some("sessions<"))
+            .is_err());
+        assert!(team
+            .check_range(Permission::Read, Some("sessions:a"), None)
+            .is_err());
+
+        let ops = acl.authenticate(Some("Bearer ops=")).unwrap();
+        assert!(ops.check(Permission::Admin, "").is_ok());
+        assert!(ops.check_range(Permission::Write, None, None).is_ok());
+
+        assert!("team".parse::<AclToken>().is_err());
+        assert!("team=own:*".parse::<AclToken>().is_err());
+        assert!("=read:*".parse::<AclToken>().is_err());
+    }
+}
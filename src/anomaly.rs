@@ -0,0 +1,205 @@
+use crate::utils::unix_time_ms;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::{info, warn};
+
+/// The kind of mutation whose rate is tracked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MutationKind {
+    Write,
+    Delete,
+}
+
+impl MutationKind {
+    const ALL: [MutationKind; 2] = [MutationKind::Write, MutationKind::Delete];
+
+    fn index(self) -> usize {
+        match self {
+            MutationKind::Write => 0,
+            MutationKind::Delete => 1,
+        }
+    }
+}
+
+/// A sudden rise in the rate of a kind of mutation, such as a write storm or a mass delete.
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub kind: MutationKind,
+    /// The rate, per second, that triggered the alert.
+    pub rate: f64,
+    /// The usual rate, per second, before the alert.
+    pub baseline: f64,
+    /// Unix timestamp, in milliseconds, at which the alert was raised.
+    pub timestamp_ms: u64,
+}
+
+/// Configuration of the anomaly detector.
+///
+/// # Fields
+///
+/// - `factor`: How many times its baseline a rate must reach to be anomalous.
+/// - `min_rate`: The rate per second below which no alert is raised, whatever the baseline.
+/// - `smoothing`: The weight of the latest rate in the exponentially weighted baseline, between 0 and 1.
+/// - `capacity`: The number of recent alerts kept.
+#[derive(Debug, Clone)]
+pub struct AnomalyConfig {
+    pub factor: f64,
+    pub min_rate: f64,
+    pub smoothing: f64,
+    pub capacity: usize,
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        Self {
+            factor: 10.0,
+            min_rate: 100.0,
+            smoothing: 0.1,
+            capacity: 64,
+        }
+    }
+}
+
+/// Detects anomalies in the rate of writes and deletes applied to the keyspace.
+///
+/// Mutations are counted as they are applied, locally or from gossip. Each evaluation compares
+/// the rate since the previous one against an exponentially weighted baseline, and raises an
+/// alert when a rate enters the anomalous range. Alerts are logged and the most recent ones
+/// are served at `/cluster/alerts`.
+///
+/// # Example
+///
+/// ```rust
+/// let anomalies = AnomalyDetector::new(AnomalyConfig::default());
+/// anomalies.record(MutationKind::Delete, 1);
+/// anomalies.evaluate();
+/// println!("{:?}", anomalies.alerts());
+/// ```
+#[derive(Debug)]
+pub struct AnomalyDetector {
+    config: AnomalyConfig,
+    counts: [AtomicU64; 2],
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    last_evaluated: Instant,
+    baselines: [Option<f64>; 2],
+    active: [bool; 2],
+    alerts: VecDeque<Alert>,
+}
+
+impl AnomalyDetector {
+    /// Creates a new `AnomalyDetector`.
+    pub fn new(config: AnomalyConfig) -> Self {
+        Self {
+            counts: [AtomicU64::new(0), AtomicU64::new(0)],
+            state: Mutex::new(State {
+                last_evaluated: Instant::now(),
+                baselines: [None; 2],
+                active: [false; 2],
+                alerts: VecDeque::with_capacity(config.capacity),
+            }),
+            config,
+        }
+    }
+
+    /// Counts applied mutations of the given kind.
+    pub fn record(&self, kind: MutationKind, count: u64) {
+        self.counts[kind.index()].fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Evaluates the rates since the previous evaluation, and updates the baselines.
+    ///
+    /// # Returns
+    ///
+    /// * The alerts raised by this evaluation.
+    pub fn evaluate(&self) -> Vec<Alert> {
+        let mut state = self.state.lock().unwrap();
+        let elapsed = state.last_evaluated.elapsed().as_secs_f64();
+        if elapsed <= 0.0 {
+            return Vec::new();
+        }
+        state.last_evaluated = Instant::now();
+
+        let mut raised = Vec::new();
+        for kind in MutationKind::ALL {
+            let i = kind.index();
+            let rate = self.counts[i].swap(0, Ordering::Relaxed) as f64 / elapsed;
+            let Some(baseline) = state.baselines[i] else {
+                state.baselines[i] = Some(rate);
+                continue;
+            };
+
+            let anomalous = rate >= self.config.min_rate && rate > baseline * self.config.factor;
+            if anomalous && !state.active[i] {
+                warn!(
+                    "Anomalous {:?} rate: {:.1}/s against a baseline of {:.1}/s",
+                    kind, rate, baseline
+                );
+                raised.push(Alert {
+                    kind,
+                    rate,
+                    baseline,
+                    timestamp_ms: unix_time_ms(),
+                });
+            } else if !anomalous && state.active[i] {
+                info!("{:?} rate back to {:.1}/s", kind, rate);
+            }
+            state.active[i] = anomalous;
+            state.baselines[i] = Some(baseline + self.config.smoothing * (rate - baseline));
+        }
+
+        for alert in &raised {
+            if state.alerts.len() == self.config.capacity {
+                state.alerts.pop_front();
+            }
+            state.alerts.push_back(alert.clone());
+        }
+
+        raised
+    }
+
+    /// Returns the most recent alerts, oldest first.
+    pub fn alerts(&self) -> Vec<Alert> {
+        self.state.lock().unwrap().alerts.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Unit test for raising a single alert when the delete rate spikes.
+    #[test]
+    fn test_anomaly_detection() {
+        let anomalies = AnomalyDetector::new(AnomalyConfig {
+            min_rate: 1.0,
+            ..AnomalyConfig::default()
+        });
+
+        for _ in 0..3 {
+            std::thread::sleep(Duration::from_millis(20));
+            anomalies.record(MutationKind::Write, 1);
+            assert!(anomalies.evaluate().is_empty());
+        }
+
+        // A mass delete raises an alert once, while it lasts.
+        for _ in 0..2 {
+            std::thread::sleep(Duration::from_millis(20));
+            anomalies.record(MutationKind::Write, 1);
+            anomalies.record(MutationKind::Delete, 10_000);
+            anomalies.evaluate();
+        }
+        let alerts = anomalies.alerts();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].kind, MutationKind::Delete);
+        assert!(alerts[0].rate > alerts[0].baseline);
+    }
+}
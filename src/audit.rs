@@ -0,0 +1,207 @@
+use crate::utils::etag;
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::error;
+
+/// How values are written to the audit log.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ValueRedaction {
+    /// Values are written as-is.
+    Full,
+    /// Only the value's `ETag` is written, so changes can be correlated without exposing data.
+    Hash,
+    /// Values are left out.
+    #[default]
+    Omit,
+}
+
+/// The mutation recorded by an audit log entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOperation {
+    Insert,
+    Remove,
+}
+
+/// Where a mutation came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "origin", content = "address", rename_all = "lowercase")]
+pub enum AuditOrigin {
+    /// An HTTP request from the given client address.
+    Http(SocketAddr),
+    /// A gossip message from the given peer address.
+    Gossip(SocketAddr),
+    /// A scheduled expiration on this node.
+    Expiration,
+    /// An eviction on this node, keeping a namespace within its quota.
+    Eviction,
+}
+
+/// A single line of the audit log.
+#[derive(Debug, Serialize)]
+struct AuditEntry<'a> {
+    timestamp_ms: u64,
+    operation: AuditOperation,
+    key: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<String>,
+    #[serde(flatten)]
+    origin: AuditOrigin,
+}
+
+/// An optional append-only log of every mutation applied on this node.
+///
+/// Each insert and remove, whether it came from an HTTP client or a gossip peer, is appended to
+/// the log file as a JSON line with its timestamp, origin, key and (depending on the configured
+/// `ValueRedaction`) value. A disabled audit log ignores all records.
+///
+/// # Example
+///
+/// ```rust
+/// let audit = AuditLog::open("audit.log", ValueRedaction::Hash).await?;
+/// audit
+///     .record(AuditOperation::Insert, "key", Some("value"), AuditOrigin::Http(client))
+///     .await;
+/// ```
+#[derive(Debug)]
+pub struct AuditLog {
+    file: Option<Mutex<File>>,
+    redaction: ValueRedaction,
+}
+
+impl AuditLog {
+    /// Opens (or creates) the audit log file at `path` for appending.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened.
+    pub async fn open(path: impl AsRef<Path>, redaction: ValueRedaction) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+
+        Ok(Self {
+            file: Some(Mutex::new(file)),
+            redaction,
+        })
+    }
+
+    /// Creates a disabled audit log that records nothing.
+    pub fn disabled() -> Self {
+        Self {
+            file: None,
+            redaction: ValueRedaction::default(),
+        }
+    }
+
+    /// Appends a mutation to the audit log.
+    ///
+    /// Write failures are logged rather than returned, so auditing never fails the mutation itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The applied mutation.
+    /// * `key` - The key the mutation applies to.
+    /// * `value` - The inserted value, if any. It is redacted according to the configured `ValueRedaction`.
+    /// * `origin` - The HTTP client or gossip peer the mutation came from.
+    pub async fn record(
+        &self,
+        operation: AuditOperation,
+        key: &str,
+        value: Option<&str>,
+        origin: AuditOrigin,
+    ) {
+        let Some(file) = &self.file else {
+            return;
+        };
+
+        let entry = AuditEntry {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            operation,
+            key,
+            value: self.redact(value),
+            origin,
+        };
+        let mut line = match serde_json::to_vec(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Failed to serialize audit log entry: {:?}", e);
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        if let Err(e) = file.lock().await.write_all(&line).await {
+            error!("Failed to write audit log entry: {:?}", e);
+        }
+    }
+
+    fn redact(&self, value: Option<&str>) -> Option<String> {
+        match self.redaction {
+            ValueRedaction::Full => value.map(str::to_string),
+            ValueRedaction::Hash => value.map(etag),
+            ValueRedaction::Omit => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit test for `AuditLog`.
+    ///
+    /// Entries are appended as JSON lines, with values redacted as configured.
+    #[tokio::test]
+    async fn test_audit_log() {
+        let path = std::env::temp_dir().join(format!("audit-test-{}.log", std::process::id()));
+        let audit = AuditLog::open(&path, ValueRedaction::Hash).await.unwrap();
+        let client: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+
+        audit
+            .record(
+                AuditOperation::Insert,
+                "hello",
+                Some("world"),
+                AuditOrigin::Http(client),
+            )
+            .await;
+        audit
+            .record(
+                AuditOperation::Remove,
+                "hello",
+                None,
+                AuditOrigin::Gossip(client),
+            )
+            .await;
+
+        let contents = tokio::fs::read_to_string(&path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["operation"], "insert");
+        assert_eq!(lines[0]["key"], "hello");
+        assert_eq!(lines[0]["value"], etag("world"));
+        assert_eq!(lines[0]["origin"], "http");
+        assert_eq!(lines[0]["address"], "127.0.0.1:5000");
+        assert_eq!(lines[1]["operation"], "remove");
+        assert_eq!(lines[1]["origin"], "gossip");
+        assert!(lines[1].get("value").is_none());
+    }
+}
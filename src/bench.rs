@@ -0,0 +1,289 @@
+use anyhow::{anyhow, Result};
+use futures::{StreamExt, TryStreamExt};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::json;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Configures a run of the `bench` subcommand.
+///
+/// # Fields
+///
+/// - `targets`: The base URLs of the nodes requests are spread over, round-robin, passed using
+///   `--target`, which can be repeated. Defaults to `http://127.0.0.1:3001`.
+/// - `keys`: The number of distinct keys read and written, passed using `--keys`. Defaults to `10000`.
+/// - `value_size`: The size of the written values in bytes, passed using `--value-size`. Defaults to `100`.
+/// - `read_ratio`: The fraction of requests that are reads, the others being writes, passed using
+///   `--read-ratio`. Defaults to `0.9`.
+/// - `concurrency`: The number of requests in flight, passed using `--concurrency`. Defaults to `16`.
+/// - `duration_secs`: How long requests are sent for, in seconds, passed using `--duration-secs`.
+///   Defaults to `10`.
+/// - `key_prefix`: The prefix of the keys, so a run does not overwrite other data, passed using
+///   `--key-prefix`. Defaults to `bench:`.
+/// - `seed`: The seed the keys and operations are drawn from, so runs are reproducible, passed
+///   using `--seed`. Defaults to `0`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct BenchOptions {
+    #[arg(long = "target", default_value = "http://127.0.0.1:3001")]
+    pub targets: Vec<String>,
+
+    #[arg(long, default_value_t = 10_000)]
+    pub keys: usize,
+
+    #[arg(long, default_value_t = 100)]
+    pub value_size: usize,
+
+    #[arg(long, default_value_t = 0.9)]
+    pub read_ratio: f64,
+
+    #[arg(long, default_value_t = 16)]
+    pub concurrency: usize,
+
+    #[arg(long, default_value_t = 10)]
+    pub duration_secs: u64,
+
+    #[arg(long, default_value = "bench:")]
+    pub key_prefix: String,
+
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+}
+
+/// The latency percentiles of the requests of one kind.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub p999: Duration,
+    pub max: Duration,
+}
+
+impl LatencySummary {
+    /// Summarizes the latencies of successful requests.
+    pub fn new(mut latencies: Vec<Duration>) -> Self {
+        latencies.sort_unstable();
+        let percentile = |p: f64| {
+            let rank = ((latencies.len() as f64 * p).ceil() as usize).max(1);
+            latencies.get(rank - 1).copied().unwrap_or(Duration::ZERO)
+        };
+        Self {
+            count: latencies.len(),
+            p50: percentile(0.5),
+            p90: percentile(0.9),
+            p99: percentile(0.99),
+            p999: percentile(0.999),
+            max: latencies.last().copied().unwrap_or(Duration::ZERO),
+        }
+    }
+}
+
+impl fmt::Display for LatencySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ok, p50 {:.2?}, p90 {:.2?}, p99 {:.2?}, p99.9 {:.2?}, max {:.2?}",
+            self.count, self.p50, self.p90, self.p99, self.p999, self.max
+        )
+    }
+}
+
+/// The outcome of a run of the `bench` subcommand.
+///
+/// # Fields
+///
+/// - `elapsed`: How long requests were sent for.
+/// - `reads`: The latencies of the successful reads.
+/// - `writes`: The latencies of the successful writes.
+/// - `errors`: The number of requests that failed or were answered with an error status.
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub elapsed: Duration,
+    pub reads: LatencySummary,
+    pub writes: LatencySummary,
+    pub errors: usize,
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let requests = self.reads.count + self.writes.count + self.errors;
+        writeln!(
+            f,
+            "{} requests in {:.2?}, {:.1} requests/s, {} errors",
+            requests,
+            self.elapsed,
+            requests as f64 / self.elapsed.as_secs_f64().max(1e-9),
+            self.errors
+        )?;
+        writeln!(f, "reads:  {}", self.reads)?;
+        write!(f, "writes: {}", self.writes)
+    }
+}
+
+/// The latencies and errors recorded by one worker.
+#[derive(Default)]
+struct Samples {
+    reads: Vec<Duration>,
+    writes: Vec<Duration>,
+    errors: usize,
+}
+
+/// Drives the HTTP API of one or more nodes with a mix of `/query` reads and `/add` writes,
+/// then reports the throughput and latency percentiles.
+///
+/// Every key is written once before the run, so reads hit. Each of the `concurrency` workers
+/// draws its keys and operations from its own generator seeded from `seed`, so runs with the
+/// same options send the same requests.
+///
+/// # Example
+///
+/// ```rust
+/// let report = bench::run(BenchOptions {
+///     targets: vec!["http://127.0.0.1:3001".to_string(), "http://127.0.0.1:3002".to_string()],
+///     ..options
+/// })
+/// .await?;
+/// println!("{}", report);
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if the options are invalid, or the keys cannot be written before the run.
+pub async fn run(options: BenchOptions) -> Result<BenchReport> {
+    if options.targets.is_empty() || options.keys == 0 || options.concurrency == 0 {
+        return Err(anyhow!(
+            "A run needs at least one target, one key and one concurrent request"
+        ));
+    }
+    if !(0.0..=1.0).contains(&options.read_ratio) {
+        return Err(anyhow!(
+            "The read ratio must be between 0 and 1, got {}",
+            options.read_ratio
+        ));
+    }
+    let client = reqwest::Client::builder()
+        .pool_max_idle_per_host(options.concurrency)
+        .build()?;
+    let value = "x".repeat(options.value_size);
+
+    futures::stream::iter(0..options.keys)
+        .map(|key| {
+            let target = &options.targets[key % options.targets.len()];
+            write(&client, target, key_name(&options, key), &value)
+        })
+        .buffer_unordered(options.concurrency)
+        .try_collect::<Vec<_>>()
+        .await
+        .map_err(|e| anyhow!("Failed to write the keys before the run: {:?}", e))?;
+
+    let started = Instant::now();
+    let deadline = started + Duration::from_secs(options.duration_secs);
+    let workers: Vec<_> = (0..options.concurrency)
+        .map(|worker| {
+            tokio::spawn(run_worker(
+                client.clone(),
+                options.clone(),
+                value.clone(),
+                worker,
+                deadline,
+            ))
+        })
+        .collect();
+
+    let mut samples = Samples::default();
+    for worker in workers {
+        let worker = worker.await?;
+        samples.reads.extend(worker.reads);
+        samples.writes.extend(worker.writes);
+        samples.errors += worker.errors;
+    }
+    Ok(BenchReport {
+        elapsed: started.elapsed(),
+        reads: LatencySummary::new(samples.reads),
+        writes: LatencySummary::new(samples.writes),
+        errors: samples.errors,
+    })
+}
+
+/// Sends requests one after the other until `deadline`.
+async fn run_worker(
+    client: reqwest::Client,
+    options: BenchOptions,
+    value: String,
+    worker: usize,
+    deadline: Instant,
+) -> Samples {
+    let mut rng = StdRng::seed_from_u64(options.seed.wrapping_add(worker as u64));
+    let mut samples = Samples::default();
+    let mut next_target = worker;
+    while Instant::now() < deadline {
+        let target = &options.targets[next_target % options.targets.len()];
+        next_target += 1;
+        let key = key_name(&options, rng.random_range(0..options.keys));
+        let is_read = rng.random_bool(options.read_ratio);
+
+        let started = Instant::now();
+        let result = if is_read {
+            read(&client, target, &key).await
+        } else {
+            write(&client, target, key, &value).await
+        };
+        match (result, is_read) {
+            (Ok(()), true) => samples.reads.push(started.elapsed()),
+            (Ok(()), false) => samples.writes.push(started.elapsed()),
+            (Err(_), _) => samples.errors += 1,
+        }
+    }
+    samples
+}
+
+fn key_name(options: &BenchOptions, key: usize) -> String {
+    format!("{}{}", options.key_prefix, key)
+}
+
+/// Reads a key. A key not replicated to the target yet is a miss, not an error.
+async fn read(client: &reqwest::Client, target: &str, key: &str) -> Result<()> {
+    let response = client
+        .get(format!("{}/query", target.trim_end_matches('/')))
+        .query(&[("key", key)])
+        .send()
+        .await?;
+    if response.status() != reqwest::StatusCode::NOT_FOUND {
+        response.error_for_status()?;
+    }
+    Ok(())
+}
+
+async fn write(client: &reqwest::Client, target: &str, key: String, value: &str) -> Result<()> {
+    client
+        .post(format!("{}/add", target.trim_end_matches('/')))
+        .json(&json!({ "key": key, "value": value }))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit test for `LatencySummary::new`.
+    ///
+    /// Percentiles are the nearest rank of the sorted latencies, and are zero without latencies.
+    #[test]
+    fn test_latency_summary() {
+        let latencies = (1..=1000).rev().map(Duration::from_millis).collect();
+        let summary = LatencySummary::new(latencies);
+        assert_eq!(summary.count, 1000);
+        assert_eq!(summary.p50, Duration::from_millis(500));
+        assert_eq!(summary.p90, Duration::from_millis(900));
+        assert_eq!(summary.p99, Duration::from_millis(990));
+        assert_eq!(summary.p999, Duration::from_millis(999));
+        assert_eq!(summary.max, Duration::from_millis(1000));
+
+        assert_eq!(LatencySummary::new(Vec::new()), LatencySummary::default());
+    }
+}
@@ -1,13 +1,80 @@
-use crate::gossip::{Command, GossipNode, Message};
+use crate::anomaly::{AnomalyDetector, MutationKind};
+use crate::audit::{AuditLog, AuditOperation, AuditOrigin};
+use crate::error::Error;
+use crate::events::{ExpirationCause, KeyEvent, KeyEventKind, KeyEvents};
+use crate::gossip::{Command, GossipNode, Message, Replication};
+use crate::limits::Limits;
+use crate::log::set_parent_context;
+use crate::queue::QueueReceiver;
+use crate::sequence::{SequenceOptions, SequenceTracker};
+use crate::session::Sessions;
+use crate::slowlog::{SlowLog, SlowLogKind};
+use crate::staleness::Staleness;
+use crate::tags::TagIndex;
+use crate::topology::Topology;
+use crate::utils::unix_time_ms;
+use crate::wal::Wal;
+use crate::wire::{self, Reassembler};
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use clap::ValueEnum;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 use tokio::{select, time};
-use tracing::{info, warn};
+use tracing::{error, info, info_span, warn, Instrument};
 const TICK_INTERVAL: Duration = Duration::from_secs(3);
+const HOUSEKEEPING_INTERVAL: Duration = Duration::from_secs(1);
+const CHUNK_REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The storage backing the cache of a node.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum CacheBackend {
+    /// The in-memory `foyer` cache.
+    #[default]
+    Foyer,
+    /// The in-memory `moka` cache.
+    Moka,
+    /// The embedded `sled` database, which persists entries on disk and keeps them sorted by key.
+    Sled,
+}
+
+/// Configuration shared by the cache backends.
+///
+/// # Fields
+///
+/// - `capacity`: The maximum number of entries, used when `capacity_bytes` is not set.
+/// - `capacity_bytes`: An optional capacity in bytes. When set, entries are weighted by `entry_weight`.
+/// - `time_to_idle`: An optional window after which entries that have not been read expire.
+/// - `events`: Where the backend publishes an `Expired` event for each entry that expires.
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    pub capacity: usize,
+    pub capacity_bytes: Option<usize>,
+    pub time_to_idle: Option<Duration>,
+    pub events: Option<KeyEvents>,
+}
+
+impl CacheConfig {
+    /// Creates a new `CacheConfig` bounded by entry count, without expiration.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            capacity_bytes: None,
+            time_to_idle: None,
+            events: None,
+        }
+    }
+}
+
+/// Returns the weight of a cache entry, used by the backends when capacity is expressed in bytes.
+///
+/// The weight is the combined length of the key and the value in bytes. Bookkeeping overhead
+/// of the underlying cache is not included.
+pub fn entry_weight(key: &str, value: &str) -> usize {
+    key.len() + value.len()
+}
 
 #[async_trait]
 /// Trait that defines a basic asynchronous cache (BCache) with common cache operations.
@@ -39,6 +106,11 @@ const TICK_INTERVAL: Duration = Duration::from_secs(3);
 ///     async fn remove(&mut self, key: String) {
 ///         // remove from cache logic
 ///     }
+///
+///     async fn entries(&mut self) -> Vec<(String, String)> {
+///         // list entries logic
+///         Vec::new()
+///     }
 /// }
 /// ```
 ///
@@ -71,6 +143,115 @@ pub trait BCache: Send + Sync {
     ///
     /// * `key` - A `String` representing the key to be removed.
     async fn remove(&mut self, key: String);
+
+    /// Asynchronously returns every entry of the cache that has not been evicted or expired.
+    ///
+    /// Reading the entries does not count as an access, so it does not extend their time-to-idle.
+    ///
+    /// # Returns
+    ///
+    /// * The key-value pairs of the cache, in no particular order.
+    async fn entries(&mut self) -> Vec<(String, String)>;
+
+    /// Asynchronously returns the entries whose keys are between `start` (inclusive) and `end`
+    /// (exclusive), in lexicographic order of their bytes.
+    ///
+    /// Only backends that keep their keys ordered support range queries; the others return an
+    /// error.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The first key of the range, or `None` to start from the first key.
+    /// * `end` - The key the range stops before, or `None` to run to the last key.
+    /// * `limit` - The maximum number of entries returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backend does not keep its keys ordered, or cannot be read.
+    async fn range(
+        &mut self,
+        _start: Option<String>,
+        _end: Option<String>,
+        _limit: usize,
+    ) -> Result<Vec<(String, String)>> {
+        Err(
+            Error::Unsupported("The cache backend does not support range queries".to_string())
+                .into(),
+        )
+    }
+}
+
+/// Removes every key carrying a tag from the cache and the tag index.
+///
+/// # Arguments
+///
+/// * `bcache` - The cache to remove the keys from.
+/// * `tags` - The tag index the keys are looked up in.
+/// * `audit` - The audit log the removals are recorded in.
+/// * `tag` - The tag to invalidate.
+/// * `origin` - Where the invalidation came from, recorded in the audit log.
+///
+/// # Returns
+///
+/// * The keys that were removed.
+pub async fn invalidate_tag(
+    bcache: &Mutex<Box<dyn BCache>>,
+    tags: &TagIndex,
+    audit: &AuditLog,
+    tag: &str,
+    origin: AuditOrigin,
+) -> Vec<String> {
+    let keys = tags.take_tag(tag);
+    {
+        let mut cache = bcache.lock().await;
+        for key in &keys {
+            cache.remove(key.clone()).await;
+        }
+    }
+    for key in &keys {
+        audit
+            .record(AuditOperation::Remove, key, None, origin)
+            .await;
+    }
+
+    keys
+}
+
+/// The node state that `sync_data` applies gossip messages to and records them in.
+///
+/// # Fields
+///
+/// - `bcache`: A thread-safe, asynchronous cache implementing the `BCache` trait. Used to store and retrieve key-value pairs.
+/// - `tags`: The tag index, updated with the tags of replicated writes.
+/// - `slowlog`: The slow log that gossip message applications exceeding the threshold are recorded in.
+/// - `audit`: The audit log that mutations applied from gossip are recorded in.
+/// - `anomalies`: The anomaly detector that mutations applied from gossip are counted in.
+/// - `wal`: The write-ahead log that mutations applied from gossip are appended to.
+/// - `limits`: The limits that keys and values received from gossip are checked against, so a
+///   peer with laxer limits cannot push oversized entries onto this node.
+/// - `events`: Where expirations are published, including those replicated from other nodes.
+/// - `gossip_expirations`: Whether entries that expire in the cache are replicated as `Expire`
+///   messages, so other nodes drop them eagerly instead of when they expire there.
+/// - `topology`: The members of the cluster and their addresses, updated from the membership
+///   and the pings of the other members.
+/// - `sessions`: Where the checkpoints of the other members are recorded, releasing the reads
+///   of sessions waiting for them.
+/// - `staleness`: Where the keys modified by gossip, and the members messages were applied from,
+///   are recorded, so reads can tell how stale the data of this node may be.
+#[derive(Clone)]
+pub struct SyncContext {
+    pub bcache: Arc<Mutex<Box<dyn BCache>>>,
+    pub tags: Arc<TagIndex>,
+    pub slowlog: Arc<SlowLog>,
+    pub audit: Arc<AuditLog>,
+    pub anomalies: Arc<AnomalyDetector>,
+    pub wal: Arc<Wal>,
+    pub limits: Limits,
+    pub events: KeyEvents,
+    pub gossip_expirations: bool,
+    pub topology: Arc<Topology>,
+    pub sessions: Arc<Sessions>,
+    pub staleness: Arc<Staleness>,
 }
 
 /// Asynchronously synchronizes data between an in-memory cache (`bcache`),
@@ -78,19 +259,45 @@ pub trait BCache: Send + Sync {
 ///
 /// This function runs an infinite loop where it periodically performs the following tasks:
 ///
-/// - Sends a `Ping` message to all nodes in the gossip network at a fixed interval.
-/// - Listens for incoming gossip messages, deserializes them, and processes them based on their command:
-///     - `Ping`: Logs that a ping message was received.
+/// - Sends a `Ping` message to all nodes in the gossip network at a fixed interval, announcing this
+///   node's HTTP address, and refreshes the topology of the cluster from the membership.
+/// - Listens for incoming gossip frames, decodes them with `wire::decode_with_limits`, and processes them based on their command:
+///     - `Ping`: Records what the sender announced about itself in the topology.
 ///     - `Insert`: Adds the key-value pair from the gossip message into the cache.
 ///     - `Remove`: Removes the key from the cache.
-/// - Listens for incoming HTTP messages and forwards them to all nodes in the gossip network.
+///     - `InvalidateTag`: Removes every key carrying the tag from the cache.
+///     - `ExpireTag`: Schedules the tag to expire.
+///     - `Patch`: Applies the patch to the JSON document in the cache.
+///     - `Update`: Applies the type-aware operation to the typed value in the cache.
+///     - `InsertIfAbsent`: Adds the key-value pair to the cache if the key is not in it.
+///     - `Expire`: Removes the key from the cache, publishing its expiration.
+///     - `Checkpoint`: Records that the writes of the sender up to a session position were applied.
+/// - Reassembles gossip frames that were split into chunks, dropping incomplete ones after a timeout.
+/// - Acknowledges envelopes that request it, and resends this node's unacknowledged envelopes with backoff.
+/// - Applies each rumor once, relaying it on to the members it names, or to random members while
+///   it has hops left.
+/// - Rejoins the cluster when the node looks partitioned from it, then resyncs with every member.
+/// - Elects the coordinator of cluster-wide tasks from the membership.
+/// - Replays the writes kept as hints for members that were down once they return.
+/// - Drops duplicate sequenced messages and applies them in order, asking their sender to resync
+///   when one is missing. Rumors are ordered apart from the messages sent to this node alone.
+/// - Invalidates tags whose scheduled expiration is due, publishing the expiration of their keys.
+/// - Replicates the expirations of cache entries as `Expire` messages, if `gossip_expirations` is set.
+/// - Evaluates the rates of writes and deletes for anomalies.
+/// - Applies transactions atomically, under a single lock of the cache.
+/// - Rejects inserts, removes and transactions whose keys or values exceed the node's limits.
+/// - Appends applied mutations to the write-ahead log, and syncs it under `FsyncPolicy::Interval`.
+/// - Listens for incoming HTTP messages and forwards them to the nodes selected by their `Replication`,
+///   disseminating messages for every node as rumors when the gossip node has a fanout.
+///   Messages are buffered for the gossip node's batch window, or until its batch size is reached,
+///   and sent as batches that replicas apply in order.
 ///
 /// # Arguments
 ///
-/// * `bcache` - A thread-safe, asynchronous cache implementing the `BCache` trait. Used to store and retrieve key-value pairs.
+/// * `ctx` - The cache, and the indexes and logs kept alongside it.
 /// * `gossip` - The gossip network node, responsible for sending and receiving messages across the network.
-/// * `gossip_receiver` - A `Receiver` for receiving serialized gossip messages.
-/// * `http_receiver` - A `Receiver` for receiving HTTP messages that need to be propagated to the gossip network.
+/// * `gossip_receiver` - A `Receiver` for receiving serialized gossip messages, along with the address of the sending peer.
+/// * `http_receiver` - A `Receiver` for receiving HTTP messages, and their replication targets, that need to be propagated to the gossip network.
 ///
 /// # Returns
 ///
@@ -103,12 +310,13 @@ pub trait BCache: Send + Sync {
 ///
 /// # Errors
 ///
-/// * If a gossip message cannot be deserialized, the function will return an error using `anyhow::Error`.
+/// * Gossip frames that cannot be decoded, including frames of an unsupported protocol version and frames
+///   exceeding the decode limits of the node's `WireOptions`, are logged and skipped.
 ///
 /// # Example
 ///
 /// ```rust
-/// sync_data(bcache, gossip, gossip_receiver, http_receiver).await?;
+/// sync_data(ctx, gossip, gossip_receiver, http_receiver).await?;
 /// ```
 ///
 /// This function will run indefinitely unless interrupted.
@@ -118,60 +326,472 @@ pub trait BCache: Send + Sync {
 /// * This function does not handle panics explicitly, but unexpected deserialization failures or locking issues
 ///   in the cache (`bcache`) might cause runtime errors that would result in early termination.
 pub async fn sync_data(
-    bcache: Arc<Mutex<Box<dyn BCache>>>,
+    ctx: SyncContext,
     gossip: GossipNode,
-    mut gossip_receiver: Receiver<Vec<u8>>,
-    mut http_receiver: Receiver<Message>,
+    mut gossip_receiver: QueueReceiver<(SocketAddr, Vec<u8>)>,
+    mut http_receiver: QueueReceiver<(Message, Replication)>,
 ) -> Result<()> {
     let mut ticker = time::interval(TICK_INTERVAL);
+    let mut housekeeping_ticker = time::interval(HOUSEKEEPING_INTERVAL);
+    let mut reassembler = Reassembler::new(CHUNK_REASSEMBLY_TIMEOUT);
+    let mut sequences: SequenceTracker<(SocketAddr, Message)> =
+        SequenceTracker::new(SequenceOptions::default());
+    let mut rumor_sequences: SequenceTracker<(SocketAddr, Message)> =
+        SequenceTracker::new(SequenceOptions::default());
+    let batch_options = gossip.batch_options().clone();
+    let mut batch: Vec<(Message, Replication)> = Vec::new();
+    let mut batch_ticker = time::interval(batch_options.window.max(Duration::from_millis(1)));
+    let mut expirations = ctx.events.subscribe();
 
     loop {
         select! {
             _ = ticker.tick() => {
-                gossip.send_msg_to_all(Message{key: "".to_string(), value: "".to_string(), cmd: Command::Ping}).await;
+                gossip.send_msg_to_all(ctx.topology.announcement()).await;
+                if ctx.topology.set_members(gossip.members().await) {
+                    info!("Topology changed, now at version {}", ctx.topology.version());
+                }
+                gossip.rejoin_if_isolated().await;
+                ctx.topology.set_leader(gossip.elect_leader().await);
+            },
+            _ = housekeeping_ticker.tick() => {
+                for tag in ctx.tags.take_expired(unix_time_ms()) {
+                    let keys = invalidate_tag(&ctx.bcache, &ctx.tags, &ctx.audit, &tag, AuditOrigin::Expiration).await;
+                    info!("Tag {} expired, removed {} keys", tag, keys.len());
+                    for key in keys {
+                        ctx.events.publish(KeyEvent::expired(key, ExpirationCause::Tag));
+                    }
+                }
+                ctx.anomalies.evaluate();
+                let dropped = reassembler.evict_expired();
+                if dropped > 0 {
+                    warn!("Dropped {} incomplete chunked gossip frames", dropped);
+                }
+                gossip.retry_pending().await;
+                gossip.evict_rumors();
+                gossip.replay_hints().await;
+                if ctx.wal.needs_periodic_sync() {
+                    if let Err(e) = ctx.wal.sync().await {
+                        error!("Failed to sync the write-ahead log: {:?}", e);
+                    }
+                }
+                let mut stalled = sequences.release_stalled(Instant::now());
+                stalled.extend(rumor_sequences.release_stalled(Instant::now()));
+                if !stalled.is_empty() {
+                    warn!("Gave up on missing gossip messages, applying {} held back ones", stalled.len());
+                }
+                for (from, msg) in stalled {
+                    // Given up on messages were acknowledged long ago, so they are not resent.
+                    if let Err(e) = apply_traced_gossip_message(from, msg, &ctx).await {
+                        error!("Dropped a held back gossip message: {:?}", e);
+                    }
+                }
             },
-            Some(gossip_msg) = gossip_receiver.recv() => {
-                match handle_gossip_message(&gossip_msg, &bcache).await {
+            Some((from, gossip_msg)) = gossip_receiver.recv() => {
+                let frame = match reassembler.accept(from, gossip_msg) {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        warn!("Failed to reassemble gossip message: {:?}", e);
+                        continue;
+                    }
+                };
+                match handle_gossip_message(from, &frame, &ctx, &gossip, &mut sequences, &mut rumor_sequences).await {
                     Ok(()) => {},
                     Err(e) => {
                         warn!("Failed to process gossip message: {:?}", e);
                     }
                 }
             },
-            Some(http_msg) = http_receiver.recv() => {
+            Some((http_msg, replication)) = http_receiver.recv() => {
                 println!("receiver http msg: {:?}", http_msg);
-                gossip.send_msg_to_all(http_msg).await;
+                batch.push((http_msg, replication));
+                if batch_options.window.is_zero() || batch.len() >= batch_options.max_messages {
+                    gossip.send_batch(std::mem::take(&mut batch)).await;
+                }
+            },
+            event = expirations.recv(), if ctx.gossip_expirations => {
+                match event {
+                    Ok(event) if event.kind == (KeyEventKind::Expired { cause: ExpirationCause::TimeToIdle }) => {
+                        batch.push((Message::new(Command::Expire, event.key, String::new()), Replication::All));
+                        if batch_options.window.is_zero() || batch.len() >= batch_options.max_messages {
+                            gossip.send_batch(std::mem::take(&mut batch)).await;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("Missed expirations to replicate: {:?}", e),
+                }
+            },
+            _ = batch_ticker.tick(), if !batch.is_empty() => {
+                gossip.send_batch(std::mem::take(&mut batch)).await;
             },
         }
     }
 }
 
 async fn handle_gossip_message(
+    from: SocketAddr,
     msg_bytes: &[u8],
-    bcache: &Arc<Mutex<Box<dyn BCache>>>,
+    ctx: &SyncContext,
+    gossip: &GossipNode,
+    sequences: &mut SequenceTracker<(SocketAddr, Message)>,
+    rumor_sequences: &mut SequenceTracker<(SocketAddr, Message)>,
+) -> Result<()> {
+    let envelope = wire::decode_with_limits(msg_bytes, &gossip.wire().limits)?;
+    // A sequenced rumor is received again when its acknowledgement was lost, or when this node
+    // could not log it: it goes to the tracker, which drops the messages already applied.
+    if !gossip.accept_rumor(from, &envelope).await
+        && envelope.messages.iter().all(|msg| msg.sequence.is_none())
+    {
+        info!("Dropping rumor {:?} received again", envelope.rumor);
+        return Ok(());
+    }
+    let sequences = if envelope.rumor.is_some() {
+        rumor_sequences
+    } else {
+        sequences
+    };
+
+    // A frame may carry a batch of messages, which are applied in order. A message that
+    // fails to apply does not prevent the rest of the batch from being applied, unless it could
+    // not be logged: the envelope is then not acknowledged, so that it is resent.
+    let mut durable = true;
+    for msg in envelope.messages {
+        match msg.cmd {
+            Command::Ack => {
+                match msg.key.parse() {
+                    Ok(id) => gossip.acknowledge(id),
+                    Err(e) => warn!("Invalid ack {} from {}: {:?}", msg.key, from, e),
+                }
+                continue;
+            }
+            Command::Resync => {
+                gossip.resync(&msg.value).await;
+                continue;
+            }
+            _ => {}
+        }
+
+        // Sequenced messages are applied in order, once, even if they are resent or arrive
+        // out of order.
+        let Some(sequence) = msg.sequence.clone() else {
+            if let Err(e) = apply_traced_gossip_message(from, msg, ctx).await {
+                error!("{:?}", e);
+                durable = false;
+                break;
+            }
+            continue;
+        };
+        let accepted = sequences.accept(&sequence, (from, msg), Instant::now());
+        if accepted.duplicate {
+            info!(
+                "Dropping duplicate gossip message {} from {}",
+                sequence.seq, sequence.origin
+            );
+        }
+        if let Some(missing) = accepted.missing {
+            warn!(
+                "Gossip message {} from {} is missing, requesting a resync",
+                missing, sequence.origin
+            );
+            gossip.request_resync(from, missing).await;
+        }
+        let mut ready = accepted.ready.into_iter();
+        while let Some((from, msg)) = ready.next() {
+            let released = msg.sequence.clone().unwrap_or_else(|| sequence.clone());
+            if let Err(e) = apply_traced_gossip_message(from, msg, ctx).await {
+                error!("{:?}", e);
+                // The messages released after it wait for it to be resent.
+                let rest = ready
+                    .by_ref()
+                    .filter_map(|item| Some((item.1.sequence.as_ref()?.seq, item)))
+                    .collect();
+                sequences.rewind(&released, rest, Instant::now());
+                durable = false;
+                break;
+            }
+        }
+        if !durable {
+            break;
+        }
+    }
+
+    // The sender resends the envelope until it is acknowledged. Messages that failed to
+    // apply are acknowledged too, as applying them again would fail the same way, unless they
+    // could not be logged.
+    if envelope.id != 0 && durable {
+        gossip.send_ack(from, envelope.id).await;
+    }
+
+    Ok(())
+}
+
+/// A gossip message was not applied because it could not be appended to the write-ahead log.
+#[derive(Debug, thiserror::Error)]
+#[error("The gossip message could not be logged, and was not applied")]
+struct NotLogged;
+
+/// Applies a gossip message in a span linked to the trace of the request that produced it,
+/// recording it in the slow log if it takes too long.
+///
+/// # Errors
+///
+/// Returns an error if the message was not applied because it could not be logged, in which
+/// case it must not be acknowledged. Other failures are only logged, as applying the message
+/// again would fail the same way.
+async fn apply_traced_gossip_message(
+    from: SocketAddr,
+    msg: Message,
+    ctx: &SyncContext,
 ) -> Result<()> {
-    let msg: Message = bincode::deserialize(msg_bytes)
-        .map_err(|e| anyhow!("Failed to deserialize message: {:?}", e))?;
+    let span = info_span!("apply_gossip_message", cmd = ?msg.cmd, key = %msg.key);
+    set_parent_context(&span, &msg.trace_context);
 
+    let _timer = ctx.slowlog.start(
+        SlowLogKind::Gossip,
+        format!("{:?}", msg.cmd),
+        Some(msg.key.clone()),
+    );
+    let key = msg.key.clone();
+    match apply_gossip_message(from, msg, ctx).instrument(span).await {
+        Err(e) if e.is::<NotLogged>() => Err(e),
+        Err(e) => {
+            warn!("Failed to apply gossip message for key {}: {:?}", key, e);
+            Ok(())
+        }
+        Ok(()) => Ok(()),
+    }
+}
+
+/// Checks the keys and values of an insert, a remove or the operations of a transaction, and
+/// the key of a patch or an update, against the limits of this node.
+fn check_limits(limits: &Limits, msg: &Message) -> Result<()> {
+    match msg.cmd {
+        Command::Insert | Command::InsertIfAbsent => limits.check_entry(&msg.key, &msg.value)?,
+        Command::Remove | Command::Patch | Command::Update | Command::Expire => {
+            limits.check_key(&msg.key)?
+        }
+        Command::Txn => {
+            for op in msg.txn_messages()? {
+                check_limits(limits, &op)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+async fn apply_gossip_message(from: SocketAddr, msg: Message, ctx: &SyncContext) -> Result<()> {
     info!("Gossip Message: {:?}", msg);
+    let SyncContext {
+        bcache,
+        tags,
+        audit,
+        anomalies,
+        wal,
+        limits,
+        events,
+        topology,
+        sessions,
+        staleness,
+        ..
+    } = ctx;
+
+    // Pings and checkpoints carry the name of their sender, other messages their origin once
+    // they are sequenced.
+    let origin = match msg.cmd {
+        Command::Ping | Command::Checkpoint => Some(msg.key.as_str()),
+        _ => msg
+            .sequence
+            .as_ref()
+            .map(|sequence| sequence.origin.as_str()),
+    };
+    if let Some(origin) = origin {
+        staleness.heard_from(origin, unix_time_ms());
+    }
+
+    check_limits(limits, &msg)?;
+    if !matches!(
+        msg.cmd,
+        Command::Ping | Command::Ack | Command::Resync | Command::Checkpoint
+    ) {
+        // A mutation that is not durable is not applied either, and its envelope is not
+        // acknowledged, so that its sender resends it.
+        if let Err(e) = wal.append(&msg).await {
+            error!(
+                "Failed to append gossip message for key {} to the write-ahead log: {:?}",
+                msg.key, e
+            );
+            return Err(NotLogged.into());
+        }
+    }
 
     match msg.cmd {
         Command::Ping => {
             info!("Received ping message");
+            if topology.announced(&msg.key, &msg.value) {
+                info!("Topology changed, now at version {}", topology.version());
+            }
         }
         Command::Insert => {
             let mut cache = bcache.lock().await;
             cache.insert(msg.key.clone(), msg.value.clone()).await;
+            tags.set_tags(&msg.key, &msg.tags);
+            anomalies.record(MutationKind::Write, 1);
             info!(
                 "Message added to cache: {:?}",
                 cache.get(msg.key.clone()).await
             );
+            audit
+                .record(
+                    AuditOperation::Insert,
+                    &msg.key,
+                    Some(&msg.value),
+                    AuditOrigin::Gossip(from),
+                )
+                .await;
         }
         Command::Remove => {
             bcache.lock().await.remove(msg.key.clone()).await;
+            tags.remove_key(&msg.key);
+            anomalies.record(MutationKind::Delete, 1);
             info!("Message removed from cache");
+            audit
+                .record(
+                    AuditOperation::Remove,
+                    &msg.key,
+                    None,
+                    AuditOrigin::Gossip(from),
+                )
+                .await;
+        }
+        Command::InvalidateTag => {
+            let keys =
+                invalidate_tag(bcache, tags, audit, &msg.key, AuditOrigin::Gossip(from)).await;
+            anomalies.record(MutationKind::Delete, keys.len() as u64);
+            info!("Invalidated {} keys tagged {}", keys.len(), msg.key);
+        }
+        Command::ExpireTag => {
+            let expire_at_ms = msg
+                .value
+                .parse()
+                .map_err(|e| anyhow!("Invalid expiration of tag {}: {:?}", msg.key, e))?;
+            tags.expire_at(&msg.key, expire_at_ms);
+            info!("Tag {} scheduled to expire at {}", msg.key, expire_at_ms);
+        }
+        Command::Txn => {
+            let ops = msg.txn_messages()?;
+            // The lock is held across every operation, so readers never observe a partially
+            // applied transaction.
+            {
+                let mut cache = bcache.lock().await;
+                for op in &ops {
+                    if op.cmd == Command::Insert {
+                        cache.insert(op.key.clone(), op.value.clone()).await;
+                        tags.set_tags(&op.key, &op.tags);
+                    } else {
+                        cache.remove(op.key.clone()).await;
+                        tags.remove_key(&op.key);
+                    }
+                }
+            }
+            for op in &ops {
+                let (kind, operation, value) = if op.cmd == Command::Insert {
+                    (
+                        MutationKind::Write,
+                        AuditOperation::Insert,
+                        Some(op.value.as_str()),
+                    )
+                } else {
+                    (MutationKind::Delete, AuditOperation::Remove, None)
+                };
+                anomalies.record(kind, 1);
+                audit
+                    .record(operation, &op.key, value, AuditOrigin::Gossip(from))
+                    .await;
+            }
+            info!("Applied a transaction of {} operations", ops.len());
+        }
+        Command::Expire => {
+            let existed = {
+                let mut cache = bcache.lock().await;
+                let existed = cache.get(msg.key.clone()).await.is_ok();
+                cache.remove(msg.key.clone()).await;
+                existed
+            };
+            tags.remove_key(&msg.key);
+            // The key may have expired here already, in which case its expiration was published.
+            if existed {
+                events.publish(KeyEvent::expired(
+                    msg.key.clone(),
+                    ExpirationCause::Replicated,
+                ));
+                audit
+                    .record(
+                        AuditOperation::Remove,
+                        &msg.key,
+                        None,
+                        AuditOrigin::Gossip(from),
+                    )
+                    .await;
+            }
+            info!("Key {} expired on {}", msg.key, from);
+        }
+        Command::InsertIfAbsent => {
+            {
+                let mut cache = bcache.lock().await;
+                if cache.get(msg.key.clone()).await.is_ok() {
+                    info!("Key {} already exists, not inserting it", msg.key);
+                    return Ok(());
+                }
+                cache.insert(msg.key.clone(), msg.value.clone()).await;
+                tags.set_tags(&msg.key, &msg.tags);
+            }
+            anomalies.record(MutationKind::Write, 1);
+            info!("Message added to cache as the key was absent");
+            audit
+                .record(
+                    AuditOperation::Insert,
+                    &msg.key,
+                    Some(&msg.value),
+                    AuditOrigin::Gossip(from),
+                )
+                .await;
+        }
+        Command::Patch | Command::Update => {
+            // The lock is held from the read of the current value to the write of the updated
+            // one, so no other write is lost in between.
+            let value = {
+                let mut cache = bcache.lock().await;
+                let current = cache.get(msg.key.clone()).await.ok();
+                let value = msg.updated_value(current.as_deref())?;
+                limits.check_entry(&msg.key, &value)?;
+                cache.insert(msg.key.clone(), value.clone()).await;
+                value
+            };
+            anomalies.record(MutationKind::Write, 1);
+            info!("Applied {:?} to {}", msg.cmd, msg.key);
+            audit
+                .record(
+                    AuditOperation::Insert,
+                    &msg.key,
+                    Some(&value),
+                    AuditOrigin::Gossip(from),
+                )
+                .await;
+        }
+        Command::Checkpoint => {
+            let position = msg
+                .value
+                .parse()
+                .map_err(|e| anyhow!("Invalid checkpoint of {}: {:?}", msg.key, e))?;
+            sessions.record(&msg.key, position);
+            info!("Applied the writes of {} up to {}", msg.key, position);
+        }
+        Command::Ack | Command::Resync => {
+            // Acknowledgements and resync requests are handled before messages are applied.
         }
     }
+    staleness.record(&msg, unix_time_ms());
 
     Ok(())
 }
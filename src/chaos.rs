@@ -0,0 +1,383 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::time;
+use tracing::{debug, warn};
+
+use crate::cache_trait::BCache;
+use crate::transport::{Member, Transport};
+
+/// The faults injected into replication and cache operations, served and updated at `/admin/chaos`.
+///
+/// Rates are percentages of frames or operations, from `0` to `100`.
+///
+/// # Fields
+///
+/// - `drop_percent`: The frames that are never sent.
+/// - `duplicate_percent`: The frames that are sent twice.
+/// - `delay_percent`: The frames that are sent after a random delay of up to `max_delay_ms`,
+///   so later frames overtake them.
+/// - `reorder_percent`: The frames that are held back and sent right after the next frame.
+/// - `max_delay_ms`: The longest a frame is delayed or held back, in milliseconds.
+/// - `stall_percent`: The cache operations that stall for a random time of up to `max_stall_ms`.
+/// - `max_stall_ms`: The longest a cache operation stalls, in milliseconds.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChaosOptions {
+    pub drop_percent: f64,
+    pub duplicate_percent: f64,
+    pub delay_percent: f64,
+    pub reorder_percent: f64,
+    pub max_delay_ms: u64,
+    pub stall_percent: f64,
+    pub max_stall_ms: u64,
+}
+
+impl ChaosOptions {
+    /// Returns whether any fault is injected.
+    pub fn is_enabled(&self) -> bool {
+        self.rates().iter().any(|(_, rate)| *rate > 0.0)
+    }
+
+    fn rates(&self) -> [(&'static str, f64); 5] {
+        [
+            ("drop_percent", self.drop_percent),
+            ("duplicate_percent", self.duplicate_percent),
+            ("delay_percent", self.delay_percent),
+            ("reorder_percent", self.reorder_percent),
+            ("stall_percent", self.stall_percent),
+        ]
+    }
+
+    /// Checks that every rate is a percentage.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the first rate outside of `0..=100`.
+    pub fn validate(&self) -> Result<()> {
+        match self
+            .rates()
+            .into_iter()
+            .find(|(_, rate)| !(0.0..=100.0).contains(rate))
+        {
+            Some((name, rate)) => Err(anyhow!("{} must be between 0 and 100, got {}", name, rate)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The fault injection settings of a node, shared by its chaos layers and updated at runtime.
+///
+/// Meant for staging clusters, to check that retries and anti-entropy make nodes converge
+/// despite an unreliable network. Every fault is disabled by default.
+#[derive(Debug, Default)]
+pub struct Chaos {
+    options: RwLock<ChaosOptions>,
+    /// Whether any fault is injected, so the layers cost nothing while chaos is disabled.
+    enabled: AtomicBool,
+}
+
+impl Chaos {
+    /// Creates fault injection settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a rate is not a percentage.
+    pub fn new(options: ChaosOptions) -> Result<Self> {
+        let chaos = Self::default();
+        chaos.set(options)?;
+        Ok(chaos)
+    }
+
+    /// Returns the current settings.
+    pub fn options(&self) -> ChaosOptions {
+        self.options.read().unwrap().clone()
+    }
+
+    /// Replaces the settings, which apply to the following frames and operations.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a rate is not a percentage, in which case the settings are unchanged.
+    pub fn set(&self, options: ChaosOptions) -> Result<()> {
+        options.validate()?;
+        if options.is_enabled() {
+            warn!("Injecting faults: {:?}", options);
+        }
+        self.enabled.store(options.is_enabled(), Ordering::Relaxed);
+        *self.options.write().unwrap() = options;
+        Ok(())
+    }
+
+    /// Returns the settings, if any fault is injected.
+    fn active(&self) -> Option<ChaosOptions> {
+        self.enabled.load(Ordering::Relaxed).then(|| self.options())
+    }
+}
+
+/// Returns `true` for `percent` percent of the calls.
+fn roll(percent: f64) -> bool {
+    percent > 0.0 && rand::rng().random_range(0.0..100.0) < percent
+}
+
+/// Returns a random duration of up to `max_ms` milliseconds.
+fn random_duration(max_ms: u64) -> Duration {
+    Duration::from_millis(rand::rng().random_range(0..=max_ms))
+}
+
+/// A frame held back, with the member it is sent to.
+type HeldFrame = Arc<Mutex<Option<(SocketAddr, Vec<u8>)>>>;
+
+/// `ChaosTransport` wraps a `Transport`, dropping, duplicating, delaying and reordering the
+/// frames it sends as configured by its `Chaos`.
+///
+/// # Example
+///
+/// ```rust
+/// let chaos = Arc::new(Chaos::new(ChaosOptions { drop_percent: 5.0, ..ChaosOptions::default() })?);
+/// let transport: Arc<dyn Transport> = Arc::new(ChaosTransport::new(transport, chaos));
+/// ```
+pub struct ChaosTransport {
+    inner: Arc<dyn Transport>,
+    chaos: Arc<Chaos>,
+    /// A frame held back until the next one is sent.
+    held: HeldFrame,
+}
+
+impl ChaosTransport {
+    /// Creates a new `ChaosTransport` wrapping `inner`.
+    pub fn new(inner: Arc<dyn Transport>, chaos: Arc<Chaos>) -> Self {
+        Self {
+            inner,
+            chaos,
+            held: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Sends the frame held back, if any.
+    async fn release_held(&self) -> Result<()> {
+        let held = self.held.lock().unwrap().take();
+        match held {
+            Some((target, frame)) => self.inner.send(target, &frame).await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for ChaosTransport {
+    async fn members(&self) -> Result<Vec<Member>> {
+        self.inner.members().await
+    }
+
+    async fn send(&self, target: SocketAddr, frame: &[u8]) -> Result<()> {
+        let Some(options) = self.chaos.active() else {
+            return self.inner.send(target, frame).await;
+        };
+
+        if roll(options.drop_percent) {
+            debug!("Chaos: dropping a frame to {}", target);
+            return Ok(());
+        }
+        if roll(options.reorder_percent) {
+            let mut held = self.held.lock().unwrap();
+            if held.is_none() {
+                debug!("Chaos: holding back a frame to {}", target);
+                *held = Some((target, frame.to_vec()));
+                let (inner, held) = (self.inner.clone(), self.held.clone());
+                let max_delay = Duration::from_millis(options.max_delay_ms);
+                tokio::spawn(async move {
+                    time::sleep(max_delay).await;
+                    let frame = held.lock().unwrap().take();
+                    if let Some((target, frame)) = frame {
+                        let _ = inner.send(target, &frame).await;
+                    }
+                });
+                return Ok(());
+            }
+        }
+        if roll(options.delay_percent) {
+            let delay = random_duration(options.max_delay_ms);
+            debug!("Chaos: delaying a frame to {} by {:?}", target, delay);
+            let (inner, frame) = (self.inner.clone(), frame.to_vec());
+            tokio::spawn(async move {
+                time::sleep(delay).await;
+                let _ = inner.send(target, &frame).await;
+            });
+            return self.release_held().await;
+        }
+
+        self.inner.send(target, frame).await?;
+        if roll(options.duplicate_percent) {
+            debug!("Chaos: duplicating a frame to {}", target);
+            self.inner.send(target, frame).await?;
+        }
+        self.release_held().await
+    }
+
+    async fn join(&self, addr: SocketAddr) -> Result<()> {
+        self.inner.join(addr).await
+    }
+}
+
+/// `ChaosCache` wraps a `BCache`, stalling its operations as configured by its `Chaos`.
+pub struct ChaosCache {
+    inner: Box<dyn BCache>,
+    chaos: Arc<Chaos>,
+}
+
+impl ChaosCache {
+    /// Creates a new `ChaosCache` wrapping `inner`.
+    pub fn new(inner: Box<dyn BCache>, chaos: Arc<Chaos>) -> Self {
+        Self { inner, chaos }
+    }
+
+    /// Stalls the current operation, for the configured share of operations.
+    async fn maybe_stall(&self) {
+        let Some(options) = self.chaos.active() else {
+            return;
+        };
+        if roll(options.stall_percent) {
+            let stall = random_duration(options.max_stall_ms);
+            debug!("Chaos: stalling a cache operation for {:?}", stall);
+            time::sleep(stall).await;
+        }
+    }
+}
+
+#[async_trait]
+impl BCache for ChaosCache {
+    /// Asynchronously inserts a key-value pair into the wrapped cache, possibly after a stall.
+    async fn insert(&mut self, key: String, val: String) {
+        self.maybe_stall().await;
+        self.inner.insert(key, val).await;
+    }
+
+    /// Asynchronously retrieves the value of a key from the wrapped cache, possibly after a stall.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the wrapped cache fails to return the key.
+    async fn get(&mut self, key: String) -> Result<String> {
+        self.maybe_stall().await;
+        self.inner.get(key).await
+    }
+
+    /// Asynchronously removes a key from the wrapped cache, possibly after a stall.
+    async fn remove(&mut self, key: String) {
+        self.maybe_stall().await;
+        self.inner.remove(key).await;
+    }
+
+    /// Asynchronously returns the entries of the wrapped cache, possibly after a stall.
+    async fn entries(&mut self) -> Vec<(String, String)> {
+        self.maybe_stall().await;
+        self.inner.entries().await
+    }
+
+    /// Asynchronously returns a range of the entries of the wrapped cache, possibly after a stall.
+    async fn range(
+        &mut self,
+        start: Option<String>,
+        end: Option<String>,
+        limit: usize,
+    ) -> Result<Vec<(String, String)>> {
+        self.maybe_stall().await;
+        self.inner.range(start, end, limit).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A transport recording the frames it sends.
+    #[derive(Default)]
+    struct RecordingTransport {
+        sent: Mutex<Vec<Vec<u8>>>,
+    }
+
+    #[async_trait]
+    impl Transport for RecordingTransport {
+        async fn members(&self) -> Result<Vec<Member>> {
+            Ok(Vec::new())
+        }
+
+        async fn send(&self, _target: SocketAddr, frame: &[u8]) -> Result<()> {
+            self.sent.lock().unwrap().push(frame.to_vec());
+            Ok(())
+        }
+
+        async fn join(&self, _addr: SocketAddr) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Unit test for `ChaosTransport`.
+    ///
+    /// Frames are dropped, duplicated, reordered and delayed as configured, and sent untouched
+    /// once chaos is disabled again.
+    #[tokio::test(start_paused = true)]
+    async fn test_chaos_transport() {
+        let target: SocketAddr = "127.0.0.1:4001".parse().unwrap();
+        let recorder = Arc::new(RecordingTransport::default());
+        let chaos = Arc::new(Chaos::default());
+        let transport = ChaosTransport::new(recorder.clone(), chaos.clone());
+        let sent = || recorder.sent.lock().unwrap().drain(..).collect::<Vec<_>>();
+
+        let send_all = |options: ChaosOptions| {
+            chaos.set(options).unwrap();
+            async {
+                transport.send(target, b"1").await.unwrap();
+                transport.send(target, b"2").await.unwrap();
+            }
+        };
+        send_all(ChaosOptions::default()).await;
+        assert_eq!(sent(), vec![b"1".to_vec(), b"2".to_vec()]);
+
+        send_all(ChaosOptions {
+            drop_percent: 100.0,
+            ..ChaosOptions::default()
+        })
+        .await;
+        assert!(sent().is_empty());
+
+        send_all(ChaosOptions {
+            duplicate_percent: 100.0,
+            ..ChaosOptions::default()
+        })
+        .await;
+        assert_eq!(sent().len(), 4);
+
+        send_all(ChaosOptions {
+            reorder_percent: 100.0,
+            max_delay_ms: 1000,
+            ..ChaosOptions::default()
+        })
+        .await;
+        assert_eq!(sent(), vec![b"2".to_vec(), b"1".to_vec()]);
+
+        send_all(ChaosOptions {
+            delay_percent: 100.0,
+            max_delay_ms: 100,
+            ..ChaosOptions::default()
+        })
+        .await;
+        assert!(sent().is_empty());
+        time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(sent().len(), 2);
+
+        assert!(chaos
+            .set(ChaosOptions {
+                drop_percent: 101.0,
+                ..ChaosOptions::default()
+            })
+            .is_err());
+        assert_eq!(chaos.options().delay_percent, 100.0);
+    }
+}
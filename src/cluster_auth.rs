@@ -0,0 +1,687 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use ring::hmac;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::transport::{Member, Transport};
+use crate::utils::unix_time_ms;
+
+/// The length in bytes of the tag appended to every authenticated frame.
+const TAG_LEN: usize = 32;
+
+/// The length in bytes of the fixed part of the header of an authenticated frame: its kind,
+/// its nonce and the length of the name of its sender.
+const HEADER_LEN: usize = 11;
+
+/// The minimum time between two greetings of a member that has not authenticated yet.
+const GREETING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The maximum number of peers whose rejected frames are reported individually. Frames of
+/// further peers are only counted in the total, so spoofed addresses cannot grow the report.
+const MAX_REJECTED_PEERS: usize = 64;
+
+/// The maximum number of challenges awaiting a response. Further challenges are dropped until
+/// the responses are sent, so replayed challenges cannot grow the queue.
+const MAX_PENDING_RESPONSES: usize = 64;
+
+/// The secret shared by the members of a cluster, which they authenticate their gossip frames
+/// with. It must not be empty, and is never printed.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ClusterSecret(String);
+
+impl fmt::Debug for ClusterSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ClusterSecret(<redacted>)")
+    }
+}
+
+impl FromStr for ClusterSecret {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.is_empty() {
+            return Err(anyhow!("The cluster secret must not be empty"));
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+/// A peer as seen by the authentication of the cluster.
+///
+/// # Fields
+///
+/// - `name`: The name the peer joined the membership protocol with, once it did.
+/// - `verified`: Whether the peer sent a frame authenticated with the secret of the cluster.
+/// - `greeted`: When the peer was last greeted, while it has not authenticated yet.
+/// - `challenge`: The random nonce the peer was challenged with, which it must sign to
+///   authenticate. It is kept once the peer authenticated, so repeated responses are ignored.
+#[derive(Debug, Default)]
+struct Peer {
+    name: Option<String>,
+    verified: bool,
+    greeted: Option<Instant>,
+    challenge: Option<u64>,
+}
+
+/// What an authenticated frame carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameKind {
+    /// A gossip frame, handed to the node.
+    Data = 0,
+    /// A greeting challenging the receiver to sign its nonce.
+    Challenge = 1,
+    /// The response to a challenge, carrying its nonce.
+    Response = 2,
+}
+
+impl FrameKind {
+    fn from_u8(kind: u8) -> Option<Self> {
+        match kind {
+            0 => Some(Self::Data),
+            1 => Some(Self::Challenge),
+            2 => Some(Self::Response),
+            _ => None,
+        }
+    }
+}
+
+/// The header of an authenticated frame.
+///
+/// # Fields
+///
+/// - `kind`: What the frame carries.
+/// - `nonce`: The nonce of the challenge for challenges and responses, `0` for data.
+/// - `sender`: The name of the node that sent the frame.
+#[derive(Debug)]
+struct Header {
+    kind: FrameKind,
+    nonce: u64,
+    sender: String,
+}
+
+/// The frames rejected from a peer, as served at `/cluster/auth`.
+///
+/// # Fields
+///
+/// - `addr`: The address the frames came from.
+/// - `frames`: The number of frames rejected.
+/// - `last_rejected_ms`: When the last frame was rejected, as a Unix timestamp in milliseconds.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RejectedPeer {
+    pub addr: SocketAddr,
+    pub frames: u64,
+    pub last_rejected_ms: u64,
+}
+
+/// The authentication of the gossip frames of a node, as served at `/cluster/auth`.
+///
+/// # Fields
+///
+/// - `enabled`: Whether frames are authenticated.
+/// - `verified_members`: The names of the members that authenticated, sorted.
+/// - `pending_members`: The names of the members that joined the membership protocol but have
+///   not authenticated, and receive no replicated data, sorted.
+/// - `rejected_frames`: The number of frames rejected since the node started.
+/// - `rejected_peers`: The peers frames were rejected from, most rejected first.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClusterAuthStats {
+    pub enabled: bool,
+    pub verified_members: Vec<String>,
+    pub pending_members: Vec<String>,
+    pub rejected_frames: u64,
+    pub rejected_peers: Vec<RejectedPeer>,
+}
+
+/// Authenticates the gossip frames exchanged by the members of a cluster with a shared secret.
+///
+/// Every frame sent carries the name of its sender and an HMAC-SHA256 tag of its content, its
+/// sender and its receiver, and frames received without a valid tag are dropped, logged and
+/// counted. A member is only admitted through a challenge: it is greeted with a random nonce,
+/// issued for its address, and admitted once it sends back a response signing that nonce
+/// together with both names. Until then, it is left out of the members replicated to, and its
+/// gossip frames are rejected. A frame captured on the network therefore cannot admit anyone:
+/// replayed from another address, it answers no challenge issued to that address, and its
+/// sender is not the member there.
+///
+/// The membership protocol itself is not authenticated, so peers without the secret still
+/// learn the addresses of the members, but they receive no replicated data. Frames are
+/// authenticated, not encrypted, and gossip frames are not numbered: a peer able to spoof the
+/// address of an admitted member can replay its frames to the same receiver.
+///
+/// Authentication is disabled when no secret is configured.
+///
+/// # Example
+///
+/// ```rust
+/// let auth = Arc::new(ClusterAuth::new("node-1".to_string(), Some("s3cr3t".parse()?)));
+/// let frame = auth.sign(peer, b"frame");
+/// assert!(auth.verify(peer, frame).is_none()); // Not from an admitted member
+/// ```
+pub struct ClusterAuth {
+    /// The name of this node, which frames are signed for and by.
+    name: String,
+    key: Option<hmac::Key>,
+    peers: Mutex<HashMap<SocketAddr, Peer>>,
+    /// The responses to the challenges received, by the address they are sent to.
+    responses: Mutex<HashMap<SocketAddr, Vec<u8>>>,
+    rejected_frames: AtomicU64,
+    rejected_peers: Mutex<HashMap<SocketAddr, RejectedPeer>>,
+}
+
+impl fmt::Debug for ClusterAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClusterAuth")
+            .field("enabled", &self.is_enabled())
+            .finish()
+    }
+}
+
+impl Default for ClusterAuth {
+    fn default() -> Self {
+        Self::new(String::new(), None)
+    }
+}
+
+impl ClusterAuth {
+    /// Creates the authentication of the node named `name`, disabled when `secret` is `None`.
+    pub fn new(name: String, secret: Option<ClusterSecret>) -> Self {
+        Self {
+            name,
+            key: secret.map(|secret| hmac::Key::new(hmac::HMAC_SHA256, secret.0.as_bytes())),
+            peers: Mutex::new(HashMap::new()),
+            responses: Mutex::new(HashMap::new()),
+            rejected_frames: AtomicU64::new(0),
+            rejected_peers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns whether frames are authenticated.
+    pub fn is_enabled(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// Returns the name of this node.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns a gossip frame for the peer at `to` with its header and tag appended, or the
+    /// frame itself when authentication is disabled.
+    pub fn sign(&self, to: SocketAddr, frame: &[u8]) -> Vec<u8> {
+        match &self.key {
+            Some(key) => self.seal(key, FrameKind::Data, 0, &self.peer_name(to), frame),
+            None => frame.to_vec(),
+        }
+    }
+
+    /// Returns the challenge greeting the member named `name` at `to`, whose nonce is drawn
+    /// the first time the member is challenged, or `None` when authentication is disabled.
+    fn challenge(&self, to: SocketAddr, name: &str) -> Option<Vec<u8>> {
+        let key = self.key.as_ref()?;
+        let nonce = *self
+            .peers
+            .lock()
+            .unwrap()
+            .entry(to)
+            .or_default()
+            .challenge
+            .get_or_insert_with(rand::random);
+        Some(self.seal(key, FrameKind::Challenge, nonce, name, &[]))
+    }
+
+    /// Appends the header of a frame and its tag, which also covers the name of its receiver.
+    fn seal(
+        &self,
+        key: &hmac::Key,
+        kind: FrameKind,
+        nonce: u64,
+        receiver: &str,
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let mut frame = payload.to_vec();
+        frame.push(kind as u8);
+        frame.extend_from_slice(&nonce.to_be_bytes());
+        frame.extend_from_slice(self.name.as_bytes());
+        frame.extend_from_slice(&(self.name.len() as u16).to_be_bytes());
+        let tag = hmac::sign(key, &[frame.as_slice(), receiver.as_bytes()].concat());
+        frame.extend_from_slice(tag.as_ref());
+        frame
+    }
+
+    /// Checks the tag of a frame signed for this node, and splits it into its payload and
+    /// header.
+    fn open(&self, key: &hmac::Key, mut frame: Vec<u8>) -> Option<(Vec<u8>, Header)> {
+        if frame.len() < HEADER_LEN + TAG_LEN {
+            return None;
+        }
+        let tag = frame.split_off(frame.len() - TAG_LEN);
+        hmac::verify(
+            key,
+            &[frame.as_slice(), self.name.as_bytes()].concat(),
+            &tag,
+        )
+        .ok()?;
+        let len = frame.len();
+        let name_len = u16::from_be_bytes([frame[len - 2], frame[len - 1]]) as usize;
+        frame.truncate(len - 2);
+        if frame.len() < name_len + HEADER_LEN - 2 {
+            return None;
+        }
+        let sender = String::from_utf8(frame.split_off(frame.len() - name_len)).ok()?;
+        let nonce = u64::from_be_bytes(frame.split_off(frame.len() - 8).try_into().ok()?);
+        let kind = FrameKind::from_u8(frame.pop()?)?;
+        Some((
+            frame,
+            Header {
+                kind,
+                nonce,
+                sender,
+            },
+        ))
+    }
+
+    /// Returns the name of the peer at `addr`, or an empty name if it is not known yet.
+    fn peer_name(&self, addr: SocketAddr) -> String {
+        self.peers
+            .lock()
+            .unwrap()
+            .get(&addr)
+            .and_then(|peer| peer.name.clone())
+            .unwrap_or_default()
+    }
+
+    /// Checks a frame received from `from`. Challenges are answered, responses to the challenge
+    /// issued to `from` admit the peer, and gossip frames are only accepted from the admitted
+    /// member at `from`. Other frames are logged and counted as rejected.
+    ///
+    /// # Returns
+    ///
+    /// * The gossip frame without its header and tag, empty for challenges and responses, and
+    ///   the name of the member admitted by it, if any, or `None` if the frame was rejected.
+    pub fn verify(&self, from: SocketAddr, frame: Vec<u8>) -> Option<(Vec<u8>, Option<String>)> {
+        let Some(key) = &self.key else {
+            return Some((frame, None));
+        };
+        let accepted = self
+            .open(key, frame)
+            .and_then(|(payload, header)| self.accept(key, from, payload, header));
+        if accepted.is_none() {
+            self.reject(from);
+        }
+        accepted
+    }
+
+    /// Applies an authenticated frame received from `from`, as described by `verify`.
+    fn accept(
+        &self,
+        key: &hmac::Key,
+        from: SocketAddr,
+        payload: Vec<u8>,
+        header: Header,
+    ) -> Option<(Vec<u8>, Option<String>)> {
+        if header.kind == FrameKind::Challenge {
+            // The response only admits this node at the address the challenge was issued to,
+            // so it is sent wherever the challenge came from.
+            let mut responses = self.responses.lock().unwrap();
+            if responses.len() < MAX_PENDING_RESPONSES || responses.contains_key(&from) {
+                let response =
+                    self.seal(key, FrameKind::Response, header.nonce, &header.sender, &[]);
+                responses.insert(from, response);
+            }
+            return Some((Vec::new(), None));
+        }
+
+        let mut peers = self.peers.lock().unwrap();
+        let peer = peers.get_mut(&from)?;
+        if peer
+            .name
+            .as_ref()
+            .is_some_and(|name| *name != header.sender)
+        {
+            return None;
+        }
+        match header.kind {
+            FrameKind::Data => peer.verified.then_some((payload, None)),
+            _ if peer.challenge != Some(header.nonce) => None,
+            _ if peer.verified => Some((Vec::new(), None)),
+            _ => {
+                peer.verified = true;
+                peer.name = Some(header.sender.clone());
+                info!(
+                    "Peer {} authenticated with the cluster secret as {}",
+                    from, header.sender
+                );
+                Some((Vec::new(), Some(header.sender)))
+            }
+        }
+    }
+
+    /// Takes the responses to the challenges received, with the addresses to send them to.
+    fn take_responses(&self) -> Vec<(SocketAddr, Vec<u8>)> {
+        self.responses.lock().unwrap().drain().collect()
+    }
+
+    /// Records a frame rejected from `from`.
+    fn reject(&self, from: SocketAddr) {
+        let total = self.rejected_frames.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut rejected = self.rejected_peers.lock().unwrap();
+        let len = rejected.len();
+        let frames = match rejected.get_mut(&from) {
+            Some(peer) => {
+                peer.frames += 1;
+                peer.last_rejected_ms = unix_time_ms();
+                peer.frames
+            }
+            None if len < MAX_REJECTED_PEERS => {
+                rejected.insert(
+                    from,
+                    RejectedPeer {
+                        addr: from,
+                        frames: 1,
+                        last_rejected_ms: unix_time_ms(),
+                    },
+                );
+                1
+            }
+            None => 0,
+        };
+        // Only powers of two are logged, so a peer flooding the node does not flood the logs.
+        if frames.is_power_of_two() || (frames == 0 && total.is_power_of_two()) {
+            warn!(
+                "Rejected an unauthenticated gossip frame from {} ({} from this peer, {} in total)",
+                from, frames, total
+            );
+        }
+    }
+
+    /// Records that a member joined the membership protocol.
+    ///
+    /// # Returns
+    ///
+    /// * Whether the member is admitted: it already authenticated, or authentication is disabled.
+    pub fn joined(&self, name: &str, addr: SocketAddr) -> bool {
+        if !self.is_enabled() {
+            return true;
+        }
+        let mut peers = self.peers.lock().unwrap();
+        let peer = peers.entry(addr).or_default();
+        peer.name = Some(name.to_string());
+        peer.verified
+    }
+
+    /// Returns whether the member named `name` is admitted.
+    pub fn is_admitted(&self, name: &str) -> bool {
+        !self.is_enabled()
+            || self
+                .peers
+                .lock()
+                .unwrap()
+                .values()
+                .any(|peer| peer.verified && peer.name.as_deref() == Some(name))
+    }
+
+    /// Forgets a member that left the cluster, which has to authenticate again if it returns.
+    pub fn left(&self, name: &str) {
+        self.peers
+            .lock()
+            .unwrap()
+            .retain(|_, peer| peer.name.as_deref() != Some(name));
+    }
+
+    /// Returns whether the peer at `addr` authenticated, or authentication is disabled.
+    pub fn is_verified(&self, addr: SocketAddr) -> bool {
+        !self.is_enabled()
+            || self
+                .peers
+                .lock()
+                .unwrap()
+                .get(&addr)
+                .is_some_and(|peer| peer.verified)
+    }
+
+    /// Returns whether the peer at `addr` is due a greeting, recording it as greeted if so.
+    fn greet(&self, addr: SocketAddr, now: Instant) -> bool {
+        let mut peers = self.peers.lock().unwrap();
+        let peer = peers.entry(addr).or_default();
+        let due = peer
+            .greeted
+            .is_none_or(|greeted| now.duration_since(greeted) >= GREETING_INTERVAL);
+        if due {
+            peer.greeted = Some(now);
+        }
+        due
+    }
+
+    /// Returns the members admitted and pending, and the frames rejected.
+    pub fn stats(&self) -> ClusterAuthStats {
+        let (mut verified_members, mut pending_members) = (Vec::new(), Vec::new());
+        for peer in self.peers.lock().unwrap().values() {
+            if let Some(name) = &peer.name {
+                if peer.verified {
+                    verified_members.push(name.clone());
+                } else {
+                    pending_members.push(name.clone());
+                }
+            }
+        }
+        verified_members.sort();
+        pending_members.sort();
+        let mut rejected_peers: Vec<RejectedPeer> = self
+            .rejected_peers
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect();
+        rejected_peers.sort_by(|a, b| b.frames.cmp(&a.frames).then(a.addr.cmp(&b.addr)));
+        ClusterAuthStats {
+            enabled: self.is_enabled(),
+            verified_members,
+            pending_members,
+            rejected_frames: self.rejected_frames.load(Ordering::Relaxed),
+            rejected_peers,
+        }
+    }
+}
+
+/// `AuthTransport` wraps a `Transport`, signing the frames it sends with the secret of the
+/// cluster and leaving the members that have not authenticated out of its members.
+///
+/// Frames received are checked by the `TransportEvents` of the node, which share its
+/// `ClusterAuth`. The responses to the challenges they received are sent along with the
+/// greetings, whenever the members are listed.
+pub struct AuthTransport {
+    inner: Arc<dyn Transport>,
+    auth: Arc<ClusterAuth>,
+}
+
+impl AuthTransport {
+    /// Creates a new `AuthTransport` wrapping `inner`, for the node `auth` was created for.
+    pub fn new(inner: Arc<dyn Transport>, auth: Arc<ClusterAuth>) -> Self {
+        Self { inner, auth }
+    }
+}
+
+#[async_trait]
+impl Transport for AuthTransport {
+    /// Returns the members that authenticated, this node included. Members that have not are
+    /// challenged, at most once per `GREETING_INTERVAL`.
+    async fn members(&self) -> Result<Vec<Member>> {
+        let mut members = self.inner.members().await?;
+        let now = Instant::now();
+        let mut frames = self.auth.take_responses();
+        members.retain(|member| {
+            if member.name == self.auth.name() || self.auth.is_verified(member.addr) {
+                return true;
+            }
+            if self.auth.greet(member.addr, now) {
+                frames.extend(
+                    self.auth
+                        .challenge(member.addr, &member.name)
+                        .map(|challenge| (member.addr, challenge)),
+                );
+            }
+            false
+        });
+        for (addr, frame) in frames {
+            if let Err(e) = self.inner.send(addr, &frame).await {
+                warn!("Failed to greet {}: {}", addr, e);
+            }
+        }
+        Ok(members)
+    }
+
+    async fn send(&self, target: SocketAddr, frame: &[u8]) -> Result<()> {
+        self.inner
+            .send(target, &self.auth.sign(target, frame))
+            .await
+    }
+
+    async fn join(&self, addr: SocketAddr) -> Result<()> {
+        self.inner.join(addr).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::QueueOptions;
+    use crate::transport::{LoopbackNetwork, TransportEvents};
+
+    /// Unit test for `ClusterAuth` and `AuthTransport`.
+    ///
+    /// Members sharing the secret admit each other once they exchanged a greeting and a frame,
+    /// while a node with another secret is never admitted, and its frames are rejected and
+    /// counted.
+    #[tokio::test]
+    async fn test_cluster_auth() {
+        let disabled = ClusterAuth::default();
+        let from: SocketAddr = "127.0.0.1:4009".parse().unwrap();
+        assert_eq!(disabled.sign(from, b"frame"), b"frame".to_vec());
+        assert!(disabled.verify(from, b"frame".to_vec()).is_some());
+        assert!("".parse::<ClusterSecret>().is_err());
+
+        let network = LoopbackNetwork::default();
+        let mut nodes = Vec::new();
+        for (i, secret) in ["s3cr3t", "s3cr3t", "guess"].into_iter().enumerate() {
+            let name = format!("node-{}", i + 1);
+            let addr: SocketAddr = format!("127.0.0.1:400{}", i + 1).parse().unwrap();
+            let auth = Arc::new(ClusterAuth::new(
+                name.clone(),
+                Some(secret.parse().unwrap()),
+            ));
+            let (events, receiver) = TransportEvents::new(&QueueOptions::new(16), auth.clone());
+            let inner = Arc::new(network.attach(&name, addr, events));
+            let transport = AuthTransport::new(inner, auth.clone());
+            nodes.push((transport, auth, receiver, addr));
+        }
+        nodes[0].0.join(nodes[1].3).await.unwrap();
+        nodes[2].0.join(nodes[0].3).await.unwrap();
+        assert_eq!(nodes[0].0.members().await.unwrap().len(), 1);
+
+        for (transport, ..) in &nodes {
+            transport.members().await.unwrap();
+        }
+        nodes[1].0.send(nodes[0].3, b"ping").await.unwrap();
+        let names = |members: Vec<Member>| {
+            let mut names: Vec<String> = members.into_iter().map(|m| m.name).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            names(nodes[0].0.members().await.unwrap()),
+            vec!["node-1", "node-2"]
+        );
+        assert_eq!(
+            names(nodes[1].0.members().await.unwrap()),
+            vec!["node-1", "node-2"]
+        );
+        assert_eq!(names(nodes[2].0.members().await.unwrap()), vec!["node-3"]);
+
+        nodes[2].0.send(nodes[0].3, b"let me in").await.unwrap();
+        nodes[0].0.send(nodes[1].3, b"replicated").await.unwrap();
+        let (_, frame) = nodes[1].2.recv().await.unwrap();
+        assert_eq!(frame, b"replicated".to_vec());
+        let (from, frame) = nodes[0].2.recv().await.unwrap();
+        assert_eq!((from, frame), (nodes[1].3, b"ping".to_vec()));
+        assert_eq!(nodes[0].2.counters().stats().len, 0);
+
+        let stats = nodes[0].1.stats();
+        assert!(stats.enabled);
+        assert_eq!(stats.verified_members, vec!["node-2"]);
+        assert_eq!(stats.pending_members, vec!["node-3"]);
+        assert_eq!(stats.rejected_frames, 2);
+        assert_eq!(stats.rejected_peers[0].addr, nodes[2].3);
+        assert!(nodes[0].1.is_admitted("node-2"));
+        assert!(!nodes[0].1.is_admitted("node-3"));
+
+        let frame = nodes[0].1.sign(nodes[1].3, b"frame");
+        let tampered = {
+            let mut frame = frame.clone();
+            frame[0] ^= 1;
+            frame
+        };
+        assert!(nodes[1].1.verify(nodes[0].3, tampered).is_none());
+        assert_eq!(
+            nodes[1].1.verify(nodes[0].3, frame),
+            Some((b"frame".to_vec(), None))
+        );
+        nodes[1].1.left("node-1");
+        assert!(!nodes[1].1.is_verified(nodes[0].3));
+    }
+
+    /// Unit test for replaying captured frames.
+    ///
+    /// A response captured on its way to a node does not admit the peer it is replayed from,
+    /// as no challenge was issued to that address for it, and neither does a gossip frame or a
+    /// challenge meant for another member.
+    #[test]
+    fn test_replayed_frames() {
+        let secret: ClusterSecret = "s3cr3t".parse().unwrap();
+        let node_1 = ClusterAuth::new("node-1".to_string(), Some(secret.clone()));
+        let node_2 = ClusterAuth::new("node-2".to_string(), Some(secret));
+        let addr_1: SocketAddr = "127.0.0.1:4001".parse().unwrap();
+        let addr_2: SocketAddr = "127.0.0.1:4002".parse().unwrap();
+        let intruder: SocketAddr = "127.0.0.1:4009".parse().unwrap();
+        assert!(!node_1.joined("node-2", addr_2));
+        assert!(!node_1.joined("intruder", intruder));
+        assert!(!node_2.joined("node-1", addr_1));
+
+        let challenge = node_1.challenge(addr_2, "node-2").unwrap();
+        assert_eq!(node_2.verify(addr_1, challenge), Some((Vec::new(), None)));
+        let (to, response) = node_2.take_responses().pop().unwrap();
+        assert_eq!(to, addr_1);
+
+        node_1.challenge(intruder, "intruder").unwrap();
+        assert!(node_1.verify(intruder, response.clone()).is_none());
+        assert!(!node_1.is_verified(intruder));
+        assert_eq!(
+            node_1.verify(addr_2, response.clone()),
+            Some((Vec::new(), Some("node-2".to_string())))
+        );
+        assert_eq!(node_1.verify(addr_2, response), Some((Vec::new(), None)));
+
+        let frame = node_2.sign(addr_1, b"replicated");
+        assert!(node_1.verify(intruder, frame.clone()).is_none());
+        assert_eq!(
+            node_1.verify(addr_2, frame),
+            Some((b"replicated".to_vec(), None))
+        );
+
+        let challenge = node_1.challenge(intruder, "intruder").unwrap();
+        assert!(node_2.verify(intruder, challenge).is_none());
+        assert!(node_2.take_responses().is_empty());
+
+        assert!(node_1.is_admitted("node-2"));
+        assert!(!node_1.is_admitted("intruder"));
+        assert_eq!(node_1.stats().rejected_frames, 2);
+    }
+}
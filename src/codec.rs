@@ -0,0 +1,405 @@
+use crate::gossip::{Command, Message, Sequence};
+use crate::wire::{Envelope, Rumor};
+use anyhow::{anyhow, Result};
+use bincode::Options;
+use clap::ValueEnum;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+
+/// Serializes the envelopes of gossip frames, from protocol version 16 on.
+///
+/// Decoders are fed bytes from any peer that can reach the gossip port, so they must fail
+/// cleanly on arbitrary input, without panicking nor allocating more than the payload justifies.
+/// The size of payloads and the contents of the decoded envelopes are checked by `wire::decode`.
+pub trait Codec: Send + Sync {
+    /// Serializes an envelope into a payload.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the envelope cannot be serialized.
+    fn encode(&self, envelope: &Envelope) -> Result<Vec<u8>>;
+
+    /// Deserializes a payload into an envelope.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload is malformed, or has bytes left after the envelope.
+    fn decode(&self, payload: &[u8]) -> Result<Envelope>;
+}
+
+/// The codecs a node can encode its gossip frames with, flagged in the header of v16 and later
+/// frames. Nodes decode frames of every codec, whichever they encode with.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum WireCodec {
+    /// Compact and fast, the layout of every frame before v16.
+    #[default]
+    Bincode,
+    /// Readable in packet captures, at the cost of larger payloads.
+    Json,
+    /// Decodable by peers that are not written in Rust.
+    Protobuf,
+}
+
+impl WireCodec {
+    pub(crate) fn flag(self) -> u8 {
+        match self {
+            WireCodec::Bincode => 0,
+            WireCodec::Json => 1,
+            WireCodec::Protobuf => 2,
+        }
+    }
+
+    pub(crate) fn from_flag(flag: u8) -> Result<Self> {
+        match flag {
+            0 => Ok(WireCodec::Bincode),
+            1 => Ok(WireCodec::Json),
+            2 => Ok(WireCodec::Protobuf),
+            f => Err(anyhow!("Unknown gossip codec flag {}", f)),
+        }
+    }
+
+    /// Returns the implementation of the codec.
+    pub fn codec(self) -> &'static dyn Codec {
+        match self {
+            WireCodec::Bincode => &BincodeCodec,
+            WireCodec::Json => &JsonCodec,
+            WireCodec::Protobuf => &ProtobufCodec,
+        }
+    }
+}
+
+/// Bounds what a gossip frame received from a peer may carry, so that a malformed or malicious
+/// frame is rejected before it costs memory or reaches the cache.
+///
+/// # Fields
+///
+/// - `max_payload_bytes`: The maximum size of a frame, and of its payload once decompressed.
+/// - `max_messages`: The maximum number of messages in a frame.
+/// - `max_tags`: The maximum number of tags of a message.
+/// - `max_trace_context`: The maximum number of trace context entries of a message.
+#[derive(Clone, Debug)]
+pub struct DecodeLimits {
+    pub max_payload_bytes: usize,
+    pub max_messages: usize,
+    pub max_tags: usize,
+    pub max_trace_context: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_payload_bytes: 16 * 1024 * 1024,
+            max_messages: 10_000,
+            max_tags: 1024,
+            max_trace_context: 32,
+        }
+    }
+}
+
+/// Deserializes a bincode payload in the layout of `bincode::serialize`, rejecting length
+/// prefixes larger than the payload before allocating for them, and bytes left after the value.
+pub(crate) fn deserialize_bincode<T: DeserializeOwned>(payload: &[u8]) -> bincode::Result<T> {
+    bincode::options()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .with_limit(payload.len() as u64)
+        .deserialize(payload)
+}
+
+/// Encodes envelopes with bincode, in the layout of the envelopes of v18 frames.
+pub struct BincodeCodec;
+
+impl Codec for BincodeCodec {
+    fn encode(&self, envelope: &Envelope) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(envelope)?)
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<Envelope> {
+        deserialize_bincode(payload)
+            .map_err(|e| anyhow!("Failed to deserialize bincode envelope: {:?}", e))
+    }
+}
+
+/// Encodes envelopes as JSON objects with the fields of `Envelope`.
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn encode(&self, envelope: &Envelope) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(envelope)?)
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<Envelope> {
+        serde_json::from_slice(payload)
+            .map_err(|e| anyhow!("Failed to deserialize JSON envelope: {:?}", e))
+    }
+}
+
+/// Encodes envelopes with protobuf, following this schema:
+///
+/// ```proto
+/// message Envelope {
+///   uint64 id = 1;
+///   repeated Message messages = 2;
+///   optional Rumor rumor = 3;
+/// }
+///
+/// message Message {
+///   uint32 cmd = 1; // The index of the variant of `Command`, in declaration order
+///   string key = 2;
+///   string value = 3;
+///   map<string, string> trace_context = 4;
+///   repeated string tags = 5;
+///   optional Sequence sequence = 6;
+/// }
+///
+/// message Sequence {
+///   string origin = 1;
+///   uint64 incarnation = 2;
+///   uint64 seq = 3;
+/// }
+///
+/// message Rumor {
+///   string origin = 1;
+///   uint64 id = 2;
+///   uint32 hops = 3;
+///   repeated string members = 4;
+/// }
+/// ```
+pub struct ProtobufCodec;
+
+impl Codec for ProtobufCodec {
+    fn encode(&self, envelope: &Envelope) -> Result<Vec<u8>> {
+        Ok(prost::Message::encode_to_vec(&ProtoEnvelope::from(
+            envelope,
+        )))
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<Envelope> {
+        let envelope: ProtoEnvelope = prost::Message::decode(payload)
+            .map_err(|e| anyhow!("Failed to deserialize protobuf envelope: {:?}", e))?;
+        envelope.try_into()
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ProtoEnvelope {
+    #[prost(uint64, tag = "1")]
+    id: u64,
+    #[prost(message, repeated, tag = "2")]
+    messages: Vec<ProtoMessage>,
+    #[prost(message, optional, tag = "3")]
+    rumor: Option<ProtoRumor>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ProtoMessage {
+    #[prost(uint32, tag = "1")]
+    cmd: u32,
+    #[prost(string, tag = "2")]
+    key: String,
+    #[prost(string, tag = "3")]
+    value: String,
+    #[prost(map = "string, string", tag = "4")]
+    trace_context: HashMap<String, String>,
+    #[prost(string, repeated, tag = "5")]
+    tags: Vec<String>,
+    #[prost(message, optional, tag = "6")]
+    sequence: Option<ProtoSequence>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ProtoSequence {
+    #[prost(string, tag = "1")]
+    origin: String,
+    #[prost(uint64, tag = "2")]
+    incarnation: u64,
+    #[prost(uint64, tag = "3")]
+    seq: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ProtoRumor {
+    #[prost(string, tag = "1")]
+    origin: String,
+    #[prost(uint64, tag = "2")]
+    id: u64,
+    #[prost(uint32, tag = "3")]
+    hops: u32,
+    #[prost(string, repeated, tag = "4")]
+    members: Vec<String>,
+}
+
+/// Returns the protobuf number of a command, the index of its variant like in bincode.
+fn command_number(cmd: &Command) -> u32 {
+    match cmd {
+        Command::Ping => 0,
+        Command::Insert => 1,
+        Command::Remove => 2,
+        Command::InvalidateTag => 3,
+        Command::ExpireTag => 4,
+        Command::Ack => 5,
+        Command::Resync => 6,
+        Command::Txn => 7,
+        Command::Patch => 8,
+        Command::Update => 9,
+        Command::InsertIfAbsent => 10,
+        Command::Expire => 11,
+        Command::Checkpoint => 12,
+    }
+}
+
+fn command_from_number(number: u32) -> Result<Command> {
+    Ok(match number {
+        0 => Command::Ping,
+        1 => Command::Insert,
+        2 => Command::Remove,
+        3 => Command::InvalidateTag,
+        4 => Command::ExpireTag,
+        5 => Command::Ack,
+        6 => Command::Resync,
+        7 => Command::Txn,
+        8 => Command::Patch,
+        9 => Command::Update,
+        10 => Command::InsertIfAbsent,
+        11 => Command::Expire,
+        12 => Command::Checkpoint,
+        n => return Err(anyhow!("Unknown gossip command {}", n)),
+    })
+}
+
+impl From<&Envelope> for ProtoEnvelope {
+    fn from(envelope: &Envelope) -> Self {
+        Self {
+            id: envelope.id,
+            messages: envelope
+                .messages
+                .iter()
+                .map(|msg| ProtoMessage {
+                    cmd: command_number(&msg.cmd),
+                    key: msg.key.clone(),
+                    value: msg.value.clone(),
+                    trace_context: msg.trace_context.clone(),
+                    tags: msg.tags.clone(),
+                    sequence: msg.sequence.as_ref().map(|sequence| ProtoSequence {
+                        origin: sequence.origin.clone(),
+                        incarnation: sequence.incarnation,
+                        seq: sequence.seq,
+                    }),
+                })
+                .collect(),
+            rumor: envelope.rumor.as_ref().map(|rumor| ProtoRumor {
+                origin: rumor.origin.clone(),
+                id: rumor.id,
+                hops: rumor.hops,
+                members: rumor.members.clone(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<ProtoEnvelope> for Envelope {
+    type Error = anyhow::Error;
+
+    fn try_from(envelope: ProtoEnvelope) -> Result<Self> {
+        Ok(Self {
+            id: envelope.id,
+            messages: envelope
+                .messages
+                .into_iter()
+                .map(|msg| {
+                    Ok(Message {
+                        cmd: command_from_number(msg.cmd)?,
+                        key: msg.key,
+                        value: msg.value,
+                        trace_context: msg.trace_context,
+                        tags: msg.tags,
+                        sequence: msg.sequence.map(|sequence| Sequence {
+                            origin: sequence.origin,
+                            incarnation: sequence.incarnation,
+                            seq: sequence.seq,
+                        }),
+                    })
+                })
+                .collect::<Result<_>>()?,
+            rumor: envelope.rumor.map(|rumor| Rumor {
+                origin: rumor.origin,
+                id: rumor.id,
+                hops: rumor.hops,
+                members: rumor.members,
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit test for the codecs.
+    ///
+    /// Every codec round-trips an envelope, and rejects truncated payloads, trailing bytes and
+    /// length prefixes larger than the payload.
+    #[test]
+    fn test_codecs() {
+        let envelope = Envelope {
+            id: 42,
+            messages: vec![
+                Message {
+                    trace_context: HashMap::from([("traceparent".to_string(), "00-1".to_string())]),
+                    tags: vec!["sale".to_string()],
+                    sequence: Some(Sequence {
+                        origin: "node-1".to_string(),
+                        incarnation: 7,
+                        seq: 3,
+                    }),
+                    ..Message::new(Command::Insert, "hello".to_string(), "world".to_string())
+                },
+                Message::new(Command::Expire, "hello".to_string(), "1000".to_string()),
+            ],
+            rumor: Some(Rumor {
+                origin: "node-1".to_string(),
+                id: 9,
+                hops: 2,
+                members: vec!["node-2".to_string(), "node-3".to_string()],
+            }),
+        };
+
+        for codec in [WireCodec::Bincode, WireCodec::Json, WireCodec::Protobuf] {
+            assert_eq!(WireCodec::from_flag(codec.flag()).unwrap(), codec);
+            let payload = codec.codec().encode(&envelope).unwrap();
+            let decoded = codec.codec().decode(&payload).unwrap();
+            assert_eq!(decoded.id, 42);
+            assert_eq!(decoded.rumor, envelope.rumor);
+            assert_eq!(decoded.messages.len(), 2);
+            assert_eq!(decoded.messages[0].value, "world");
+            assert_eq!(decoded.messages[0].tags, envelope.messages[0].tags);
+            assert_eq!(decoded.messages[0].sequence, envelope.messages[0].sequence);
+            assert_eq!(
+                decoded.messages[0].trace_context,
+                envelope.messages[0].trace_context
+            );
+            assert_eq!(decoded.messages[1].cmd, Command::Expire);
+
+            assert!(codec.codec().decode(&payload[..payload.len() - 1]).is_err());
+        }
+        assert!(WireCodec::from_flag(3).is_err());
+
+        let mut payload = BincodeCodec.encode(&envelope).unwrap();
+        payload.push(0);
+        assert!(BincodeCodec.decode(&payload).is_err());
+
+        // An envelope claiming 2^60 messages is rejected without allocating for them.
+        let mut payload = 42u64.to_le_bytes().to_vec();
+        payload.extend((1u64 << 60).to_le_bytes());
+        assert!(BincodeCodec.decode(&payload).is_err());
+
+        let unknown_command = prost::Message::encode_to_vec(&ProtoEnvelope {
+            messages: vec![ProtoMessage {
+                cmd: 99,
+                ..ProtoMessage::default()
+            }],
+            ..ProtoEnvelope::default()
+        });
+        assert!(ProtobufCodec.decode(&unknown_command).is_err());
+    }
+}
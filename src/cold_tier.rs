@@ -0,0 +1,284 @@
+use async_trait::async_trait;
+use clap::ValueEnum;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tracing::{debug, warn};
+
+use crate::cache_trait::BCache;
+use crate::error::Error;
+use crate::utils::fnv1a;
+use anyhow::Result;
+
+/// How the objects of the cold tier are laid out under the prefix.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum KeyLayout {
+    /// Objects are stored as `<prefix>/<key>`.
+    #[default]
+    Flat,
+    /// Objects are stored as `<prefix>/<shard>/<key>`, where the shard is two hexadecimal digits
+    /// derived from the key, so requests spread over the bucket's partitions.
+    Hashed,
+}
+
+/// Configures the cold tier.
+///
+/// # Fields
+///
+/// - `bucket`: The S3-compatible bucket entries are offloaded to.
+/// - `prefix`: The prefix of every object written by the tier.
+/// - `layout`: How objects are laid out under the prefix.
+/// - `endpoint`: An optional endpoint for S3-compatible services other than AWS, such as MinIO.
+/// - `region`: An optional region. Credentials and a default region are read from the `AWS_*`
+///   environment variables.
+/// - `concurrency`: The maximum number of concurrent uploads and deletes.
+#[derive(Debug, Clone)]
+pub struct ColdTierOptions {
+    pub bucket: String,
+    pub prefix: String,
+    pub layout: KeyLayout,
+    pub endpoint: Option<String>,
+    pub region: Option<String>,
+    pub concurrency: usize,
+}
+
+impl ColdTierOptions {
+    /// Creates `ColdTierOptions` for a bucket, with a flat layout under `kv` and 8 concurrent requests.
+    pub fn new(bucket: String) -> Self {
+        Self {
+            bucket,
+            prefix: "kv".to_string(),
+            layout: KeyLayout::default(),
+            endpoint: None,
+            region: None,
+            concurrency: 8,
+        }
+    }
+}
+
+/// A write offloaded to the object store in the background.
+enum Offload {
+    Put(Path, String),
+    Delete(Path),
+}
+
+/// `ColdTierCache` wraps a `BCache` with an S3-compatible object store holding every entry.
+///
+/// Writes go to the wrapped in-memory cache and are offloaded to the bucket in the background,
+/// so entries evicted from memory stay available. A miss in memory is fetched from the bucket
+/// and populates the in-memory cache again. Offloads run on `concurrency` workers; all
+/// offloads of a key go to the same worker, so they reach the bucket in order.
+///
+/// # Example
+///
+/// ```rust
+/// let inner: Box<dyn BCache> = Box::new(MokaCache::new(1000).await);
+/// let mut cache = ColdTierCache::new(inner, ColdTierOptions::new("my-bucket".to_string()))?;
+/// cache.insert("key".to_string(), "value".to_string()).await;
+/// ```
+pub struct ColdTierCache {
+    /// The in-memory cache entries are served from.
+    inner: Box<dyn BCache>,
+    store: Arc<dyn ObjectStore>,
+    options: ColdTierOptions,
+    workers: Vec<UnboundedSender<Offload>>,
+}
+
+impl ColdTierCache {
+    /// Creates a new `ColdTierCache` offloading to the S3 bucket of `options`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the S3 client cannot be configured.
+    pub fn new(inner: Box<dyn BCache>, options: ColdTierOptions) -> Result<Self> {
+        let mut builder = AmazonS3Builder::from_env().with_bucket_name(&options.bucket);
+        if let Some(region) = &options.region {
+            builder = builder.with_region(region);
+        }
+        if let Some(endpoint) = &options.endpoint {
+            builder = builder
+                .with_endpoint(endpoint)
+                .with_allow_http(endpoint.starts_with("http://"));
+        }
+        Ok(Self::with_store(inner, Arc::new(builder.build()?), options))
+    }
+
+    /// Creates a new `ColdTierCache` offloading to the given object store.
+    ///
+    /// Must be called from within a Tokio runtime, which the offload workers are spawned on.
+    pub fn with_store(
+        inner: Box<dyn BCache>,
+        store: Arc<dyn ObjectStore>,
+        options: ColdTierOptions,
+    ) -> Self {
+        let workers = (0..options.concurrency.max(1))
+            .map(|_| {
+                let (sender, receiver) = mpsc::unbounded_channel();
+                tokio::spawn(offload(store.clone(), receiver));
+                sender
+            })
+            .collect();
+
+        Self {
+            inner,
+            store,
+            options,
+            workers,
+        }
+    }
+
+    /// Returns the path of the object holding a key.
+    fn path(&self, key: &str) -> Path {
+        let prefix = Path::from(self.options.prefix.as_str());
+        match self.options.layout {
+            KeyLayout::Flat => prefix.child(key),
+            KeyLayout::Hashed => prefix
+                .child(format!("{:02x}", fnv1a(key.as_bytes()) as u8))
+                .child(key),
+        }
+    }
+
+    /// Queues an offload on the worker responsible for `key`.
+    fn enqueue(&self, key: &str, offload: Offload) {
+        let worker = fnv1a(key.as_bytes()) as usize % self.workers.len();
+        if self.workers[worker].send(offload).is_err() {
+            warn!("Cold tier worker stopped, dropping offload of key {}", key);
+        }
+    }
+}
+
+/// Applies the offloads of one worker to the object store, one at a time.
+async fn offload(store: Arc<dyn ObjectStore>, mut receiver: UnboundedReceiver<Offload>) {
+    while let Some(offload) = receiver.recv().await {
+        let result = match &offload {
+            Offload::Put(path, value) => store.put(path, value.clone().into()).await.map(|_| ()),
+            Offload::Delete(path) => match store.delete(path).await {
+                Err(object_store::Error::NotFound { .. }) => Ok(()),
+                result => result,
+            },
+        };
+        match (result, offload) {
+            (Ok(()), Offload::Put(path, _)) => debug!("Offloaded {} to the cold tier", path),
+            (Ok(()), Offload::Delete(path)) => debug!("Deleted {} from the cold tier", path),
+            (Err(e), Offload::Put(path, _)) => warn!("Failed to offload {}: {:?}", path, e),
+            (Err(e), Offload::Delete(path)) => warn!("Failed to delete {}: {:?}", path, e),
+        }
+    }
+}
+
+#[async_trait]
+impl BCache for ColdTierCache {
+    /// Asynchronously inserts a key-value pair into the in-memory cache and queues its upload.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A `String` representing the key.
+    /// * `val` - A `String` representing the value associated with the key.
+    async fn insert(&mut self, key: String, val: String) {
+        self.enqueue(&key, Offload::Put(self.path(&key), val.clone()));
+        self.inner.insert(key, val).await;
+    }
+
+    /// Asynchronously retrieves the value of a key from memory, or from the bucket on a miss.
+    ///
+    /// A value fetched from the bucket is inserted into the in-memory cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A `String` representing the key to retrieve.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key is neither in memory nor in the bucket, or the bucket cannot be read.
+    async fn get(&mut self, key: String) -> Result<String> {
+        if let Ok(value) = self.inner.get(key.clone()).await {
+            return Ok(value);
+        }
+
+        let object = match self.store.get(&self.path(&key)).await {
+            Ok(object) => object,
+            Err(object_store::Error::NotFound { .. }) => return Err(Error::NotFound.into()),
+            Err(e) => return Err(Error::Backend(e.to_string()).into()),
+        };
+        let value = String::from_utf8(object.bytes().await?.to_vec())?;
+        debug!("Fetched key {} from the cold tier", key);
+        self.inner.insert(key, value.clone()).await;
+        Ok(value)
+    }
+
+    /// Asynchronously removes a key from the in-memory cache and queues its deletion from the bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A `String` representing the key to remove.
+    async fn remove(&mut self, key: String) {
+        self.enqueue(&key, Offload::Delete(self.path(&key)));
+        self.inner.remove(key).await;
+    }
+
+    /// Asynchronously returns the entries held in memory. Entries only held in the bucket are
+    /// not listed.
+    async fn entries(&mut self) -> Vec<(String, String)> {
+        self.inner.entries().await
+    }
+
+    /// Asynchronously returns a range of the entries held in memory. Entries only held in the
+    /// bucket are not listed.
+    async fn range(
+        &mut self,
+        start: Option<String>,
+        end: Option<String>,
+        limit: usize,
+    ) -> Result<Vec<(String, String)>> {
+        self.inner.range(start, end, limit).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moka_cache::MokaCache;
+    use object_store::memory::InMemory;
+    use std::time::Duration;
+
+    /// Unit test for `ColdTierCache`.
+    ///
+    /// An entry written through one cache is fetched from the shared bucket by another one
+    /// whose memory does not hold it, and is gone from both once removed.
+    #[tokio::test]
+    async fn test_cold_tier_cache() {
+        let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let options = ColdTierOptions {
+            layout: KeyLayout::Hashed,
+            ..ColdTierOptions::new("bucket".to_string())
+        };
+        let mut writer = ColdTierCache::with_store(
+            Box::new(MokaCache::new(16).await),
+            store.clone(),
+            options.clone(),
+        );
+        let mut reader =
+            ColdTierCache::with_store(Box::new(MokaCache::new(16).await), store.clone(), options);
+
+        writer
+            .insert("user/1".to_string(), "alice".to_string())
+            .await;
+        let path = writer.path("user/1");
+        while store.head(&path).await.is_err() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(reader.get("user/1".to_string()).await.unwrap(), "alice");
+        assert_eq!(
+            reader.inner.get("user/1".to_string()).await.unwrap(),
+            "alice"
+        );
+
+        writer.remove("user/1".to_string()).await;
+        while store.head(&path).await.is_ok() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(writer.get("user/1".to_string()).await.is_err());
+    }
+}
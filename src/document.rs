@@ -0,0 +1,196 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The content type of RFC 7386 JSON merge patches.
+pub const MERGE_PATCH_CONTENT_TYPE: &str = "application/merge-patch+json";
+/// The content type of RFC 6902 JSON patches.
+pub const JSON_PATCH_CONTENT_TYPE: &str = "application/json-patch+json";
+
+/// A change to a JSON document value, replicated in place of the whole patched document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "patch", rename_all = "snake_case")]
+pub enum DocumentPatch {
+    /// An RFC 7386 merge patch: objects are merged recursively and `null` members are removed.
+    Merge(Value),
+    /// An RFC 6902 patch: a list of operations applied in order, all or none.
+    Json(json_patch::Patch),
+}
+
+impl DocumentPatch {
+    /// Parses the body of a `PATCH` request according to its content type.
+    ///
+    /// `application/json` bodies are read as merge patches.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the content type is not supported or the body is not a valid patch.
+    pub fn parse(content_type: &str, body: &[u8]) -> Result<Self> {
+        let media_type = content_type.split(';').next().unwrap_or("").trim();
+        match media_type {
+            MERGE_PATCH_CONTENT_TYPE | "application/json" => {
+                Ok(DocumentPatch::Merge(serde_json::from_slice(body)?))
+            }
+            JSON_PATCH_CONTENT_TYPE => Ok(DocumentPatch::Json(serde_json::from_slice(body)?)),
+            _ => Err(anyhow!(
+                "Unsupported patch content type '{}', expected {} or {}",
+                content_type,
+                MERGE_PATCH_CONTENT_TYPE,
+                JSON_PATCH_CONTENT_TYPE
+            )),
+        }
+    }
+
+    /// Applies the patch to a document.
+    ///
+    /// # Arguments
+    ///
+    /// * `document` - The current value of the key, or `None` if it is absent, in which case the
+    ///   patch is applied to `null`.
+    ///
+    /// # Returns
+    ///
+    /// * The patched document, serialized.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the current value is not a JSON document, or an operation of an
+    /// RFC 6902 patch fails, in which case none of them is applied.
+    pub fn apply(&self, document: Option<&str>) -> Result<String> {
+        let mut document = match document {
+            Some(document) => serde_json::from_str(document)
+                .map_err(|e| anyhow!("The value is not a JSON document: {}", e))?,
+            None => Value::Null,
+        };
+        match self {
+            DocumentPatch::Merge(patch) => json_patch::merge(&mut document, patch),
+            DocumentPatch::Json(patch) => json_patch::patch(&mut document, patch)?,
+        }
+        Ok(serde_json::to_string(&document)?)
+    }
+}
+
+/// Returns the field of a JSON document at a path.
+///
+/// Paths are either a JSONPath of member names and array indices, such as `$.user.name`,
+/// `$.items[0]` or `$['first name']`, or an RFC 6901 JSON pointer such as `/user/name`.
+///
+/// # Errors
+///
+/// Returns an error if the value is not a JSON document, the path is malformed or no field
+/// exists at the path.
+pub fn select(document: &str, path: &str) -> Result<Value> {
+    let document: Value = serde_json::from_str(document)
+        .map_err(|e| anyhow!("The value is not a JSON document: {}", e))?;
+    let pointer = if path.starts_with('/') || path.is_empty() {
+        path.to_string()
+    } else {
+        json_path_to_pointer(path)?
+    };
+    document
+        .pointer(&pointer)
+        .cloned()
+        .ok_or_else(|| anyhow!("No field at {}", path))
+}
+
+/// Converts a JSONPath of member names and array indices into a JSON pointer.
+fn json_path_to_pointer(path: &str) -> Result<String> {
+    let malformed = || anyhow!("Malformed path {}", path);
+    let mut rest = path.strip_prefix('$').ok_or_else(malformed)?;
+    let mut pointer = String::new();
+
+    while !rest.is_empty() {
+        let token = if let Some(after) = rest.strip_prefix("['") {
+            let end = after.find("']").ok_or_else(malformed)?;
+            rest = &after[end + 2..];
+            &after[..end]
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(malformed)?;
+            rest = &after[end + 1..];
+            let index = &after[..end];
+            index.parse::<usize>().map_err(|_| malformed())?;
+            index
+        } else if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            rest = &after[end..];
+            &after[..end]
+        } else {
+            return Err(malformed());
+        };
+        if token.is_empty() {
+            return Err(malformed());
+        }
+        pointer.push('/');
+        pointer.push_str(&token.replace('~', "~0").replace('/', "~1"));
+    }
+    Ok(pointer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Unit test for selecting fields of a document with JSONPaths and JSON pointers.
+    #[test]
+    fn test_select() {
+        let document =
+            r#"{"user": {"name": "alice", "first name": "Al", "roles": ["admin", "dev"]}}"#;
+        assert_eq!(select(document, "$.user.name").unwrap(), json!("alice"));
+        assert_eq!(select(document, "$.user.roles[1]").unwrap(), json!("dev"));
+        assert_eq!(
+            select(document, "$.user['first name']").unwrap(),
+            json!("Al")
+        );
+        assert_eq!(select(document, "/user/roles/0").unwrap(), json!("admin"));
+        assert_eq!(
+            select(document, "$").unwrap(),
+            serde_json::from_str::<Value>(document).unwrap()
+        );
+        assert!(select(document, "$.user.age").is_err());
+        assert!(select(document, "user.name").is_err());
+        assert!(select(document, "$.user.roles[x]").is_err());
+        assert!(select("not json", "$.user").is_err());
+    }
+
+    /// Unit test for applying merge patches and RFC 6902 patches.
+    #[test]
+    fn test_apply_patch() {
+        let document = r#"{"user":{"name":"alice","age":30}}"#;
+
+        let merge = DocumentPatch::parse(
+            MERGE_PATCH_CONTENT_TYPE,
+            br#"{"user": {"age": null, "city": "Paris"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&merge.apply(Some(document)).unwrap()).unwrap(),
+            json!({"user": {"name": "alice", "city": "Paris"}})
+        );
+        assert_eq!(merge.apply(None).unwrap(), r#"{"user":{"city":"Paris"}}"#);
+
+        let patch = DocumentPatch::parse(
+            JSON_PATCH_CONTENT_TYPE,
+            br#"[{"op": "replace", "path": "/user/name", "value": "bob"}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&patch.apply(Some(document)).unwrap()).unwrap(),
+            json!({"user": {"name": "bob", "age": 30}})
+        );
+        let failing = DocumentPatch::parse(
+            JSON_PATCH_CONTENT_TYPE,
+            br#"[{"op": "test", "path": "/user/name", "value": "carol"}]"#,
+        )
+        .unwrap();
+        assert!(failing.apply(Some(document)).is_err());
+        assert!(patch.apply(Some("plain text")).is_err());
+
+        let encoded = serde_json::to_string(&patch).unwrap();
+        assert_eq!(
+            serde_json::from_str::<DocumentPatch>(&encoded).unwrap(),
+            patch
+        );
+        assert!(DocumentPatch::parse("text/plain", b"{}").is_err());
+    }
+}
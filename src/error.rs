@@ -0,0 +1,189 @@
+use axum::http::StatusCode;
+
+use crate::limits::Violation;
+
+/// The errors of the cache, its replication and the HTTP API, which clients can tell apart.
+///
+/// Caches return `NotFound` for missing keys and `Backend` or `Timeout` when they fail, wrapped
+/// in an `anyhow::Error`; `Error::classify` recovers them. The HTTP API answers each error with
+/// its `status` and a JSON envelope carrying its machine-readable `code` in the `error` field.
+///
+/// # Example
+///
+/// ```rust
+/// match bcache.get(key).await {
+///     Ok(value) => println!("{}", value),
+///     Err(e) => match Error::classify(e) {
+///         Error::NotFound => println!("missing"),
+///         e => println!("{} ({})", e, e.code()),
+///     },
+/// }
+/// ```
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The key does not exist, or expired.
+    #[error("key not found")]
+    NotFound,
+    /// The request conflicts with the state of the key, such as an insert of an existing key
+    /// with `nx`, or with a request in progress.
+    #[error("{0}")]
+    Conflict(String),
+    /// An `If-Match` header or a check of a transaction does not hold.
+    #[error("Precondition failed: {0}")]
+    PreconditionFailed(String),
+    /// The request is malformed, such as a missing or invalid parameter.
+    #[error("{0}")]
+    InvalidRequest(String),
+    /// The key or value of the request exceeds the configured limits, or the key is not allowed.
+    #[error("Invalid entry: {0}")]
+    LimitExceeded(Violation),
+    /// The request does not apply to the value it targets, such as a patch of a value that is
+    /// not a JSON document.
+    #[error("{0}")]
+    Unprocessable(String),
+    /// The cache backend did not answer in time.
+    #[error("The cache backend timed out: {0}")]
+    Timeout(String),
+    /// The mutation was applied on this node, but could not be logged or handed over for
+    /// replication to the other nodes.
+    #[error("Failed to replicate the mutation: {0}")]
+    ReplicationFailed(String),
+    /// The cache backend failed.
+    #[error("The cache backend failed: {0}")]
+    Backend(String),
+    /// The node cannot take the request now; it may succeed when retried.
+    #[error("{0}")]
+    Unavailable(String),
+    /// The cache backend does not support the operation.
+    #[error("{0}")]
+    Unsupported(String),
+    /// The data of this node for a key may be older than the read allows, and the read cannot
+    /// be redirected to the owner of the key.
+    #[error("{0}")]
+    Stale(String),
+    /// The request carries no bearer token, or an unknown one, while access control is enabled.
+    #[error("{0}")]
+    Unauthorized(String),
+    /// The token of the request has no grant on a key, or the route, it accesses.
+    #[error("{0}")]
+    Forbidden(String),
+    /// The write would take the namespace of its key over its quota.
+    #[error("{0}")]
+    QuotaExceeded(String),
+    /// Any other failure of the node.
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl Error {
+    /// Recovers the `Error` an `anyhow::Error` carries, or classifies it as a `Backend` error.
+    pub fn classify(error: anyhow::Error) -> Self {
+        match error.downcast::<Error>() {
+            Ok(error) => error,
+            Err(error) => Error::Backend(format!("{:#}", error)),
+        }
+    }
+
+    /// Returns whether an `anyhow::Error` is a `NotFound` error, as opposed to a failure.
+    pub fn is_not_found(error: &anyhow::Error) -> bool {
+        matches!(error.downcast_ref::<Error>(), Some(Error::NotFound))
+    }
+
+    /// Returns the HTTP status the error is answered with.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Error::NotFound => StatusCode::NOT_FOUND,
+            Error::Conflict(_) => StatusCode::CONFLICT,
+            Error::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            Error::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            Error::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Error::Forbidden(_) => StatusCode::FORBIDDEN,
+            Error::LimitExceeded(violation) if violation.is_too_large() => {
+                StatusCode::PAYLOAD_TOO_LARGE
+            }
+            Error::LimitExceeded(_) | Error::Unprocessable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::Timeout(_) | Error::Stale(_) => StatusCode::GATEWAY_TIMEOUT,
+            Error::ReplicationFailed(_) | Error::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Backend(_) => StatusCode::BAD_GATEWAY,
+            Error::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::Unsupported(_) => StatusCode::NOT_IMPLEMENTED,
+            Error::QuotaExceeded(_) => StatusCode::INSUFFICIENT_STORAGE,
+        }
+    }
+
+    /// Returns the machine-readable code of the error, stable across releases.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::NotFound => "not_found",
+            Error::Conflict(_) => "conflict",
+            Error::PreconditionFailed(_) => "precondition_failed",
+            Error::InvalidRequest(_) => "invalid_request",
+            Error::Unauthorized(_) => "unauthorized",
+            Error::Forbidden(_) => "forbidden",
+            Error::LimitExceeded(_) => "limit_exceeded",
+            Error::Unprocessable(_) => "unprocessable",
+            Error::Timeout(_) => "timeout",
+            Error::ReplicationFailed(_) => "replication_failed",
+            Error::Backend(_) => "backend_error",
+            Error::Unavailable(_) => "unavailable",
+            Error::Unsupported(_) => "unsupported",
+            Error::Stale(_) => "stale",
+            Error::QuotaExceeded(_) => "quota_exceeded",
+            Error::Internal(_) => "internal",
+        }
+    }
+}
+
+/// Turns the `NotFound` error of a cache lookup into `None`, keeping the other errors.
+///
+/// # Errors
+///
+/// Returns the error of the lookup if it is not a `NotFound` error.
+pub fn optional<T>(result: anyhow::Result<T>) -> anyhow::Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if Error::is_not_found(&e) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    /// Unit test for `Error::classify`.
+    ///
+    /// Errors carried by an `anyhow::Error` are recovered with their status and code, other
+    /// failures are backend errors, and only misses become `None` through `optional`.
+    #[test]
+    fn test_error_classification() {
+        let missing: anyhow::Error = Error::NotFound.into();
+        assert!(Error::is_not_found(&missing));
+        let error = Error::classify(missing);
+        assert_eq!(
+            (error.status(), error.code()),
+            (StatusCode::NOT_FOUND, "not_found")
+        );
+
+        let error = Error::classify(anyhow!("connection refused").context("Failed to read"));
+        assert_eq!(error.code(), "backend_error");
+        assert_eq!(error.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            error.to_string(),
+            "The cache backend failed: Failed to read: connection refused"
+        );
+
+        let too_large = Error::LimitExceeded(Violation::ValueTooLarge { len: 2, max: 1 });
+        assert_eq!(too_large.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let invalid = Error::LimitExceeded(Violation::EmptyKey);
+        assert_eq!(invalid.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        assert_eq!(
+            optional(Err::<String, _>(Error::NotFound.into())).unwrap(),
+            None
+        );
+        assert_eq!(optional(Ok("value")).unwrap(), Some("value"));
+        assert!(optional::<String>(Err(Error::Timeout("500ms".to_string()).into())).is_err());
+    }
+}
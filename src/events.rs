@@ -0,0 +1,148 @@
+use crate::utils::unix_time_ms;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// The number of events kept for subscribers that fall behind, by default.
+pub const DEFAULT_EVENTS_CAPACITY: usize = 1024;
+
+/// Why a key expired.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpirationCause {
+    /// The key was not read within the time-to-idle of the cache.
+    TimeToIdle,
+    /// A tag of the key reached its scheduled expiration.
+    Tag,
+    /// The key expired on another node, which replicated the expiration.
+    Replicated,
+}
+
+/// Why a key was evicted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionCause {
+    /// The namespace of the key exceeded its quota, and evicts its entries written longest ago.
+    Quota,
+}
+
+/// The kind of a key event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum KeyEventKind {
+    /// The key was removed because it expired.
+    Expired { cause: ExpirationCause },
+    /// The key was removed to make room for other entries.
+    Evicted { cause: EvictionCause },
+}
+
+/// An event about a key, as sent to the subscribers of `/events`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KeyEvent {
+    #[serde(flatten)]
+    pub kind: KeyEventKind,
+    pub key: String,
+    /// Unix timestamp, in milliseconds, of the event.
+    pub at_ms: u64,
+}
+
+impl KeyEvent {
+    /// Creates an `Expired` event for `key`, timestamped now.
+    pub fn expired(key: String, cause: ExpirationCause) -> Self {
+        Self {
+            kind: KeyEventKind::Expired { cause },
+            key,
+            at_ms: unix_time_ms(),
+        }
+    }
+
+    /// Creates an `Evicted` event for `key`, timestamped now.
+    pub fn evicted(key: String, cause: EvictionCause) -> Self {
+        Self {
+            kind: KeyEventKind::Evicted { cause },
+            key,
+            at_ms: unix_time_ms(),
+        }
+    }
+
+    /// Returns the name of the event, such as `expired`.
+    pub fn name(&self) -> &'static str {
+        match self.kind {
+            KeyEventKind::Expired { .. } => "expired",
+            KeyEventKind::Evicted { .. } => "evicted",
+        }
+    }
+}
+
+/// Broadcasts key events, like Redis keyspace notifications, to every subscriber.
+///
+/// Events are not stored: subscribers only receive the events published after they
+/// subscribed, and a subscriber falling more than `capacity` events behind misses the oldest.
+///
+/// # Example
+///
+/// ```rust
+/// let events = KeyEvents::new(DEFAULT_EVENTS_CAPACITY);
+/// let mut subscriber = events.subscribe();
+/// events.publish(KeyEvent::expired("session:1".to_string(), ExpirationCause::TimeToIdle));
+/// println!("{:?}", subscriber.recv().await?);
+/// ```
+#[derive(Debug, Clone)]
+pub struct KeyEvents {
+    sender: broadcast::Sender<KeyEvent>,
+}
+
+impl KeyEvents {
+    /// Creates a `KeyEvents` keeping up to `capacity` events for slow subscribers.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            sender: broadcast::channel(capacity.max(1)).0,
+        }
+    }
+
+    /// Sends an event to the current subscribers, if any.
+    pub fn publish(&self, event: KeyEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    /// Subscribes to the events published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<KeyEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for KeyEvents {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENTS_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit test for `KeyEvents`.
+    ///
+    /// Subscribers receive the events published after they subscribed, serialized with their kind.
+    #[tokio::test]
+    async fn test_key_events() {
+        let events = KeyEvents::new(8);
+        events.publish(KeyEvent::expired(
+            "before".to_string(),
+            ExpirationCause::Tag,
+        ));
+
+        let mut subscriber = events.subscribe();
+        events.publish(KeyEvent::expired(
+            "session:1".to_string(),
+            ExpirationCause::TimeToIdle,
+        ));
+        let event = subscriber.recv().await.unwrap();
+        assert_eq!(event.key, "session:1");
+        assert_eq!(event.name(), "expired");
+
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["event"], "expired");
+        assert_eq!(json["cause"], "time_to_idle");
+        assert!(subscriber.try_recv().is_err());
+    }
+}
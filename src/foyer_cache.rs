@@ -1,8 +1,20 @@
 use async_trait::async_trait;
-use foyer::{Cache, CacheBuilder};
+use foyer::{Cache, CacheBuilder, EventListener};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::{self, Instant};
 
-use crate::cache_trait::BCache;
+use crate::cache_trait::{entry_weight, BCache, CacheConfig};
+use crate::error::Error;
+use crate::events::{ExpirationCause, KeyEvent, KeyEvents};
 use anyhow::Result;
+use tracing::debug;
+
+/// The shortest interval between two sweeps of the idle entries.
+const MIN_SWEEP_INTERVAL: Duration = Duration::from_millis(10);
+/// The longest interval between two sweeps of the idle entries.
+const MAX_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
 
 /// `FoyerCache` is an implementation of the `BCache` trait using the `foyer` caching library.
 ///
@@ -19,7 +31,37 @@ use anyhow::Result;
 #[derive(Debug, Clone)]
 pub struct FoyerCache {
     /// The inner cache structure provided by the `foyer` crate.
-    cc: Cache<String, String>,
+    cc: Cache<String, FoyerValue>,
+    /// Optional time-to-idle. `foyer` has no native expiration, so it is checked on read and
+    /// by a periodic sweep.
+    time_to_idle: Option<Duration>,
+    /// The keys inserted and not removed since. `foyer` cannot list its entries, so they are
+    /// looked up from here; keys evicted in the meantime are dropped when entries are listed.
+    keys: Arc<Mutex<HashSet<String>>>,
+    /// Where entries found idle past `time_to_idle` are published as expired.
+    events: Option<KeyEvents>,
+}
+
+/// A cached value together with the time it was last read or written.
+#[derive(Debug)]
+struct FoyerValue {
+    value: String,
+    last_access: Mutex<Instant>,
+}
+
+impl FoyerValue {
+    fn new(value: String) -> Self {
+        Self {
+            value,
+            last_access: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Returns whether the value has been idle longer than `time_to_idle`.
+    fn idle(&self, time_to_idle: Option<Duration>) -> bool {
+        time_to_idle
+            .is_some_and(|time_to_idle| self.last_access.lock().unwrap().elapsed() > time_to_idle)
+    }
 }
 
 impl FoyerCache {
@@ -39,9 +81,114 @@ impl FoyerCache {
     /// let cache = FoyerCache::new(10).await;
     /// ```
     pub async fn new(cache_capacity: usize) -> Self {
-        let cache: Cache<String, String> = CacheBuilder::new(cache_capacity).with_shards(1).build();
+        Self::from_config(&CacheConfig::new(cache_capacity)).await
+    }
+
+    /// Creates a new `FoyerCache` instance from a `CacheConfig`.
+    ///
+    /// When `capacity_bytes` is set, each entry is weighted by `entry_weight`, so memory usage
+    /// stays bounded by the size of the stored keys and values rather than by the number of entries.
+    /// When `time_to_idle` is set, entries that have not been read within the window are dropped
+    /// on their next access, or by a sweep running every half window, and published to `events`.
+    /// An idle entry thus frees its capacity at most half a window after it expires.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The capacity and expiration settings for the cache.
+    ///
+    /// # Returns
+    ///
+    /// * A new `FoyerCache` instance.
+    pub async fn from_config(config: &CacheConfig) -> Self {
+        let mut builder = CacheBuilder::new(config.capacity_bytes.unwrap_or(config.capacity))
+            .with_shards(1)
+            .with_event_listener(Arc::new(ReleaseListener));
+        if config.capacity_bytes.is_some() {
+            builder = builder
+                .with_weighter(|key: &String, value: &FoyerValue| entry_weight(key, &value.value));
+        }
 
-        Self { cc: cache }
+        let cache = Self {
+            cc: builder.build(),
+            time_to_idle: config.time_to_idle,
+            keys: Arc::new(Mutex::new(HashSet::new())),
+            events: config.events.clone(),
+        };
+        if let Some(time_to_idle) = config.time_to_idle {
+            cache.spawn_sweeper(time_to_idle);
+        }
+        cache
+    }
+
+    /// Spawns the task removing idle entries, which stops once every clone of the cache is
+    /// dropped.
+    fn spawn_sweeper(&self, time_to_idle: Duration) {
+        // The sweeper shares the entries of the cache but not its keys, which it only borrows
+        // while a clone of the cache holds them.
+        let sweeper = Self {
+            keys: Arc::new(Mutex::new(HashSet::new())),
+            ..self.clone()
+        };
+        let keys = Arc::downgrade(&self.keys);
+        let interval = (time_to_idle / 2).clamp(MIN_SWEEP_INTERVAL, MAX_SWEEP_INTERVAL);
+        tokio::spawn(async move {
+            let mut ticker = time::interval_at(Instant::now() + interval, interval);
+            loop {
+                ticker.tick().await;
+                let Some(keys) = keys.upgrade() else {
+                    return;
+                };
+                sweeper.sweep(&mut keys.lock().unwrap());
+            }
+        });
+    }
+
+    /// Removes the entries idle longer than `time_to_idle` and publishes their expiration, and
+    /// forgets the keys evicted since they were inserted.
+    ///
+    /// # Returns
+    ///
+    /// * The remaining entries, in no particular order.
+    fn sweep(&self, keys: &mut HashSet<String>) -> Vec<(String, String)> {
+        let mut entries = Vec::new();
+        keys.retain(|key| match self.cc.get(key) {
+            Some(entry) if !entry.value().idle(self.time_to_idle) => {
+                entries.push((key.clone(), entry.value().value.clone()));
+                true
+            }
+            Some(_) => {
+                self.cc.remove(key);
+                self.expired(key);
+                false
+            }
+            None => false,
+        });
+        entries
+    }
+
+    /// Publishes the expiration of an idle entry.
+    fn expired(&self, key: &str) {
+        if let Some(events) = &self.events {
+            events.publish(KeyEvent::expired(
+                key.to_string(),
+                ExpirationCause::TimeToIdle,
+            ));
+        }
+    }
+}
+
+/// Event listener that logs entries released from the in-memory cache.
+///
+/// `foyer` reports capacity evictions, explicit removals and replaced values through
+/// the same callback, so every release is logged at `debug` level.
+struct ReleaseListener;
+
+impl EventListener for ReleaseListener {
+    type Key = String;
+    type Value = FoyerValue;
+
+    fn on_memory_release(&self, key: Self::Key, _value: Self::Value) {
+        debug!("Key {} released from foyer cache", key);
     }
 }
 
@@ -60,7 +207,8 @@ impl BCache for FoyerCache {
     /// cache.insert("key".to_string(), "value".to_string()).await;
     /// ```
     async fn insert(&mut self, key: String, val: String) {
-        self.cc.insert(key, val);
+        self.keys.lock().unwrap().insert(key.clone());
+        self.cc.insert(key, FoyerValue::new(val));
     }
 
     /// Asynchronously retrieves the value associated with the given key from the cache.
@@ -75,7 +223,8 @@ impl BCache for FoyerCache {
     ///
     /// # Errors
     ///
-    /// If the key does not exist in the cache, an `anyhow::Error` is returned.
+    /// If the key does not exist in the cache, or has been idle longer than the configured
+    /// time-to-idle, an `anyhow::Error` is returned.
     ///
     /// # Example
     ///
@@ -84,14 +233,26 @@ impl BCache for FoyerCache {
     /// assert_eq!(value, "value".to_string());
     /// ```
     async fn get(&mut self, key: String) -> Result<String> {
-        let value = match self.cc.get(&key) {
-            Some(e) => e.value().clone(),
+        let entry = match self.cc.get(&key) {
+            Some(e) => e,
             None => {
-                return Err(anyhow::anyhow!("key not found"));
+                return Err(Error::NotFound.into());
             }
         };
 
-        Ok(value)
+        if let Some(time_to_idle) = self.time_to_idle {
+            let mut last_access = entry.value().last_access.lock().unwrap();
+            if last_access.elapsed() > time_to_idle {
+                drop(last_access);
+                self.cc.remove(&key);
+                self.keys.lock().unwrap().remove(&key);
+                self.expired(&key);
+                return Err(Error::NotFound.into());
+            }
+            *last_access = Instant::now();
+        }
+
+        Ok(entry.value().value.clone())
     }
 
     /// Asynchronously removes the key-value pair from the cache if it exists.
@@ -106,8 +267,21 @@ impl BCache for FoyerCache {
     /// cache.remove("key".to_string()).await;
     /// ```
     async fn remove(&mut self, key: String) {
+        self.keys.lock().unwrap().remove(&key);
         self.cc.remove(&key);
     }
+
+    /// Asynchronously returns every entry of the cache that has not been evicted or been idle
+    /// longer than the configured time-to-idle.
+    ///
+    /// # Returns
+    ///
+    /// * The key-value pairs of the cache, in no particular order.
+    async fn entries(&mut self) -> Vec<(String, String)> {
+        let keys = self.keys.clone();
+        let mut keys = keys.lock().unwrap();
+        self.sweep(&mut keys)
+    }
 }
 
 #[cfg(test)]
@@ -127,4 +301,62 @@ mod tests {
             "world".to_string()
         );
     }
+
+    /// Unit test for listing the entries of a `FoyerCache`.
+    #[tokio::test]
+    async fn test_foyer_cache_entries() {
+        let mut cache = FoyerCache::new(4).await;
+        cache.insert("hello".to_string(), "world".to_string()).await;
+        cache.insert("foo".to_string(), "bar".to_string()).await;
+        cache.remove("foo".to_string()).await;
+        assert_eq!(
+            cache.entries().await,
+            vec![("hello".to_string(), "world".to_string())]
+        );
+    }
+
+    /// Unit test for a byte-weighted `FoyerCache`.
+    #[tokio::test]
+    async fn test_foyer_cache_with_capacity_bytes() {
+        let config = CacheConfig {
+            capacity_bytes: Some(1024),
+            ..CacheConfig::new(2)
+        };
+        let mut cache = FoyerCache::from_config(&config).await;
+        cache.insert("hello".to_string(), "world".to_string()).await;
+        assert_eq!(
+            cache.get("hello".to_string()).await.unwrap(),
+            "world".to_string()
+        );
+    }
+
+    /// Unit test for time-to-idle expiration in `FoyerCache`.
+    ///
+    /// A read refreshes the entry, while an entry left idle past the window is swept without
+    /// being read, and published as expired. The clock is paused, so the sleeps are exact.
+    #[tokio::test(start_paused = true)]
+    async fn test_foyer_cache_time_to_idle() {
+        let events = KeyEvents::new(8);
+        let mut expirations = events.subscribe();
+        let config = CacheConfig {
+            time_to_idle: Some(Duration::from_millis(50)),
+            events: Some(events),
+            ..CacheConfig::new(2)
+        };
+        let mut cache = FoyerCache::from_config(&config).await;
+        cache.insert("hello".to_string(), "world".to_string()).await;
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(cache.get("hello".to_string()).await.is_ok());
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(cache.get("hello".to_string()).await.is_ok());
+
+        // Sweeps run every 25ms; the one 65ms after the last read finds the entry idle.
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(expirations.try_recv().is_err());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(expirations.try_recv().unwrap().key, "hello");
+        assert!(cache.get("hello".to_string()).await.is_err());
+        assert!(cache.entries().await.is_empty());
+    }
 }
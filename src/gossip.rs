@@ -1,36 +1,199 @@
 use anyhow::{anyhow, Context, Result};
 use std::cmp::PartialEq;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::net::Ipv4Addr;
 use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::utils::parse_address;
+use crate::chaos::{Chaos, ChaosTransport};
+use crate::cluster_auth::{AuthTransport, ClusterAuth};
+use crate::document::DocumentPatch;
+use crate::hints::{HintOptions, HintStore};
+use crate::log::current_trace_context;
+use crate::queue::{QueueOptions, QueueReceiver};
+use crate::retry::{Retry, RetryOptions, RetryQueue};
+use crate::transport::{Member, Transport, TransportEvents};
+use crate::typed_value::ValueOp;
+use crate::utils::{parse_address, unix_time_ms};
+use crate::wire::{self, Envelope, Rumor, WireOptions};
 use async_trait::async_trait;
+use clap::ValueEnum;
 use gossipod::{
     config::{GossipodConfigBuilder, NetworkType},
     DispatchEventHandler, Gossipod, Node, NodeMetadata,
 };
+use rand::seq::IndexedRandom;
 use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc::{self};
-use tokio::time;
-use tracing::{error, info};
+use tokio::{net, time};
+use tracing::{error, info, warn};
 
 pub struct GossipNode {
-    pub gossipod: Arc<Gossipod>,
-    config: gossipod::config::GossipodConfig,
+    /// Carries frames between the members, and tracks who they are.
+    transport: Arc<dyn Transport>,
+    /// The name of this node.
+    name: String,
+    wire: WireOptions,
+    batch: BatchOptions,
+    /// The identifier of the next envelope or frame split into chunks.
+    next_frame_id: AtomicU64,
+    /// Envelopes awaiting acknowledgement by their receivers.
+    retries: RetryQueue,
+    /// Identifies this run of the node in the sequences of the messages it sends.
+    incarnation: u64,
+    /// The sequence number of the last message sent to each member.
+    sequences: std::sync::Mutex<HashMap<String, u64>>,
+    /// The sequence number of the last message disseminated as a rumor.
+    rumor_sequence: AtomicU64,
+    fanout: usize,
+    /// The rumors this node started or received recently, by origin and ID, and when.
+    seen_rumors: std::sync::Mutex<HashMap<(String, u64), Instant>>,
+    /// The addresses the node joins the cluster through.
+    seeds: Vec<String>,
+    membership: Arc<std::sync::Mutex<Membership>>,
+    /// Writes for members that were down, replayed when they return.
+    hints: HintStore,
+    /// The coordinator of cluster-wide tasks, as last elected by this node.
+    leader: std::sync::Mutex<Option<String>>,
 }
 
+/// How long a rumor is remembered, so that it is not applied again when relayed back.
+const RUMOR_RETENTION: Duration = Duration::from_secs(60);
+
+/// The delay before joining the seeds is first retried. It doubles with every attempt.
+const JOIN_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The maximum delay between two attempts to join the seeds.
+const JOIN_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// The window in which half of the known members being declared dead is taken as this node
+/// being partitioned from them.
+const DEATH_FLOOD_WINDOW: Duration = Duration::from_secs(10);
+
 pub struct GossipodConfig {
     pub name: String,
     pub ip: String,
     pub port: u16,
-    pub join_addr: Option<String>,
+    /// The addresses of the members to join the cluster through, tried in order.
+    pub seeds: Vec<String>,
+    /// The protocol version and compression of the frames emitted by this node. Pinning
+    /// the version to an older one lets a cluster be upgraded node by node.
+    pub wire: WireOptions,
+    pub batch: BatchOptions,
+    pub retry: RetryOptions,
+    /// The number of members each write for the whole cluster is sent to, each relaying it on to
+    /// a share of the others. `0` sends such writes to every member instead. Rumors are
+    /// acknowledged and resent at every hop, and applied in the order of their origin.
+    pub fanout: usize,
+    /// The failure detection timings of the membership protocol.
+    pub protocol: ProtocolOptions,
+    /// How writes for members that are down are kept until they return.
+    pub hints: HintOptions,
+    /// The faults injected into the frames this node sends, if any, to check that the cluster
+    /// converges over an unreliable network.
+    pub chaos: Option<Arc<Chaos>>,
+    /// How the frames received by this node are buffered until the sync task reads them.
+    pub receive_queue: QueueOptions,
+    /// How the frames exchanged with the other members are authenticated, so that peers
+    /// without the secret of the cluster receive no replicated data. It must be created for
+    /// `name`, which frames are signed by. Disabled by default.
+    pub auth: Arc<ClusterAuth>,
+}
+
+/// The kind of network the cluster runs on, which sets the default timings of failure detection.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum Network {
+    /// All nodes run on the same host.
+    #[default]
+    Local,
+    /// Nodes run in the same datacenter.
+    Lan,
+    /// Nodes are spread across datacenters.
+    Wan,
+}
+
+impl From<Network> for NetworkType {
+    fn from(network: Network) -> Self {
+        match network {
+            Network::Local => NetworkType::Local,
+            Network::Lan => NetworkType::LAN,
+            Network::Wan => NetworkType::WAN,
+        }
+    }
+}
+
+/// Controls how the membership protocol probes members and declares them dead.
+///
+/// # Fields
+///
+/// - `network`: The kind of network the cluster runs on.
+/// - `probing_interval`: How often a random member is probed.
+/// - `ack_timeout`: How long a direct probe waits for its acknowledgement.
+/// - `indirect_ack_timeout`: How long a probe relayed through other members waits for its acknowledgement.
+/// - `suspicious_timeout`: How long a member that failed a probe is suspected before being declared dead.
+#[derive(Clone, Debug)]
+pub struct ProtocolOptions {
+    pub network: Network,
+    pub probing_interval: Duration,
+    pub ack_timeout: Duration,
+    pub indirect_ack_timeout: Duration,
+    pub suspicious_timeout: Duration,
+}
+
+impl ProtocolOptions {
+    /// Returns the default timings for a kind of network.
+    ///
+    /// Higher latencies and more frequent packet loss across datacenters call for longer
+    /// timeouts, or members are declared dead while they are merely slow to answer.
+    pub fn for_network(network: Network) -> Self {
+        let (probing_interval, ack_timeout, indirect_ack_timeout, suspicious_timeout) =
+            match network {
+                Network::Local => (5_000, 500, 1_000, 5_000),
+                Network::Lan => (2_000, 500, 1_000, 10_000),
+                Network::Wan => (5_000, 3_000, 6_000, 30_000),
+            };
+        Self {
+            network,
+            probing_interval: Duration::from_millis(probing_interval),
+            ack_timeout: Duration::from_millis(ack_timeout),
+            indirect_ack_timeout: Duration::from_millis(indirect_ack_timeout),
+            suspicious_timeout: Duration::from_millis(suspicious_timeout),
+        }
+    }
+}
+
+impl Default for ProtocolOptions {
+    fn default() -> Self {
+        Self::for_network(Network::default())
+    }
+}
+
+/// Controls how replicated mutations are coalesced into batched gossip frames.
+///
+/// # Fields
+///
+/// - `window`: How long mutations are buffered before being sent. Zero sends every mutation immediately.
+/// - `max_messages`: The number of buffered mutations that flushes the buffer before the window ends.
+#[derive(Clone, Debug)]
+pub struct BatchOptions {
+    pub window: Duration,
+    pub max_messages: usize,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_millis(10),
+            max_messages: 128,
+        }
+    }
 }
 
 impl GossipodConfig {
-    pub fn new(name: String, addr: String, join_addr: Option<String>) -> Self {
+    pub fn new(name: String, addr: String, seeds: Vec<String>) -> Self {
         let gossip_addr = parse_address(Some(addr)).unwrap();
         let ip = gossip_addr.ip().to_string();
         let port = gossip_addr.port();
@@ -39,7 +202,16 @@ impl GossipodConfig {
             name,
             ip,
             port,
-            join_addr,
+            seeds,
+            wire: WireOptions::default(),
+            batch: BatchOptions::default(),
+            retry: RetryOptions::default(),
+            fanout: 3,
+            protocol: ProtocolOptions::default(),
+            hints: HintOptions::default(),
+            chaos: None,
+            receive_queue: QueueOptions::new(1000),
+            auth: Arc::new(ClusterAuth::default()),
         }
     }
 }
@@ -49,6 +221,28 @@ pub enum Command {
     Ping,
     Insert,
     Remove,
+    /// Removes every key carrying the tag in `key`.
+    InvalidateTag,
+    /// Schedules the tag in `key` to expire at the Unix timestamp, in milliseconds, in `value`.
+    ExpireTag,
+    /// Acknowledges the receipt of the envelope whose identifier is in `key`.
+    Ack,
+    /// Asks for the messages from sequence number `key` on (`0` after a rejoin) to be resent to
+    /// the node named in `value`.
+    Resync,
+    /// Applies the `Insert` and `Remove` messages JSON-encoded in `value` atomically, in order.
+    Txn,
+    /// Applies the `DocumentPatch` JSON-encoded in `value` to the JSON document in `key`.
+    Patch,
+    /// Applies the `ValueOp` JSON-encoded in `value` to the typed value in `key`.
+    Update,
+    /// Inserts like `Insert`, unless `key` already exists.
+    InsertIfAbsent,
+    /// Removes `key`, which expired on the node that sent the message.
+    Expire,
+    /// Marks the writes the node named in `key` accepted up to the session position in `value`
+    /// as sent before it, so replicas applying it can serve the sessions that made them.
+    Checkpoint,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -56,16 +250,255 @@ pub struct Message {
     pub cmd: Command,
     pub key: String,
     pub value: String,
+    /// W3C trace context of the span that produced the message, so applying it on a
+    /// replica can be correlated with the originating request.
+    pub trace_context: HashMap<String, String>,
+    /// Tags attached to an `Insert`, indexed for tag-based invalidation.
+    pub tags: Vec<String>,
+    /// The position of the message among those sent by its origin to the receiver, which
+    /// lets the receiver drop duplicates and apply messages in order. Set when the message is sent.
+    pub sequence: Option<Sequence>,
 }
 
-struct EventHandler {
-    sender: mpsc::Sender<Vec<u8>>,
+/// The position of a message in the stream of messages an origin sends to one receiver.
+///
+/// # Fields
+///
+/// - `origin`: The name of the node that sent the message.
+/// - `incarnation`: Identifies the run of the origin, which restarts its sequence numbers.
+/// - `seq`: The sequence number of the message, incremented by one for every message.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Sequence {
+    pub origin: String,
+    pub incarnation: u64,
+    pub seq: u64,
+}
+
+impl Message {
+    /// Creates a new `Message` carrying the trace context of the current span.
+    pub fn new(cmd: Command, key: String, value: String) -> Self {
+        Self {
+            cmd,
+            key,
+            value,
+            trace_context: current_trace_context(),
+            tags: Vec::new(),
+            sequence: None,
+        }
+    }
+
+    /// Attaches tags to the message.
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Creates a `Txn` message, which replicas apply as a single unit.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the messages is not an `Insert` or a `Remove`.
+    pub fn txn(messages: Vec<Message>) -> Result<Self> {
+        check_txn_messages(&messages)?;
+        Ok(Self::new(
+            Command::Txn,
+            String::new(),
+            serde_json::to_string(&messages)?,
+        ))
+    }
+
+    /// Returns the messages of a `Txn` message, in the order they are applied.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message is not a `Txn`, or its messages are malformed or
+    /// anything other than `Insert` and `Remove` messages.
+    pub fn txn_messages(&self) -> Result<Vec<Message>> {
+        if self.cmd != Command::Txn {
+            return Err(anyhow!("{:?} is not a transaction", self.cmd));
+        }
+        let messages: Vec<Message> = serde_json::from_str(&self.value)
+            .map_err(|e| anyhow!("Invalid transaction: {:?}", e))?;
+        check_txn_messages(&messages)?;
+        Ok(messages)
+    }
+
+    /// Creates a `Patch` message, which replicas apply to their copy of the document instead of
+    /// receiving the whole patched document.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the patch cannot be serialized.
+    pub fn patch(key: String, patch: &DocumentPatch) -> Result<Self> {
+        Ok(Self::new(
+            Command::Patch,
+            key,
+            serde_json::to_string(patch)?,
+        ))
+    }
+
+    /// Returns the patch carried by a `Patch` message.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message is not a `Patch`, or its patch is malformed.
+    pub fn document_patch(&self) -> Result<DocumentPatch> {
+        if self.cmd != Command::Patch {
+            return Err(anyhow!("{:?} is not a patch", self.cmd));
+        }
+        serde_json::from_str(&self.value).map_err(|e| anyhow!("Invalid patch: {:?}", e))
+    }
+
+    /// Creates an `Update` message, which replicas apply to their copy of the value instead of
+    /// receiving the updated value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the operation cannot be serialized.
+    pub fn update(key: String, op: &ValueOp) -> Result<Self> {
+        Ok(Self::new(Command::Update, key, serde_json::to_string(op)?))
+    }
+
+    /// Returns the operation carried by an `Update` message.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message is not an `Update`, or its operation is malformed.
+    pub fn value_op(&self) -> Result<ValueOp> {
+        if self.cmd != Command::Update {
+            return Err(anyhow!("{:?} is not an update", self.cmd));
+        }
+        serde_json::from_str(&self.value).map_err(|e| anyhow!("Invalid operation: {:?}", e))
+    }
+
+    /// Returns the value of `key` once a `Patch` or `Update` message is applied to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `current` - The current value of `key`, or `None` if it is absent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message is neither a `Patch` nor an `Update`, or it does not
+    /// apply to the current value.
+    pub fn updated_value(&self, current: Option<&str>) -> Result<String> {
+        match self.cmd {
+            Command::Patch => self.document_patch()?.apply(current),
+            Command::Update => self.value_op()?.apply(current),
+            _ => Err(anyhow!("{:?} does not update a value in place", self.cmd)),
+        }
+    }
+}
+
+/// Checks that a transaction only holds `Insert` and `Remove` messages.
+fn check_txn_messages(messages: &[Message]) -> Result<()> {
+    match messages
+        .iter()
+        .find(|msg| !matches!(msg.cmd, Command::Insert | Command::Remove))
+    {
+        Some(msg) => Err(anyhow!("{:?} is not allowed in a transaction", msg.cmd)),
+        None => Ok(()),
+    }
+}
+
+/// Selects which cluster members a locally originated message is replicated to.
+///
+/// Parsed from a write's `replicate_to` parameter: `local-only` keeps the write on this node,
+/// a comma-separated list of node names restricts replication to those members, and an
+/// absent parameter replicates to every member.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum Replication {
+    #[default]
+    All,
+    Nodes(Vec<String>),
+    LocalOnly,
+}
+
+impl Replication {
+    /// Returns whether the member with the given name is a replication target.
+    pub fn includes(&self, name: &str) -> bool {
+        match self {
+            Replication::All => true,
+            Replication::Nodes(names) => names.iter().any(|n| n == name),
+            Replication::LocalOnly => false,
+        }
+    }
 }
 
-impl EventHandler {
-    fn new(sender: mpsc::Sender<Vec<u8>>) -> Self {
-        Self { sender }
+impl FromStr for Replication {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.trim() == "local-only" {
+            return Ok(Replication::LocalOnly);
+        }
+
+        let nodes: Vec<String> = s
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+        if nodes.is_empty() {
+            return Err(anyhow!("No replication targets specified"));
+        }
+
+        Ok(Replication::Nodes(nodes))
+    }
+}
+
+/// The members this node learned of from membership events.
+///
+/// # Fields
+///
+/// - `known`: The address of every member seen since this node started, except those that left.
+/// - `alive`: The members currently considered alive.
+/// - `deaths`: When members were recently declared dead, to detect a flood of deaths.
+/// - `rejoining`: Whether the node is currently rejoining the cluster.
+/// - `rejoined`: Whether the node rejoined the cluster and has yet to resync.
+#[derive(Debug, Default)]
+pub(crate) struct Membership {
+    known: HashMap<String, SocketAddr>,
+    alive: HashSet<String>,
+    deaths: Vec<Instant>,
+    rejoining: bool,
+    rejoined: bool,
+}
+
+impl Membership {
+    /// Returns whether this node looks cut off from the members it knew of: either none is
+    /// alive anymore, or at least half of them were declared dead within `DEATH_FLOOD_WINDOW`.
+    fn isolated(&mut self, now: Instant) -> bool {
+        self.deaths
+            .retain(|death| now.duration_since(*death) < DEATH_FLOOD_WINDOW);
+        !self.known.is_empty()
+            && (self.alive.is_empty()
+                || (self.deaths.len() >= 2 && self.deaths.len() * 2 >= self.known.len()))
+    }
+
+    /// Records that a member joined the cluster.
+    pub(crate) fn joined(&mut self, name: &str, addr: SocketAddr) {
+        self.known.insert(name.to_string(), addr);
+        self.alive.insert(name.to_string());
     }
+
+    /// Records that a member was declared dead.
+    pub(crate) fn died(&mut self, name: &str, now: Instant) {
+        self.alive.remove(name);
+        self.deaths.push(now);
+    }
+
+    /// Records that a member left the cluster.
+    pub(crate) fn left(&mut self, name: &str) {
+        self.alive.remove(name);
+        self.known.remove(name);
+    }
+}
+
+/// Reports the events of the membership protocol to the `TransportEvents` of the node, which
+/// hold back joining members and drop received frames until they authenticate.
+struct EventHandler {
+    events: TransportEvents,
 }
 
 type DispatchError = Box<dyn Error + Send + Sync>;
@@ -73,17 +506,17 @@ type DispatchError = Box<dyn Error + Send + Sync>;
 #[async_trait]
 impl<M: NodeMetadata> DispatchEventHandler<M> for EventHandler {
     async fn notify_dead(&self, node: &Node<M>) -> Result<(), DispatchError> {
-        info!("Node {} detected as dead", node.name);
+        self.events.died(&node.name);
         Ok(())
     }
 
     async fn notify_leave(&self, node: &Node<M>) -> Result<(), DispatchError> {
-        info!("Node {} is leaving the cluster", node.name);
+        self.events.left(&node.name);
         Ok(())
     }
 
     async fn notify_join(&self, node: &Node<M>) -> Result<(), DispatchError> {
-        info!("Node {} has joined the cluster", node.name);
+        self.events.joined(&node.name, node.socket_addr()?);
         Ok(())
     }
 
@@ -93,61 +526,43 @@ impl<M: NodeMetadata> DispatchEventHandler<M> for EventHandler {
         message: Vec<u8>,
     ) -> Result<(), DispatchError> {
         info!("Received message from {}: {:?}", from, message);
-        self.sender.send(message).await?;
+        self.events.received(from, message).await?;
         Ok(())
     }
 }
 
-impl GossipNode {
-    pub async fn start(args: GossipodConfig) -> Result<(Self, mpsc::Receiver<Vec<u8>>)> {
+/// The `Transport` of the gossipod membership protocol, which carries frames over UDP.
+pub struct GossipodTransport {
+    gossipod: Arc<Gossipod>,
+}
+
+impl GossipodTransport {
+    /// Starts the membership protocol, reporting its events to `events`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the protocol cannot be configured or started.
+    pub async fn start(args: &GossipodConfig, events: TransportEvents) -> Result<Self> {
         let config = GossipodConfigBuilder::new()
             .with_name(&args.name)
             .with_port(args.port)
             .with_addr(args.ip.parse::<Ipv4Addr>().expect("Invalid IP address"))
-            .with_probing_interval(Duration::from_secs(5))
-            .with_ack_timeout(Duration::from_millis(500))
-            .with_indirect_ack_timeout(Duration::from_secs(1))
-            .with_suspicious_timeout(Duration::from_secs(5))
-            .with_network_type(NetworkType::Local)
+            .with_probing_interval(args.protocol.probing_interval)
+            .with_ack_timeout(args.protocol.ack_timeout)
+            .with_indirect_ack_timeout(args.protocol.indirect_ack_timeout)
+            .with_suspicious_timeout(args.protocol.suspicious_timeout)
+            .with_network_type(args.protocol.network.into())
             .build()
             .await?;
 
-        let (sender, receiver) = mpsc::channel(1000);
-        let dispatch_event_handler = EventHandler::new(sender);
-
-        let gossipod =
-            Gossipod::with_event_handler(config.clone(), Arc::new(dispatch_event_handler))
-                .await
-                .context("Failed to initialize Gossipod with custom metadata")?;
-
-        let mut gossip = GossipNode {
+        let gossipod = Gossipod::with_event_handler(config, Arc::new(EventHandler { events }))
+            .await
+            .context("Failed to initialize Gossipod with custom metadata")?;
+        let transport = Self {
             gossipod: gossipod.into(),
-            config,
         };
-        gossip.start_node().await?;
-        gossip.join_node(args.join_addr.clone()).await?;
-
-        Ok((gossip, receiver))
-    }
-
-    async fn join_node(&mut self, join_addr: Option<String>) -> Result<()> {
-        if let Some(join_addr) = join_addr {
-            return match join_addr.parse::<SocketAddr>() {
-                Ok(addr) => {
-                    if let Err(e) = self.gossipod.join(addr).await {
-                        return Err(anyhow!("Failed to join {}: {:?}", addr, e));
-                    } else {
-                        info!("Successfully joined {}", addr);
-                        Ok(())
-                    }
-                }
-                Err(e) => Err(anyhow!("Invalid join address {}: {:?}", join_addr, e)),
-            };
-        }
-
-        info!("No join address specified. Running as a standalone node.");
-
-        Ok(())
+        transport.start_node().await?;
+        Ok(transport)
     }
 
     async fn start_node(&self) -> Result<()> {
@@ -167,24 +582,892 @@ impl GossipNode {
 
         Ok(())
     }
+}
+
+#[async_trait]
+impl Transport for GossipodTransport {
+    async fn members(&self) -> Result<Vec<Member>> {
+        Ok(self
+            .gossipod
+            .members()
+            .await?
+            .into_iter()
+            .filter_map(|node| {
+                Some(Member {
+                    addr: node.socket_addr().ok()?,
+                    name: node.name,
+                })
+            })
+            .collect())
+    }
+
+    async fn send(&self, target: SocketAddr, frame: &[u8]) -> Result<()> {
+        self.gossipod.send(target, frame).await?;
+        Ok(())
+    }
+
+    async fn join(&self, addr: SocketAddr) -> Result<()> {
+        self.gossipod.join(addr).await?;
+        Ok(())
+    }
+}
+
+/// Joins the cluster through the first seed that can be reached.
+///
+/// Seeds are tried in order, and resolved on every attempt as their addresses may only become
+/// resolvable once they start. When no seed can be joined, the attempt is repeated with
+/// exponential backoff until one can.
+///
+/// # Arguments
+///
+/// * `transport` - The transport of this node.
+/// * `seeds` - The addresses, or host names and ports, of the members to join through.
+async fn join_seeds(transport: Arc<dyn Transport>, seeds: Vec<String>) {
+    let mut backoff = JOIN_INITIAL_BACKOFF;
+    loop {
+        for seed in &seeds {
+            let addr = match time::timeout(JOIN_MAX_BACKOFF, net::lookup_host(seed.as_str())).await
+            {
+                Ok(Ok(mut addrs)) => addrs.next(),
+                Ok(Err(e)) => {
+                    warn!("Failed to resolve join address {}: {:?}", seed, e);
+                    continue;
+                }
+                Err(_) => None,
+            };
+            let Some(addr) = addr else {
+                warn!("Join address {} did not resolve", seed);
+                continue;
+            };
+
+            match transport.join(addr).await {
+                Ok(()) => {
+                    info!("Successfully joined {}", addr);
+                    return;
+                }
+                Err(e) => warn!("Failed to join {}: {:?}", addr, e),
+            }
+        }
+
+        warn!("Could not join any seed, retrying in {:?}", backoff);
+        time::sleep(backoff).await;
+        backoff = (backoff * 2).min(JOIN_MAX_BACKOFF);
+    }
+}
+
+/// Elects the coordinator of cluster-wide tasks among the names of the members: the lowest one.
+///
+/// Every member elects the same coordinator from the same membership view, so no election
+/// messages are exchanged, and the next lowest member takes over when the coordinator dies.
+fn elect<'a>(names: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    names.into_iter().min()
+}
+
+impl GossipNode {
+    /// Starts a node communicating over the gossipod membership protocol, which joins the
+    /// cluster through the seeds of `args` in the background. Frames are sent through a
+    /// `ChaosTransport` when `args.chaos` is set, and authenticated as configured by `args.auth`.
+    ///
+    /// # Returns
+    ///
+    /// * The node, and the receiver of the frames it receives, which is handed to `sync_data`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the membership protocol cannot be started.
+    pub async fn start(
+        args: GossipodConfig,
+    ) -> Result<(Self, QueueReceiver<(SocketAddr, Vec<u8>)>)> {
+        let (events, receiver) = TransportEvents::new(&args.receive_queue, args.auth.clone());
+        let mut transport: Arc<dyn Transport> =
+            Arc::new(GossipodTransport::start(&args, events.clone()).await?);
+        if let Some(chaos) = &args.chaos {
+            transport = Arc::new(ChaosTransport::new(transport, chaos.clone()));
+        }
+        Ok((Self::with_transport(args, transport, &events), receiver))
+    }
+
+    /// Creates a node communicating over `transport`, which reports to `events`, and joins the
+    /// cluster through the seeds of `args` in the background. The address and protocol
+    /// timings of `args` are left to the transport.
+    ///
+    /// When `args.auth` is enabled, frames are sent through an `AuthTransport`; `events` must
+    /// have been created with the same `ClusterAuth`.
+    pub fn with_transport(
+        args: GossipodConfig,
+        mut transport: Arc<dyn Transport>,
+        events: &TransportEvents,
+    ) -> Self {
+        if args.auth.is_enabled() {
+            transport = Arc::new(AuthTransport::new(transport, args.auth.clone()));
+        }
+        let gossip = GossipNode {
+            transport,
+            name: args.name,
+            wire: args.wire,
+            batch: args.batch,
+            next_frame_id: AtomicU64::new(unix_time_ms()),
+            incarnation: unix_time_ms(),
+            sequences: std::sync::Mutex::new(HashMap::new()),
+            rumor_sequence: AtomicU64::new(0),
+            fanout: args.fanout,
+            seen_rumors: std::sync::Mutex::new(HashMap::new()),
+            seeds: args.seeds,
+            membership: events.membership(),
+            hints: HintStore::new(args.hints),
+            leader: std::sync::Mutex::new(None),
+            retries: RetryQueue::new(args.retry),
+        };
+        gossip.join_node(gossip.seeds.clone());
+        gossip
+    }
+
+    /// Joins the cluster through the seeds in the background, so that the node serves
+    /// requests while seeds that start later are still unreachable.
+    fn join_node(&self, seeds: Vec<String>) {
+        if seeds.is_empty() {
+            info!("No join address specified. Running as a standalone node.");
+            return;
+        }
+
+        tokio::spawn(join_seeds(self.transport.clone(), seeds));
+    }
+
+    /// Returns the options frames are encoded and decoded with.
+    pub fn wire(&self) -> &WireOptions {
+        &self.wire
+    }
+
+    /// Returns the current members of the cluster, this node included.
+    pub async fn members(&self) -> Vec<Member> {
+        self.transport.members().await.unwrap_or_default()
+    }
+
+    /// Encodes an envelope into frames, splitting frames that exceed the maximum frame size into chunks.
+    fn encode(&self, envelope: &Envelope) -> Result<Vec<Vec<u8>>> {
+        let mut frames = Vec::new();
+        for frame in wire::encode_envelope(envelope, &self.wire)? {
+            let id = self.next_frame_id.fetch_add(1, Ordering::Relaxed);
+            frames.extend(wire::split_frame(frame, id, &self.wire)?);
+        }
+        Ok(frames)
+    }
+
+    /// Returns the identifier to give an envelope carrying `msgs`, or `0` when it needs
+    /// no acknowledgement.
+    ///
+    /// Only replicated mutations are acknowledged, and only when the wire version
+    /// supports acknowledgements.
+    fn envelope_id(&self, msgs: &[Message]) -> u64 {
+        let needs_ack = self.wire.version >= wire::min_version(&Command::Ack)
+            && msgs
+                .iter()
+                .any(|msg| !matches!(msg.cmd, Command::Ping | Command::Ack));
+        if needs_ack {
+            self.next_frame_id.fetch_add(1, Ordering::Relaxed)
+        } else {
+            0
+        }
+    }
+
+    /// Stamps the replicated mutations among messages addressed to a member with their sequence.
+    ///
+    /// Versions that predate sequences leave the messages unsequenced.
+    fn assign_sequences(&self, member: &str, msgs: &mut [Message]) {
+        if self.wire.version < wire::min_version(&Command::Resync) {
+            return;
+        }
+        let mut sequences = self.sequences.lock().unwrap();
+        let last = sequences.entry(member.to_string()).or_insert(0);
+        for msg in msgs
+            .iter_mut()
+            .filter(|msg| !matches!(msg.cmd, Command::Ping | Command::Ack | Command::Resync))
+        {
+            *last += 1;
+            msg.sequence = Some(Sequence {
+                origin: self.name.clone(),
+                incarnation: self.incarnation,
+                seq: *last,
+            });
+        }
+    }
+
+    /// Stamps the replicated mutations among messages disseminated as a rumor with their
+    /// sequence, which every member receives alike.
+    fn assign_rumor_sequences(&self, msgs: &mut [Message]) {
+        for msg in msgs
+            .iter_mut()
+            .filter(|msg| !matches!(msg.cmd, Command::Ping | Command::Ack | Command::Resync))
+        {
+            msg.sequence = Some(Sequence {
+                origin: self.name.clone(),
+                incarnation: self.incarnation,
+                seq: self.rumor_sequence.fetch_add(1, Ordering::Relaxed) + 1,
+            });
+        }
+    }
+
+    /// Sends frames to a member, returning whether every frame was sent.
+    async fn send_frames(&self, name: &str, target: SocketAddr, frames: &[Vec<u8>]) -> bool {
+        let mut sent = true;
+        for frame in frames {
+            if let Err(e) = self.transport.send(target, frame).await {
+                error!("Failed to send message to {}: {}", name, e);
+                sent = false;
+            }
+        }
+        sent
+    }
+
+    /// Rejoins the cluster when this node looks partitioned from it, and resyncs once it has.
+    ///
+    /// The node rejoins through its seeds and every member it knew of, in the background. Once
+    /// it rejoined, it resends its unacknowledged envelopes and asks every member to do the same.
+    /// Updates missed for longer than the retry queue keeps them are not recovered.
+    pub async fn rejoin_if_isolated(&self) {
+        let (rejoin, resync) = {
+            let mut membership = self.membership.lock().unwrap();
+            let rejoin = !membership.rejoining && membership.isolated(Instant::now());
+            if rejoin {
+                membership.rejoining = true;
+            }
+            (rejoin, std::mem::take(&mut membership.rejoined))
+        };
+
+        if rejoin {
+            let mut seeds = self.seeds.clone();
+            seeds.extend(
+                self.membership
+                    .lock()
+                    .unwrap()
+                    .known
+                    .values()
+                    .map(SocketAddr::to_string),
+            );
+            warn!(
+                "Node looks isolated from the cluster, rejoining through {:?}",
+                seeds
+            );
+
+            let transport = self.transport.clone();
+            let membership = self.membership.clone();
+            tokio::spawn(async move {
+                join_seeds(transport, seeds).await;
+                let mut membership = membership.lock().unwrap();
+                membership.rejoining = false;
+                membership.rejoined = true;
+                membership.deaths.clear();
+            });
+        }
+
+        if resync {
+            info!("Rejoined the cluster, resyncing with every member");
+            let members = self.members().await;
+            for node in &members {
+                if node.name == self.name {
+                    continue;
+                }
+                self.retries.expedite(&node.name, Instant::now());
+                self.request_resync(node.addr, 0).await;
+            }
+            self.retry_pending().await;
+        }
+    }
+
+    /// Replays the hints stored for members that returned, and drops expired ones.
+    pub async fn replay_hints(&self) {
+        let dropped = self.hints.evict_expired(Instant::now());
+        if dropped > 0 {
+            warn!("Dropped {} expired hints", dropped);
+        }
+
+        let nodes = self.hints.nodes();
+        if nodes.is_empty() {
+            return;
+        }
+        let members = self.members().await;
+        for name in nodes {
+            if !members.iter().any(|node| node.name == name) {
+                continue;
+            }
+            let msgs = self.hints.take(&name, Instant::now());
+            info!("Replaying {} hints to {}", msgs.len(), name);
+            let replication = Replication::Nodes(vec![name]);
+            self.send_batch(
+                msgs.into_iter()
+                    .map(|msg| (msg, replication.clone()))
+                    .collect(),
+            )
+            .await;
+        }
+        info!("{} hints awaiting replay", self.hints.len());
+    }
+
+    /// Returns the number of writes kept for members that are down.
+    pub fn hint_backlog(&self) -> usize {
+        self.hints.len()
+    }
+
+    /// Elects the coordinator of cluster-wide tasks from the current membership.
+    ///
+    /// Changes of coordinator are logged. There is no quorum: each side of a partition elects
+    /// its own coordinator from the members it still sees, so two coordinators run until the
+    /// partition heals and the sides agree on the membership again.
+    ///
+    /// # Returns
+    ///
+    /// * The name of the coordinator.
+    pub async fn elect_leader(&self) -> String {
+        let members = self.members().await;
+        let name = &self.name;
+        let leader = elect(
+            members
+                .iter()
+                .map(|node| node.name.as_str())
+                .chain([name.as_str()]),
+        )
+        .unwrap_or(name.as_str())
+        .to_string();
+
+        let mut current = self.leader.lock().unwrap();
+        if current.as_ref() != Some(&leader) {
+            info!(
+                "Node {} is now the cluster coordinator (previously {:?})",
+                leader, current
+            );
+            *current = Some(leader.clone());
+        }
+        leader
+    }
+
+    /// Returns the coordinator of cluster-wide tasks as last elected by `elect_leader`.
+    pub fn leader(&self) -> Option<String> {
+        self.leader.lock().unwrap().clone()
+    }
+
+    /// Returns whether this node is the coordinator of cluster-wide tasks, as last elected
+    /// by `elect_leader`. More than one node may be the coordinator during a partition, so
+    /// tasks that check it must tolerate running on several nodes at once.
+    pub fn is_leader(&self) -> bool {
+        self.leader().as_deref() == Some(self.name.as_str())
+    }
+
+    /// Returns how replicated mutations are to be batched.
+    pub fn batch_options(&self) -> &BatchOptions {
+        &self.batch
+    }
 
     pub async fn send_msg_to_all(&self, msg: Message) {
-        for node in self.gossipod.members().await.unwrap_or_default() {
-            if node.name == self.config.name() {
+        self.send_msg(msg, &Replication::All).await;
+    }
+
+    /// Sends a message to the members selected by `replication`.
+    ///
+    /// Requested node names that are not current members are logged and skipped.
+    pub async fn send_msg(&self, msg: Message, replication: &Replication) {
+        self.send_batch(vec![(msg, replication.clone())]).await;
+    }
+
+    /// Sends a batch of messages, each to the members selected by its `Replication`.
+    ///
+    /// Every member receives the messages addressed to it in order, as a single frame when
+    /// the wire version supports batching. Requested node names that are not current members
+    /// are logged and skipped.
+    ///
+    /// With a non-zero fanout, messages for every member are instead disseminated as a rumor:
+    /// sent to a few members, each relaying it on to a share of the others, and so on. Rumors
+    /// are acknowledged and resent at every hop, and carry a sequence of their origin shared
+    /// by every member.
+    pub async fn send_batch(&self, batch: Vec<(Message, Replication)>) {
+        let batch = self.coalesce_checkpoints(batch);
+        if batch
+            .iter()
+            .all(|(_, replication)| *replication == Replication::LocalOnly)
+        {
+            return;
+        }
+        let members = self.members().await;
+
+        // Members that were seen before but are not members anymore are down: the writes
+        // addressed to them are kept as hints, to be replayed when they return.
+        let down: Vec<String> = self
+            .membership
+            .lock()
+            .unwrap()
+            .known
+            .keys()
+            .filter(|name| !members.iter().any(|node| &node.name == *name))
+            .cloned()
+            .collect();
+        for (msg, replication) in &batch {
+            if matches!(msg.cmd, Command::Ping | Command::Checkpoint) {
+                continue;
+            }
+            for name in down.iter().filter(|name| replication.includes(name)) {
+                self.hints.store(name, msg.clone(), Instant::now());
+            }
+            if let Replication::Nodes(names) = replication {
+                for name in names {
+                    if !members.iter().any(|node| &node.name == name) && !down.contains(name) {
+                        warn!("Replication target {} is not a cluster member", name);
+                    }
+                }
+            }
+        }
+
+        let batch = if self.disseminates() {
+            let (rumor, batch): (Vec<_>, Vec<_>) = batch
+                .into_iter()
+                .partition(|(_, replication)| *replication == Replication::All);
+            if !rumor.is_empty() {
+                self.start_rumor(rumor.into_iter().map(|(msg, _)| msg).collect(), &members)
+                    .await;
+            }
+            batch
+        } else {
+            batch
+        };
+
+        for node in members {
+            if node.name == self.name {
                 continue; // skip self
             }
-            let target = node.socket_addr().unwrap();
+            let mut msgs: Vec<Message> = batch
+                .iter()
+                .filter(|(_, replication)| replication.includes(&node.name))
+                .map(|(msg, _)| msg.clone())
+                .collect();
+            if msgs.is_empty() {
+                continue;
+            }
+
+            self.assign_sequences(&node.name, &mut msgs);
+
+            let target = node.addr;
             info!(
-                "Sending to {}: key={} value={} target={}",
-                node.name, msg.key, msg.value, target
+                "Sending {} messages to {}: keys={:?} target={}",
+                msgs.len(),
+                node.name,
+                msgs.iter().map(|msg| msg.key.as_str()).collect::<Vec<_>>(),
+                target
             );
-            if let Err(e) = self
-                .gossipod
-                .send(target, &bincode::serialize(&msg).unwrap())
-                .await
-            {
-                error!("Failed to send message to {}: {}", node.name, e);
+            let envelope = Envelope {
+                id: self.envelope_id(&msgs),
+                messages: msgs,
+                rumor: None,
+            };
+            let frames = match self.encode(&envelope) {
+                Ok(frames) => frames,
+                Err(e) => {
+                    error!("Failed to encode messages for {}: {:?}", node.name, e);
+                    continue;
+                }
+            };
+            self.send_frames(&node.name, target, &frames).await;
+            if envelope.id != 0 {
+                self.retries.track(Retry {
+                    id: envelope.id,
+                    node: node.name.clone(),
+                    target,
+                    frames,
+                    rumor: None,
+                });
+            }
+        }
+    }
+
+    /// Keeps only the last checkpoint of a batch for each replication target, as it covers the
+    /// writes of the earlier ones. Checkpoints are dropped altogether when the wire version
+    /// predates them, leaving sessions to be served by proxying to this node.
+    fn coalesce_checkpoints(
+        &self,
+        batch: Vec<(Message, Replication)>,
+    ) -> Vec<(Message, Replication)> {
+        let supported = self.wire.version >= wire::min_version(&Command::Checkpoint);
+        let mut kept = Vec::with_capacity(batch.len());
+        for (i, (msg, replication)) in batch.iter().enumerate() {
+            let superseded = msg.cmd == Command::Checkpoint
+                && (!supported
+                    || batch[i + 1..].iter().any(|(later, later_replication)| {
+                        later.cmd == Command::Checkpoint && later_replication == replication
+                    }));
+            if !superseded {
+                kept.push((msg.clone(), replication.clone()));
+            }
+        }
+        kept
+    }
+
+    /// Returns whether messages for every member are disseminated as rumors.
+    fn disseminates(&self) -> bool {
+        self.fanout > 0 && self.wire.version >= wire::RELAY_VERSION
+    }
+
+    /// Starts a rumor carrying messages for every member.
+    ///
+    /// The rumor names every other member, split between the members it is sent to, except
+    /// for pings, which only go to `fanout` random members.
+    async fn start_rumor(&self, mut msgs: Vec<Message>, members: &[Member]) {
+        let pings = msgs.iter().all(|msg| msg.cmd == Command::Ping);
+        if !pings {
+            self.assign_rumor_sequences(&mut msgs);
+        }
+        let rumor = Rumor {
+            origin: self.name.clone(),
+            id: self.next_frame_id.fetch_add(1, Ordering::Relaxed),
+            hops: 0,
+            members: Vec::new(),
+        };
+        self.seen_rumors
+            .lock()
+            .unwrap()
+            .insert((rumor.origin.clone(), rumor.id), Instant::now());
+
+        let envelope = Envelope {
+            id: 0,
+            messages: msgs,
+            rumor: Some(rumor),
+        };
+        if pings {
+            self.spread(&envelope, members, |_| true).await;
+            return;
+        }
+        let mut names: Vec<String> = members
+            .iter()
+            .filter(|node| node.name != self.name)
+            .map(|node| node.name.clone())
+            .collect();
+        names.sort();
+        self.relay(&envelope, &names, members).await;
+    }
+
+    /// Relays a rumor on to the named members.
+    ///
+    /// The members are split into `fanout` shares. The first member of each share is sent the
+    /// rumor, naming the rest of its share for it to relay on to, and resent it until it
+    /// acknowledges it. Named members that are not current members are kept as hints.
+    async fn relay(&self, envelope: &Envelope, names: &[String], members: &[Member]) {
+        let Some(rumor) = &envelope.rumor else {
+            return;
+        };
+        let mut targets = Vec::with_capacity(names.len());
+        for name in names {
+            match members.iter().find(|node| &node.name == name) {
+                Some(node) => targets.push((node.name.clone(), node.addr)),
+                None => {
+                    for msg in envelope
+                        .messages
+                        .iter()
+                        .filter(|msg| !matches!(msg.cmd, Command::Ping | Command::Checkpoint))
+                    {
+                        self.hints.store(name, msg.clone(), Instant::now());
+                    }
+                }
+            }
+        }
+        if targets.is_empty() {
+            return;
+        }
+
+        let share = targets.len().div_ceil(self.fanout.max(1));
+        for group in targets.chunks(share) {
+            let (name, target) = &group[0];
+            let relayed = Envelope {
+                id: self.envelope_id(&envelope.messages),
+                messages: envelope.messages.clone(),
+                rumor: Some(Rumor {
+                    members: group[1..].iter().map(|(name, _)| name.clone()).collect(),
+                    ..rumor.clone()
+                }),
+            };
+            let frames = match self.encode(&relayed) {
+                Ok(frames) => frames,
+                Err(e) => {
+                    error!("Failed to encode rumor for {}: {:?}", name, e);
+                    continue;
+                }
+            };
+            info!(
+                "Sending {} messages to {} as rumor {:?}",
+                relayed.messages.len(),
+                name,
+                relayed.rumor
+            );
+            self.send_frames(name, *target, &frames).await;
+            if relayed.id != 0 {
+                self.retries.track(Retry {
+                    id: relayed.id,
+                    node: name.clone(),
+                    target: *target,
+                    frames,
+                    rumor: Some(relayed),
+                });
+            }
+        }
+    }
+
+    /// Sends a rumor to `fanout` random members other than this node that pass `filter`.
+    async fn spread(
+        &self,
+        envelope: &Envelope,
+        members: &[Member],
+        filter: impl Fn(&Member) -> bool,
+    ) {
+        let candidates: Vec<(String, SocketAddr)> = members
+            .iter()
+            .filter(|node| node.name != self.name && filter(node))
+            .map(|node| (node.name.clone(), node.addr))
+            .collect();
+        let targets: Vec<(String, SocketAddr)> = candidates
+            .choose_multiple(&mut rand::rng(), self.fanout)
+            .cloned()
+            .collect();
+        if targets.is_empty() {
+            return;
+        }
+
+        let frames = match self.encode(envelope) {
+            Ok(frames) => frames,
+            Err(e) => {
+                error!("Failed to encode rumor: {:?}", e);
+                return;
+            }
+        };
+        for (name, target) in targets {
+            info!(
+                "Sending {} messages to {} as rumor {:?}",
+                envelope.messages.len(),
+                name,
+                envelope.rumor
+            );
+            self.send_frames(&name, target, &frames).await;
+        }
+    }
+
+    /// Records a received envelope if it is a rumor, relaying it on to the members it names,
+    /// or to random members while it has hops left, the first time it is received.
+    ///
+    /// # Returns
+    ///
+    /// * `false` if the envelope is a rumor that was already received.
+    pub async fn accept_rumor(&self, from: SocketAddr, envelope: &Envelope) -> bool {
+        let Some(rumor) = &envelope.rumor else {
+            return true;
+        };
+        {
+            let mut seen = self.seen_rumors.lock().unwrap();
+            let key = (rumor.origin.clone(), rumor.id);
+            if seen.contains_key(&key) {
+                return false;
+            }
+            seen.insert(key, Instant::now());
+        }
+
+        if !rumor.members.is_empty() {
+            let members = self.members().await;
+            self.relay(envelope, &rumor.members, &members).await;
+        } else if rumor.hops > 0 {
+            let members = self.members().await;
+            let relayed = Envelope {
+                rumor: Some(Rumor {
+                    hops: rumor.hops - 1,
+                    ..rumor.clone()
+                }),
+                ..envelope.clone()
+            };
+            self.spread(&relayed, &members, |node| {
+                node.name != rumor.origin && node.addr != from
+            })
+            .await;
+        }
+        true
+    }
+
+    /// Forgets rumors older than the retention period.
+    pub fn evict_rumors(&self) {
+        self.seen_rumors
+            .lock()
+            .unwrap()
+            .retain(|_, seen| seen.elapsed() < RUMOR_RETENTION);
+    }
+
+    /// Acknowledges the receipt of an envelope to its sender.
+    pub async fn send_ack(&self, target: SocketAddr, id: u64) {
+        let envelope = Envelope {
+            messages: vec![Message::new(Command::Ack, id.to_string(), String::new())],
+            ..Envelope::default()
+        };
+        match self.encode(&envelope) {
+            Ok(frames) => {
+                self.send_frames(&target.to_string(), target, &frames).await;
+            }
+            Err(e) => error!("Failed to encode ack for {}: {:?}", target, e),
+        }
+    }
+
+    /// Asks the node at `target` to resend its messages from sequence number `missing` on.
+    pub async fn request_resync(&self, target: SocketAddr, missing: u64) {
+        let envelope = Envelope {
+            messages: vec![Message::new(
+                Command::Resync,
+                missing.to_string(),
+                self.name.clone(),
+            )],
+            ..Envelope::default()
+        };
+        match self.encode(&envelope) {
+            Ok(frames) => {
+                self.send_frames(&target.to_string(), target, &frames).await;
+            }
+            Err(e) => error!("Failed to encode resync request for {}: {:?}", target, e),
+        }
+    }
+
+    /// Resends the unacknowledged envelopes addressed to a member right away.
+    ///
+    /// Only envelopes still awaiting acknowledgement can be resent; the member gives up on
+    /// older missing messages after its gap timeout.
+    pub async fn resync(&self, member: &str) {
+        let expedited = self.retries.expedite(member, Instant::now());
+        info!(
+            "Resending {} unacknowledged envelopes to {} on request",
+            expedited, member
+        );
+        self.retry_pending().await;
+    }
+
+    /// Stops resending an envelope once its receiver acknowledged it.
+    pub fn acknowledge(&self, id: u64) {
+        if !self.retries.acknowledge(id) {
+            warn!("Received an ack for unknown envelope {}", id);
+        }
+    }
+
+    /// Resends the envelopes whose acknowledgement is overdue.
+    ///
+    /// Envelopes are resent with exponential backoff and dead-lettered once they run out of
+    /// attempts, or when the node left the cluster. The members that a node which left was to
+    /// relay a rumor on to are sent it by this node instead.
+    pub async fn retry_pending(&self) {
+        let due = self.retries.due(Instant::now());
+        if due.is_empty() {
+            return;
+        }
+        let members = self.members().await;
+        for retry in due {
+            if !members.iter().any(|node| node.name == retry.node) {
+                self.retries.acknowledge(retry.id);
+                warn!(
+                    "Dropping envelope {} to {}, which is no longer a cluster member",
+                    retry.id, retry.node
+                );
+                if let Some(envelope) = &retry.rumor {
+                    if let Some(rumor) = &envelope.rumor {
+                        self.relay(envelope, &rumor.members, &members).await;
+                    }
+                }
+                continue;
             }
+            warn!(
+                "Resending unacknowledged envelope {} to {}",
+                retry.id, retry.node
+            );
+            self.send_frames(&retry.node, retry.target, &retry.frames)
+                .await;
         }
     }
+
+    /// Returns the number of envelopes dropped without being acknowledged.
+    pub fn dead_lettered(&self) -> u64 {
+        self.retries.dead_lettered()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit test for electing the member with the lowest name, and failing over.
+    #[test]
+    fn test_elect() {
+        assert_eq!(elect(["node-2", "node-1", "node-3"]), Some("node-1"));
+        assert_eq!(elect(["node-2", "node-3"]), Some("node-2"));
+        assert_eq!(elect([]), None);
+    }
+
+    /// Unit test for detecting that a node is cut off from the members it knew of.
+    #[test]
+    fn test_membership_isolated() {
+        let now = Instant::now();
+        let mut membership = Membership::default();
+        assert!(!membership.isolated(now));
+
+        for (i, name) in ["node-2", "node-3", "node-4", "node-5"].iter().enumerate() {
+            let addr = format!("127.0.0.1:{}", 4002 + i).parse().unwrap();
+            membership.known.insert(name.to_string(), addr);
+            membership.alive.insert(name.to_string());
+        }
+        assert!(!membership.isolated(now));
+
+        membership.alive.remove("node-2");
+        membership.deaths.push(now);
+        assert!(!membership.isolated(now));
+        membership.alive.remove("node-3");
+        membership.deaths.push(now);
+        assert!(membership.isolated(now));
+        assert!(!membership.isolated(now + DEATH_FLOOD_WINDOW));
+
+        membership.alive.clear();
+        assert!(membership.isolated(now + DEATH_FLOOD_WINDOW));
+    }
+
+    /// Unit test for parsing `Replication` from a `replicate_to` parameter.
+    #[test]
+    fn test_parse_replication() {
+        assert_eq!(
+            "local-only".parse::<Replication>().unwrap(),
+            Replication::LocalOnly
+        );
+        assert_eq!(
+            "node-2, node-3,".parse::<Replication>().unwrap(),
+            Replication::Nodes(vec!["node-2".to_string(), "node-3".to_string()])
+        );
+        assert!(" , ".parse::<Replication>().is_err());
+
+        let nodes = "node-2".parse::<Replication>().unwrap();
+        assert!(nodes.includes("node-2"));
+        assert!(!nodes.includes("node-3"));
+        assert!(Replication::All.includes("node-3"));
+        assert!(!Replication::LocalOnly.includes("node-2"));
+    }
+
+    /// Unit test for encoding the operations of a transaction into a `Txn` message.
+    #[test]
+    fn test_txn_messages() {
+        let txn = Message::txn(vec![
+            Message::new(Command::Insert, "to".to_string(), "session".to_string()),
+            Message::new(Command::Remove, "from".to_string(), String::new()),
+        ])
+        .unwrap();
+        let ops: Vec<(Command, String)> = txn
+            .txn_messages()
+            .unwrap()
+            .into_iter()
+            .map(|op| (op.cmd, op.key))
+            .collect();
+        assert_eq!(
+            ops,
+            vec![
+                (Command::Insert, "to".to_string()),
+                (Command::Remove, "from".to_string())
+            ]
+        );
+
+        assert!(Message::txn(vec![txn.clone()]).is_err());
+        assert!(
+            Message::new(Command::Insert, "to".to_string(), String::new())
+                .txn_messages()
+                .is_err()
+        );
+    }
 }
@@ -0,0 +1,172 @@
+use crate::gossip::Message;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Configuration of the hints kept for members that are down.
+///
+/// # Fields
+///
+/// - `ttl`: How long a hint is kept before it is dropped.
+/// - `capacity`: The maximum number of hints kept per member; the oldest are dropped first.
+#[derive(Debug, Clone)]
+pub struct HintOptions {
+    pub ttl: Duration,
+    pub capacity: usize,
+}
+
+impl Default for HintOptions {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(3600),
+            capacity: 10_000,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Hint {
+    msg: Message,
+    stored: Instant,
+}
+
+/// Keeps the messages that could not be replicated to members that were down, to replay them
+/// when the members return.
+///
+/// # Example
+///
+/// ```rust
+/// let hints = HintStore::new(HintOptions::default());
+/// hints.store("node-2", msg, Instant::now());
+/// // once node-2 is back
+/// for msg in hints.take("node-2", Instant::now()) {
+///     // send msg to node-2
+/// }
+/// ```
+#[derive(Debug)]
+pub struct HintStore {
+    options: HintOptions,
+    hints: Mutex<HashMap<String, VecDeque<Hint>>>,
+}
+
+impl HintStore {
+    /// Creates a new, empty `HintStore`.
+    pub fn new(options: HintOptions) -> Self {
+        Self {
+            options,
+            hints: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Stores a message for a member that is down.
+    pub fn store(&self, node: &str, msg: Message, now: Instant) {
+        let mut hints = self.hints.lock().unwrap();
+        let queue = hints.entry(node.to_string()).or_default();
+        queue.push_back(Hint { msg, stored: now });
+        if queue.len() > self.options.capacity {
+            queue.pop_front();
+            warn!(
+                "Hints for {} exceed {}, dropping the oldest",
+                node, self.options.capacity
+            );
+        }
+    }
+
+    /// Takes the unexpired hints stored for a member, oldest first.
+    pub fn take(&self, node: &str, now: Instant) -> Vec<Message> {
+        let ttl = self.options.ttl;
+        self.hints
+            .lock()
+            .unwrap()
+            .remove(node)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|hint| now.duration_since(hint.stored) < ttl)
+            .map(|hint| hint.msg)
+            .collect()
+    }
+
+    /// Returns the members that have hints stored.
+    pub fn nodes(&self) -> Vec<String> {
+        self.hints.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Drops the hints older than the TTL.
+    ///
+    /// # Returns
+    ///
+    /// * The number of hints dropped.
+    pub fn evict_expired(&self, now: Instant) -> usize {
+        let ttl = self.options.ttl;
+        let mut hints = self.hints.lock().unwrap();
+        let mut dropped = 0;
+        for queue in hints.values_mut() {
+            let before = queue.len();
+            queue.retain(|hint| now.duration_since(hint.stored) < ttl);
+            dropped += before - queue.len();
+        }
+        hints.retain(|_, queue| !queue.is_empty());
+        dropped
+    }
+
+    /// Returns the number of hints stored for all members.
+    pub fn len(&self) -> usize {
+        self.hints.lock().unwrap().values().map(VecDeque::len).sum()
+    }
+
+    /// Returns whether no hint is stored.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gossip::Command;
+
+    fn insert(key: &str) -> Message {
+        Message::new(Command::Insert, key.to_string(), "world".to_string())
+    }
+
+    /// Unit test for storing hints, bounded per member, and taking them in order.
+    #[test]
+    fn test_hints() {
+        let now = Instant::now();
+        let hints = HintStore::new(HintOptions {
+            ttl: Duration::from_secs(60),
+            capacity: 2,
+        });
+        hints.store("node-2", insert("a"), now);
+        hints.store("node-2", insert("b"), now);
+        hints.store("node-2", insert("c"), now);
+        hints.store("node-3", insert("d"), now);
+        assert_eq!(hints.len(), 3);
+
+        let keys: Vec<String> = hints
+            .take("node-2", now)
+            .into_iter()
+            .map(|msg| msg.key)
+            .collect();
+        assert_eq!(keys, vec!["b", "c"]);
+        assert!(hints.take("node-2", now).is_empty());
+        assert_eq!(hints.nodes(), vec!["node-3"]);
+    }
+
+    /// Unit test for dropping hints older than the TTL.
+    #[test]
+    fn test_hints_ttl() {
+        let now = Instant::now();
+        let hints = HintStore::new(HintOptions {
+            ttl: Duration::from_secs(60),
+            capacity: 10,
+        });
+        hints.store("node-2", insert("a"), now);
+        hints.store("node-2", insert("b"), now + Duration::from_secs(30));
+
+        assert_eq!(hints.evict_expired(now + Duration::from_secs(60)), 1);
+        assert_eq!(hints.take("node-2", now + Duration::from_secs(89)).len(), 1);
+        assert!(hints.is_empty());
+    }
+}
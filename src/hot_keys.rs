@@ -0,0 +1,245 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Configures how hot keys are detected, and whether they are served from a read cache.
+///
+/// # Fields
+///
+/// - `threshold`: The reads per second, averaged over `window`, from which a key is hot.
+/// - `window`: The sliding window read rates are measured over.
+/// - `capacity`: The maximum number of keys whose reads are counted.
+/// - `cache_ttl`: How long the value of a hot key is served from the read cache once read from
+///   the cache backend. Zero disables the read cache.
+#[derive(Debug, Clone)]
+pub struct HotKeyOptions {
+    pub threshold: f64,
+    pub window: Duration,
+    pub capacity: usize,
+    pub cache_ttl: Duration,
+}
+
+impl Default for HotKeyOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 1000.0,
+            window: Duration::from_secs(10),
+            capacity: 10_000,
+            cache_ttl: Duration::ZERO,
+        }
+    }
+}
+
+/// A key read at or above the hot-key threshold, as served at `/stats/hotkeys`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HotKey {
+    pub key: String,
+    /// Reads per second over the sliding window.
+    pub rate: f64,
+}
+
+/// The reads of a key in the current window and the one before it.
+#[derive(Debug)]
+struct Counter {
+    window_start: Instant,
+    current: u64,
+    previous: u64,
+}
+
+impl Counter {
+    fn new(now: Instant) -> Self {
+        Self {
+            window_start: now,
+            current: 0,
+            previous: 0,
+        }
+    }
+
+    /// Moves the window forward to the one `now` falls in.
+    fn roll(&mut self, now: Instant, window: Duration) {
+        let elapsed = now.duration_since(self.window_start);
+        if elapsed < window {
+            return;
+        }
+        let windows = (elapsed.as_nanos() / window.as_nanos().max(1)) as u32;
+        self.previous = if windows == 1 { self.current } else { 0 };
+        self.current = 0;
+        self.window_start += window * windows;
+    }
+
+    /// Estimates the reads over the last `window`, weighting the previous window by how much
+    /// of it the sliding window still overlaps.
+    fn estimate(&self, now: Instant, window: Duration) -> f64 {
+        let overlap = 1.0
+            - now.duration_since(self.window_start).as_secs_f64() / window.as_secs_f64().max(1e-9);
+        self.previous as f64 * overlap.max(0.0) + self.current as f64
+    }
+}
+
+/// Detects the keys read at a rate above a threshold, with a sliding-window counter per key,
+/// and optionally serves their values from a small read cache.
+///
+/// Reads served from the read cache do not lock the cache backend nor reach remote tiers, so a
+/// single very popular key no longer serializes every other request of the node behind it.
+/// Every node holds every key, so each node detects and caches its own hot keys. Writes made
+/// through this node invalidate the read cache; writes replicated from other nodes are seen
+/// once the cached value is older than `cache_ttl`.
+///
+/// # Example
+///
+/// ```rust
+/// let hot_keys = HotKeys::new(HotKeyOptions::default());
+/// if hot_keys.record_read("celebrity") {
+///     hot_keys.cache("celebrity", value);
+/// }
+/// println!("{:?}", hot_keys.hot());
+/// ```
+#[derive(Debug)]
+pub struct HotKeys {
+    options: HotKeyOptions,
+    counters: Mutex<HashMap<String, Counter>>,
+    cache: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl HotKeys {
+    /// Creates a `HotKeys` that has not counted any read yet.
+    pub fn new(options: HotKeyOptions) -> Self {
+        Self {
+            options,
+            counters: Mutex::new(HashMap::new()),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records a read of `key`.
+    ///
+    /// # Returns
+    ///
+    /// * Whether the key is hot.
+    pub fn record_read(&self, key: &str) -> bool {
+        let now = Instant::now();
+        let window = self.options.window;
+        let mut counters = self.counters.lock().unwrap();
+        if !counters.contains_key(key) && counters.len() >= self.options.capacity.max(1) {
+            Self::prune(&mut counters, now, window, self.options.capacity / 2);
+        }
+        let counter = counters
+            .entry(key.to_string())
+            .or_insert_with(|| Counter::new(now));
+        counter.roll(now, window);
+        counter.current += 1;
+        self.rate(counter, now) >= self.options.threshold
+    }
+
+    /// Keeps the `keep` keys read the most over the sliding window.
+    fn prune(counters: &mut HashMap<String, Counter>, now: Instant, window: Duration, keep: usize) {
+        let mut estimates: Vec<f64> = counters
+            .values_mut()
+            .map(|counter| {
+                counter.roll(now, window);
+                counter.estimate(now, window)
+            })
+            .collect();
+        estimates.sort_unstable_by(|a, b| b.total_cmp(a));
+        let threshold = estimates.get(keep).copied().unwrap_or(0.0);
+        counters.retain(|_, counter| counter.estimate(now, window) > threshold);
+    }
+
+    fn rate(&self, counter: &Counter, now: Instant) -> f64 {
+        counter.estimate(now, self.options.window) / self.options.window.as_secs_f64().max(1e-9)
+    }
+
+    /// Returns the hot keys, the most read first.
+    pub fn hot(&self) -> Vec<HotKey> {
+        let now = Instant::now();
+        let mut counters = self.counters.lock().unwrap();
+        let mut hot: Vec<HotKey> = counters
+            .iter_mut()
+            .filter_map(|(key, counter)| {
+                counter.roll(now, self.options.window);
+                let rate = self.rate(counter, now);
+                (rate >= self.options.threshold).then(|| HotKey {
+                    key: key.clone(),
+                    rate,
+                })
+            })
+            .collect();
+        hot.sort_by(|a, b| b.rate.total_cmp(&a.rate).then_with(|| a.key.cmp(&b.key)));
+        hot
+    }
+
+    /// Returns the value of `key` from the read cache, unless it is older than `cache_ttl`.
+    pub fn cached(&self, key: &str) -> Option<String> {
+        let cache = self.cache.lock().unwrap();
+        let (value, cached_at) = cache.get(key)?;
+        (cached_at.elapsed() < self.options.cache_ttl).then(|| value.clone())
+    }
+
+    /// Puts the value of a hot key in the read cache, if it is enabled.
+    pub fn cache(&self, key: &str, value: &str) {
+        if self.options.cache_ttl.is_zero() {
+            return;
+        }
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= self.options.capacity {
+            let ttl = self.options.cache_ttl;
+            cache.retain(|_, (_, cached_at)| cached_at.elapsed() < ttl);
+        }
+        cache.insert(key.to_string(), (value.to_string(), Instant::now()));
+    }
+
+    /// Drops `key` from the read cache, after it was written.
+    pub fn invalidate(&self, key: &str) {
+        self.cache.lock().unwrap().remove(key);
+    }
+
+    /// Drops every key from the read cache, after writes whose keys are not known.
+    pub fn invalidate_all(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit test for `HotKeys`.
+    ///
+    /// Keys read above the threshold are hot and cached until invalidated or too old, and stop
+    /// being hot once their reads leave the window.
+    #[test]
+    fn test_hot_keys() {
+        let hot_keys = HotKeys::new(HotKeyOptions {
+            threshold: 100.0,
+            window: Duration::from_millis(100),
+            capacity: 4,
+            cache_ttl: Duration::from_millis(50),
+        });
+        for _ in 0..5 {
+            assert!(!hot_keys.record_read("warm"));
+        }
+        let hot: Vec<bool> = (0..20).map(|_| hot_keys.record_read("celebrity")).collect();
+        assert!(!hot[0]);
+        assert!(hot[19]);
+        assert_eq!(
+            hot_keys
+                .hot()
+                .into_iter()
+                .map(|hot| hot.key)
+                .collect::<Vec<_>>(),
+            vec!["celebrity"]
+        );
+
+        hot_keys.cache("celebrity", "value");
+        assert_eq!(hot_keys.cached("celebrity").as_deref(), Some("value"));
+        hot_keys.invalidate("celebrity");
+        assert_eq!(hot_keys.cached("celebrity"), None);
+        hot_keys.cache("celebrity", "value");
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(hot_keys.cached("celebrity"), None);
+
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(hot_keys.hot().is_empty());
+    }
+}
@@ -1,29 +1,340 @@
-use crate::cache_trait::BCache;
-use crate::gossip::{Command, Message};
-use anyhow::Result;
-use axum::extract::{Query, State};
-use axum::http::StatusCode;
-use axum::routing::{delete, get, post};
+use crate::acl::{Acl, AclToken, Permission, Principal};
+use crate::anomaly::{Alert, AnomalyDetector, MutationKind};
+use crate::audit::{AuditLog, AuditOperation, AuditOrigin};
+use crate::cache_trait::{invalidate_tag as invalidate_cached_tag, BCache};
+use crate::chaos::{Chaos, ChaosOptions};
+use crate::cluster_auth::{ClusterAuth, ClusterAuthStats};
+use crate::document::{self, DocumentPatch};
+use crate::error::{self, Error};
+use crate::events::KeyEvents;
+use crate::gossip::{Command, Message, Replication};
+use crate::hot_keys::{HotKey, HotKeyOptions, HotKeys};
+use crate::idempotency::{
+    Begin, IdempotencyOptions, IdempotencyStore, InFlightGuard, StoredResponse,
+};
+use crate::key_stats::{self, KeyStat, KeyStats};
+use crate::limits::{Limits, Violation};
+use crate::ndjson::{self, NdjsonDecoder, IMPORT_BATCH};
+use crate::openapi;
+use crate::queue::{
+    queue, OverflowPolicy, QueueCounters, QueueOptions, QueueReceiver, QueueSender, QueueStats,
+};
+use crate::quota::{NamespaceStats, Quotas};
+use crate::session::{SessionToken, Sessions};
+use crate::slowlog::{SlowLog, SlowLogEntry, SlowLogKind};
+use crate::snapshot::{Snapshot, SnapshotEntry, SnapshotStore};
+use crate::staleness::Staleness;
+use crate::tags::TagIndex;
+use crate::topology::{Topology, TopologyView};
+use crate::typed_value::ValueOp;
+use crate::utils::{etag, etag_matches, parse_duration, unix_time_ms};
+use crate::wal::Wal;
+use anyhow::{anyhow, Context, Result};
+use axum::async_trait;
+use axum::body::{Body, Bytes};
+use axum::extract::{
+    ConnectInfo, DefaultBodyLimit, Extension, FromRequestParts, Path, Query, State,
+};
+use axum::http::header::{
+    AUTHORIZATION, CONNECTION, CONTENT_LENGTH, CONTENT_TYPE, ETAG, HOST, IF_MATCH, IF_NONE_MATCH,
+    LOCATION, RETRY_AFTER, TRANSFER_ENCODING, WWW_AUTHENTICATE,
+};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, Request, StatusCode, Uri};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{Html, IntoResponse};
+use axum::routing::{delete, get, patch, post, put};
 use axum::{Json, Router};
+use clap::ValueEnum;
+use futures::StreamExt;
+use hyper::body::Incoming;
+use hyper::service::service_fn;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use ring::digest;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
-use tokio::sync::mpsc::{Receiver, Sender};
-use tokio::sync::{mpsc, Mutex};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpSocket};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{Mutex, Semaphore};
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::Service;
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::{AllowHeaders, AllowOrigin, CorsLayer};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::{DefaultOnResponse, TraceLayer};
+use tower_http::LatencyUnit;
+use tracing::{info_span, Level, Span};
 
-/// Starts the HTTP server and binds it to the given address.
+/// The number of entries `/range` returns when the request sets no limit.
+const DEFAULT_RANGE_LIMIT: usize = 100;
+/// The maximum number of entries `/range` returns in one response.
+const MAX_RANGE_LIMIT: usize = 1000;
+/// The default timeout of the routes copying the whole cache, such as `/import` and `/export`.
+const BULK_REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The header carrying the ID of a request.
+const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+/// The header a client sets to the same value on the retries of a mutation.
+const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+/// The header set on responses replayed for a retried idempotency key.
+const IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+/// The header carrying the version of the topology served at `/topology`, set on the responses
+/// of the data routes so clients notice when to refresh it.
+const X_TOPOLOGY_VERSION: HeaderName = HeaderName::from_static("x-topology-version");
+/// The header carrying the `SessionToken` of a client: returned by writes, and passed on reads
+/// so they see the writes it covers.
+const X_SESSION_TOKEN: HeaderName = HeaderName::from_static("x-session-token");
+/// The header marking a read proxied by a node that had not caught up with its session, set to
+/// the name of that node, so the node it was proxied to does not proxy it again.
+const X_SESSION_PROXIED: HeaderName = HeaderName::from_static("x-session-proxied");
+/// The header carrying the Unix time, in milliseconds, at which the key read was last modified
+/// on the node serving it.
+const X_LAST_MODIFIED: HeaderName = HeaderName::from_static("x-last-modified");
+/// The header carrying how far behind the owner of the key read the node serving it may be, in
+/// milliseconds.
+const X_REPLICA_LAG: HeaderName = HeaderName::from_static("x-replica-lag");
+
+/// The request headers allowed in cross-origin requests by default, comma-separated: those of
+/// the API, including the bearer token and the session token.
+pub const DEFAULT_CORS_HEADERS: &str =
+    "authorization,content-type,idempotency-key,if-match,if-none-match,x-request-id,x-session-token";
+
+/// Configuration for the HTTP server.
+///
+/// # Fields
+///
+/// - `addr`: The address on which the server listens for incoming requests.
+/// - `request_timeout`: The maximum time a request may take to produce its response headers,
+///   including time spent waiting for an in-flight slot. Requests exceeding it are answered
+///   with `503 Service Unavailable`, but keep running to completion, so a write they make is
+///   not left applied on this node without being logged and replicated. Streamed bodies, such
+///   as the one of `/export`, are not bounded by it.
+/// - `route_timeouts`: The timeouts of the routes that do not use `request_timeout`. By default
+///   `/import`, `/export`, `/admin/snapshot` and `/admin/restore`, which copy the whole cache,
+///   get `BULK_REQUEST_TIMEOUT`.
+/// - `max_in_flight`: The maximum number of requests processed concurrently across all routes.
+///   Further requests wait for a slot.
+/// - `max_body_bytes`: The maximum size of a request body. Larger bodies are rejected with
+///   `413 Payload Too Large`.
+/// - `snapshot_dir`: The directory `/admin/snapshot` writes snapshots to and `/admin/restore`
+///   reads them from.
+/// - `http_version`: The HTTP versions connections may speak.
+/// - `keep_alive_timeout`: How long an idle HTTP/1 connection waits for the next request, and the
+///   interval of HTTP/2 keep-alive pings, whose connection is closed if one is not answered in time.
+///   Keep-alive is disabled when `None`.
+/// - `header_read_timeout`: How long an HTTP/1 connection may take to send the headers of a
+///   request, whether or not keep-alive is enabled. Connections that are slower are closed.
+/// - `max_connections`: The maximum number of open connections. Further connections wait in the
+///   listen backlog until one closes.
+/// - `backlog`: The size of the listen backlog of the socket.
+/// - `admin_addr`: An optional separate address for the operational routes (`/admin/*`,
+///   `/debug/*`, `/cluster/*` and `/stats/*`), so they can be firewalled apart from the data API and never
+///   compete with data traffic. They are served on `addr` when it is `None`.
+/// - `cors_origins`: The origins browsers may call the API from, or `*` for any origin.
+///   Cross-origin requests are not allowed when it is empty.
+/// - `cors_methods`: The methods allowed in cross-origin requests.
+/// - `cors_headers`: The request headers allowed in cross-origin requests, or `*` for any header.
+/// - `limits`: The limits on the keys and values of writes and deletes.
+/// - `idempotency`: How long, and for how many keys, the responses of `/add` and `/delete`
+///   requests carrying an `Idempotency-Key` header are remembered.
+/// - `key_stats_capacity`: The maximum number of keys whose accesses are counted, when built
+///   with the `key-stats` feature.
+/// - `hot_keys`: How keys read at a high rate are detected, and how long `/query` serves them
+///   from the hot-key read cache.
+/// - `replication_queue`: How writes are buffered until the gossip task replicates them, and
+///   what happens to writes made while the buffer is full.
+/// - `session_wait`: How long a read carrying an `X-Session-Token` header waits for this node to
+///   catch up with the writes it covers, before it is proxied to the node that accepted them.
+/// - `acl_tokens`: The bearer tokens requests must carry, and the keys each may read, write or
+///   administer. Requests are not authenticated when it is empty. `/openapi.json` and `/docs`
+///   are always public.
+#[derive(Debug, Clone)]
+pub struct HttpServerConfig {
+    pub addr: String,
+    pub request_timeout: Duration,
+    pub route_timeouts: Vec<RouteTimeout>,
+    pub max_in_flight: usize,
+    pub max_body_bytes: usize,
+    pub snapshot_dir: PathBuf,
+    pub http_version: HttpVersion,
+    pub keep_alive_timeout: Option<Duration>,
+    pub header_read_timeout: Duration,
+    pub max_connections: usize,
+    pub backlog: u32,
+    pub admin_addr: Option<String>,
+    pub cors_origins: Vec<String>,
+    pub cors_methods: Vec<String>,
+    pub cors_headers: Vec<String>,
+    pub limits: Limits,
+    pub idempotency: IdempotencyOptions,
+    pub key_stats_capacity: usize,
+    pub hot_keys: HotKeyOptions,
+    pub replication_queue: QueueOptions,
+    pub session_wait: Duration,
+    pub acl_tokens: Vec<AclToken>,
+}
+
+/// The timeout of the requests to a route, parsed from `<path>=<seconds>`, such as
+/// `/import=600`. The path is matched exactly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteTimeout {
+    pub path: String,
+    pub timeout: Duration,
+}
+
+impl FromStr for RouteTimeout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (path, secs) = s
+            .rsplit_once('=')
+            .ok_or_else(|| anyhow!("Expected <path>=<seconds>, got '{}'", s))?;
+        if !path.starts_with('/') {
+            return Err(anyhow!("Route '{}' does not start with '/'", path));
+        }
+        let secs: u64 = secs
+            .parse()
+            .map_err(|_| anyhow!("Invalid timeout '{}' for route '{}'", secs, path))?;
+        Ok(Self {
+            path: path.to_string(),
+            timeout: Duration::from_secs(secs),
+        })
+    }
+}
+
+/// The HTTP versions the server accepts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum HttpVersion {
+    /// HTTP/1.1, and HTTP/2 with prior knowledge.
+    #[default]
+    Auto,
+    /// HTTP/1.1 only.
+    Http1,
+    /// HTTP/2 with prior knowledge only.
+    Http2,
+}
+
+impl HttpServerConfig {
+    /// Creates a new `HttpServerConfig` listening on `addr` with default limits.
+    pub fn new(addr: String) -> Self {
+        Self {
+            addr,
+            request_timeout: Duration::from_secs(30),
+            route_timeouts: ["/import", "/export", "/admin/snapshot", "/admin/restore"]
+                .map(|path| RouteTimeout {
+                    path: path.to_string(),
+                    timeout: BULK_REQUEST_TIMEOUT,
+                })
+                .to_vec(),
+            max_in_flight: 1024,
+            max_body_bytes: 2 * 1024 * 1024,
+            snapshot_dir: PathBuf::from("snapshots"),
+            http_version: HttpVersion::default(),
+            keep_alive_timeout: Some(Duration::from_secs(60)),
+            header_read_timeout: Duration::from_secs(30),
+            max_connections: 10_000,
+            backlog: 1024,
+            admin_addr: None,
+            cors_origins: Vec::new(),
+            cors_methods: ["GET", "POST", "PUT", "PATCH", "DELETE"]
+                .map(str::to_string)
+                .to_vec(),
+            cors_headers: DEFAULT_CORS_HEADERS
+                .split(',')
+                .map(str::to_string)
+                .collect(),
+            limits: Limits::default(),
+            idempotency: IdempotencyOptions::default(),
+            key_stats_capacity: 10_000,
+            hot_keys: HotKeyOptions::default(),
+            replication_queue: QueueOptions::new(100),
+            session_wait: Duration::from_millis(500),
+            acl_tokens: Vec::new(),
+        }
+    }
+
+    /// Sets the timeout of a route, replacing the one it had.
+    pub fn set_route_timeout(&mut self, route: RouteTimeout) {
+        self.route_timeouts.retain(|other| other.path != route.path);
+        self.route_timeouts.push(route);
+    }
+
+    /// Returns the timeout of the requests to a path.
+    pub fn request_timeout_for(&self, path: &str) -> Duration {
+        self.route_timeouts
+            .iter()
+            .find(|route| route.path == path)
+            .map_or(self.request_timeout, |route| route.timeout)
+    }
+}
+
+/// The parts of the node the HTTP server shares with the gossip task and the rest of the node.
+///
+/// # Fields
+///
+/// - `bcache`: A thread-safe, asynchronous cache that implements the `BCache` trait.
+/// - `tags`: The tag index, updated with the tags of writes and used to invalidate tags.
+/// - `slowlog`: The slow log that handlers exceeding the HTTP threshold are recorded in.
+/// - `audit`: The audit log that mutations made through the HTTP API are recorded in.
+/// - `anomalies`: The anomaly detector that mutations made through the HTTP API are counted in.
+/// - `wal`: The write-ahead log that mutations made through the HTTP API are appended to before
+///   they are acknowledged.
+/// - `events`: The key events streamed to the subscribers of `/events`.
+/// - `chaos`: The fault injection settings served and updated at `/admin/chaos`.
+/// - `gossip_queue`: The counters of the queue of frames received by the gossip node, served at
+///   `/stats/queues` along with those of the replication queue.
+/// - `cluster_auth`: The authentication of the gossip frames, whose admitted members and
+///   rejected frames are served at `/cluster/auth`.
+/// - `topology`: The members of the cluster and their tokens, served at `/topology`.
+/// - `sessions`: The positions of the writes accepted and applied by this node, which the session
+///   tokens of clients are checked against.
+/// - `staleness`: When keys were last modified on this node and it last heard from the other
+///   members, which reads report and are bounded by.
+/// - `quotas`: The usage of the namespaces with a quota, which writes are checked against and
+///   which is served at `/stats/namespaces`.
+#[derive(Clone)]
+pub struct ServerDeps {
+    pub bcache: Arc<Mutex<Box<dyn BCache>>>,
+    pub tags: Arc<TagIndex>,
+    pub slowlog: Arc<SlowLog>,
+    pub audit: Arc<AuditLog>,
+    pub anomalies: Arc<AnomalyDetector>,
+    pub wal: Arc<Wal>,
+    pub events: KeyEvents,
+    pub chaos: Arc<Chaos>,
+    pub gossip_queue: Arc<QueueCounters>,
+    pub cluster_auth: Arc<ClusterAuth>,
+    pub topology: Arc<Topology>,
+    pub sessions: Arc<Sessions>,
+    pub staleness: Arc<Staleness>,
+    pub quotas: Arc<Quotas>,
+}
+
+/// Starts the HTTP server and binds it to the configured address.
 ///
-/// This function sets up the HTTP routes and initializes the server to listen for
-/// incoming requests. It also creates a channel for inter-task communication via `Sender` and `Receiver`.
+/// The server serves the data API (`/query`, `/add`, `/v1/keys/{key}` and the other key, tag,
+/// import and export routes) and the operational routes (`/admin/*`, `/debug/*`, `/cluster/*`
+/// and `/stats/*`), the latter on their own listener when `config.admin_addr` is set. Every
+/// request is authenticated against the ACL tokens of `config`, tagged with an `X-Request-Id`,
+/// subject to the timeout, in-flight and body-size limits of `config`, and answered with a
+/// compressed response carrying the topology version of the cluster. Mutations are applied to
+/// the cache, logged, and handed to the gossip task through the returned receiver.
 ///
 /// # Arguments
 ///
-/// * `addr` - The address on which the server will listen for incoming requests.
-/// * `bcache` - A thread-safe, asynchronous cache that implements the `BCache` trait.
+/// * `config` - The listen addresses, limits and options of the server.
+/// * `deps` - The cache and the other parts of the node the handlers read and update.
 ///
 /// # Returns
 ///
-/// * `Result<Receiver<Message>>` - A receiver that can be used to handle messages sent to the gossip system.
+/// * `Result<QueueReceiver<(Message, Replication)>>` - A receiver that can be used to handle messages, and their replication targets, sent to the gossip system.
 ///
 /// # Errors
 ///
@@ -32,34 +343,356 @@ use tokio::sync::{mpsc, Mutex};
 /// # Example
 ///
 /// ```rust
-/// let receiver = start("127.0.0.1:8080".to_string(), bcache).await?;
+/// let config = HttpServerConfig::new("127.0.0.1:8080".to_string());
+/// let receiver = start(config, deps).await?;
 /// ```
-pub async fn start(addr: String, bcache: Arc<Mutex<Box<dyn BCache>>>) -> Result<Receiver<Message>> {
-    let (sender, receiver) = mpsc::channel(100);
+pub async fn start(
+    config: HttpServerConfig,
+    deps: ServerDeps,
+) -> Result<QueueReceiver<(Message, Replication)>> {
+    let (sender, receiver) = queue("replication", config.replication_queue.clone());
+    let (slowlog, audit) = (deps.slowlog.clone(), deps.audit.clone());
+    let (data, admin) = routes(&config, deps, sender);
+
+    let cors = cors_layer(&config)?;
+    let listener = bind(&config.addr, config.backlog)
+        .await
+        .with_context(|| format!("Failed to bind the HTTP server to {}", config.addr))?;
+    match &config.admin_addr {
+        Some(admin_addr) => {
+            let admin_listener = bind(admin_addr, config.backlog)
+                .await
+                .with_context(|| format!("Failed to bind the admin server to {}", admin_addr))?;
+            let data = with_layers(data, &config, &slowlog, &audit, cors.clone());
+            let admin = with_layers(admin, &config, &slowlog, &audit, cors);
+            tokio::spawn(serve(admin_listener, admin, config.clone()));
+            tokio::spawn(serve(listener, data, config));
+        }
+        None => {
+            let app = with_layers(data.merge(admin), &config, &slowlog, &audit, cors);
+            tokio::spawn(serve(listener, app, config));
+        }
+    }
 
-    let app_state = AppState::new(sender, bcache);
+    Ok(receiver)
+}
 
-    let app = Router::new()
-        .route("/query", get(query))
+/// Builds the data routes and the operational routes of the server, with their state and the
+/// layers specific to each group, such as authentication. The layers shared by every listener
+/// are added by `with_layers`.
+///
+/// # Returns
+///
+/// * The data routes and the operational routes.
+fn routes(
+    config: &HttpServerConfig,
+    deps: ServerDeps,
+    sender: QueueSender<(Message, Replication)>,
+) -> (Router, Router) {
+    let app_state = AppState::new(sender.clone(), &deps, config);
+    let ServerDeps {
+        topology, sessions, ..
+    } = deps;
+    let session = SessionState {
+        sessions,
+        topology: topology.clone(),
+        client: reqwest::Client::new(),
+        wait: config.session_wait,
+    };
+
+    let mut idempotent = Router::new()
         .route("/add", post(add))
         .route("/delete", delete(remove))
-        .with_state(app_state.clone());
+        .route(
+            "/v1/keys/:key",
+            put(put_key).delete(delete_key).patch(patch_document),
+        )
+        .route("/txn", post(txn))
+        .route("/append", post(append))
+        .route("/keys/:key", patch(patch_document))
+        .route("/keys/:key/ops", post(update_value));
+    let idempotency = Arc::new(IdempotencyStore::new(config.idempotency.clone()));
+    if idempotency.enabled() {
+        idempotent = idempotent.route_layer(middleware::from_fn_with_state(
+            (idempotency, config.max_body_bytes),
+            replay_idempotent,
+        ));
+    }
+    let mut writes = idempotent
+        .route("/get_or_set", post(get_or_set))
+        .route("/tags/:tag", delete(invalidate_tag))
+        .route("/tags/:tag/expire", post(expire_tag))
+        .route("/import", post(import));
+    if config.replication_queue.overflow == OverflowPolicy::Reject {
+        writes = writes.route_layer(middleware::from_fn_with_state(sender, reject_when_full));
+    }
+    let writes = writes.route_layer(middleware::from_fn_with_state(
+        session.clone(),
+        with_session,
+    ));
+    let reads = Router::new()
+        .route("/query", get(query))
+        .route("/range", get(range))
+        .route("/keys/:key", get(get_document))
+        .route("/v1/keys/:key", get(get_key))
+        .route_layer(middleware::from_fn_with_state(session, with_session));
+    let acl = Arc::new(Acl::new(config.acl_tokens.clone()));
+    let data = reads
+        .route("/events", get(stream_events))
+        .route("/export", get(export))
+        .route("/topology", get(get_topology))
+        .merge(writes)
+        .route_layer(middleware::from_fn_with_state(acl.clone(), authenticate))
+        .route("/openapi.json", get(openapi_document))
+        .route("/docs", get(docs))
+        .layer(middleware::from_fn_with_state(
+            topology,
+            with_topology_version,
+        ));
+    let mut admin = Router::new()
+        .route("/debug/slowlog", get(debug_slowlog))
+        .route("/cluster/alerts", get(cluster_alerts))
+        .route("/cluster/auth", get(cluster_auth_stats))
+        .route("/admin/snapshot", post(admin_snapshot))
+        .route("/admin/restore", post(admin_restore))
+        .route("/stats/hotkeys", get(stats_hot_keys))
+        .route("/stats/queues", get(stats_queues))
+        .route("/stats/namespaces", get(stats_namespaces))
+        .route("/admin/chaos", get(admin_chaos).post(set_admin_chaos));
+    if key_stats::ENABLED {
+        admin = admin.route("/stats/keys", get(stats_keys));
+    }
+    let admin = admin
+        .route_layer(middleware::from_fn(require_admin))
+        .route_layer(middleware::from_fn_with_state(acl, authenticate));
+
+    (
+        data.with_state(app_state.clone()),
+        admin.with_state(app_state),
+    )
+}
+
+/// Builds the CORS policy of the server, or `None` when no origin is allowed.
+///
+/// Responses expose the `ETag`, request ID, topology version, session token and staleness
+/// headers, so browser clients can make conditional requests, correlate them with server logs,
+/// route keys to their owners, read their own writes and tell how stale a read may be.
+///
+/// # Errors
+///
+/// Returns an error if an origin, method or header is not valid.
+fn cors_layer(config: &HttpServerConfig) -> Result<Option<CorsLayer>> {
+    if config.cors_origins.is_empty() {
+        return Ok(None);
+    }
+
+    let origins = if config.cors_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            config
+                .cors_origins
+                .iter()
+                .map(|origin| HeaderValue::from_str(origin))
+                .collect::<Result<Vec<_>, _>>()
+                .context("Invalid CORS origin")?,
+        )
+    };
+    let methods = config
+        .cors_methods
+        .iter()
+        .map(|method| Method::from_bytes(method.to_uppercase().as_bytes()))
+        .collect::<Result<Vec<_>, _>>()
+        .context("Invalid CORS method")?;
+    let headers = if config.cors_headers.iter().any(|header| header == "*") {
+        AllowHeaders::any()
+    } else {
+        AllowHeaders::list(
+            config
+                .cors_headers
+                .iter()
+                .map(|header| HeaderName::from_bytes(header.as_bytes()))
+                .collect::<Result<Vec<_>, _>>()
+                .context("Invalid CORS header")?,
+        )
+    };
+
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .expose_headers([
+                ETAG,
+                X_REQUEST_ID,
+                IDEMPOTENT_REPLAYED,
+                X_TOPOLOGY_VERSION,
+                X_SESSION_TOKEN,
+                X_LAST_MODIFIED,
+                X_REPLICA_LAG,
+            ]),
+    ))
+}
+
+/// Wraps routes in the extensions and middleware shared by every listener.
+///
+/// Each call creates its own in-flight limit, so routes served on separate listeners do not
+/// compete for request slots. The CORS layer, if any, is outermost, so preflight requests are
+/// answered without counting against the limits.
+fn with_layers(
+    router: Router,
+    config: &HttpServerConfig,
+    slowlog: &Arc<SlowLog>,
+    audit: &Arc<AuditLog>,
+    cors: Option<CorsLayer>,
+) -> Router {
+    let router = router
+        .layer(Extension(slowlog.clone()))
+        .layer(Extension(audit.clone()))
+        .layer(DefaultBodyLimit::max(config.max_body_bytes))
+        .layer(GlobalConcurrencyLimitLayer::new(config.max_in_flight))
+        .layer(middleware::from_fn_with_state(
+            Arc::new(config.clone()),
+            with_timeout,
+        ))
+        .layer(CompressionLayer::new())
+        .layer(PropagateRequestIdLayer::new(X_REQUEST_ID))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(make_request_span)
+                .on_response(
+                    DefaultOnResponse::new()
+                        .level(Level::INFO)
+                        .latency_unit(LatencyUnit::Millis),
+                ),
+        )
+        .layer(SetRequestIdLayer::new(X_REQUEST_ID, MakeRequestUuid));
+    match cors {
+        Some(cors) => router.layer(cors),
+        None => router,
+    }
+}
+
+/// Binds a listening socket to an address, with the given backlog.
+async fn bind(addr: &str, backlog: u32) -> Result<TcpListener> {
+    let addr = tokio::net::lookup_host(addr)
+        .await?
+        .next()
+        .ok_or_else(|| anyhow!("{} did not resolve to any address", addr))?;
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    socket.set_reuseaddr(true)?;
+    socket.bind(addr)?;
+    Ok(socket.listen(backlog)?)
+}
 
-    tokio::spawn(async move {
-        let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-        axum::serve(listener, app).await.unwrap();
+/// Accepts connections on `listener` and serves `app` on them, forever.
+///
+/// Each connection is served on its own task with the HTTP versions, keep-alive settings and
+/// header read timeout of `config`, and at most `config.max_connections` are open at once. Accept errors, such as
+/// running out of file descriptors, are logged and retried after a short pause.
+async fn serve(listener: TcpListener, app: Router, config: HttpServerConfig) {
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .keep_alive(config.keep_alive_timeout.is_some())
+        .header_read_timeout(config.header_read_timeout);
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .keep_alive_interval(config.keep_alive_timeout);
+    if let Some(keep_alive_timeout) = config.keep_alive_timeout {
+        builder.http2().keep_alive_timeout(keep_alive_timeout);
+    }
+    let builder = Arc::new(match config.http_version {
+        HttpVersion::Auto => builder,
+        HttpVersion::Http1 => builder.http1_only(),
+        HttpVersion::Http2 => builder.http2_only(),
     });
+    let connections = Arc::new(Semaphore::new(config.max_connections.max(1)));
 
-    Ok(receiver)
+    loop {
+        let Ok(permit) = connections.clone().acquire_owned().await else {
+            return;
+        };
+        let (stream, remote) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                tracing::warn!("Failed to accept an HTTP connection: {:?}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+
+        let app = app.clone();
+        let builder = builder.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |mut request: Request<Incoming>| {
+                request.extensions_mut().insert(ConnectInfo(remote));
+                app.clone().call(request)
+            });
+            if let Err(e) = builder
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!("HTTP connection from {} closed: {:?}", remote, e);
+            }
+            drop(permit);
+        });
+    }
+}
+
+/// Creates the span wrapping an HTTP request, carrying its method, URI and request ID.
+///
+/// Handler spans and log lines are nested under it, and the access log line emitted
+/// when the response is sent includes its fields along with the status and latency.
+fn make_request_span(request: &Request<Body>) -> Span {
+    let request_id = request
+        .headers()
+        .get(X_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+
+    info_span!(
+        "http_request",
+        method = %request.method(),
+        uri = %request.uri(),
+        request_id = %request_id,
+    )
 }
 
-/// Holds the application state, which includes a sender for inter-task communication
-/// and the shared cache (`bcache`).
+/// Holds the application state, which includes a sender for inter-task communication,
+/// the shared cache (`bcache`), its tag index, the anomaly detector tracking its mutations,
+/// the directory snapshots are written to, the write-ahead log mutations are appended to,
+/// the limits keys and values are checked against, the access counts of keys, the hot keys
+/// the key events streamed to clients, the counters of the queue of received frames, the
+/// topology of the cluster and the maximum size of a request body, which also bounds the lines
+/// of `/import`.
 ///
 /// This struct is wrapped in an `Arc<Mutex<>>` to ensure safe concurrent access across tasks.
 pub struct AppState {
-    pub sender: Sender<Message>,
+    pub sender: QueueSender<(Message, Replication)>,
     pub bcache: Arc<Mutex<Box<dyn BCache>>>,
+    pub tags: Arc<TagIndex>,
+    pub anomalies: Arc<AnomalyDetector>,
+    pub snapshots: Arc<SnapshotStore>,
+    pub wal: Arc<Wal>,
+    pub limits: Limits,
+    pub key_stats: Arc<KeyStats>,
+    pub hot_keys: Arc<HotKeys>,
+    pub events: KeyEvents,
+    pub chaos: Arc<Chaos>,
+    pub gossip_queue: Arc<QueueCounters>,
+    pub cluster_auth: Arc<ClusterAuth>,
+    pub topology: Arc<Topology>,
+    pub sessions: Arc<Sessions>,
+    pub staleness: Arc<Staleness>,
+    pub quotas: Arc<Quotas>,
+    pub max_body_bytes: usize,
 }
 
 impl AppState {
@@ -68,29 +701,245 @@ impl AppState {
     /// # Arguments
     ///
     /// * `sender` - A sender for communicating between tasks (e.g., for gossip messages).
-    /// * `bcache` - A shared cache instance that implements the `BCache` trait.
+    /// * `deps` - The cache and the other parts of the node shared with the server.
+    /// * `config` - The server configuration, with the snapshot directory, the limits on keys
+    ///   and values, the number of keys whose accesses are counted, the hot-key options and the
+    ///   maximum size of a request body.
     ///
     /// # Returns
     ///
     /// * `Arc<Mutex<AppState>>` - A new wrapped instance of `AppState`.
-    pub fn new(sender: Sender<Message>, bcache: Arc<Mutex<Box<dyn BCache>>>) -> Arc<Mutex<Self>> {
-        Arc::new(Mutex::new(Self { sender, bcache }))
+    pub fn new(
+        sender: QueueSender<(Message, Replication)>,
+        deps: &ServerDeps,
+        config: &HttpServerConfig,
+    ) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            sender,
+            bcache: deps.bcache.clone(),
+            tags: deps.tags.clone(),
+            anomalies: deps.anomalies.clone(),
+            snapshots: Arc::new(SnapshotStore::new(config.snapshot_dir.clone())),
+            wal: deps.wal.clone(),
+            limits: config.limits.clone(),
+            key_stats: Arc::new(KeyStats::new(config.key_stats_capacity)),
+            hot_keys: Arc::new(HotKeys::new(config.hot_keys.clone())),
+            events: deps.events.clone(),
+            chaos: deps.chaos.clone(),
+            gossip_queue: deps.gossip_queue.clone(),
+            cluster_auth: deps.cluster_auth.clone(),
+            topology: deps.topology.clone(),
+            sessions: deps.sessions.clone(),
+            staleness: deps.staleness.clone(),
+            quotas: deps.quotas.clone(),
+            max_body_bytes: config.max_body_bytes,
+        }))
+    }
+
+    /// Appends a mutation to the write-ahead log, then hands it to the gossip task for replication.
+    /// The mutated keys are dropped from the hot-key read cache, and their modification time is
+    /// recorded.
+    ///
+    /// A replicated mutation advances the session position of this node, and is followed by the
+    /// `Checkpoint` carrying it to the same members. Mutations are committed one at a time,
+    /// under the lock of the state, so checkpoints follow every mutation they cover.
+    ///
+    /// The mutation was already applied locally, so it waits for room in the replication queue
+    /// even under the `Reject` overflow policy, whose requests are refused by `reject_when_full`
+    /// before they reach the handler. Under `DropOldest`, the oldest queued mutation is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the mutation cannot be logged or the gossip task has stopped.
+    async fn commit(&self, msg: Message, replication: Replication) -> Result<()> {
+        match msg.cmd {
+            Command::Txn | Command::InvalidateTag | Command::ExpireTag => {
+                self.hot_keys.invalidate_all()
+            }
+            _ => self.hot_keys.invalidate(&msg.key),
+        }
+        self.wal.append(&msg).await?;
+        self.staleness.record(&msg, unix_time_ms());
+        if replication == Replication::LocalOnly {
+            self.sender.send((msg, replication)).await?;
+        } else {
+            self.sender.send((msg, replication.clone())).await?;
+            self.sender
+                .send((self.sessions.advance(), replication))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Returns how stale the data of this node for a key may be.
+    ///
+    /// The owner of the key on the hash ring holds its latest value, assuming clients send the
+    /// writes of each key to its owner. The data of this node is then as stale as the time
+    /// since it last applied a message from the owner; it is fresh on the owner itself, and
+    /// while no member is known.
+    fn staleness_of(&self, key: &str) -> KeyStaleness {
+        let (owner, lag_ms) = match self.topology.owner(key) {
+            Some(owner) if owner != self.topology.name() => {
+                let lag_ms = self.staleness.lag_ms(&owner, unix_time_ms());
+                (Some(owner), lag_ms)
+            }
+            _ => (None, Some(0)),
+        };
+        KeyStaleness {
+            owner,
+            lag_ms,
+            last_modified: self.staleness.last_modified(key),
+        }
     }
 }
 
-/// Represents a standard HTTP response format with a status code, optional data, and a message.
+/// How stale the data of this node for a key may be, reported in the headers of its reads.
+///
+/// # Fields
+///
+/// - `owner`: The owner of the key, unless it is this node.
+/// - `lag_ms`: How far behind the owner this node may be, in milliseconds, or `None` if it
+///   never applied a message from it.
+/// - `last_modified`: When the key was last modified on this node, as a Unix time in milliseconds.
+struct KeyStaleness {
+    owner: Option<String>,
+    lag_ms: Option<u64>,
+    last_modified: Option<u64>,
+}
+
+impl KeyStaleness {
+    /// Returns the `X-Last-Modified` and `X-Replica-Lag` headers of the read.
+    fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(last_modified) = self.last_modified {
+            headers.insert(X_LAST_MODIFIED, HeaderValue::from(last_modified));
+        }
+        if let Some(lag_ms) = self.lag_ms {
+            headers.insert(X_REPLICA_LAG, HeaderValue::from(lag_ms));
+        }
+        headers
+    }
+
+    /// Checks the staleness against the `max_staleness` parameter of a read, such as `500ms`.
+    ///
+    /// # Returns
+    ///
+    /// * The response to answer the read with instead if the parameter is invalid, or the data
+    ///   may be staler than it allows: a `307 Temporary Redirect` to the same request on the
+    ///   owner of the key, or `504 Gateway Timeout` if the address of the owner is not known.
+    fn reject(
+        &self,
+        max_staleness: Option<&str>,
+        topology: &Topology,
+        uri: &Uri,
+        request_id: &Option<String>,
+    ) -> Option<axum::response::Response> {
+        let max_staleness = match parse_duration(max_staleness?) {
+            Ok(max_staleness) => max_staleness,
+            Err(e) => {
+                return Some(error_response(
+                    Error::InvalidRequest(format!("Invalid 'max_staleness' parameter: {}", e)),
+                    request_id,
+                ))
+            }
+        };
+        if self
+            .lag_ms
+            .is_some_and(|lag_ms| u128::from(lag_ms) <= max_staleness.as_millis())
+        {
+            return None;
+        }
+
+        let owner = self.owner.as_deref().unwrap_or_default();
+        let location = topology.http_addr(owner).and_then(|addr| {
+            let path = uri.path_and_query().map_or("/", |path| path.as_str());
+            HeaderValue::from_str(&format!("http://{}{}", addr, path)).ok()
+        });
+        Some(match location {
+            Some(location) => {
+                let mut headers = self.headers();
+                headers.insert(LOCATION, location);
+                (StatusCode::TEMPORARY_REDIRECT, headers).into_response()
+            }
+            None => error_response(
+                Error::Stale(format!(
+                    "The data of this node may be older than {:?}, and the address of {}, the owner of the key, is not known",
+                    max_staleness, owner
+                )),
+                request_id,
+            ),
+        })
+    }
+}
+
+/// Represents a standard HTTP response format with a status code, optional data, a message,
+/// the machine-readable code of the error, if any, and the ID of the request it answers.
+///
+/// The `code` is the HTTP status of the response, and `error` one of the codes of `Error`, so
+/// clients can tell a missing key from a failed backend without parsing `message`.
 #[derive(Serialize)]
 struct Response {
     code: u16,
     data: Option<HashMap<String, String>>,
     message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+/// Builds the response answering a request that failed with `error`: its HTTP status, and a
+/// JSON envelope with its message and code. Limit violations are described in `data`.
+fn error_response(error: Error, request_id: &Option<String>) -> axum::response::Response {
+    let data = match &error {
+        Error::LimitExceeded(violation) => Some(violation.details()),
+        _ => None,
+    };
+    error_response_with_data(error, data, request_id)
+}
+
+/// Builds the response answering a request that failed with `error`, with `data` describing
+/// the failure.
+fn error_response_with_data(
+    error: Error,
+    data: Option<HashMap<String, String>>,
+    request_id: &Option<String>,
+) -> axum::response::Response {
+    let status = error.status();
+    (
+        status,
+        Json(Response {
+            code: status.as_u16(),
+            data,
+            message: error.to_string(),
+            error: Some(error.code()),
+            request_id: request_id.clone(),
+        }),
+    )
+        .into_response()
 }
 
-/// Represents a request to add a key-value pair to the cache.
+/// Builds the response returned when a mutation was applied locally but could not be logged
+/// or handed over for replication.
+fn replication_failed(e: anyhow::Error, request_id: &Option<String>) -> axum::response::Response {
+    error_response(Error::ReplicationFailed(format!("{:#}", e)), request_id)
+}
+
+/// Represents a request to add a key-value pair to the cache, with optional tags that
+/// the entry can later be invalidated by.
 #[derive(Debug, Deserialize, Clone)]
 struct AddRequest {
     key: String,
     value: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// Represents a request to append a suffix to the value of a key.
+#[derive(Debug, Deserialize, Clone)]
+struct AppendRequest {
+    key: String,
+    value: String,
 }
 
 /// Represents a request to remove a key-value pair to the cache.
@@ -99,150 +948,2926 @@ struct RemoveRequest {
     key: String,
 }
 
+/// Represents a request to apply several operations atomically, if every check holds.
+#[derive(Debug, Deserialize, Clone)]
+struct TxnRequest {
+    #[serde(default)]
+    checks: Vec<TxnCheck>,
+    ops: Vec<TxnOp>,
+}
+
+/// A condition on the current value of a key. Every condition that is set must hold.
+#[derive(Debug, Deserialize, Clone)]
+struct TxnCheck {
+    key: String,
+    /// Whether the key must be present (`true`) or absent (`false`).
+    exists: Option<bool>,
+    /// The value the key must hold.
+    value: Option<String>,
+    /// The `ETag` the value of the key must have, as in an `If-Match` header.
+    etag: Option<String>,
+}
+
+impl TxnCheck {
+    /// Returns whether the check holds for the current value of its key.
+    fn holds(&self, current: Option<&str>) -> bool {
+        self.exists.is_none_or(|exists| exists == current.is_some())
+            && self
+                .value
+                .as_deref()
+                .is_none_or(|value| current == Some(value))
+            && self.etag.as_deref().is_none_or(|expected| {
+                current.is_some_and(|current| etag_matches(expected, &etag(current)))
+            })
+    }
+}
+
+/// An operation of a transaction.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum TxnOp {
+    Set {
+        key: String,
+        value: String,
+        #[serde(default)]
+        tags: Vec<String>,
+    },
+    Delete {
+        key: String,
+    },
+}
+
+impl TxnOp {
+    /// Returns the replicated message of the operation.
+    fn message(&self) -> Message {
+        match self {
+            TxnOp::Set { key, value, tags } => {
+                Message::new(Command::Insert, key.clone(), value.clone()).with_tags(tags.clone())
+            }
+            TxnOp::Delete { key } => Message::new(Command::Remove, key.clone(), String::new()),
+        }
+    }
+}
+
+/// Represents a request to write or restore a snapshot.
+#[derive(Debug, Deserialize, Clone, Default)]
+struct SnapshotRequest {
+    /// The file name of the snapshot in the snapshot directory. Snapshots are written to a
+    /// name derived from the current time when it is absent.
+    name: Option<String>,
+}
+
+/// Represents a request to schedule the expiration of a tag.
+#[derive(Debug, Deserialize, Clone)]
+struct ExpireTagRequest {
+    /// The Unix timestamp, in milliseconds, at which the tag expires.
+    expire_at_ms: u64,
+}
+
+/// Returns the request ID assigned to (or supplied with) the request by the request ID middleware.
+fn get_request_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(X_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Query parameters of `/range`.
+#[derive(Debug, Deserialize, Clone)]
+struct RangeParams {
+    /// The first key of the range, inclusive.
+    start: Option<String>,
+    /// The key the range stops before, exclusive.
+    end: Option<String>,
+    /// The maximum number of entries returned, capped at `MAX_RANGE_LIMIT`.
+    limit: Option<usize>,
+}
+
+/// Query parameters of `GET /keys/{key}`.
+#[derive(Debug, Deserialize, Clone)]
+struct DocumentParams {
+    /// The field of the JSON document returned, as a JSONPath or a JSON pointer. The whole
+    /// value is returned when absent.
+    path: Option<String>,
+    /// How stale the document may be, such as `500ms`. Unbounded when absent.
+    max_staleness: Option<String>,
+}
+
+/// Query parameters of `GET /v1/keys/{key}`.
+#[derive(Debug, Deserialize, Clone)]
+struct ReadParams {
+    /// How stale the value may be, such as `500ms`. Unbounded when absent.
+    max_staleness: Option<String>,
+}
+
+/// Query parameters of `/events`.
+#[derive(Debug, Deserialize, Clone)]
+struct EventsParams {
+    /// Only the events of keys starting with this prefix are sent.
+    prefix: Option<String>,
+}
+
+/// An entry of a `/range` response.
+#[derive(Serialize)]
+struct RangeEntry {
+    key: String,
+    value: String,
+}
+
+/// The response of `/range`, whose entries are sorted by key.
+///
+/// `next` is set when the limit was reached, to the `start` of the request for the next page.
+#[derive(Serialize)]
+struct RangeResponse {
+    code: u16,
+    data: Vec<RangeEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next: Option<String>,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+/// Query parameters accepted by the mutating endpoints.
+///
+/// `replicate_to` is either `local-only` or a comma-separated list of node names; when absent
+/// the mutation is replicated to every member. `nx`, honoured by `/add`, only inserts keys that
+/// do not exist yet.
+#[derive(Debug, Deserialize, Clone)]
+struct WriteParams {
+    replicate_to: Option<String>,
+    #[serde(default)]
+    nx: bool,
+}
+
+impl WriteParams {
+    /// Parses the requested replication targets.
+    fn replication(&self) -> Result<Replication> {
+        match &self.replicate_to {
+            Some(replicate_to) => replicate_to.parse(),
+            None => Ok(Replication::All),
+        }
+    }
+}
+
+/// Query parameters of `PUT /v1/keys/{key}`, along with `WriteParams`.
+#[derive(Debug, Deserialize, Clone)]
+struct TagParams {
+    /// The comma-separated tags of the entry.
+    tags: Option<String>,
+}
+
+impl TagParams {
+    /// Returns the tags of the entry, without empty ones.
+    fn tags(&self) -> Vec<String> {
+        self.tags
+            .iter()
+            .flat_map(|tags| tags.split(','))
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+/// Builds the response returned when a `replicate_to` parameter cannot be parsed.
+fn invalid_replication(e: anyhow::Error, request_id: &Option<String>) -> axum::response::Response {
+    error_response(
+        Error::InvalidRequest(format!("Invalid 'replicate_to' parameter: {}", e)),
+        request_id,
+    )
+}
+
 /// Handles HTTP GET requests to query a value from the cache.
 ///
+/// The response carries an `ETag` derived from the value. If the request's `If-None-Match`
+/// header matches it, `304 Not Modified` is returned without a body.
+///
 /// # Arguments
 ///
 /// * `app_states` - The current application state containing the cache.
-/// * `params` - The query parameters containing the key to be looked up.
+/// * `slowlog` - The slow log the request is recorded in if it exceeds the threshold.
+/// * `principal` - The bearer of the request, which must be allowed to read the key.
+/// * `headers` - The request headers, checked for `If-None-Match`.
+/// * `uri` - The URI of the request, which stale reads are redirected to on the owner of the key.
+/// * `params` - The query parameters containing the key to be looked up, and the staleness
+///   allowed.
 ///
 /// # Returns
 ///
-/// * `Json<Response>` - A JSON response containing the key-value pair, or an error message if the key is missing or the query fails.
+/// * A JSON response containing the key-value pair, or `404 Not Found` if the key is missing,
+///   and `502 Bad Gateway` or `504 Gateway Timeout` if the cache backend fails.
+#[tracing::instrument(name = "http_query", skip_all)]
 async fn query(
     State(app_states): State<Arc<Mutex<AppState>>>,
+    Extension(slowlog): Extension<Arc<SlowLog>>,
+    Extension(principal): Extension<Principal>,
+    headers: HeaderMap,
+    uri: Uri,
     params: Query<HashMap<String, String>>,
-) -> Json<Response> {
+) -> impl IntoResponse {
+    let _timer = slowlog.start(SlowLogKind::Http, "/query", params.get("key").cloned());
+    let request_id = get_request_id(&headers);
     let key = if let Some(k) = params.get("key") {
         k
     } else {
-        return Json(Response {
-            code: StatusCode::BAD_REQUEST.as_u16(),
-            data: None,
-            message: "Missing 'key' parameter".to_string(),
-        });
+        return error_response(
+            Error::InvalidRequest("Missing 'key' parameter".to_string()),
+            &request_id,
+        );
     };
+    if let Err(e) = principal.check(Permission::Read, key) {
+        return error_response(e, &request_id);
+    }
 
-    let value = {
-        match app_states
-            .lock()
-            .await
-            .bcache
-            .lock()
-            .await
-            .get(key.clone())
-            .await
-        {
-            Ok(v) => v,
-            Err(_) => {
-                return Json(Response {
-                    code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
-                    data: None,
-                    message: "Failed to retrieve value from cache".to_string(),
-                });
-            }
-        }
+    let read = KeyRead {
+        headers: &headers,
+        uri: &uri,
+        max_staleness: params.get("max_staleness").map(String::as_str),
+        request_id: &request_id,
     };
-
-    let mut data = HashMap::new();
-    data.insert(key.clone(), value);
-
-    Json(Response {
-        code: StatusCode::OK.as_u16(),
-        data: Some(data),
-        message: "ok".to_string(),
-    })
+    read_key(&app_states, key.clone(), read).await
 }
 
-/// Handles HTTP POST requests to add a key-value pair to the cache.
+/// Handles HTTP GET requests for the value of the key in the path, the resource-style
+/// equivalent of `/query`.
 ///
 /// # Arguments
 ///
 /// * `app_states` - The current application state containing the cache.
-/// * `params` - The JSON body containing the key-value pair to be added.
+/// * `slowlog` - The slow log the request is recorded in if it exceeds the threshold.
+/// * `principal` - The bearer of the request, which must be allowed to read the key.
+/// * `headers` - The request headers, checked for `If-None-Match`.
+/// * `uri` - The URI of the request, which stale reads are redirected to on the owner of the key.
+/// * `key` - The key to look up.
+/// * `params` - The query parameters with the staleness allowed.
 ///
 /// # Returns
 ///
-/// * `Json<Response>` - A JSON response indicating the success or failure of the operation.
-async fn add(
+/// * A JSON response containing the key-value pair, as `/query` returns it.
+#[tracing::instrument(name = "http_get_key", skip_all, fields(key = %key))]
+async fn get_key(
     State(app_states): State<Arc<Mutex<AppState>>>,
-    params: Json<AddRequest>,
-) -> Json<Response> {
-    let key = params.key.clone();
-    let value = params.value.clone();
-    let app_states = app_states.lock().await;
-
-    app_states
-        .bcache
-        .lock()
-        .await
-        .insert(key.clone(), value.clone())
-        .await;
-    if let Err(e) = app_states
-        .sender
-        .send(Message {
-            cmd: Command::Insert,
-            key: key.clone(),
-            value: value.clone(),
-        })
-        .await
-    {
-        tracing::error!("Failed to send insert message: {:?}", e);
-        return Json(Response {
-            code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
-            data: None,
-            message: "Failed to process add request".to_string(),
-        });
+    Extension(slowlog): Extension<Arc<SlowLog>>,
+    Extension(principal): Extension<Principal>,
+    headers: HeaderMap,
+    uri: Uri,
+    Path(key): Path<String>,
+    params: Query<ReadParams>,
+) -> impl IntoResponse {
+    let _timer = slowlog.start(SlowLogKind::Http, "/v1/keys", Some(key.clone()));
+    let request_id = get_request_id(&headers);
+    if let Err(e) = principal.check(Permission::Read, &key) {
+        return error_response(e, &request_id);
     }
+    let read = KeyRead {
+        headers: &headers,
+        uri: &uri,
+        max_staleness: params.max_staleness.as_deref(),
+        request_id: &request_id,
+    };
+    read_key(&app_states, key, read).await
+}
 
-    let mut data = HashMap::new();
-    data.insert(params.key.clone(), params.value.clone());
-
-    Json(Response {
-        code: StatusCode::OK.as_u16(),
-        data: Some(data),
-        message: "ok".to_string(),
-    })
+/// What a read of a key was asked with, besides the key.
+///
+/// # Fields
+///
+/// - `headers`: The request headers, checked for `If-None-Match`.
+/// - `uri`: The URI of the request, which stale reads are redirected to on the owner of the key.
+/// - `max_staleness`: How stale the value may be, such as `500ms`, if the read bounds it.
+/// - `request_id`: The ID of the request, echoed in the response.
+struct KeyRead<'a> {
+    headers: &'a HeaderMap,
+    uri: &'a Uri,
+    max_staleness: Option<&'a str>,
+    request_id: &'a Option<String>,
 }
 
-/// Handles HTTP DELETE requests to remove a key from the cache.
+/// Reads the value of a key for `/query` and `GET /v1/keys/{key}`, from the hot-key read cache
+/// if the key is hot and cached, and counts the read.
+///
+/// The response carries an `ETag` derived from the value. If the request's `If-None-Match`
+/// header matches it, `304 Not Modified` is returned without a body. It also carries how stale
+/// the value may be, and is redirected to the owner of the key if that exceeds `max_staleness`.
 ///
 /// # Arguments
 ///
-/// * `app_states` - The current application state containing the cache.
-/// * `params` - The JSON body containing the key to be removed.
+/// * `app_states` - The application state containing the cache.
+/// * `key` - The key to look up.
+/// * `read` - The headers, URI, staleness bound and ID of the request.
 ///
 /// # Returns
 ///
-/// * `Json<Response>` - A JSON response indicating the success or failure of the operation.
-async fn remove(
-    State(app_states): State<Arc<Mutex<AppState>>>,
-    params: Json<RemoveRequest>,
-) -> Json<Response> {
-    let app_states = app_states.lock().await;
-    let key = params.key.clone();
+/// * A JSON response containing the key-value pair, or `404 Not Found` if the key is missing,
+///   `307 Temporary Redirect` or `504 Gateway Timeout` if it may be staler than allowed, and
+///   `502 Bad Gateway` or `504 Gateway Timeout` if the cache backend fails.
+async fn read_key(
+    app_states: &Mutex<AppState>,
+    key: String,
+    read: KeyRead<'_>,
+) -> axum::response::Response {
+    let KeyRead {
+        headers,
+        uri,
+        max_staleness,
+        request_id,
+    } = read;
+    let (value, staleness) = {
+        let app_states = app_states.lock().await;
+        let staleness = app_states.staleness_of(&key);
+        if let Some(response) =
+            staleness.reject(max_staleness, &app_states.topology, uri, request_id)
+        {
+            return response;
+        }
+        app_states.key_stats.record_read(&key);
+        let hot = app_states.hot_keys.record_read(&key);
+        let cached = if hot {
+            app_states.hot_keys.cached(&key)
+        } else {
+            None
+        };
+        let value = match cached {
+            Some(v) => Ok(v),
+            None => app_states.bcache.lock().await.get(key.clone()).await,
+        };
+        match value {
+            Ok(v) => {
+                if hot {
+                    app_states.hot_keys.cache(&key, &v);
+                }
+                (v, staleness)
+            }
+            Err(e) => return error_response(Error::classify(e), request_id),
+        }
+    };
 
-    app_states.bcache.lock().await.remove(key.clone()).await;
+    let etag = etag(&value);
+    if let Some(if_none_match) = headers.get(IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+        if etag_matches(if_none_match, &etag) {
+            return (
+                StatusCode::NOT_MODIFIED,
+                staleness.headers(),
+                [(ETAG, etag)],
+            )
+                .into_response();
+        }
+    }
+
+    let mut data = HashMap::new();
+    data.insert(key, value);
+
+    (
+        staleness.headers(),
+        [(ETAG, etag)],
+        Json(Response {
+            code: StatusCode::OK.as_u16(),
+            data: Some(data),
+            message: "ok".to_string(),
+            error: None,
+            request_id: request_id.clone(),
+        }),
+    )
+        .into_response()
+}
+
+/// Handles HTTP GET requests that subscribe to key events, streamed as server-sent events.
+///
+/// Each event is named after its kind, such as `expired`, and carries a JSON object with the
+/// key, the time of the event and, for expirations, their cause. Only events published after
+/// the subscription are sent. A client falling too far behind receives a `lagged` event with
+/// the number of events it missed.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the key events.
+/// * `principal` - The bearer of the request, which must be allowed to read every key starting
+///   with the prefix.
+/// * `headers` - The request headers, carrying the request ID.
+/// * `params` - The query parameters with the prefix of the keys whose events are sent.
+///
+/// # Returns
+///
+/// * A stream of server-sent events, kept alive with comments while no event is sent.
+async fn stream_events(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+    Extension(principal): Extension<Principal>,
+    headers: HeaderMap,
+    params: Query<EventsParams>,
+) -> impl IntoResponse {
+    let prefix = params.prefix.clone().unwrap_or_default();
+    if let Err(e) = principal.check(Permission::Read, &prefix) {
+        return error_response(e, &get_request_id(&headers));
+    }
+    let receiver = app_states.lock().await.events.subscribe();
+
+    let stream = futures::stream::unfold(receiver, move |mut receiver| {
+        let prefix = prefix.clone();
+        async move {
+            loop {
+                let event = match receiver.recv().await {
+                    Ok(event) if !event.key.starts_with(&prefix) => continue,
+                    Ok(event) => Event::default().event(event.name()).json_data(&event),
+                    Err(RecvError::Lagged(missed)) => {
+                        Ok(Event::default().event("lagged").data(missed.to_string()))
+                    }
+                    Err(RecvError::Closed) => return None,
+                };
+                return Some((event, receiver));
+            }
+        }
+    });
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Handles HTTP GET requests for the entries whose keys are in a range, in lexicographic order.
+///
+/// Only ordered backends, such as `sled`, support range queries; the others answer with
+/// `501 Not Implemented`. Results are paged: when `limit` entries are returned, `next` holds
+/// the `start` of the following page.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the cache.
+/// * `slowlog` - The slow log the request is recorded in if it exceeds the threshold.
+/// * `principal` - The bearer of the request, which must be allowed to read every key of the range.
+/// * `headers` - The request headers, carrying the request ID.
+/// * `params` - The query parameters with the bounds of the range and the page size.
+///
+/// # Returns
+///
+/// * A JSON response with the entries of the range, sorted by key.
+async fn range(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+    Extension(slowlog): Extension<Arc<SlowLog>>,
+    Extension(principal): Extension<Principal>,
+    headers: HeaderMap,
+    params: Query<RangeParams>,
+) -> impl IntoResponse {
+    let _timer = slowlog.start(SlowLogKind::Http, "/range", params.start.clone());
+    let request_id = get_request_id(&headers);
+    if let Err(e) = principal.check_range(
+        Permission::Read,
+        params.start.as_deref(),
+        params.end.as_deref(),
+    ) {
+        return error_response(e, &request_id);
+    }
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_RANGE_LIMIT)
+        .clamp(1, MAX_RANGE_LIMIT);
+
+    let entries = app_states
+        .lock()
+        .await
+        .bcache
+        .lock()
+        .await
+        .range(params.start.clone(), params.end.clone(), limit)
+        .await;
+    let entries = match entries {
+        Ok(entries) => entries,
+        Err(e) => return error_response(Error::classify(e), &request_id),
+    };
+
+    // The smallest key after the last one returned.
+    let next = (entries.len() == limit)
+        .then(|| entries.last().map(|(key, _)| format!("{}\0", key)))
+        .flatten();
+    Json(RangeResponse {
+        code: StatusCode::OK.as_u16(),
+        data: entries
+            .into_iter()
+            .map(|(key, value)| RangeEntry { key, value })
+            .collect(),
+        next,
+        message: "ok".to_string(),
+        request_id,
+    })
+    .into_response()
+}
+
+/// Handles HTTP GET requests that stream every entry of this node, with its tags, as
+/// newline-delimited JSON, in the format `/import` accepts.
+///
+/// Entries are read from the cache a page at a time, as the client consumes the response, so
+/// exporting a large keyspace does not buffer it on the node.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the cache and its tag index.
+/// * `principal` - The bearer of the request, which must be allowed to read every key.
+/// * `headers` - The request headers, carrying the request ID.
+///
+/// # Returns
+///
+/// * An `application/x-ndjson` response with one `{"key", "value", "tags"}` object per line.
+async fn export(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+    Extension(principal): Extension<Principal>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    if let Err(e) = principal.check(Permission::Read, "") {
+        return error_response(e, &get_request_id(&headers));
+    }
+    let (bcache, tags) = {
+        let app_state = app_states.lock().await;
+        (app_state.bcache.clone(), app_state.tags.clone())
+    };
+    (
+        [(CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(ndjson::export(bcache, tags)),
+    )
+        .into_response()
+}
+
+/// Handles HTTP POST requests that write the entries of a newline-delimited JSON body, in the
+/// format of `/export`, to the cache.
+///
+/// The body is read as it arrives, and its entries are applied in batches of `IMPORT_BATCH`:
+/// each entry is written with its tags, recorded in the audit log and replicated like a
+/// regular write. Lines are bounded by the maximum body size instead of the whole body.
+/// An invalid line stops the import; the batches applied before it stay written, and the number
+/// of their entries is returned in `data`.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the cache and its tag index.
+/// * `ctx` - The context of the request: its bearer, which must be allowed to write every imported
+///   key.
+/// * `params` - The query parameters selecting the replication targets.
+/// * `body` - The newline-delimited JSON entries.
+///
+/// # Returns
+///
+/// * A JSON response with the number of entries imported, or the error of the first invalid
+///   line.
+#[tracing::instrument(name = "http_import", skip_all)]
+async fn import(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+    ctx: RequestContext,
+    params: Query<WriteParams>,
+    body: Body,
+) -> impl IntoResponse {
+    let RequestContext {
+        slowlog,
+        principal,
+        audit,
+        client,
+        headers,
+    } = ctx;
+    let _timer = slowlog.start(SlowLogKind::Http, "/import", None);
+    let request_id = get_request_id(&headers);
+    let replication = match params.replication() {
+        Ok(replication) => replication,
+        Err(e) => return invalid_replication(e, &request_id),
+    };
+    let (limits, max_line_bytes) = {
+        let app_state = app_states.lock().await;
+        (app_state.limits.clone(), app_state.max_body_bytes)
+    };
+
+    let mut decoder = NdjsonDecoder::new(max_line_bytes);
+    let mut body = body.into_data_stream();
+    let mut batch = Vec::with_capacity(IMPORT_BATCH);
+    let mut imported = 0;
+    let mut done = false;
+    while !done {
+        let entries = match body.next().await {
+            Some(Ok(chunk)) => decoder.feed(&chunk),
+            Some(Err(e)) => Err(Error::InvalidRequest(format!(
+                "Failed to read the body: {}",
+                e
+            ))),
+            None => {
+                done = true;
+                decoder.finish().map(Vec::from_iter)
+            }
+        };
+        let entries = match entries {
+            Ok(entries) => entries,
+            Err(e) => return import_failed(e, imported, &request_id),
+        };
+        for entry in entries {
+            if let Err(violation) = limits.check_entry(&entry.key, &entry.value) {
+                return import_failed(Error::LimitExceeded(violation), imported, &request_id);
+            }
+            if let Err(e) = principal.check(Permission::Write, &entry.key) {
+                return import_failed(e, imported, &request_id);
+            }
+            batch.push(entry);
+        }
+        if batch.len() >= IMPORT_BATCH || (done && !batch.is_empty()) {
+            let entries = std::mem::replace(&mut batch, Vec::with_capacity(IMPORT_BATCH));
+            let count = entries.len();
+            let app_state = app_states.lock().await;
+            let written: Vec<(&str, Option<&str>)> = entries
+                .iter()
+                .map(|entry| (entry.key.as_str(), Some(entry.value.as_str())))
+                .collect();
+            let quota = app_state
+                .quotas
+                .check(&mut *app_state.bcache.lock().await, &written)
+                .await;
+            if let Err(e) = quota {
+                return import_failed(e, imported, &request_id);
+            }
+            if let Err(e) = import_batch(&app_state, &audit, client, entries, &replication).await {
+                tracing::error!("Failed to send insert message: {:?}", e);
+                return replication_failed(e, &request_id);
+            }
+            imported += count;
+        }
+    }
+
+    let mut data = HashMap::new();
+    data.insert("imported".to_string(), imported.to_string());
+    Json(Response {
+        code: StatusCode::OK.as_u16(),
+        data: Some(data),
+        message: "ok".to_string(),
+        error: None,
+        request_id,
+    })
+    .into_response()
+}
+
+/// Writes a batch of imported or restored entries, with their tags, to the cache under a single lock, then
+/// records them in the audit log and hands them over for replication.
+///
+/// # Errors
+///
+/// Returns an error if a mutation cannot be logged or the gossip task has stopped.
+async fn import_batch(
+    app_state: &AppState,
+    audit: &AuditLog,
+    client: SocketAddr,
+    entries: Vec<SnapshotEntry>,
+    replication: &Replication,
+) -> Result<()> {
+    {
+        let mut bcache = app_state.bcache.lock().await;
+        for entry in &entries {
+            bcache.insert(entry.key.clone(), entry.value.clone()).await;
+            app_state.tags.set_tags(&entry.key, &entry.tags);
+        }
+    }
+    for entry in entries {
+        audit
+            .record(
+                AuditOperation::Insert,
+                &entry.key,
+                Some(&entry.value),
+                AuditOrigin::Http(client),
+            )
+            .await;
+        let msg = Message::new(Command::Insert, entry.key, entry.value).with_tags(entry.tags);
+        app_state.commit(msg, replication.clone()).await?;
+    }
+    Ok(())
+}
+
+/// Builds the response returned when an import stops at an invalid line, with the number of
+/// entries imported before it.
+fn import_failed(
+    error: Error,
+    imported: usize,
+    request_id: &Option<String>,
+) -> axum::response::Response {
+    let mut data = match &error {
+        Error::LimitExceeded(violation) => violation.details(),
+        _ => HashMap::new(),
+    };
+    data.insert("imported".to_string(), imported.to_string());
+    error_response_with_data(error, Some(data), request_id)
+}
+
+/// Checks the `If-Match` precondition of a mutation against the current value of `key`.
+///
+/// Requests without an `If-Match` header always pass. Otherwise the key must exist and
+/// its current `ETag` must be listed in the header (or the header must be `*`).
+///
+/// # Arguments
+///
+/// * `headers` - The request headers.
+/// * `bcache` - The locked cache holding the current value.
+/// * `key` - The key the mutation applies to.
+///
+/// # Errors
+///
+/// Returns `PreconditionFailed` if the mutation may not proceed, or the error of the cache if
+/// the current value cannot be read.
+async fn check_if_match(
+    headers: &HeaderMap,
+    bcache: &mut Box<dyn BCache>,
+    key: &str,
+) -> Result<(), Error> {
+    let Some(if_match) = headers.get(IF_MATCH) else {
+        return Ok(());
+    };
+    let changed = || Error::PreconditionFailed("value has changed".to_string());
+    let Ok(if_match) = if_match.to_str() else {
+        return Err(changed());
+    };
+
+    match error::optional(bcache.get(key.to_string()).await).map_err(Error::classify)? {
+        Some(current) if etag_matches(if_match, &etag(&current)) => Ok(()),
+        _ => Err(changed()),
+    }
+}
+
+/// Builds the response returned when a key or value exceeds the configured limits.
+///
+/// Size violations are answered with `413 Payload Too Large` and disallowed keys with
+/// `422 Unprocessable Entity`. The `data` of the response describes the violation.
+fn limit_exceeded(violation: Violation, request_id: &Option<String>) -> axum::response::Response {
+    error_response(Error::LimitExceeded(violation), request_id)
+}
+
+/// Refuses writes with `503 Service Unavailable` and a `Retry-After` header while the
+/// replication queue is full, under the `Reject` overflow policy, before they are applied.
+async fn reject_when_full(
+    State(sender): State<QueueSender<(Message, Replication)>>,
+    request: Request<Body>,
+    next: Next,
+) -> axum::response::Response {
+    if sender.admit() {
+        return next.run(request).await;
+    }
+    let mut response = error_response(
+        Error::Unavailable("The replication queue is full, retry later".to_string()),
+        &get_request_id(request.headers()),
+    );
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from_static("1"));
+    response
+}
+
+/// Answers requests that do not produce their response headers within the timeout of their
+/// route with `503 Service Unavailable`.
+///
+/// The request runs in its own task, which is not cancelled when it times out: a handler
+/// dropped between applying a write and committing it would leave the write applied and
+/// logged on this node, but never replicated.
+async fn with_timeout(
+    State(config): State<Arc<HttpServerConfig>>,
+    request: Request<Body>,
+    next: Next,
+) -> axum::response::Response {
+    let timeout = config.request_timeout_for(request.uri().path());
+    let request_id = get_request_id(request.headers());
+    let handler = tokio::spawn(next.run(request));
+    match tokio::time::timeout(timeout, handler).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => error_response(
+            Error::Internal(format!("The request handler failed: {}", e)),
+            &request_id,
+        ),
+        Err(_) => error_response(
+            Error::Unavailable(format!(
+                "The request did not complete within {:?}; it keeps running, so a write it makes is still applied and replicated",
+                timeout
+            )),
+            &request_id,
+        ),
+    }
+}
+
+/// Sets the `X-Topology-Version` header on responses, so clients routing keys to their owners
+/// refresh `/topology` when the cluster changes.
+async fn with_topology_version(
+    State(topology): State<Arc<Topology>>,
+    request: Request<Body>,
+    next: Next,
+) -> axum::response::Response {
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .insert(X_TOPOLOGY_VERSION, HeaderValue::from(topology.version()));
+    response
+}
+
+/// The parts of a request that the handlers of writes share, extracted together.
+///
+/// # Fields
+///
+/// - `slowlog`: The slow log the request is recorded in if it exceeds the threshold.
+/// - `principal`: The bearer of the request, set by `authenticate`, which handlers check against
+///   the keys they access.
+/// - `audit`: The audit log the mutations of the request are recorded in.
+/// - `client`: The address of the HTTP client, recorded as the origin of the mutations.
+/// - `headers`: The request headers, carrying the request ID and the preconditions of the request.
+struct RequestContext {
+    slowlog: Arc<SlowLog>,
+    principal: Principal,
+    audit: Arc<AuditLog>,
+    client: SocketAddr,
+    headers: HeaderMap,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RequestContext {
+    type Rejection = axum::response::Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Extension(slowlog) = Extension::<Arc<SlowLog>>::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let Extension(principal) = Extension::<Principal>::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let Extension(audit) = Extension::<Arc<AuditLog>>::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let ConnectInfo(client) = ConnectInfo::<SocketAddr>::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        Ok(Self {
+            slowlog,
+            principal,
+            audit,
+            client,
+            headers: parts.headers.clone(),
+        })
+    }
+}
+
+/// Authenticates requests by their `Authorization: Bearer <token>` header, and passes the
+/// `Principal` of the token to the handlers, which check it against the keys they access.
+///
+/// Requests without a known token are answered with `401 Unauthorized` while access control is
+/// enabled; otherwise every request gets an unrestricted principal.
+async fn authenticate(
+    State(acl): State<Arc<Acl>>,
+    mut request: Request<Body>,
+    next: Next,
+) -> axum::response::Response {
+    let authorization = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok());
+    match acl.authenticate(authorization) {
+        Ok(principal) => {
+            request.extensions_mut().insert(principal);
+            next.run(request).await
+        }
+        Err(e) => {
+            let mut response = error_response(e, &get_request_id(request.headers()));
+            response
+                .headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            response
+        }
+    }
+}
+
+/// Answers requests to the operational routes with `403 Forbidden` unless their token has an
+/// admin grant on every key. Runs after `authenticate`.
+async fn require_admin(
+    Extension(principal): Extension<Principal>,
+    request: Request<Body>,
+    next: Next,
+) -> axum::response::Response {
+    match principal.check(Permission::Admin, "") {
+        Ok(()) => next.run(request).await,
+        Err(e) => error_response(e, &get_request_id(request.headers())),
+    }
+}
+
+/// What `with_session` needs to serve the sessions of clients.
+///
+/// # Fields
+///
+/// - `sessions`: The positions of the writes accepted and applied by this node.
+/// - `topology`: Where the addresses of the nodes reads are proxied to are looked up.
+/// - `client`: The client reads are proxied with.
+/// - `wait`: How long a read waits for this node to catch up before it is proxied.
+#[derive(Clone)]
+struct SessionState {
+    sessions: Arc<Sessions>,
+    topology: Arc<Topology>,
+    client: reqwest::Client,
+    wait: Duration,
+}
+
+/// Gives clients read-your-writes consistency through the `X-Session-Token` header.
+///
+/// Successful writes answer with the token the request carried, merged with the position of
+/// this node. A read carrying a token waits until this node applied the writes it covers; if
+/// it has not after `SessionState::wait`, the read is proxied to the first node it is behind
+/// on, which accepted those writes. A read that was already proxied, or that cannot be, is
+/// answered with `503 Service Unavailable`.
+///
+/// Only writes replicated to this node are covered: a read of a node a write was not
+/// replicated to does not see it, even once it caught up.
+async fn with_session(
+    State(state): State<SessionState>,
+    request: Request<Body>,
+    next: Next,
+) -> axum::response::Response {
+    let request_id = get_request_id(request.headers());
+    let token = match request.headers().get(X_SESSION_TOKEN).map(|header| {
+        header
+            .to_str()
+            .map_err(|e| Error::InvalidRequest(format!("Invalid session token: {}", e)))
+            .and_then(SessionToken::parse)
+    }) {
+        Some(Ok(token)) => token,
+        Some(Err(e)) => return error_response(e, &request_id),
+        None => SessionToken::default(),
+    };
+
+    let read = request.method() == Method::GET;
+    if read && !token.is_empty() {
+        let behind = state.sessions.wait(&token, state.wait).await;
+        if let Some(origin) = behind.first() {
+            if request.headers().contains_key(X_SESSION_PROXIED) {
+                return error_response(
+                    Error::Unavailable(format!(
+                        "This node has not caught up with the writes of {} yet, retry later",
+                        origin
+                    )),
+                    &request_id,
+                );
+            }
+            return match proxy_read(&state, origin, request).await {
+                Ok(response) => response,
+                Err(e) => error_response(e, &request_id),
+            };
+        }
+    }
+
+    let mut token = token;
+    let mut response = next.run(request).await;
+    if !read && response.status().is_success() {
+        token.merge(&state.sessions.token());
+    }
+    if !token.is_empty() {
+        if let Ok(value) = HeaderValue::from_str(&token.to_string()) {
+            response.headers_mut().insert(X_SESSION_TOKEN, value);
+        }
+    }
+    response
+}
+
+/// Proxies a read to the node that accepted the writes of a session this node has not applied
+/// yet, marking it with the `X-Session-Proxied` header.
+///
+/// # Errors
+///
+/// Returns an `Unavailable` error if the HTTP address of the node is not known, or it cannot
+/// be reached.
+async fn proxy_read(
+    state: &SessionState,
+    origin: &str,
+    request: Request<Body>,
+) -> Result<axum::response::Response, Error> {
+    let behind = || {
+        format!(
+            "This node has not caught up with the writes of {} yet",
+            origin
+        )
+    };
+    let addr = state.topology.http_addr(origin).ok_or_else(|| {
+        Error::Unavailable(format!(
+            "{}, and its address is not known, retry later",
+            behind()
+        ))
+    })?;
+    let path = request
+        .uri()
+        .path_and_query()
+        .map_or("/", |path| path.as_str());
+    let mut headers = request.headers().clone();
+    headers.remove(HOST);
+    headers.insert(
+        X_SESSION_PROXIED,
+        HeaderValue::from_str(state.sessions.name()).unwrap_or(HeaderValue::from_static("1")),
+    );
+
+    let upstream = state
+        .client
+        .get(format!("http://{}{}", addr, path))
+        .headers(headers)
+        .send()
+        .await
+        .map_err(|e| {
+            Error::Unavailable(format!("{}, and it cannot be reached: {}", behind(), e))
+        })?;
+    let status = upstream.status();
+    let mut headers = upstream.headers().clone();
+    for header in [CONNECTION, CONTENT_LENGTH, TRANSFER_ENCODING] {
+        headers.remove(header);
+    }
+    let body = upstream
+        .bytes()
+        .await
+        .map_err(|e| Error::Unavailable(format!("{}, and it failed to answer: {}", behind(), e)))?;
+    let mut response = (status, body).into_response();
+    response.headers_mut().extend(headers);
+    Ok(response)
+}
+
+/// Answers retries of a request carrying an `Idempotency-Key` header with the stored response.
+///
+/// Keys are scoped to the method and path of the request, and to its bearer token, so a token
+/// never gets the response stored for another one. The first request with a key runs the
+/// handler and its response is stored, unless it is a server error, which may succeed when
+/// retried. Retries get the stored response with an `Idempotent-Replayed: true` header, or
+/// `409 Conflict` while the first request is still being applied. A key reused with another
+/// query or body is refused with `422 Unprocessable Entity`.
+///
+/// The body is buffered to be fingerprinted, so bodies over `max_body_bytes` are refused with
+/// `413 Payload Too Large` here.
+async fn replay_idempotent(
+    State((store, max_body_bytes)): State<(Arc<IdempotencyStore>, usize)>,
+    request: Request<Body>,
+    next: Next,
+) -> axum::response::Response {
+    let Some(key) = request
+        .headers()
+        .get(IDEMPOTENCY_KEY)
+        .and_then(|key| key.to_str().ok())
+    else {
+        return next.run(request).await;
+    };
+    let token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .map_or(&[][..], HeaderValue::as_bytes);
+    let key = format!(
+        "{} {} {} {}",
+        request.method(),
+        request.uri().path(),
+        to_hex(digest::digest(&digest::SHA256, token).as_ref()),
+        key
+    );
+
+    let (parts, body) = request.into_parts();
+    let body = match axum::body::to_bytes(body, max_body_bytes).await {
+        Ok(body) => body,
+        Err(_) => return StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+    };
+    let mut fingerprint = digest::Context::new(&digest::SHA256);
+    fingerprint.update(parts.uri.query().unwrap_or_default().as_bytes());
+    fingerprint.update(b"\n");
+    fingerprint.update(&body);
+    let request = Request::from_parts(parts, Body::from(body));
+
+    match store.begin(&key, fingerprint.finish().as_ref()) {
+        Begin::Started => {}
+        Begin::Mismatch => {
+            return error_response(
+                Error::Unprocessable(
+                    "The Idempotency-Key was already used with another request".to_string(),
+                ),
+                &get_request_id(request.headers()),
+            );
+        }
+        Begin::InFlight => {
+            return error_response(
+                Error::Conflict("A request with this Idempotency-Key is in progress".to_string()),
+                &get_request_id(request.headers()),
+            );
+        }
+        Begin::Completed(stored) => {
+            let mut response = stored.into_response();
+            response
+                .headers_mut()
+                .insert(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+            return response;
+        }
+    }
+
+    let guard = InFlightGuard::new(store, key);
+    let (parts, body) = next.run(request).await.into_parts();
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!("Failed to buffer idempotent response: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    if !parts.status.is_server_error() {
+        guard.complete(StoredResponse {
+            status: parts.status,
+            headers: parts.headers.clone(),
+            body: body.clone(),
+        });
+    }
+    axum::response::Response::from_parts(parts, Body::from(body))
+}
+
+/// Formats bytes as lowercase hexadecimal.
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Handles HTTP POST requests to add a key-value pair to the cache.
+///
+/// If the request carries an `If-Match` header, the value is only replaced when the current
+/// `ETag` matches; otherwise `412 Precondition Failed` is returned. Entries exceeding the
+/// configured limits are rejected with `413 Payload Too Large` or `422 Unprocessable Entity`.
+///
+/// With `nx=true`, the value is only inserted if the key does not exist, and `409 Conflict` is
+/// returned otherwise. The insert is replicated as an `InsertIfAbsent` message, so replicas
+/// that already hold the key keep their value too, which makes it usable to acquire locks.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the cache.
+/// * `ctx` - The context of the request: its bearer, which must be allowed to write the key, and
+///   its headers, checked for `If-Match`.
+/// * `write_params` - The query parameters selecting the replication targets, and `nx`.
+/// * `params` - The JSON body containing the key-value pair to be added, and its tags.
+///
+/// # Returns
+///
+/// * A JSON response indicating the success or failure of the operation, with the `ETag` of the new value.
+#[tracing::instrument(name = "http_add", skip_all, fields(key = %params.key))]
+async fn add(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+    ctx: RequestContext,
+    write_params: Query<WriteParams>,
+    params: Json<AddRequest>,
+) -> impl IntoResponse {
+    let RequestContext {
+        slowlog,
+        principal,
+        audit,
+        client,
+        headers,
+    } = ctx;
+    let _timer = slowlog.start(SlowLogKind::Http, "/add", Some(params.key.clone()));
+    let request_id = get_request_id(&headers);
+    if let Err(e) = principal.check(Permission::Write, &params.key) {
+        return error_response(e, &request_id);
+    }
+    let app_states = app_states.lock().await;
+    write_key(
+        &app_states,
+        &audit,
+        client,
+        &headers,
+        &write_params,
+        params.0,
+        &request_id,
+    )
+    .await
+}
+
+/// Handles HTTP PUT requests that set the value of the key in the path to the body of the
+/// request, the resource-style equivalent of `/add`.
+///
+/// Tags are passed as a comma-separated `tags` query parameter, along with `replicate_to` and
+/// `nx`. The body must be UTF-8.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the cache.
+/// * `ctx` - The context of the request: its bearer, which must be allowed to write the key, and
+///   its headers, checked for `If-Match`.
+/// * `key` - The key to set.
+/// * `write_params` - The query parameters selecting the replication targets, and `nx`.
+/// * `tag_params` - The query parameters with the tags of the entry.
+/// * `body` - The value.
+///
+/// # Returns
+///
+/// * A JSON response as `/add` returns it, with the `ETag` of the new value.
+#[tracing::instrument(name = "http_put_key", skip_all, fields(key = %key))]
+async fn put_key(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+    ctx: RequestContext,
+    Path(key): Path<String>,
+    write_params: Query<WriteParams>,
+    tag_params: Query<TagParams>,
+    body: Bytes,
+) -> impl IntoResponse {
+    let RequestContext {
+        slowlog,
+        principal,
+        audit,
+        client,
+        headers,
+    } = ctx;
+    let _timer = slowlog.start(SlowLogKind::Http, "/v1/keys", Some(key.clone()));
+    let request_id = get_request_id(&headers);
+    if let Err(e) = principal.check(Permission::Write, &key) {
+        return error_response(e, &request_id);
+    }
+    let Ok(value) = String::from_utf8(body.to_vec()) else {
+        return error_response(
+            Error::InvalidRequest("The value must be UTF-8".to_string()),
+            &request_id,
+        );
+    };
+    let entry = AddRequest {
+        key,
+        value,
+        tags: tag_params.tags(),
+    };
+    let app_states = app_states.lock().await;
+    write_key(
+        &app_states,
+        &audit,
+        client,
+        &headers,
+        &write_params,
+        entry,
+        &request_id,
+    )
+    .await
+}
+
+/// Writes an entry for `/add` and `PUT /v1/keys/{key}`, then replicates it.
+///
+/// If the request carries an `If-Match` header, the value is only replaced when the current
+/// `ETag` matches. With `nx`, the value is only inserted if the key does not exist.
+///
+/// # Arguments
+///
+/// * `app_states` - The locked application state containing the cache.
+/// * `audit` - The audit log the mutation is recorded in.
+/// * `client` - The address of the HTTP client, recorded as the origin of the mutation.
+/// * `headers` - The request headers, checked for `If-Match`.
+/// * `write_params` - The query parameters selecting the replication targets, and `nx`.
+/// * `entry` - The key, value and tags to write.
+/// * `request_id` - The ID of the request, echoed in the response.
+///
+/// # Returns
+///
+/// * A JSON response containing the key-value pair, with the `ETag` of the new value.
+async fn write_key(
+    app_states: &AppState,
+    audit: &AuditLog,
+    client: SocketAddr,
+    headers: &HeaderMap,
+    write_params: &WriteParams,
+    entry: AddRequest,
+    request_id: &Option<String>,
+) -> axum::response::Response {
+    let AddRequest { key, value, tags } = entry;
+    let replication = match write_params.replication() {
+        Ok(replication) => replication,
+        Err(e) => return invalid_replication(e, request_id),
+    };
+    if let Err(violation) = app_states.limits.check_entry(&key, &value) {
+        return limit_exceeded(violation, request_id);
+    }
+    app_states.key_stats.record_write(&key);
+
+    {
+        let mut bcache = app_states.bcache.lock().await;
+        if let Err(e) = check_if_match(headers, &mut bcache, &key).await {
+            return error_response(e, request_id);
+        }
+        if let Err(e) = app_states
+            .quotas
+            .check(&mut bcache, &[(&key, Some(&value))])
+            .await
+        {
+            return error_response(e, request_id);
+        }
+        if write_params.nx {
+            match error::optional(bcache.get(key.clone()).await) {
+                Ok(None) => {}
+                Ok(Some(_)) => {
+                    return error_response(
+                        Error::Conflict(format!("Key {} already exists", key)),
+                        request_id,
+                    )
+                }
+                Err(e) => return error_response(Error::classify(e), request_id),
+            }
+        }
+        bcache.insert(key.clone(), value.clone()).await;
+        app_states.tags.set_tags(&key, &tags);
+    }
+    app_states.anomalies.record(MutationKind::Write, 1);
+    audit
+        .record(
+            AuditOperation::Insert,
+            &key,
+            Some(&value),
+            AuditOrigin::Http(client),
+        )
+        .await;
+    let cmd = if write_params.nx {
+        Command::InsertIfAbsent
+    } else {
+        Command::Insert
+    };
+    if let Err(e) = app_states
+        .commit(
+            Message::new(cmd, key.clone(), value.clone()).with_tags(tags),
+            replication,
+        )
+        .await
+    {
+        tracing::error!("Failed to send insert message: {:?}", e);
+        return replication_failed(e, request_id);
+    }
+
+    let etag = etag(&value);
+    let mut data = HashMap::new();
+    data.insert(key, value);
+
+    (
+        [(ETAG, etag)],
+        Json(Response {
+            code: StatusCode::OK.as_u16(),
+            data: Some(data),
+            message: "ok".to_string(),
+            error: None,
+            request_id: request_id.clone(),
+        }),
+    )
+        .into_response()
+}
+
+/// Handles HTTP POST requests that return an existing value or insert a default one.
+///
+/// The cache lock is held across the lookup and the insert, so concurrent requests for the
+/// same missing key are serialized: only the first one inserts (and gossips) the default,
+/// and the others observe the value it stored. The lock is released before the default is
+/// committed, since a full replication queue would otherwise wait on the gossip task, which
+/// needs the cache lock to apply incoming messages. Entries exceeding the configured limits are
+/// rejected before the lookup.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the cache.
+/// * `ctx` - The context of the request: its bearer, which must be allowed to write the key.
+/// * `write_params` - The query parameters selecting the replication targets of an inserted default.
+/// * `params` - The JSON body containing the key and the default value (and its tags) to insert if it is missing.
+///
+/// # Returns
+///
+/// * A JSON response containing the existing or newly inserted key-value pair.
+#[tracing::instrument(name = "http_get_or_set", skip_all, fields(key = %params.key))]
+async fn get_or_set(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+    ctx: RequestContext,
+    write_params: Query<WriteParams>,
+    params: Json<AddRequest>,
+) -> impl IntoResponse {
+    let RequestContext {
+        slowlog,
+        principal,
+        audit,
+        client,
+        headers,
+    } = ctx;
+    let _timer = slowlog.start(SlowLogKind::Http, "/get_or_set", Some(params.key.clone()));
+    let request_id = get_request_id(&headers);
+    if let Err(e) = principal.check(Permission::Write, &params.key) {
+        return error_response(e, &request_id);
+    }
+    let key = params.key.clone();
+    let replication = match write_params.replication() {
+        Ok(replication) => replication,
+        Err(e) => return invalid_replication(e, &request_id),
+    };
+    let app_states = app_states.lock().await;
+    if let Err(violation) = app_states.limits.check_entry(&key, &params.value) {
+        return limit_exceeded(violation, &request_id);
+    }
+
+    let (value, inserted) = {
+        let mut bcache = app_states.bcache.lock().await;
+        match error::optional(bcache.get(key.clone()).await) {
+            Ok(Some(v)) => {
+                app_states.key_stats.record_read(&key);
+                app_states.hot_keys.record_read(&key);
+                (v, false)
+            }
+            Err(e) => return error_response(Error::classify(e), &request_id),
+            Ok(None) => {
+                if let Err(e) = app_states
+                    .quotas
+                    .check(&mut bcache, &[(&key, Some(&params.value))])
+                    .await
+                {
+                    return error_response(e, &request_id);
+                }
+                app_states.key_stats.record_write(&key);
+                let value = params.value.clone();
+                bcache.insert(key.clone(), value.clone()).await;
+                app_states.tags.set_tags(&key, &params.tags);
+                (value, true)
+            }
+        }
+    };
+
+    if inserted {
+        app_states.anomalies.record(MutationKind::Write, 1);
+        audit
+            .record(
+                AuditOperation::Insert,
+                &key,
+                Some(&value),
+                AuditOrigin::Http(client),
+            )
+            .await;
+        if let Err(e) = app_states
+            .commit(
+                Message::new(Command::Insert, key.clone(), value.clone())
+                    .with_tags(params.tags.clone()),
+                replication,
+            )
+            .await
+        {
+            tracing::error!("Failed to send insert message: {:?}", e);
+            return replication_failed(e, &request_id);
+        }
+    }
+
+    let mut data = HashMap::new();
+    data.insert(key, value);
+
+    Json(Response {
+        code: StatusCode::OK.as_u16(),
+        data: Some(data),
+        message: "ok".to_string(),
+        error: None,
+        request_id: request_id.clone(),
+    })
+    .into_response()
+}
+
+/// Handles HTTP DELETE requests to remove a key from the cache.
+///
+/// If the request carries an `If-Match` header, the key is only removed when the current
+/// `ETag` matches; otherwise `412 Precondition Failed` is returned. Keys exceeding the
+/// configured limits are rejected with `413 Payload Too Large` or `422 Unprocessable Entity`.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the cache.
+/// * `ctx` - The context of the request: its bearer, which must be allowed to write the key, and
+///   its headers, checked for `If-Match`.
+/// * `write_params` - The query parameters selecting the replication targets.
+/// * `params` - The JSON body containing the key to be removed.
+///
+/// # Returns
+///
+/// * A JSON response indicating the success or failure of the operation.
+#[tracing::instrument(name = "http_delete", skip_all, fields(key = %params.key))]
+async fn remove(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+    ctx: RequestContext,
+    write_params: Query<WriteParams>,
+    params: Json<RemoveRequest>,
+) -> impl IntoResponse {
+    let RequestContext {
+        slowlog,
+        principal,
+        audit,
+        client,
+        headers,
+    } = ctx;
+    let _timer = slowlog.start(SlowLogKind::Http, "/delete", Some(params.key.clone()));
+    let request_id = get_request_id(&headers);
+    if let Err(e) = principal.check(Permission::Write, &params.key) {
+        return error_response(e, &request_id);
+    }
+    let app_states = app_states.lock().await;
+    remove_key(
+        &app_states,
+        &audit,
+        client,
+        &headers,
+        &write_params,
+        params.key.clone(),
+        &request_id,
+    )
+    .await
+}
+
+/// Handles HTTP DELETE requests that remove the key in the path, the resource-style
+/// equivalent of `/delete`.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the cache.
+/// * `ctx` - The context of the request: its bearer, which must be allowed to write the key, and
+///   its headers, checked for `If-Match`.
+/// * `key` - The key to remove.
+/// * `write_params` - The query parameters selecting the replication targets.
+///
+/// # Returns
+///
+/// * A JSON response as `/delete` returns it.
+#[tracing::instrument(name = "http_delete_key", skip_all, fields(key = %key))]
+async fn delete_key(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+    ctx: RequestContext,
+    Path(key): Path<String>,
+    write_params: Query<WriteParams>,
+) -> impl IntoResponse {
+    let RequestContext {
+        slowlog,
+        principal,
+        audit,
+        client,
+        headers,
+    } = ctx;
+    let _timer = slowlog.start(SlowLogKind::Http, "/v1/keys", Some(key.clone()));
+    let request_id = get_request_id(&headers);
+    if let Err(e) = principal.check(Permission::Write, &key) {
+        return error_response(e, &request_id);
+    }
+    let app_states = app_states.lock().await;
+    remove_key(
+        &app_states,
+        &audit,
+        client,
+        &headers,
+        &write_params,
+        key,
+        &request_id,
+    )
+    .await
+}
+
+/// Removes a key for `/delete` and `DELETE /v1/keys/{key}`, then replicates the removal.
+///
+/// If the request carries an `If-Match` header, the key is only removed when the current
+/// `ETag` matches.
+///
+/// # Arguments
+///
+/// * `app_states` - The locked application state containing the cache.
+/// * `audit` - The audit log the mutation is recorded in.
+/// * `client` - The address of the HTTP client, recorded as the origin of the mutation.
+/// * `headers` - The request headers, checked for `If-Match`.
+/// * `write_params` - The query parameters selecting the replication targets.
+/// * `key` - The key to remove.
+/// * `request_id` - The ID of the request, echoed in the response.
+///
+/// # Returns
+///
+/// * A JSON response indicating the success or failure of the removal.
+async fn remove_key(
+    app_states: &AppState,
+    audit: &AuditLog,
+    client: SocketAddr,
+    headers: &HeaderMap,
+    write_params: &WriteParams,
+    key: String,
+    request_id: &Option<String>,
+) -> axum::response::Response {
+    let replication = match write_params.replication() {
+        Ok(replication) => replication,
+        Err(e) => return invalid_replication(e, request_id),
+    };
+    if let Err(violation) = app_states.limits.check_key(&key) {
+        return limit_exceeded(violation, request_id);
+    }
+    app_states.key_stats.record_write(&key);
+
+    {
+        let mut bcache = app_states.bcache.lock().await;
+        if let Err(e) = check_if_match(headers, &mut bcache, &key).await {
+            return error_response(e, request_id);
+        }
+        bcache.remove(key.clone()).await;
+        app_states.tags.remove_key(&key);
+    }
+    app_states.anomalies.record(MutationKind::Delete, 1);
+    audit
+        .record(
+            AuditOperation::Remove,
+            &key,
+            None,
+            AuditOrigin::Http(client),
+        )
+        .await;
+    if let Err(e) = app_states
+        .commit(
+            Message::new(Command::Remove, key, "".to_string()),
+            replication,
+        )
+        .await
+    {
+        tracing::error!("Failed to send remove message: {:?}", e);
+        return replication_failed(e, request_id);
+    }
+
+    Json(Response {
+        code: StatusCode::OK.as_u16(),
+        data: None,
+        message: "ok".to_string(),
+        error: None,
+        request_id: request_id.clone(),
+    })
+    .into_response()
+}
+
+/// Handles HTTP POST requests that apply several writes and deletes atomically.
+///
+/// The checks are evaluated and the operations applied, in order, under a single lock of the
+/// cache, so no other request observes or interleaves with a partial transaction. If any check
+/// fails, nothing is applied and `412 Precondition Failed` is returned, naming the first failed
+/// check. The operations are replicated as a single `Txn` message, which replicas apply
+/// atomically too.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the cache.
+/// * `ctx` - The context of the request: its bearer, which must be allowed to write the keys of the
+///   operations and read those of the checks.
+/// * `write_params` - The query parameters selecting the replication targets.
+/// * `params` - The JSON body containing the checks and the operations.
+///
+/// # Returns
+///
+/// * A JSON response with the values written by the transaction, or the reason it was rejected.
+#[tracing::instrument(name = "http_txn", skip_all, fields(ops = params.ops.len()))]
+async fn txn(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+    ctx: RequestContext,
+    write_params: Query<WriteParams>,
+    params: Json<TxnRequest>,
+) -> impl IntoResponse {
+    let RequestContext {
+        slowlog,
+        principal,
+        audit,
+        client,
+        headers,
+    } = ctx;
+    let _timer = slowlog.start(SlowLogKind::Http, "/txn", None);
+    let request_id = get_request_id(&headers);
+    let replication = match write_params.replication() {
+        Ok(replication) => replication,
+        Err(e) => return invalid_replication(e, &request_id),
+    };
+    if params.ops.is_empty() {
+        return error_response(
+            Error::InvalidRequest("A transaction needs at least one operation".to_string()),
+            &request_id,
+        );
+    }
+    let messages: Vec<Message> = params.ops.iter().map(TxnOp::message).collect();
+    let allowed = messages
+        .iter()
+        .try_for_each(|message| principal.check(Permission::Write, &message.key))
+        .and_then(|()| {
+            params
+                .checks
+                .iter()
+                .try_for_each(|check| principal.check(Permission::Read, &check.key))
+        });
+    if let Err(e) = allowed {
+        return error_response(e, &request_id);
+    }
+    let app_states = app_states.lock().await;
+    for message in &messages {
+        let checked = match message.cmd {
+            Command::Insert => app_states.limits.check_entry(&message.key, &message.value),
+            _ => app_states.limits.check_key(&message.key),
+        };
+        if let Err(violation) = checked {
+            return limit_exceeded(violation, &request_id);
+        }
+    }
+
+    {
+        let mut bcache = app_states.bcache.lock().await;
+        for (index, check) in params.checks.iter().enumerate() {
+            let current = match error::optional(bcache.get(check.key.clone()).await) {
+                Ok(current) => current,
+                Err(e) => return error_response(Error::classify(e), &request_id),
+            };
+            if !check.holds(current.as_deref()) {
+                let mut data = HashMap::new();
+                data.insert("check".to_string(), index.to_string());
+                data.insert("key".to_string(), check.key.clone());
+                return error_response_with_data(
+                    Error::PreconditionFailed(format!("check on {} does not hold", check.key)),
+                    Some(data),
+                    &request_id,
+                );
+            }
+        }
+        let written: Vec<(&str, Option<&str>)> = messages
+            .iter()
+            .map(|message| {
+                let value = (message.cmd == Command::Insert).then_some(message.value.as_str());
+                (message.key.as_str(), value)
+            })
+            .collect();
+        if let Err(e) = app_states.quotas.check(&mut bcache, &written).await {
+            return error_response(e, &request_id);
+        }
+        for message in &messages {
+            if message.cmd == Command::Insert {
+                bcache
+                    .insert(message.key.clone(), message.value.clone())
+                    .await;
+                app_states.tags.set_tags(&message.key, &message.tags);
+            } else {
+                bcache.remove(message.key.clone()).await;
+                app_states.tags.remove_key(&message.key);
+            }
+        }
+    }
+
+    let mut data = HashMap::new();
+    for message in &messages {
+        let (kind, operation, value) = if message.cmd == Command::Insert {
+            data.insert(message.key.clone(), message.value.clone());
+            (
+                MutationKind::Write,
+                AuditOperation::Insert,
+                Some(message.value.as_str()),
+            )
+        } else {
+            data.remove(&message.key);
+            (MutationKind::Delete, AuditOperation::Remove, None)
+        };
+        app_states.anomalies.record(kind, 1);
+        app_states.key_stats.record_write(&message.key);
+        audit
+            .record(operation, &message.key, value, AuditOrigin::Http(client))
+            .await;
+    }
+    let committed = match Message::txn(messages) {
+        Ok(msg) => app_states.commit(msg, replication).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = committed {
+        tracing::error!("Failed to send transaction message: {:?}", e);
+        return replication_failed(e, &request_id);
+    }
+
+    Json(Response {
+        code: StatusCode::OK.as_u16(),
+        data: Some(data),
+        message: "ok".to_string(),
+        error: None,
+        request_id: request_id.clone(),
+    })
+    .into_response()
+}
+
+/// Builds the `422 Unprocessable Entity` response returned when a request does not apply to
+/// the value it targets, such as a patch of a value that is not a JSON document.
+fn unprocessable(message: String, request_id: &Option<String>) -> axum::response::Response {
+    error_response(Error::Unprocessable(message), request_id)
+}
+
+/// Handles HTTP GET requests for a JSON document value, or one of its fields.
+///
+/// With a `path` parameter, such as `$.user.name` or `/user/name`, only the field at the path
+/// is returned, serialized as JSON; `422 Unprocessable Entity` is returned if the value is not
+/// a JSON document or has no such field. The `ETag` is that of the whole document, so it can
+/// be used as the `If-Match` precondition of a patch. Like `/query`, the response reports how
+/// stale the document may be, and is redirected to the owner of the key if that exceeds the
+/// `max_staleness` parameter.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the cache.
+/// * `slowlog` - The slow log the request is recorded in if it exceeds the threshold.
+/// * `principal` - The bearer of the request, which must be allowed to read the key.
+/// * `headers` - The request headers, carrying the request ID.
+/// * `uri` - The URI of the request, which stale reads are redirected to on the owner of the key.
+/// * `key` - The key of the document.
+/// * `params` - The query parameters with the path of the field and the staleness allowed.
+///
+/// # Returns
+///
+/// * A JSON response containing the key and the selected field, or an error message.
+#[tracing::instrument(name = "http_get_document", skip_all, fields(key = %key))]
+async fn get_document(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+    Extension(slowlog): Extension<Arc<SlowLog>>,
+    Extension(principal): Extension<Principal>,
+    headers: HeaderMap,
+    uri: Uri,
+    Path(key): Path<String>,
+    params: Query<DocumentParams>,
+) -> impl IntoResponse {
+    let _timer = slowlog.start(SlowLogKind::Http, "/keys", Some(key.clone()));
+    let request_id = get_request_id(&headers);
+    if let Err(e) = principal.check(Permission::Read, &key) {
+        return error_response(e, &request_id);
+    }
+
+    let (value, staleness) = {
+        let app_states = app_states.lock().await;
+        let staleness = app_states.staleness_of(&key);
+        if let Some(response) = staleness.reject(
+            params.max_staleness.as_deref(),
+            &app_states.topology,
+            &uri,
+            &request_id,
+        ) {
+            return response;
+        }
+        app_states.key_stats.record_read(&key);
+        app_states.hot_keys.record_read(&key);
+        let value = app_states.bcache.lock().await.get(key.clone()).await;
+        match value {
+            Ok(v) => (v, staleness),
+            Err(e) => return error_response(Error::classify(e), &request_id),
+        }
+    };
+
+    let etag = etag(&value);
+    let field = match &params.path {
+        Some(path) => match document::select(&value, path) {
+            Ok(field) => field.to_string(),
+            Err(e) => return unprocessable(format!("Invalid document: {}", e), &request_id),
+        },
+        None => value,
+    };
+
+    let mut data = HashMap::new();
+    data.insert(key, field);
+
+    (
+        staleness.headers(),
+        [(ETAG, etag)],
+        Json(Response {
+            code: StatusCode::OK.as_u16(),
+            data: Some(data),
+            message: "ok".to_string(),
+            error: None,
+            request_id: request_id.clone(),
+        }),
+    )
+        .into_response()
+}
+
+/// Handles HTTP PATCH requests that apply a patch to a JSON document value.
+///
+/// A body sent as `application/json-patch+json` is an RFC 6902 patch, one sent as
+/// `application/merge-patch+json` or `application/json` an RFC 7386 merge patch. A missing key
+/// is patched as `null`. Only the patch is replicated, as a `Patch` message that replicas apply
+/// to their copy of the document, so small updates of large documents stay small on the wire.
+///
+/// If the request carries an `If-Match` header, the patch is only applied when the current
+/// `ETag` matches; otherwise `412 Precondition Failed` is returned. Invalid patches, values
+/// that are not JSON documents and failed RFC 6902 operations are rejected with
+/// `422 Unprocessable Entity`, and patched documents exceeding the configured limits with
+/// `413 Payload Too Large`.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the cache.
+/// * `ctx` - The context of the request: its bearer, which must be allowed to write the key, and
+///   its headers, carrying the content type and `If-Match`.
+/// * `key` - The key of the document.
+/// * `write_params` - The query parameters selecting the replication targets.
+/// * `body` - The patch.
+///
+/// # Returns
+///
+/// * A JSON response containing the patched document, with its `ETag`.
+#[tracing::instrument(name = "http_patch_document", skip_all, fields(key = %key))]
+async fn patch_document(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+    ctx: RequestContext,
+    Path(key): Path<String>,
+    write_params: Query<WriteParams>,
+    body: Bytes,
+) -> impl IntoResponse {
+    let RequestContext {
+        slowlog,
+        principal,
+        audit,
+        client,
+        headers,
+    } = ctx;
+    let _timer = slowlog.start(SlowLogKind::Http, "/keys", Some(key.clone()));
+    let request_id = get_request_id(&headers);
+    if let Err(e) = principal.check(Permission::Write, &key) {
+        return error_response(e, &request_id);
+    }
+    let replication = match write_params.replication() {
+        Ok(replication) => replication,
+        Err(e) => return invalid_replication(e, &request_id),
+    };
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or(document::MERGE_PATCH_CONTENT_TYPE);
+    let message = match DocumentPatch::parse(content_type, &body)
+        .and_then(|patch| Message::patch(key, &patch))
+    {
+        Ok(message) => message,
+        Err(e) => return unprocessable(format!("Invalid patch: {}", e), &request_id),
+    };
+
+    let app_states = app_states.lock().await;
+    update_in_place(
+        &app_states,
+        &audit,
+        client,
+        &headers,
+        message,
+        replication,
+        &request_id,
+    )
+    .await
+}
+
+/// Handles HTTP POST requests that append a suffix to the value of a key, creating it if absent.
+///
+/// The suffix is appended under the cache lock, so concurrent appends are never lost, and only
+/// the suffix is replicated, as an `Update` message, however long the value grows. Appending to
+/// a list, set or integer is rejected with `422 Unprocessable Entity`.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the cache.
+/// * `ctx` - The context of the request: its bearer, which must be allowed to write the key, and
+///   its headers, checked for `If-Match`.
+/// * `write_params` - The query parameters selecting the replication targets.
+/// * `params` - The JSON body containing the key and the suffix to append.
+///
+/// # Returns
+///
+/// * A JSON response containing the value after the append, with its `ETag`.
+#[tracing::instrument(name = "http_append", skip_all, fields(key = %params.key))]
+async fn append(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+    ctx: RequestContext,
+    write_params: Query<WriteParams>,
+    params: Json<AppendRequest>,
+) -> impl IntoResponse {
+    let RequestContext {
+        slowlog,
+        principal,
+        audit,
+        client,
+        headers,
+    } = ctx;
+    let _timer = slowlog.start(SlowLogKind::Http, "/append", Some(params.key.clone()));
+    let request_id = get_request_id(&headers);
+    if let Err(e) = principal.check(Permission::Write, &params.key) {
+        return error_response(e, &request_id);
+    }
+    let replication = match write_params.replication() {
+        Ok(replication) => replication,
+        Err(e) => return invalid_replication(e, &request_id),
+    };
+    let op = ValueOp::Append {
+        suffix: params.value.clone(),
+    };
+    let message = match Message::update(params.key.clone(), &op) {
+        Ok(message) => message,
+        Err(e) => return unprocessable(format!("Invalid operation: {}", e), &request_id),
+    };
+
+    let app_states = app_states.lock().await;
+    update_in_place(
+        &app_states,
+        &audit,
+        client,
+        &headers,
+        message,
+        replication,
+        &request_id,
+    )
+    .await
+}
+
+/// Handles HTTP POST requests that apply a type-aware operation to a typed value.
+///
+/// Lists can be appended or prepended to, members added to or removed from sets, integers
+/// added to and strings appended to. A missing key is created as an empty list, set or
+/// string, or zero. Only the operation is replicated, as an `Update` message, so clients do not have to read, modify and
+/// write values back, racing with each other.
+///
+/// If the request carries an `If-Match` header, the operation is only applied when the current
+/// `ETag` matches; otherwise `412 Precondition Failed` is returned. Operations that do not
+/// apply to the type of the value, or overflow an integer, are rejected with
+/// `422 Unprocessable Entity`.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the cache.
+/// * `ctx` - The context of the request: its bearer, which must be allowed to write the key, and
+///   its headers, checked for `If-Match`.
+/// * `key` - The key of the value.
+/// * `write_params` - The query parameters selecting the replication targets.
+/// * `op` - The JSON body containing the operation.
+///
+/// # Returns
+///
+/// * A JSON response containing the updated value, with its `ETag`.
+#[tracing::instrument(name = "http_update_value", skip_all, fields(key = %key))]
+async fn update_value(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+    ctx: RequestContext,
+    Path(key): Path<String>,
+    write_params: Query<WriteParams>,
+    op: Json<ValueOp>,
+) -> impl IntoResponse {
+    let RequestContext {
+        slowlog,
+        principal,
+        audit,
+        client,
+        headers,
+    } = ctx;
+    let _timer = slowlog.start(SlowLogKind::Http, "/keys/ops", Some(key.clone()));
+    let request_id = get_request_id(&headers);
+    if let Err(e) = principal.check(Permission::Write, &key) {
+        return error_response(e, &request_id);
+    }
+    let replication = match write_params.replication() {
+        Ok(replication) => replication,
+        Err(e) => return invalid_replication(e, &request_id),
+    };
+    let message = match Message::update(key, &op) {
+        Ok(message) => message,
+        Err(e) => return unprocessable(format!("Invalid operation: {}", e), &request_id),
+    };
+
+    let app_states = app_states.lock().await;
+    update_in_place(
+        &app_states,
+        &audit,
+        client,
+        &headers,
+        message,
+        replication,
+        &request_id,
+    )
+    .await
+}
+
+/// Applies a `Patch` or `Update` message to the value of its key, then replicates the message.
+///
+/// The cache lock is held from the read of the current value to the write of the updated one,
+/// so concurrent updates of the key are serialized rather than lost.
+///
+/// # Arguments
+///
+/// * `app_states` - The locked application state containing the cache.
+/// * `audit` - The audit log the mutation is recorded in.
+/// * `client` - The address of the HTTP client, recorded as the origin of the mutation.
+/// * `headers` - The request headers, checked for `If-Match`.
+/// * `message` - The update to apply and replicate.
+/// * `replication` - The replication targets of the update.
+/// * `request_id` - The ID of the request, echoed in the response.
+///
+/// # Returns
+///
+/// * A JSON response containing the updated value, with its `ETag`.
+async fn update_in_place(
+    app_states: &AppState,
+    audit: &AuditLog,
+    client: SocketAddr,
+    headers: &HeaderMap,
+    message: Message,
+    replication: Replication,
+    request_id: &Option<String>,
+) -> axum::response::Response {
+    let key = message.key.clone();
+    if let Err(violation) = app_states.limits.check_key(&key) {
+        return limit_exceeded(violation, request_id);
+    }
+    app_states.key_stats.record_write(&key);
+
+    let value = {
+        let mut bcache = app_states.bcache.lock().await;
+        if let Err(e) = check_if_match(headers, &mut bcache, &key).await {
+            return error_response(e, request_id);
+        }
+        let current = match error::optional(bcache.get(key.clone()).await) {
+            Ok(current) => current,
+            Err(e) => return error_response(Error::classify(e), request_id),
+        };
+        let value = match message.updated_value(current.as_deref()) {
+            Ok(value) => value,
+            Err(e) => return unprocessable(format!("Cannot update {}: {}", key, e), request_id),
+        };
+        if let Err(violation) = app_states.limits.check_entry(&key, &value) {
+            return limit_exceeded(violation, request_id);
+        }
+        if let Err(e) = app_states
+            .quotas
+            .check(&mut bcache, &[(&key, Some(&value))])
+            .await
+        {
+            return error_response(e, request_id);
+        }
+        bcache.insert(key.clone(), value.clone()).await;
+        value
+    };
+    app_states.anomalies.record(MutationKind::Write, 1);
+    audit
+        .record(
+            AuditOperation::Insert,
+            &key,
+            Some(&value),
+            AuditOrigin::Http(client),
+        )
+        .await;
+    let cmd = message.cmd.clone();
+    if let Err(e) = app_states.commit(message, replication).await {
+        tracing::error!("Failed to send {:?} message: {:?}", cmd, e);
+        return replication_failed(e, request_id);
+    }
+
+    let etag = etag(&value);
+    let mut data = HashMap::new();
+    data.insert(key, value);
+
+    (
+        [(ETAG, etag)],
+        Json(Response {
+            code: StatusCode::OK.as_u16(),
+            data: Some(data),
+            message: "ok".to_string(),
+            error: None,
+            request_id: request_id.clone(),
+        }),
+    )
+        .into_response()
+}
+
+/// Handles HTTP DELETE requests that invalidate every entry carrying a tag.
+///
+/// The entries are removed locally and the invalidation is replicated as a single message;
+/// each replica removes the keys its own tag index holds for the tag.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the cache and its tag index.
+/// * `ctx` - The context of the request: its bearer, which must be allowed to write every key,
+///   since tags span namespaces.
+/// * `write_params` - The query parameters selecting the replication targets.
+/// * `tag` - The tag to invalidate.
+///
+/// # Returns
+///
+/// * A JSON response with the number of entries removed on this node.
+#[tracing::instrument(name = "http_invalidate_tag", skip_all, fields(tag = %tag))]
+async fn invalidate_tag(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+    ctx: RequestContext,
+    write_params: Query<WriteParams>,
+    Path(tag): Path<String>,
+) -> impl IntoResponse {
+    let RequestContext {
+        slowlog,
+        principal,
+        audit,
+        client,
+        headers,
+    } = ctx;
+    let _timer = slowlog.start(SlowLogKind::Http, "/tags", None);
+    let request_id = get_request_id(&headers);
+    if let Err(e) = principal.check(Permission::Write, "") {
+        return error_response(e, &request_id);
+    }
+    let replication = match write_params.replication() {
+        Ok(replication) => replication,
+        Err(e) => return invalid_replication(e, &request_id),
+    };
+    let app_states = app_states.lock().await;
+
+    let keys = invalidate_cached_tag(
+        &app_states.bcache,
+        &app_states.tags,
+        &audit,
+        &tag,
+        AuditOrigin::Http(client),
+    )
+    .await;
+    app_states
+        .anomalies
+        .record(MutationKind::Delete, keys.len() as u64);
     if let Err(e) = app_states
-        .sender
-        .send(Message {
-            cmd: Command::Remove,
-            key,
-            value: "".to_string(),
-        })
+        .commit(
+            Message::new(Command::InvalidateTag, tag, "".to_string()),
+            replication,
+        )
         .await
     {
-        tracing::error!("Failed to send remove message: {:?}", e);
-        return Json(Response {
-            code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
-            data: None,
-            message: "Failed to process remove request".to_string(),
-        });
+        tracing::error!("Failed to send invalidate tag message: {:?}", e);
+        return replication_failed(e, &request_id);
     }
 
+    let mut data = HashMap::new();
+    data.insert("removed".to_string(), keys.len().to_string());
+
     Json(Response {
         code: StatusCode::OK.as_u16(),
-        data: None,
+        data: Some(data),
+        message: "ok".to_string(),
+        error: None,
+        request_id: request_id.clone(),
+    })
+    .into_response()
+}
+
+/// Handles HTTP POST requests that schedule every entry carrying a tag to expire at once.
+///
+/// Only the schedule is replicated, as a single message. When it is due, each node invalidates
+/// the tag as `DELETE /tags/{tag}` would, including entries tagged after the schedule was set.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the tag index.
+/// * `slowlog` - The slow log the request is recorded in if it exceeds the threshold.
+/// * `principal` - The bearer of the request, which must be allowed to write every key, since
+///   tags span namespaces.
+/// * `headers` - The request headers, carrying the request ID.
+/// * `write_params` - The query parameters selecting the replication targets.
+/// * `tag` - The tag to expire.
+/// * `params` - The JSON body containing the expiration time.
+///
+/// # Returns
+///
+/// * A JSON response indicating the success or failure of the operation.
+#[tracing::instrument(name = "http_expire_tag", skip_all, fields(tag = %tag))]
+async fn expire_tag(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+    Extension(slowlog): Extension<Arc<SlowLog>>,
+    Extension(principal): Extension<Principal>,
+    headers: HeaderMap,
+    write_params: Query<WriteParams>,
+    Path(tag): Path<String>,
+    params: Json<ExpireTagRequest>,
+) -> impl IntoResponse {
+    let _timer = slowlog.start(SlowLogKind::Http, "/tags/expire", None);
+    let request_id = get_request_id(&headers);
+    if let Err(e) = principal.check(Permission::Write, "") {
+        return error_response(e, &request_id);
+    }
+    let replication = match write_params.replication() {
+        Ok(replication) => replication,
+        Err(e) => return invalid_replication(e, &request_id),
+    };
+    let app_states = app_states.lock().await;
+
+    app_states.tags.expire_at(&tag, params.expire_at_ms);
+    if let Err(e) = app_states
+        .commit(
+            Message::new(
+                Command::ExpireTag,
+                tag.clone(),
+                params.expire_at_ms.to_string(),
+            ),
+            replication,
+        )
+        .await
+    {
+        tracing::error!("Failed to send expire tag message: {:?}", e);
+        return replication_failed(e, &request_id);
+    }
+
+    let mut data = HashMap::new();
+    data.insert(tag, params.expire_at_ms.to_string());
+
+    Json(Response {
+        code: StatusCode::OK.as_u16(),
+        data: Some(data),
+        message: "ok".to_string(),
+        error: None,
+        request_id: request_id.clone(),
+    })
+    .into_response()
+}
+
+/// Handles HTTP GET requests for the slow log.
+///
+/// # Arguments
+///
+/// * `slowlog` - The slow log of this node.
+///
+/// # Returns
+///
+/// * `Json<Vec<SlowLogEntry>>` - The most recent slow HTTP requests and gossip applications, oldest first.
+async fn debug_slowlog(Extension(slowlog): Extension<Arc<SlowLog>>) -> Json<Vec<SlowLogEntry>> {
+    Json(slowlog.entries())
+}
+
+/// Handles HTTP GET requests for the anomaly alerts of this node.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the anomaly detector.
+///
+/// # Returns
+///
+/// * `Json<Vec<Alert>>` - The most recent write storms and mass deletes detected, oldest first.
+async fn cluster_alerts(State(app_states): State<Arc<Mutex<AppState>>>) -> Json<Vec<Alert>> {
+    Json(app_states.lock().await.anomalies.alerts())
+}
+
+/// Handles HTTP GET requests for the authentication of the gossip frames of this node.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the authentication of the cluster.
+///
+/// # Returns
+///
+/// * `Json<ClusterAuthStats>` - The members that authenticated and those awaiting it, and the
+///   frames rejected from peers without the secret of the cluster.
+async fn cluster_auth_stats(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+) -> Json<ClusterAuthStats> {
+    Json(app_states.lock().await.cluster_auth.stats())
+}
+
+/// Query parameters of `/stats/keys`.
+#[derive(Debug, Deserialize, Clone)]
+struct StatsParams {
+    /// The number of keys returned. Defaults to 50.
+    top: Option<usize>,
+}
+
+/// Handles HTTP GET requests for the most accessed keys of this node. Only served when built
+/// with the `key-stats` feature.
+///
+/// Reads through `/query` and `/get_or_set`, and writes and removes through the HTTP API, are
+/// counted. Mutations replicated from other nodes are not.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the access counts.
+/// * `params` - The query parameters with the number of keys to return.
+///
+/// # Returns
+///
+/// * `Json<Vec<KeyStat>>` - The most accessed keys, by reads and writes combined, most accessed first.
+async fn stats_keys(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+    params: Query<StatsParams>,
+) -> Json<Vec<KeyStat>> {
+    let key_stats = app_states.lock().await.key_stats.clone();
+    Json(key_stats.top(params.top.unwrap_or(50)))
+}
+
+/// Handles HTTP GET requests for the keys of this node read at or above the hot-key threshold.
+///
+/// Reads through `/query`, `/get_or_set` and `GET /keys/:key` are counted, over a sliding
+/// window. When the hot-key read cache is enabled, `/query` serves these keys from it.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the hot keys.
+///
+/// # Returns
+///
+/// * `Json<Vec<HotKey>>` - The hot keys with their reads per second, most read first.
+async fn stats_hot_keys(State(app_states): State<Arc<Mutex<AppState>>>) -> Json<Vec<HotKey>> {
+    let hot_keys = app_states.lock().await.hot_keys.clone();
+    Json(hot_keys.hot())
+}
+
+/// The queues between the tasks of a node, as served at `/stats/queues`.
+#[derive(Serialize)]
+struct QueuesStats {
+    /// Writes made through the HTTP API, awaiting replication by the gossip task.
+    replication: QueueStats,
+    /// Frames received from other members, awaiting the gossip task.
+    gossip: QueueStats,
+}
+
+/// Handles HTTP GET requests for the occupancy of the queues between the tasks of this node,
+/// and the items their overflow policies dropped or rejected.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the queues.
+///
+/// # Returns
+///
+/// * `Json<QueuesStats>` - The capacity, overflow policy, length and overflows of each queue.
+async fn stats_queues(State(app_states): State<Arc<Mutex<AppState>>>) -> Json<QueuesStats> {
+    let app_state = app_states.lock().await;
+    Json(QueuesStats {
+        replication: app_state.sender.counters().stats(),
+        gossip: app_state.gossip_queue.stats(),
+    })
+}
+
+/// Handles HTTP GET requests for the faults this node injects into the replication frames it
+/// sends and its cache operations.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the fault injection settings.
+///
+/// # Returns
+///
+/// * `Json<ChaosOptions>` - The current fault injection settings.
+async fn admin_chaos(State(app_states): State<Arc<Mutex<AppState>>>) -> Json<ChaosOptions> {
+    let chaos = app_states.lock().await.chaos.clone();
+    Json(chaos.options())
+}
+
+/// Handles HTTP GET requests for the usage of the namespaces with a quota, and the writes they
+/// rejected or the entries they evicted.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the quotas.
+///
+/// # Returns
+///
+/// * `Json<Vec<NamespaceStats>>` - The entries, bytes, quota, rejections and evictions of each
+///   namespace, sorted by prefix.
+async fn stats_namespaces(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+) -> Json<Vec<NamespaceStats>> {
+    let quotas = app_states.lock().await.quotas.clone();
+    Json(quotas.stats())
+}
+
+/// Handles HTTP POST requests that replace the faults this node injects, for staging clusters.
+/// Rates left out of the body are set to zero, so posting `{}` stops injecting faults.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the fault injection settings.
+/// * `headers` - The request headers, carrying the request ID.
+/// * `options` - The JSON body with the new settings.
+///
+/// # Returns
+///
+/// * The new settings, or `422 Unprocessable Entity` if a rate is not a percentage.
+#[tracing::instrument(name = "http_admin_chaos", skip_all)]
+async fn set_admin_chaos(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+    headers: HeaderMap,
+    Json(options): Json<ChaosOptions>,
+) -> impl IntoResponse {
+    let request_id = get_request_id(&headers);
+    let chaos = app_states.lock().await.chaos.clone();
+    match chaos.set(options) {
+        Ok(()) => Json(chaos.options()).into_response(),
+        Err(e) => unprocessable(e.to_string(), &request_id),
+    }
+}
+
+/// Handles HTTP GET requests for the topology of the cluster: its members, their addresses and
+/// their tokens on the hash ring, so clients can send each key to the member owning it.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the topology.
+///
+/// # Returns
+///
+/// * `Json<TopologyView>` - The version of the topology, the replication factor and the members.
+async fn get_topology(State(app_states): State<Arc<Mutex<AppState>>>) -> Json<TopologyView> {
+    let topology = app_states.lock().await.topology.clone();
+    Json(topology.view())
+}
+
+/// Handles HTTP GET requests for the OpenAPI 3 document of the HTTP API.
+///
+/// # Returns
+///
+/// * `Json<serde_json::Value>` - The document, describing every route and its types.
+async fn openapi_document() -> Json<serde_json::Value> {
+    Json(openapi::document())
+}
+
+/// Handles HTTP GET requests for the interactive documentation of the HTTP API, a Swagger UI
+/// browsing `/openapi.json`.
+///
+/// # Returns
+///
+/// * `Html<&'static str>` - The Swagger UI page.
+async fn docs() -> Html<&'static str> {
+    Html(openapi::SWAGGER_UI)
+}
+
+/// Handles HTTP POST requests that write a snapshot of every entry of this node to a file.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the cache, its tag index and
+///   the snapshot directory.
+/// * `slowlog` - The slow log the request is recorded in if it exceeds the threshold.
+/// * `headers` - The request headers, carrying the request ID.
+/// * `params` - The optional JSON body naming the snapshot file.
+///
+/// # Returns
+///
+/// * A JSON response with the path of the snapshot, its number of entries and its checksum.
+#[tracing::instrument(name = "http_admin_snapshot", skip_all)]
+async fn admin_snapshot(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+    Extension(slowlog): Extension<Arc<SlowLog>>,
+    headers: HeaderMap,
+    params: Option<Json<SnapshotRequest>>,
+) -> impl IntoResponse {
+    let _timer = slowlog.start(SlowLogKind::Http, "/admin/snapshot", None);
+    let request_id = get_request_id(&headers);
+    let name = params
+        .and_then(|Json(params)| params.name)
+        .unwrap_or_else(|| format!("snapshot-{}.json", unix_time_ms()));
+
+    let (bcache, tags, snapshots) = {
+        let app_states = app_states.lock().await;
+        (
+            app_states.bcache.clone(),
+            app_states.tags.clone(),
+            app_states.snapshots.clone(),
+        )
+    };
+    let result = match Snapshot::capture(&bcache, &tags).await {
+        Ok(snapshot) => snapshots
+            .save(&snapshot, &name)
+            .await
+            .map(|path| (path, snapshot.metadata)),
+        Err(e) => Err(e),
+    };
+    let (path, metadata) = match result {
+        Ok(saved) => saved,
+        Err(e) => {
+            tracing::error!("Failed to write snapshot {}: {:?}", name, e);
+            return error_response(
+                Error::Internal(format!("Failed to write snapshot: {}", e)),
+                &request_id,
+            );
+        }
+    };
+
+    let mut data = HashMap::new();
+    data.insert("path".to_string(), path.display().to_string());
+    data.insert("entries".to_string(), metadata.entry_count.to_string());
+    data.insert("checksum".to_string(), metadata.checksum);
+
+    Json(Response {
+        code: StatusCode::OK.as_u16(),
+        data: Some(data),
+        message: "ok".to_string(),
+        error: None,
+        request_id,
+    })
+    .into_response()
+}
+
+/// Handles HTTP POST requests that load a snapshot into the cache.
+///
+/// The snapshot's checksum and every entry are checked before any entry is loaded, so a
+/// snapshot holding an entry over the configured limits is refused as a whole. Entries are
+/// then written in batches like those of `/import`: each batch is checked against the
+/// namespace quotas, and its entries are written with their tags, recorded in the audit log
+/// and replicated like regular writes.
+///
+/// # Arguments
+///
+/// * `app_states` - The current application state containing the cache, its tag index and
+///   the snapshot directory.
+/// * `slowlog` - The slow log the request is recorded in if it exceeds the threshold.
+/// * `audit` - The audit log the restored entries are recorded in.
+/// * `client` - The address of the HTTP client, recorded as the origin of the writes.
+/// * `headers` - The request headers, carrying the request ID.
+/// * `write_params` - The query parameters selecting the replication targets.
+/// * `params` - The JSON body naming the snapshot file.
+///
+/// # Returns
+///
+/// * A JSON response with the number of entries restored, also reported with the error when a
+///   batch exceeds a quota.
+#[tracing::instrument(name = "http_admin_restore", skip_all)]
+async fn admin_restore(
+    State(app_states): State<Arc<Mutex<AppState>>>,
+    Extension(slowlog): Extension<Arc<SlowLog>>,
+    Extension(audit): Extension<Arc<AuditLog>>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    write_params: Query<WriteParams>,
+    Json(params): Json<SnapshotRequest>,
+) -> impl IntoResponse {
+    let _timer = slowlog.start(SlowLogKind::Http, "/admin/restore", None);
+    let request_id = get_request_id(&headers);
+    let replication = match write_params.replication() {
+        Ok(replication) => replication,
+        Err(e) => return invalid_replication(e, &request_id),
+    };
+    let Some(name) = params.name else {
+        return error_response(
+            Error::InvalidRequest("Missing 'name' parameter".to_string()),
+            &request_id,
+        );
+    };
+    let snapshots = app_states.lock().await.snapshots.clone();
+    let snapshot = match snapshots.load(&name).await {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            return error_response(
+                Error::InvalidRequest(format!("Failed to load snapshot: {:#}", e)),
+                &request_id,
+            );
+        }
+    };
+
+    let app_states = app_states.lock().await;
+    for entry in &snapshot.entries {
+        if let Err(violation) = app_states.limits.check_entry(&entry.key, &entry.value) {
+            return error_response(Error::LimitExceeded(violation), &request_id);
+        }
+    }
+    let mut restored = 0;
+    let mut entries = snapshot.entries.into_iter().peekable();
+    while entries.peek().is_some() {
+        let batch: Vec<SnapshotEntry> = entries.by_ref().take(IMPORT_BATCH).collect();
+        let count = batch.len();
+        let written: Vec<(&str, Option<&str>)> = batch
+            .iter()
+            .map(|entry| (entry.key.as_str(), Some(entry.value.as_str())))
+            .collect();
+        let quota = app_states
+            .quotas
+            .check(&mut *app_states.bcache.lock().await, &written)
+            .await;
+        if let Err(e) = quota {
+            let data = HashMap::from([("restored".to_string(), restored.to_string())]);
+            return error_response_with_data(e, Some(data), &request_id);
+        }
+        if let Err(e) = import_batch(&app_states, &audit, client, batch, &replication).await {
+            tracing::error!("Failed to send insert message: {:?}", e);
+            return replication_failed(e, &request_id);
+        }
+        restored += count;
+    }
+
+    let mut data = HashMap::new();
+    data.insert("restored".to_string(), restored.to_string());
+
+    Json(Response {
+        code: StatusCode::OK.as_u16(),
+        data: Some(data),
         message: "ok".to_string(),
+        error: None,
+        request_id,
     })
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moka_cache::MokaCache;
+    use crate::topology::NodeInfo;
+    use tower::ServiceExt;
+
+    /// Builds the routes of a node accepting the given ACL tokens, along with the receiver of
+    /// its replication queue, which must be kept for writes to be accepted.
+    async fn app(tokens: &[&str]) -> (Router, QueueReceiver<(Message, Replication)>) {
+        app_with(HttpServerConfig {
+            acl_tokens: tokens.iter().map(|token| token.parse().unwrap()).collect(),
+            ..HttpServerConfig::new("127.0.0.1:0".to_string())
+        })
+        .await
+    }
+
+    /// Builds the routes of a node with the given configuration, along with the receiver of
+    /// its replication queue.
+    async fn app_with(config: HttpServerConfig) -> (Router, QueueReceiver<(Message, Replication)>) {
+        let deps = ServerDeps {
+            bcache: Arc::new(Mutex::new(Box::new(MokaCache::new(64).await))),
+            tags: Arc::new(TagIndex::default()),
+            slowlog: Arc::new(SlowLog::new(
+                Duration::from_secs(1),
+                Duration::from_secs(1),
+                8,
+            )),
+            audit: Arc::new(AuditLog::disabled()),
+            anomalies: Arc::new(AnomalyDetector::new(Default::default())),
+            wal: Arc::new(Wal::disabled()),
+            events: KeyEvents::default(),
+            chaos: Arc::new(Chaos::default()),
+            gossip_queue: queue::<()>("gossip", QueueOptions::new(1)).1.counters(),
+            cluster_auth: Arc::new(ClusterAuth::default()),
+            topology: Arc::new(Topology::new("node-1".to_string(), NodeInfo::default())),
+            sessions: Arc::new(Sessions::new("node-1".to_string())),
+            staleness: Arc::new(Staleness::default()),
+            quotas: Arc::new(Quotas::new(Vec::new())),
+        };
+        let (sender, receiver) = queue("replication", config.replication_queue.clone());
+        let (slowlog, audit) = (deps.slowlog.clone(), deps.audit.clone());
+        let (data, admin) = routes(&config, deps, sender);
+        let app = with_layers(data.merge(admin), &config, &slowlog, &audit, None);
+        (app, receiver)
+    }
+
+    /// Sends a request from a local client, with the bearer token if any.
+    async fn send(
+        app: &Router,
+        method: Method,
+        uri: &str,
+        token: Option<&str>,
+        body: &str,
+    ) -> axum::response::Response {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(CONTENT_TYPE, "application/json");
+        if let Some(token) = token {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let mut request = request.body(Body::from(body.to_string())).unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+        app.clone().oneshot(request).await.unwrap()
+    }
+
+    /// Unit test for `authenticate`, `require_admin` and the checks of the handlers against
+    /// the `Principal` of the request.
+    ///
+    /// Requests without a token are answered with `401`, except for the documentation. A token
+    /// is refused the keys, ranges and operational routes its grants do not cover with `403`.
+    #[tokio::test]
+    async fn test_access_control() {
+        let (app, _receiver) = app(&["team=write:sessions:*", "ops=admin:*"]).await;
+
+        let response = send(&app, Method::GET, "/query?key=sessions:1", None, "").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()[WWW_AUTHENTICATE], "Bearer");
+        let response = send(&app, Method::GET, "/query?key=a", Some("guess"), "").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = send(&app, Method::GET, "/openapi.json", None, "").await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let add = |key: &str| format!(r#"{{"key":"{}","value":"v"}}"#, key);
+        let response = send(&app, Method::POST, "/add", Some("team"), &add("config:x")).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = send(&app, Method::PUT, "/v1/keys/config:x", Some("team"), "v").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = send(&app, Method::POST, "/add", Some("team"), &add("sessions:1")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(&app, Method::GET, "/query?key=sessions:1", Some("team"), "").await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Moka caches cannot scan ranges, so an allowed range gets past the check to a `501`.
+        let range = "/range?start=sessions:&end=sessions;";
+        let response = send(&app, Method::GET, range, Some("team"), "").await;
+        assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+        for range in ["/range?start=a&end=z", "/range?start=sessions:", "/range"] {
+            let response = send(&app, Method::GET, range, Some("team"), "").await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN, "{}", range);
+        }
+
+        let response = send(&app, Method::GET, "/admin/chaos", None, "").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = send(&app, Method::GET, "/admin/chaos", Some("team"), "").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = send(&app, Method::GET, "/admin/chaos", Some("ops"), "").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(&app, Method::POST, "/add", Some("ops"), &add("config:x")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    /// Sends an `/add` of `key` with a bearer token and an idempotency key.
+    async fn add_idempotent(
+        app: &Router,
+        token: &str,
+        idempotency_key: &str,
+        key: &str,
+    ) -> axum::response::Response {
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri("/add")
+            .header(CONTENT_TYPE, "application/json")
+            .header(AUTHORIZATION, format!("Bearer {}", token))
+            .header(IDEMPOTENCY_KEY, idempotency_key)
+            .body(Body::from(format!(r#"{{"key":"{}","value":"v"}}"#, key)))
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+        app.clone().oneshot(request).await.unwrap()
+    }
+
+    /// Unit test for `replay_idempotent`.
+    ///
+    /// A retry with the same token and body replays the stored response, while the key reused
+    /// with another body is refused with `422`. The same key sent with another token is applied
+    /// and checked against the grants of that token, instead of replaying the stored response.
+    #[tokio::test]
+    async fn test_idempotency_keys() {
+        let (app, _receiver) = app(&["team=write:sessions:*", "ops=admin:*"]).await;
+
+        let response = add_idempotent(&app, "team", "k1", "sessions:1").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(IDEMPOTENT_REPLAYED));
+        let response = add_idempotent(&app, "team", "k1", "sessions:1").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[IDEMPOTENT_REPLAYED], "true");
+        let response = add_idempotent(&app, "team", "k1", "sessions:2").await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let response = add_idempotent(&app, "ops", "k1", "sessions:1").await;
+        assert!(!response.headers().contains_key(IDEMPOTENT_REPLAYED));
+
+        let response = add_idempotent(&app, "ops", "k2", "config:x").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = add_idempotent(&app, "team", "k2", "config:x").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    /// Unit test for `admin_restore`.
+    ///
+    /// A snapshot holding an entry over the limits of the node restoring it is refused as a
+    /// whole, before any of its entries is written.
+    #[tokio::test]
+    async fn test_restore_limits() {
+        let dir = std::env::temp_dir().join(format!("restore-test-{}", unix_time_ms()));
+        let config = HttpServerConfig {
+            snapshot_dir: dir.clone(),
+            ..HttpServerConfig::new("127.0.0.1:0".to_string())
+        };
+        let (app, _receiver) = app_with(config.clone()).await;
+        for body in [
+            r#"{"key": "small", "value": "v"}"#,
+            r#"{"key": "big", "value": "0123456789"}"#,
+        ] {
+            let response = send(&app, Method::POST, "/add", None, body).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let snapshot = r#"{"name": "backup.json"}"#;
+        let response = send(&app, Method::POST, "/admin/snapshot", None, snapshot).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let (app, _receiver) = app_with(HttpServerConfig {
+            limits: Limits {
+                max_value_bytes: 4,
+                ..Limits::default()
+            },
+            ..config
+        })
+        .await;
+        let response = send(&app, Method::POST, "/admin/restore", None, snapshot).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let response = send(&app, Method::GET, "/query?key=small", None, "").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Unit test for `RouteTimeout`, `HttpServerConfig::request_timeout_for` and `with_timeout`.
+    ///
+    /// The bulk routes get `BULK_REQUEST_TIMEOUT` by default, a configured route timeout
+    /// replaces that of its route, and other routes get the request timeout. A write that
+    /// times out while waiting for room in the replication queue is answered with `503`, and
+    /// is still replicated.
+    #[tokio::test(start_paused = true)]
+    async fn test_route_timeouts() {
+        let mut config = HttpServerConfig::new("127.0.0.1:0".to_string());
+        assert_eq!(config.request_timeout_for("/export"), BULK_REQUEST_TIMEOUT);
+        assert_eq!(config.request_timeout_for("/query"), config.request_timeout);
+
+        config.set_route_timeout("/export=5".parse().unwrap());
+        config.set_route_timeout("/range=60".parse().unwrap());
+        assert_eq!(
+            config.request_timeout_for("/export"),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            config.request_timeout_for("/range"),
+            Duration::from_secs(60)
+        );
+        assert_eq!(config.request_timeout_for("/import"), BULK_REQUEST_TIMEOUT);
+        assert_eq!(config.route_timeouts.len(), 5);
+
+        assert!("/export".parse::<RouteTimeout>().is_err());
+        assert!("export=5".parse::<RouteTimeout>().is_err());
+        assert!("/export=soon".parse::<RouteTimeout>().is_err());
+
+        config.set_route_timeout("/add=1".parse().unwrap());
+        config.replication_queue = QueueOptions::new(1);
+        let (app, mut receiver) = app_with(config).await;
+        let response = send(
+            &app,
+            Method::POST,
+            "/add",
+            None,
+            r#"{"key": "hello", "value": "world"}"#,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "unavailable");
+        let (msg, _) = receiver.recv().await.unwrap();
+        assert_eq!((msg.cmd, msg.key.as_str()), (Command::Insert, "hello"));
+        let (msg, _) = receiver.recv().await.unwrap();
+        assert_eq!(msg.cmd, Command::Checkpoint);
+    }
 }
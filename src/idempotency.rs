@@ -0,0 +1,259 @@
+use axum::body::{Body, Bytes};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Configures how long the outcomes of idempotent requests are remembered.
+///
+/// # Fields
+///
+/// - `window`: How long a retry with the same `Idempotency-Key` is answered with the stored
+///   response. Idempotency keys are ignored when it is zero.
+/// - `capacity`: The maximum number of remembered keys. The oldest ones are forgotten first.
+#[derive(Debug, Clone)]
+pub struct IdempotencyOptions {
+    pub window: Duration,
+    pub capacity: usize,
+}
+
+impl Default for IdempotencyOptions {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(300),
+            capacity: 10_000,
+        }
+    }
+}
+
+/// The response a request completed with, replayed to its retries.
+#[derive(Debug, Clone)]
+pub struct StoredResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl IntoResponse for StoredResponse {
+    fn into_response(self) -> Response {
+        let mut response = Response::new(Body::from(self.body));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers;
+        response
+    }
+}
+
+/// What to do with a request carrying an idempotency key.
+#[derive(Debug)]
+pub enum Begin {
+    /// The key is new: the request must be applied, then completed with `IdempotencyStore::complete`.
+    Started,
+    /// A request with the same key is still being applied.
+    InFlight,
+    /// A request with the same key completed with this response.
+    Completed(StoredResponse),
+    /// The key was used by a request with another fingerprint, such as another body.
+    Mismatch,
+}
+
+#[derive(Debug)]
+enum Slot {
+    InFlight,
+    Completed(StoredResponse),
+}
+
+#[derive(Debug)]
+struct Entry {
+    slot: Slot,
+    started_at: Instant,
+    /// The fingerprint of the request that started the key.
+    fingerprint: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    entries: HashMap<String, Entry>,
+    /// The keys in the order they were started, to expire and evict the oldest ones first.
+    order: VecDeque<(Instant, String)>,
+}
+
+/// Remembers the outcome of recent requests by idempotency key, so a client retrying a request
+/// after a timeout gets the original response instead of the mutation being applied and
+/// replicated again.
+///
+/// # Example
+///
+/// ```rust
+/// let store = IdempotencyStore::new(IdempotencyOptions::default());
+/// if let Begin::Started = store.begin("POST /add 8e0f", &body_hash) {
+///     store.complete("POST /add 8e0f", response);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct IdempotencyStore {
+    options: IdempotencyOptions,
+    state: Mutex<State>,
+}
+
+impl IdempotencyStore {
+    /// Creates a new, empty `IdempotencyStore`.
+    pub fn new(options: IdempotencyOptions) -> Self {
+        Self {
+            options,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Returns whether idempotency keys are honoured.
+    pub fn enabled(&self) -> bool {
+        !self.options.window.is_zero() && self.options.capacity > 0
+    }
+
+    /// Looks up a key, marking it in flight if it is new.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The idempotency key, scoped as the caller sees fit.
+    /// * `fingerprint` - What identifies the request, such as a hash of its body. A key reused
+    ///   with another fingerprint is a `Mismatch`.
+    pub fn begin(&self, key: &str, fingerprint: &[u8]) -> Begin {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        self.evict(&mut state, now);
+
+        match state.entries.get(key) {
+            Some(entry) if entry.fingerprint != fingerprint => return Begin::Mismatch,
+            Some(Entry {
+                slot: Slot::InFlight,
+                ..
+            }) => return Begin::InFlight,
+            Some(Entry {
+                slot: Slot::Completed(response),
+                ..
+            }) => return Begin::Completed(response.clone()),
+            None => {}
+        }
+        state.entries.insert(
+            key.to_string(),
+            Entry {
+                slot: Slot::InFlight,
+                started_at: now,
+                fingerprint: fingerprint.to_vec(),
+            },
+        );
+        state.order.push_back((now, key.to_string()));
+        Begin::Started
+    }
+
+    /// Stores the response of a started request, to be replayed to its retries.
+    pub fn complete(&self, key: &str, response: StoredResponse) {
+        if let Some(entry) = self.state.lock().unwrap().entries.get_mut(key) {
+            entry.slot = Slot::Completed(response);
+        }
+    }
+
+    /// Forgets a started request that did not complete, so it can be retried.
+    pub fn abandon(&self, key: &str) {
+        let mut state = self.state.lock().unwrap();
+        if matches!(
+            state.entries.get(key).map(|entry| &entry.slot),
+            Some(Slot::InFlight)
+        ) {
+            state.entries.remove(key);
+        }
+    }
+
+    /// Forgets the keys started more than `window` ago, and the oldest keys over capacity.
+    fn evict(&self, state: &mut State, now: Instant) {
+        while let Some((started_at, _)) = state.order.front() {
+            let expired = now.duration_since(*started_at) >= self.options.window;
+            if !expired && state.order.len() < self.options.capacity {
+                break;
+            }
+            let (started_at, key) = state.order.pop_front().unwrap();
+            // The key may have been abandoned and started again since.
+            if state
+                .entries
+                .get(&key)
+                .is_some_and(|entry| entry.started_at == started_at)
+            {
+                state.entries.remove(&key);
+            }
+        }
+    }
+}
+
+/// Abandons a started request when dropped before being completed, for instance when the
+/// request times out, so its retries are not answered as in flight until the window ends.
+pub struct InFlightGuard {
+    store: Arc<IdempotencyStore>,
+    key: String,
+    completed: bool,
+}
+
+impl InFlightGuard {
+    /// Creates a guard for a request started with `IdempotencyStore::begin`.
+    pub fn new(store: Arc<IdempotencyStore>, key: String) -> Self {
+        Self {
+            store,
+            key,
+            completed: false,
+        }
+    }
+
+    /// Stores the response of the request.
+    pub fn complete(mut self, response: StoredResponse) {
+        self.store.complete(&self.key, response);
+        self.completed = true;
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.store.abandon(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(body: &'static str) -> StoredResponse {
+        StoredResponse {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: Bytes::from_static(body.as_bytes()),
+        }
+    }
+
+    /// Unit test for `IdempotencyStore`.
+    ///
+    /// A key is in flight until completed, then replays its response until the window ends,
+    /// unless reused with another fingerprint. An abandoned key can be started again.
+    #[test]
+    fn test_idempotency_store() {
+        let store = Arc::new(IdempotencyStore::new(IdempotencyOptions {
+            window: Duration::from_millis(50),
+            capacity: 2,
+        }));
+
+        assert!(matches!(store.begin("a", b"1"), Begin::Started));
+        assert!(matches!(store.begin("a", b"1"), Begin::InFlight));
+        InFlightGuard::new(store.clone(), "a".to_string()).complete(response("first"));
+        match store.begin("a", b"1") {
+            Begin::Completed(stored) => assert_eq!(stored.body, "first"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(store.begin("a", b"2"), Begin::Mismatch));
+
+        assert!(matches!(store.begin("b", b"1"), Begin::Started));
+        drop(InFlightGuard::new(store.clone(), "b".to_string()));
+        assert!(matches!(store.begin("b", b"2"), Begin::Started));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(matches!(store.begin("a", b"2"), Begin::Started));
+    }
+}
@@ -0,0 +1,148 @@
+use crate::utils::unix_time_ms;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Whether per-key statistics are collected, set by the `key-stats` feature. Recording is a
+/// no-op without it, so builds that do not need the statistics pay nothing for them.
+pub const ENABLED: bool = cfg!(feature = "key-stats");
+
+/// The access counters of a key.
+#[derive(Debug, Clone, Default)]
+struct Counters {
+    reads: u64,
+    writes: u64,
+    last_access_ms: u64,
+}
+
+impl Counters {
+    fn total(&self) -> u64 {
+        self.reads + self.writes
+    }
+}
+
+/// The access statistics of a key, as served at `/stats/keys`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KeyStat {
+    pub key: String,
+    pub reads: u64,
+    pub writes: u64,
+    /// Unix timestamp, in milliseconds, of the last read or write.
+    pub last_access_ms: u64,
+}
+
+/// Counts the reads and writes of each key served by this node.
+///
+/// At most `capacity` keys are tracked. When a new key would exceed it, the least accessed
+/// half of the keys is forgotten, so keys that dominate traffic stay tracked while the memory
+/// used stays bounded. Counts of keys that were forgotten and accessed again restart from zero.
+///
+/// # Example
+///
+/// ```rust
+/// let stats = KeyStats::new(10_000);
+/// stats.record_read("user:1");
+/// stats.record_write("user:1");
+/// println!("{:?}", stats.top(50));
+/// ```
+#[derive(Debug)]
+pub struct KeyStats {
+    capacity: usize,
+    keys: Mutex<HashMap<String, Counters>>,
+}
+
+impl KeyStats {
+    /// Creates an empty `KeyStats` tracking at most `capacity` keys.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            keys: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records a read of `key`.
+    pub fn record_read(&self, key: &str) {
+        self.record(key, |counters| counters.reads += 1);
+    }
+
+    /// Records a write, or a removal, of `key`.
+    pub fn record_write(&self, key: &str) {
+        self.record(key, |counters| counters.writes += 1);
+    }
+
+    fn record(&self, key: &str, count: impl FnOnce(&mut Counters)) {
+        if !ENABLED {
+            return;
+        }
+        let mut keys = self.keys.lock().unwrap();
+        if !keys.contains_key(key) && keys.len() >= self.capacity {
+            Self::prune(&mut keys, self.capacity / 2);
+        }
+        let counters = keys.entry(key.to_string()).or_default();
+        count(counters);
+        counters.last_access_ms = unix_time_ms();
+    }
+
+    /// Keeps at most the `keep` most accessed keys. Keys tied with the first key that does not
+    /// fit are forgotten too.
+    fn prune(keys: &mut HashMap<String, Counters>, keep: usize) {
+        let mut totals: Vec<u64> = keys.values().map(Counters::total).collect();
+        totals.sort_unstable_by(|a, b| b.cmp(a));
+        let threshold = totals.get(keep).copied().unwrap_or(0);
+        keys.retain(|_, counters| counters.total() > threshold);
+    }
+
+    /// Returns the `n` most accessed keys, by reads and writes combined, most accessed first.
+    pub fn top(&self, n: usize) -> Vec<KeyStat> {
+        let keys = self.keys.lock().unwrap();
+        let mut stats: Vec<KeyStat> = keys
+            .iter()
+            .map(|(key, counters)| KeyStat {
+                key: key.clone(),
+                reads: counters.reads,
+                writes: counters.writes,
+                last_access_ms: counters.last_access_ms,
+            })
+            .collect();
+        stats.sort_by(|a, b| {
+            (b.reads + b.writes)
+                .cmp(&(a.reads + a.writes))
+                .then_with(|| a.key.cmp(&b.key))
+        });
+        stats.truncate(n);
+        stats
+    }
+}
+
+#[cfg(all(test, feature = "key-stats"))]
+mod tests {
+    use super::*;
+
+    /// Unit test for `KeyStats`.
+    ///
+    /// Keys are ranked by accesses, and the least accessed ones are forgotten at capacity.
+    #[test]
+    fn test_key_stats() {
+        let stats = KeyStats::new(4);
+        for _ in 0..3 {
+            stats.record_read("hot");
+        }
+        stats.record_write("hot");
+        stats.record_write("warm");
+        stats.record_read("warm");
+        stats.record_read("cold-1");
+        stats.record_read("cold-2");
+
+        let top = stats.top(2);
+        assert_eq!(
+            top.iter()
+                .map(|stat| (stat.key.as_str(), stat.reads, stat.writes))
+                .collect::<Vec<_>>(),
+            vec![("hot", 3, 1), ("warm", 1, 1)]
+        );
+
+        stats.record_read("new");
+        let keys: Vec<String> = stats.top(10).into_iter().map(|stat| stat.key).collect();
+        assert_eq!(keys, vec!["hot", "warm", "new"]);
+    }
+}
@@ -1,7 +1,45 @@
+pub mod acl;
+pub mod anomaly;
+pub mod audit;
+pub mod bench;
 pub mod cache_trait;
+pub mod chaos;
+pub mod cluster_auth;
+pub mod codec;
+pub mod cold_tier;
+pub mod document;
+pub mod error;
+pub mod events;
 pub mod foyer_cache;
 pub mod gossip;
+pub mod hints;
+pub mod hot_keys;
 pub mod http_server;
+pub mod idempotency;
+pub mod key_stats;
+pub mod limits;
 pub mod log;
 pub mod moka_cache;
+pub mod ndjson;
+pub mod negative_cache;
+pub mod openapi;
+pub mod preload;
+pub mod queue;
+pub mod quota;
+pub mod redis_cache;
+pub mod retry;
+pub mod ring;
+pub mod sequence;
+pub mod session;
+pub mod sled_cache;
+pub mod slowlog;
+pub mod snapshot;
+pub mod staleness;
+pub mod tags;
+pub mod topology;
+pub mod transport;
+pub mod typed_value;
 pub mod utils;
+pub mod wal;
+pub mod wire;
+pub mod write_behind;
@@ -0,0 +1,207 @@
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::fmt;
+
+/// The characters a key may contain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum KeyPolicy {
+    /// Any UTF-8 character.
+    Any,
+    /// Any UTF-8 character except control characters, so keys are safe to log and display.
+    #[default]
+    Printable,
+    /// ASCII letters and digits, and `-`, `_`, `.`, `~`, `/` and `:`, so keys can be used
+    /// unescaped in URLs and object store paths.
+    UrlSafe,
+}
+
+impl KeyPolicy {
+    /// Returns whether a key may contain a character.
+    fn allows(self, c: char) -> bool {
+        match self {
+            KeyPolicy::Any => true,
+            KeyPolicy::Printable => !c.is_control(),
+            KeyPolicy::UrlSafe => c.is_ascii_alphanumeric() || "-_.~/:".contains(c),
+        }
+    }
+}
+
+/// Limits on the keys and values accepted by a node, so a single client cannot exhaust the
+/// memory of every node or the size of gossip frames.
+///
+/// # Fields
+///
+/// - `max_key_bytes`: The maximum length of a key, in bytes.
+/// - `max_value_bytes`: The maximum size of a value, in bytes.
+/// - `key_policy`: The characters a key may contain.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    pub max_key_bytes: usize,
+    pub max_value_bytes: usize,
+    pub key_policy: KeyPolicy,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_key_bytes: 1024,
+            max_value_bytes: 1024 * 1024,
+            key_policy: KeyPolicy::default(),
+        }
+    }
+}
+
+/// The reason a key or value was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    EmptyKey,
+    KeyTooLong {
+        len: usize,
+        max: usize,
+    },
+    ValueTooLarge {
+        len: usize,
+        max: usize,
+    },
+    /// The key contains a character its `KeyPolicy` does not allow, at the given byte offset.
+    InvalidKeyCharacter {
+        position: usize,
+    },
+}
+
+impl Violation {
+    /// Returns whether the violation is about size, as opposed to the content of the key.
+    pub fn is_too_large(&self) -> bool {
+        matches!(
+            self,
+            Violation::KeyTooLong { .. } | Violation::ValueTooLarge { .. }
+        )
+    }
+
+    /// Returns a machine-readable description of the violation: its `error` code and, depending
+    /// on the violation, the `limit` and `actual` sizes or the `position` of the character.
+    pub fn details(&self) -> HashMap<String, String> {
+        let (error, fields) = match self {
+            Violation::EmptyKey => ("empty_key", vec![]),
+            Violation::KeyTooLong { len, max } => (
+                "key_too_long",
+                vec![("limit", max.to_string()), ("actual", len.to_string())],
+            ),
+            Violation::ValueTooLarge { len, max } => (
+                "value_too_large",
+                vec![("limit", max.to_string()), ("actual", len.to_string())],
+            ),
+            Violation::InvalidKeyCharacter { position } => (
+                "invalid_key_character",
+                vec![("position", position.to_string())],
+            ),
+        };
+
+        let mut details: HashMap<String, String> = fields
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        details.insert("error".to_string(), error.to_string());
+        details
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::EmptyKey => write!(f, "key is empty"),
+            Violation::KeyTooLong { len, max } => {
+                write!(f, "key is {} bytes long, the limit is {}", len, max)
+            }
+            Violation::ValueTooLarge { len, max } => {
+                write!(f, "value is {} bytes, the limit is {}", len, max)
+            }
+            Violation::InvalidKeyCharacter { position } => {
+                write!(f, "key has a disallowed character at byte {}", position)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Violation {}
+
+impl Limits {
+    /// Checks a key against the length limit and the key policy.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first `Violation` found.
+    pub fn check_key(&self, key: &str) -> Result<(), Violation> {
+        if key.is_empty() {
+            return Err(Violation::EmptyKey);
+        }
+        if key.len() > self.max_key_bytes {
+            return Err(Violation::KeyTooLong {
+                len: key.len(),
+                max: self.max_key_bytes,
+            });
+        }
+        match key
+            .char_indices()
+            .find(|(_, c)| !self.key_policy.allows(*c))
+        {
+            Some((position, _)) => Err(Violation::InvalidKeyCharacter { position }),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks a key and the value written to it.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first `Violation` found.
+    pub fn check_entry(&self, key: &str, value: &str) -> Result<(), Violation> {
+        self.check_key(key)?;
+        if value.len() > self.max_value_bytes {
+            return Err(Violation::ValueTooLarge {
+                len: value.len(),
+                max: self.max_value_bytes,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit test for `Limits::check_entry` with each `KeyPolicy`.
+    #[test]
+    fn test_check_entry() {
+        let limits = Limits {
+            max_key_bytes: 8,
+            max_value_bytes: 4,
+            key_policy: KeyPolicy::UrlSafe,
+        };
+        assert_eq!(limits.check_entry("user/1", "abcd"), Ok(()));
+        assert_eq!(limits.check_key(""), Err(Violation::EmptyKey));
+        assert_eq!(
+            limits.check_key("user/1234"),
+            Err(Violation::KeyTooLong { len: 9, max: 8 })
+        );
+        assert_eq!(
+            limits.check_entry("user/1", "abcde"),
+            Err(Violation::ValueTooLarge { len: 5, max: 4 })
+        );
+        assert_eq!(
+            limits.check_key("a b"),
+            Err(Violation::InvalidKeyCharacter { position: 1 })
+        );
+
+        let limits = Limits {
+            key_policy: KeyPolicy::Printable,
+            ..limits
+        };
+        assert_eq!(limits.check_key("a b"), Ok(()));
+        assert_eq!(
+            limits.check_key("a\nb"),
+            Err(Violation::InvalidKeyCharacter { position: 1 })
+        );
+    }
+}
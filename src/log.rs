@@ -1,10 +1,97 @@
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use opentelemetry::global;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::Span;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
-use tracing_subscriber::{fmt, EnvFilter};
+use tracing_subscriber::{fmt, EnvFilter, Layer};
+
+/// The format of emitted log lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable text, ANSI-colored when writing to stdout.
+    #[default]
+    Text,
+    /// One JSON object per line, for log aggregation pipelines.
+    Json,
+}
+
+/// How often the log file is rotated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Minutely => Rotation::MINUTELY,
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
+
+/// Configuration for the tracing output.
+///
+/// # Fields
+///
+/// - `format`: The format of emitted log lines.
+/// - `file`: An optional log file. When set, logs are written to this file (through a
+///   non-blocking appender) instead of stdout.
+/// - `rotation`: How often the log file is rotated. Rotated files get a date suffix.
+/// - `max_files`: An optional number of rotated log files to keep; older files are deleted.
+/// - `otlp_endpoint`: An optional OTLP/HTTP traces endpoint (e.g. `http://localhost:4318/v1/traces`).
+///   When set, spans are exported there and W3C trace context is propagated across nodes.
+/// - `service_name`: The service name reported with exported spans, usually the node name.
+#[derive(Debug, Clone, Default)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    pub file: Option<PathBuf>,
+    pub rotation: LogRotation,
+    pub max_files: Option<usize>,
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+}
+
+/// Keeps the tracing output alive; returned by `setup_tracing`.
+///
+/// Dropping the guard flushes buffered log lines and exports any pending spans,
+/// so it must be held for the lifetime of the application.
+pub struct TracingGuard {
+    _worker_guard: Option<WorkerGuard>,
+    tracer_provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for TracingGuard {
+    fn drop(&mut self) {
+        if let Some(tracer_provider) = self.tracer_provider.take() {
+            if let Err(e) = tracer_provider.shutdown() {
+                eprintln!("Failed to shut down the OTLP tracer provider: {:?}", e);
+            }
+        }
+    }
+}
 
 /// Sets up tracing for the application using `tracing_subscriber`.
 ///
-/// This function configures the tracing system with two layers:
+/// This function configures the tracing system with the following layers:
 ///
 /// - `fmt::layer()`: Provides structured formatting of tracing events, enabling
 ///   features such as ANSI-colored output and logging the target and log level.
@@ -17,7 +104,9 @@ use tracing_subscriber::{fmt, EnvFilter};
 /// # Layers
 ///
 /// - `fmt::layer()`:
-///   - Enables ANSI coloring for the log output.
+///   - Emits text or JSON lines, depending on `config.format`.
+///   - Writes to stdout, or to a rotating log file when `config.file` is set.
+///   - Enables ANSI coloring for text output on stdout.
 ///   - Logs the target of each log message (the module or crate where it originated).
 ///   - Logs the level (e.g., `info`, `debug`, `error`) of each message.
 ///
@@ -25,16 +114,34 @@ use tracing_subscriber::{fmt, EnvFilter};
 ///   - Attempts to read the log level from the environment using `RUST_LOG`.
 ///   - If no environment variable is found, it defaults to `debug`.
 ///
+/// - `tracing_opentelemetry` (only when `config.otlp_endpoint` is set):
+///   - Exports spans to the OTLP endpoint in batches.
+///   - Installs the W3C trace-context propagator used by `current_trace_context` and
+///     `set_parent_context`.
+///
+/// # Arguments
+///
+/// * `config` - The log format and output settings.
+///
+/// # Returns
+///
+/// * `Ok(TracingGuard)` - A guard that must be kept alive for the lifetime of the application.
+///
+/// # Errors
+///
+/// - Returns an error if the log file path has no file name or the log file cannot be created.
+/// - Returns an error if the OTLP exporter cannot be built.
+///
 /// # Example
 ///
 /// ```rust
-/// setup_tracing();
+/// let _guard = setup_tracing(&LogConfig::default())?;
 /// tracing::info!("Application started");
 /// ```
 ///
 /// # Panics
 ///
-/// - The function will panic if there is an error setting up the `EnvFilter` from the environment.
+/// - The function will panic if a global subscriber has already been set.
 ///
 /// # Environment Variables
 ///
@@ -43,17 +150,105 @@ use tracing_subscriber::{fmt, EnvFilter};
 ///   RUST_LOG=info ./my_app
 ///   ```
 ///
-pub fn setup_tracing() {
+pub fn setup_tracing(config: &LogConfig) -> Result<TracingGuard> {
+    let (writer, worker_guard) = match &config.file {
+        Some(path) => {
+            let (writer, guard) = tracing_appender::non_blocking(file_appender(config, path)?);
+            (BoxMakeWriter::new(writer), Some(guard))
+        }
+        None => (BoxMakeWriter::new(std::io::stdout), None),
+    };
+
     let fmt_layer = fmt::layer()
         .with_target(true)
-        .with_ansi(true)
-        .with_level(true);
+        .with_ansi(config.file.is_none())
+        .with_level(true)
+        .with_writer(writer);
+    let fmt_layer = match config.format {
+        LogFormat::Text => fmt_layer.boxed(),
+        LogFormat::Json => fmt_layer.json().boxed(),
+    };
 
     let filter_layer =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug"));
 
+    let tracer_provider = match &config.otlp_endpoint {
+        Some(endpoint) => Some(otlp_tracer_provider(endpoint, &config.service_name)?),
+        None => None,
+    };
+    let otel_layer = tracer_provider.as_ref().map(|tracer_provider| {
+        tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("http-distributed-kv"))
+    });
+
     tracing_subscriber::registry()
         .with(filter_layer)
         .with(fmt_layer)
+        .with(otel_layer)
         .init();
+
+    Ok(TracingGuard {
+        _worker_guard: worker_guard,
+        tracer_provider,
+    })
+}
+
+/// Builds a tracer provider exporting spans to an OTLP/HTTP endpoint, and installs the
+/// W3C trace-context propagator.
+fn otlp_tracer_provider(endpoint: &str, service_name: &str) -> Result<SdkTracerProvider> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()?;
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            Resource::builder()
+                .with_service_name(service_name.to_string())
+                .build(),
+        )
+        .build())
+}
+
+/// Returns the trace context of the current span as a W3C trace-context carrier.
+///
+/// The carrier is empty when OTLP export is disabled or there is no active span.
+pub fn current_trace_context() -> HashMap<String, String> {
+    let mut carrier = HashMap::new();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&Span::current().context(), &mut carrier)
+    });
+    carrier
+}
+
+/// Makes the trace context in `carrier`, as produced by `current_trace_context` on another node,
+/// the parent of `span`.
+pub fn set_parent_context(span: &Span, carrier: &HashMap<String, String>) {
+    let parent = global::get_text_map_propagator(|propagator| propagator.extract(carrier));
+    let _ = span.set_parent(parent);
+}
+
+/// Builds the rotating file appender for `path`.
+///
+/// The file name of `path` is used as the prefix of the rotated files, which are created
+/// in its parent directory.
+fn file_appender(config: &LogConfig, path: &Path) -> Result<RollingFileAppender> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid log file path: {}", path.display()))?;
+    let directory = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+
+    let mut builder = RollingFileAppender::builder()
+        .rotation(config.rotation.into())
+        .filename_prefix(file_name.to_string_lossy());
+    if let Some(max_files) = config.max_files {
+        builder = builder.max_log_files(max_files);
+    }
+
+    Ok(builder.build(directory)?)
 }
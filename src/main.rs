@@ -1,18 +1,72 @@
-use clap::Parser;
-use std::sync::Arc;
-mod cache_trait;
-mod foyer_cache;
-mod gossip;
-mod http_server;
-mod log;
-mod utils;
-
-use crate::cache_trait::{sync_data, BCache};
-use crate::foyer_cache::FoyerCache;
-use crate::gossip::{GossipNode, GossipodConfig};
 use anyhow::Result;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
-use tracing::info;
+use tracing::{info, warn};
+use untitled::acl::AclToken;
+use untitled::anomaly::{AnomalyConfig, AnomalyDetector};
+use untitled::audit::{AuditLog, ValueRedaction};
+use untitled::bench::{self, BenchOptions};
+use untitled::cache_trait::{sync_data, BCache, CacheBackend, CacheConfig, SyncContext};
+use untitled::chaos::{Chaos, ChaosCache, ChaosOptions};
+use untitled::cluster_auth::{ClusterAuth, ClusterSecret};
+use untitled::codec::{DecodeLimits, WireCodec};
+use untitled::cold_tier::{ColdTierCache, ColdTierOptions, KeyLayout};
+use untitled::events::{KeyEvents, DEFAULT_EVENTS_CAPACITY};
+use untitled::foyer_cache::FoyerCache;
+use untitled::gossip::{BatchOptions, GossipNode, GossipodConfig, Network, ProtocolOptions};
+use untitled::hints::HintOptions;
+use untitled::hot_keys::HotKeyOptions;
+use untitled::http_server::{HttpServerConfig, HttpVersion, RouteTimeout, ServerDeps};
+use untitled::idempotency::IdempotencyOptions;
+use untitled::limits::{KeyPolicy, Limits};
+use untitled::log::{LogConfig, LogFormat, LogRotation};
+use untitled::moka_cache::MokaCache;
+use untitled::negative_cache::{NamespaceTtl, NegativeCache, NegativeCacheOptions};
+use untitled::preload::{self, PreloadFormat};
+use untitled::queue::{OverflowPolicy, QueueOptions};
+use untitled::quota::{NamespaceQuota, QuotaCache, Quotas};
+use untitled::redis_cache::{RedisCache, RedisOptions};
+use untitled::retry::RetryOptions;
+use untitled::session::Sessions;
+use untitled::sled_cache::SledCache;
+use untitled::slowlog::SlowLog;
+use untitled::snapshot::{SnapshotStore, PERIODIC_SNAPSHOT};
+use untitled::staleness::Staleness;
+use untitled::tags::TagIndex;
+use untitled::topology::{NodeInfo, Topology, DEFAULT_VNODES, MAX_VNODES};
+use untitled::wal::{FsyncPolicy, Wal, WalOptions};
+use untitled::wire::{Compression, WireOptions};
+use untitled::write_behind::{WriteBehindCache, WriteBehindOptions};
+use untitled::{http_server, log, snapshot, wal, wire, write_behind};
+
+/// The command line of the application: either the arguments of a node, or a subcommand.
+// Without any argument, the usage is printed rather than a node started without its arguments.
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    arg_required_else_help = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    node: Option<Args>,
+}
+
+/// The subcommands of the application.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Drives the HTTP API of running nodes and reports throughput and latency percentiles.
+    Bench(BenchOptions),
+}
 
 /// Command-line arguments for the application.
 ///
@@ -25,13 +79,238 @@ use tracing::info;
 /// - `name`: The name of the Gossip node, passed using `-n` or `--name`.
 /// - `http_addr`: The address for the HTTP server, passed using `--http-addr`.
 ///   Defaults to `0.0.0.0:3001`.
+/// - `advertise_http_addr`: The address clients reach the HTTP server at, announced to the other
+///   members and served at `/topology`, passed using `--advertise-http-addr`. Defaults to `http_addr`.
+/// - `vnodes`: The number of tokens this node takes on the hash ring, announced to the other members,
+///   passed using `--vnodes`. Larger machines can take more to own proportionally more keys. Defaults to `64`.
 /// - `gossip_addr`: The address for the Gossip protocol, passed using `-g` or `--gossip-addr`.
 ///   Defaults to `0.0.0.0:4001`.
 /// - `cache_capacity`: The maximum capacity for the in-memory cache, passed using `-c` or `--cache-capacity`.
 ///   Defaults to `128`.
-/// - `gossip_join_addr`: An optional address for joining an existing Gossip network, passed using `--gossip-join-addr`.
-#[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+/// - `cache_capacity_bytes`: An optional capacity in bytes, passed using `--cache-capacity-bytes`.
+///   When set, entries are weighted by size and this replaces `cache_capacity`.
+/// - `tti`: An optional time-to-idle in seconds, passed using `--tti`. Entries not read within
+///   this window expire, which is streamed to the subscribers of `/events`.
+/// - `gossip_expirations`: Whether entries expiring through `--tti` are removed from the other
+///   nodes too, passed using `--gossip-expirations`. Disabled by default.
+/// - `events_capacity`: The number of events kept for `/events` subscribers that fall behind,
+///   passed using `--events-capacity`. Defaults to `1024`.
+/// - `cache_backend`: The storage of the cache (`foyer`, `moka` or `sled`), passed using `--cache-backend`.
+///   Defaults to `foyer`. The `sled` backend persists entries on disk and ignores the capacity and
+///   time-to-idle settings.
+/// - `sled_path`: The directory of the `sled` database, passed using `--sled-path`. Defaults to `data.sled`.
+/// - `gossip_join_addr`: The addresses of members of an existing Gossip network to join, passed using
+///   `--gossip-join-addr` as a comma-separated list. Each is tried in turn, retrying with backoff until
+///   one can be joined. The node runs standalone when none is given.
+/// - `gossip_wire_version`: The gossip protocol version this node emits, passed using `--gossip-wire-version`.
+///   Defaults to the latest version. Pin it to the previous version while a cluster is upgraded node by node.
+/// - `gossip_compression`: The compression of large gossip payloads (`none`, `lz4` or `zstd`), passed using
+///   `--gossip-compression`. Defaults to `lz4`.
+/// - `gossip_compression_threshold`: The payload size in bytes from which gossip payloads are compressed,
+///   passed using `--gossip-compression-threshold`. Defaults to `1024`.
+/// - `gossip_max_frame_bytes`: The gossip frame size in bytes above which frames are split into chunks,
+///   passed using `--gossip-max-frame-bytes`. Defaults to `1400`.
+/// - `gossip_codec`: The serialization of gossip payloads (`bincode`, `json` or `protobuf`), passed using
+///   `--gossip-codec`. Defaults to `bincode`. Frames of every codec are decoded, whichever is set.
+/// - `gossip_max_payload_bytes`: The maximum size in bytes of a received gossip frame, and of its payload
+///   once decompressed, passed using `--gossip-max-payload-bytes`. Defaults to `16777216`.
+/// - `gossip_max_messages`: The maximum number of messages in a received gossip frame, passed using
+///   `--gossip-max-messages`. Defaults to `10000`.
+/// - `gossip_batch_window_ms`: How long replicated writes are buffered to be sent as one batched gossip
+///   frame, in milliseconds, passed using `--gossip-batch-window-ms`. Defaults to `10`; `0` disables batching.
+/// - `gossip_batch_max_messages`: The number of buffered writes that sends a batch before its window ends,
+///   passed using `--gossip-batch-max-messages`. Defaults to `128`.
+/// - `gossip_fanout`: The number of members a write for the whole cluster is sent to, each relaying it on to a
+///   share of the others, passed using `--gossip-fanout`. Defaults to `3`; `0` sends such writes to every member.
+///   Either way writes are acknowledged, resent and applied in order. Nodes emitting a wire version before 18
+///   send such writes to every member.
+/// - `gossip_network`: The kind of network the cluster runs on (`local`, `lan` or `wan`), passed using
+///   `--gossip-network`. Defaults to `local`. It sets the defaults of the failure detection timings below.
+/// - `gossip_probing_interval_ms`: An optional interval between probes of random members in milliseconds,
+///   passed using `--gossip-probing-interval-ms`.
+/// - `gossip_ack_timeout_ms`: An optional timeout of direct probes in milliseconds, passed using
+///   `--gossip-ack-timeout-ms`.
+/// - `gossip_indirect_ack_timeout_ms`: An optional timeout of probes relayed through other members in
+///   milliseconds, passed using `--gossip-indirect-ack-timeout-ms`.
+/// - `gossip_suspicious_timeout_ms`: An optional time a member stays suspected before it is declared dead,
+///   in milliseconds, passed using `--gossip-suspicious-timeout-ms`.
+/// - `gossip_hint_ttl_secs`: How long writes for a member that is down are kept to be replayed when it returns,
+///   in seconds, passed using `--gossip-hint-ttl-secs`. Defaults to `3600`.
+/// - `gossip_hint_capacity`: The number of writes kept per member that is down, passed using
+///   `--gossip-hint-capacity`. Defaults to `10000`; the oldest are dropped beyond it.
+/// - `gossip_retry_max_attempts`: How many times a replicated batch is sent until a member acknowledges it,
+///   passed using `--gossip-retry-max-attempts`. Defaults to `8`.
+/// - `gossip_retry_queue_capacity`: The number of unacknowledged batches kept for resending, passed using
+///   `--gossip-retry-queue-capacity`. Defaults to `10000`; batches beyond it are dropped and logged.
+/// - `gossip_receive_queue_capacity`: The number of received frames buffered until they are applied,
+///   passed using `--gossip-receive-queue-capacity`. Defaults to `1000`.
+/// - `gossip_receive_queue_overflow`: What happens to frames received while that buffer is full,
+///   passed using `--gossip-receive-queue-overflow`: `block` stalls the membership protocol until
+///   there is room, `drop-oldest` and `reject` drop the oldest or the newest frame, which the
+///   sender resends when it is not acknowledged. Defaults to `block`.
+/// - `cluster_secret`: The secret shared by the members of the cluster, passed using `--cluster-secret`.
+///   Gossip frames are authenticated with it once set, and members that cannot authenticate are
+///   left out of replication, with their rejected frames logged and counted at `/cluster/auth`.
+///   Every member must be started with the same secret.
+/// - `http_replication_queue_capacity`: The number of writes made through the HTTP API buffered
+///   until they are replicated, passed using `--http-replication-queue-capacity`. Defaults to `100`.
+/// - `http_replication_queue_overflow`: What happens to writes made while that buffer is full,
+///   passed using `--http-replication-queue-overflow`: `block` stalls the handler until there is
+///   room, `drop-oldest` drops the oldest write from replication, leaving it to anti-entropy, and
+///   `reject` answers the request with `503 Service Unavailable` before applying it. Overflows are
+///   counted at `/stats/queues`. Defaults to `block`.
+/// - `session_wait_ms`: How long a read carrying an `X-Session-Token` header waits for this node to
+///   apply the writes it covers before it is proxied to the node that accepted them, in milliseconds,
+///   passed using `--session-wait-ms`. Defaults to `500`.
+/// - `acl_token`: A bearer token of the HTTP API and the keys it may read, write or administer,
+///   passed as `<token>=<permission>:<prefix>[,...]`, such as `s3cr3t=write:sessions:*,read:*`,
+///   using `--acl-token`, which can be repeated. Every request must carry one of the tokens in an
+///   `Authorization: Bearer` header once any is set. Admin grants on `*` open the operational routes.
+/// - `http_request_timeout`: The maximum time in seconds an HTTP request may take to produce its
+///   response headers, passed using `--http-request-timeout`. Slower requests get `503` but still
+///   complete, and the body streamed by `/export` is not bounded. Defaults to `30`.
+/// - `http_route_timeout`: The maximum duration of the requests to a route, passed as `<path>=<seconds>`,
+///   such as `/import=600`, using `--http-route-timeout`, which can be repeated. `/import`, `/export`,
+///   `/admin/snapshot` and `/admin/restore` default to `300`, and other routes to `--http-request-timeout`.
+/// - `http_max_in_flight`: The maximum number of HTTP requests processed concurrently, passed using
+///   `--http-max-in-flight`. Defaults to `1024`.
+/// - `http_max_body_bytes`: The maximum HTTP request body size in bytes, passed using
+///   `--http-max-body-bytes`. Defaults to `2097152` (2 MiB).
+/// - `http_version`: The HTTP versions accepted (`auto`, `http1` or `http2`), passed using `--http-version`.
+///   Defaults to `auto`, which serves HTTP/1.1 and HTTP/2 with prior knowledge.
+/// - `http_keep_alive_timeout_secs`: How long an idle connection is kept open in seconds, passed using
+///   `--http-keep-alive-timeout-secs`. Defaults to `60`; `0` disables keep-alive.
+/// - `http_header_read_timeout_secs`: How long an HTTP/1 connection may take to send the headers of a request
+///   in seconds, passed using `--http-header-read-timeout-secs`. Defaults to `30`, with or without keep-alive.
+/// - `http_max_connections`: The maximum number of open HTTP connections, passed using
+///   `--http-max-connections`. Defaults to `10000`.
+/// - `http_backlog`: The listen backlog of the HTTP socket, passed using `--http-backlog`. Defaults to `1024`.
+/// - `admin_addr`: An optional separate address for the `/admin/*`, `/debug/*`, `/cluster/*` and `/stats/*` routes,
+///   passed using `--admin-addr`. They are served on `http_addr` when unset.
+/// - `cors_allow_origin`: The origins browsers may call the API from, passed using `--cors-allow-origin`
+///   as a comma-separated list, or `*` for any origin. Cross-origin requests are blocked when unset.
+/// - `cors_allow_methods`: The methods allowed in cross-origin requests, passed using
+///   `--cors-allow-methods` as a comma-separated list. Defaults to `GET,POST,PUT,PATCH,DELETE`.
+/// - `cors_allow_headers`: The request headers allowed in cross-origin requests, passed using
+///   `--cors-allow-headers` as a comma-separated list, or `*` for any header.
+///   Defaults to `authorization,content-type,idempotency-key,if-match,if-none-match,x-request-id,x-session-token`.
+/// - `max_key_bytes`: The maximum length of a key in bytes, passed using `--max-key-bytes`. Defaults to `1024`.
+/// - `max_value_bytes`: The maximum size of a value in bytes, passed using `--max-value-bytes`.
+///   Defaults to `1048576`. Writes over either limit are rejected with `413`, over HTTP and gossip alike.
+/// - `key_policy`: The characters a key may contain, passed using `--key-policy`: `any`, `printable`
+///   or `url-safe`. Defaults to `printable`. Writes breaking it are rejected with `422`.
+/// - `idempotency_window_secs`: How long the responses of `/add` and `/delete` requests carrying an
+///   `Idempotency-Key` header are replayed to their retries, passed using `--idempotency-window-secs`.
+///   Defaults to `300`. `0` disables idempotency keys.
+/// - `idempotency_capacity`: The maximum number of remembered idempotency keys, passed using
+///   `--idempotency-capacity`. Defaults to `10000`.
+/// - `key_stats_capacity`: The maximum number of keys whose reads and writes are counted, passed
+///   using `--key-stats-capacity`. Defaults to `10000`. Only used when built with the `key-stats` feature.
+/// - `hot_key_threshold`: The reads per second from which a key is hot and listed at
+///   `/stats/hotkeys`, passed using `--hot-key-threshold`. Defaults to `1000`.
+/// - `hot_key_window_secs`: The sliding window, in seconds, read rates are measured over, passed
+///   using `--hot-key-window-secs`. Defaults to `10`.
+/// - `hot_key_cache_ttl_ms`: How long, in milliseconds, `/query` serves a hot key from a small
+///   read cache instead of the cache backend, passed using `--hot-key-cache-ttl-ms`. Writes
+///   replicated from other nodes may be seen this much later on hot keys. Defaults to `0`, which
+///   disables the read cache.
+/// - `snapshot_dir`: The directory `/admin/snapshot` writes snapshots to and `/admin/restore` reads
+///   them from, passed using `--snapshot-dir`. Defaults to `snapshots`. On startup, the node loads
+///   `latest.json` from this directory, if present, before joining the cluster.
+/// - `snapshot_interval_secs`: An optional interval in seconds at which the cache is written to
+///   `latest.json` in the snapshot directory, passed using `--snapshot-interval-secs`. Disabled when unset.
+/// - `wal_dir`: An optional directory for the write-ahead log, passed using `--wal-dir`. When set, every
+///   accepted mutation is logged before it is acknowledged, and the records not covered by `latest.json`
+///   are replayed on startup. Disabled when unset.
+/// - `wal_fsync`: When write-ahead log records are synced to disk (`always`, `interval` or `never`),
+///   passed using `--wal-fsync`. Defaults to `always`.
+/// - `wal_segment_bytes`: The size of a write-ahead log segment in bytes, passed using `--wal-segment-bytes`.
+///   Defaults to `67108864` (64 MiB).
+/// - `preload_file`: An optional file of entries loaded into the cache on startup, before the HTTP server
+///   accepts requests, passed using `--preload-file`. Keys restored from `latest.json` or the write-ahead
+///   log keep their value, so the same file can be preloaded on every start. Disabled when unset.
+/// - `preload_format`: The format of the preload file (`json`, `ndjson` or `csv`), passed using
+///   `--preload-format`. Told from the extension of the file when unset.
+/// - `preload_gossip`: Whether the preloaded entries are also gossiped to the other members, which
+///   insert those they do not hold, passed using `--preload-gossip`. The node waits up to 10 seconds
+///   for a member to join before it starts the HTTP server. Defaults to `false`.
+/// - `cold_tier_bucket`: An optional S3-compatible bucket every entry is offloaded to, passed using
+///   `--cold-tier-bucket`. Misses in memory are then fetched from it. Credentials are read from the
+///   `AWS_*` environment variables. Disabled when unset.
+/// - `cold_tier_prefix`: The prefix of the objects in the bucket, passed using `--cold-tier-prefix`.
+///   Defaults to `kv`.
+/// - `cold_tier_layout`: How objects are laid out under the prefix (`flat` or `hashed`), passed using
+///   `--cold-tier-layout`. Defaults to `flat`.
+/// - `cold_tier_endpoint`: An optional endpoint of an S3-compatible service, passed using `--cold-tier-endpoint`.
+/// - `cold_tier_region`: An optional bucket region, passed using `--cold-tier-region`.
+/// - `cold_tier_concurrency`: The maximum number of concurrent uploads and deletes, passed using
+///   `--cold-tier-concurrency`. Defaults to `8`.
+/// - `redis_url`: An optional Redis or Valkey server the cache writes through to and reads misses
+///   from, passed using `--redis-url`. The local cache then acts as a near-cache. Disabled when unset.
+/// - `redis_pool_size`: The number of connections to Redis, passed using `--redis-pool-size`. Defaults to `4`.
+/// - `redis_connect_timeout_ms`: The Redis connection timeout in milliseconds, passed using
+///   `--redis-connect-timeout-ms`. Defaults to `1000`.
+/// - `redis_response_timeout_ms`: The Redis command timeout in milliseconds, passed using
+///   `--redis-response-timeout-ms`. Defaults to `500`.
+/// - `redis_key_prefix`: A prefix added to the keys stored in Redis, passed using `--redis-key-prefix`.
+/// - `write_behind_url`: An optional store mutations are persisted to in the background, passed using
+///   `--write-behind-url`: `redis://<host>` or `s3://<bucket>/<prefix>`. Disabled when unset.
+/// - `write_behind_queue_capacity`: The number of mutations waiting to be persisted before writers wait,
+///   passed using `--write-behind-queue-capacity`. Defaults to `10000`.
+/// - `write_behind_batch_size`: The maximum number of mutations persisted in one batch, passed using
+///   `--write-behind-batch-size`. Defaults to `100`.
+/// - `write_behind_flush_interval_ms`: The maximum time in milliseconds a mutation waits to be persisted,
+///   passed using `--write-behind-flush-interval-ms`. Defaults to `100`.
+/// - `write_behind_max_attempts`: The number of times a batch is written before it is dropped, passed using
+///   `--write-behind-max-attempts`. Defaults to `5`.
+/// - `negative_cache_ttl_ms`: How long, in milliseconds, a read of a missing key is remembered, so
+///   repeated reads of it do not reach Redis or the cold tier, passed using `--negative-cache-ttl-ms`.
+///   Defaults to `0`, which disables the negative cache.
+/// - `negative_cache_namespace`: The time-to-live of the misses of the keys starting with a prefix,
+///   passed as `<prefix>=<milliseconds>` using `--negative-cache-namespace`, which can be repeated.
+/// - `negative_cache_capacity`: The maximum number of remembered misses, passed using
+///   `--negative-cache-capacity`. Defaults to `10000`.
+/// - `namespace_quota`: The maximum number of entries and bytes of the keys starting with a prefix,
+///   and whether writes exceeding them are rejected or evict the oldest entries of the namespace,
+///   passed as `<prefix>=entries=<count>,bytes=<bytes>,policy=<reject|evict>` using
+///   `--namespace-quota`, which can be repeated. Usage is reported at `/stats/namespaces`. Evictions are
+///   published at `/events` and audited but not replicated, so the nodes of a cluster may evict different keys.
+/// - `chaos_drop_percent`: The percentage of replication frames dropped, for staging clusters only,
+///   passed using `--chaos-drop-percent`. Defaults to `0`. Faults can also be changed at runtime
+///   through `/admin/chaos`.
+/// - `chaos_duplicate_percent`: The percentage of replication frames sent twice, passed using
+///   `--chaos-duplicate-percent`. Defaults to `0`.
+/// - `chaos_delay_percent`: The percentage of replication frames delayed by up to `chaos_max_delay_ms`,
+///   passed using `--chaos-delay-percent`. Defaults to `0`.
+/// - `chaos_reorder_percent`: The percentage of replication frames sent after the next one, passed
+///   using `--chaos-reorder-percent`. Defaults to `0`.
+/// - `chaos_max_delay_ms`: The longest a replication frame is delayed or held back, in milliseconds,
+///   passed using `--chaos-max-delay-ms`. Defaults to `500`.
+/// - `chaos_stall_percent`: The percentage of cache operations stalled by up to `chaos_max_stall_ms`,
+///   passed using `--chaos-stall-percent`. Defaults to `0`.
+/// - `chaos_max_stall_ms`: The longest a cache operation stalls, in milliseconds, passed using
+///   `--chaos-max-stall-ms`. Defaults to `200`.
+/// - `log_format`: The log line format (`text` or `json`), passed using `--log-format`. Defaults to `text`.
+/// - `log_file`: An optional log file, passed using `--log-file`. Logs go to stdout when unset.
+/// - `log_rotation`: How often the log file is rotated (`minutely`, `hourly`, `daily` or `never`),
+///   passed using `--log-rotation`. Defaults to `daily`.
+/// - `log_max_files`: An optional number of rotated log files to keep, passed using `--log-max-files`.
+/// - `slow_request_threshold_ms`: HTTP requests slower than this are logged and kept in the slow log,
+///   passed using `--slow-request-threshold-ms`. Defaults to `100`.
+/// - `slow_gossip_apply_threshold_ms`: Gossip message applications slower than this are logged and kept
+///   in the slow log, passed using `--slow-gossip-apply-threshold-ms`. Defaults to `50`.
+/// - `slowlog_capacity`: The number of entries kept in the slow log served at `/debug/slowlog`,
+///   passed using `--slowlog-capacity`. Defaults to `128`.
+/// - `audit_log`: An optional append-only audit log file recording every mutation, passed using `--audit-log`.
+/// - `audit_redaction`: How values are written to the audit log (`full`, `hash` or `omit`), passed using
+///   `--audit-redaction`. Defaults to `omit`.
+/// - `anomaly_factor`: How many times its usual rate the write or delete rate must reach to raise an alert,
+///   passed using `--anomaly-factor`. Defaults to `10`.
+/// - `anomaly_min_rate`: The write or delete rate per second below which no alert is raised, passed using
+///   `--anomaly-min-rate`. Defaults to `100`.
+/// - `otlp_endpoint`: An optional OTLP/HTTP traces endpoint, passed using `--otlp-endpoint`.
+///   When set, spans are exported there and trace context rides along in gossip messages.
+#[derive(clap::Args, Debug)]
 struct Args {
     #[arg(short, long)]
     name: String,
@@ -39,6 +318,12 @@ struct Args {
     #[arg(long, default_value = "0.0.0.0:3001")]
     http_addr: String,
 
+    #[arg(long)]
+    advertise_http_addr: Option<String>,
+
+    #[arg(long, default_value_t = DEFAULT_VNODES, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=MAX_VNODES as u64))]
+    vnodes: usize,
+
     #[arg(short, long, default_value = "0.0.0.0:4001")]
     gossip_addr: String,
 
@@ -46,35 +331,668 @@ struct Args {
     cache_capacity: usize,
 
     #[arg(long)]
-    gossip_join_addr: Option<String>,
+    cache_capacity_bytes: Option<usize>,
+
+    #[arg(long)]
+    tti: Option<u64>,
+
+    #[arg(long)]
+    gossip_expirations: bool,
+
+    #[arg(long, default_value_t = DEFAULT_EVENTS_CAPACITY)]
+    events_capacity: usize,
+
+    #[arg(long, value_enum, default_value_t = CacheBackend::Foyer)]
+    cache_backend: CacheBackend,
+
+    #[arg(long, default_value = "data.sled")]
+    sled_path: PathBuf,
+
+    #[arg(long, value_delimiter = ',')]
+    gossip_join_addr: Vec<String>,
+
+    #[arg(long, default_value_t = wire::PROTOCOL_VERSION,
+        value_parser = clap::value_parser!(u8).range(wire::MIN_PROTOCOL_VERSION as i64..=wire::PROTOCOL_VERSION as i64))]
+    gossip_wire_version: u8,
+
+    #[arg(long, value_enum, default_value_t = Compression::Lz4)]
+    gossip_compression: Compression,
+
+    #[arg(long, default_value_t = 1024)]
+    gossip_compression_threshold: usize,
+
+    #[arg(long, default_value_t = 1400)]
+    gossip_max_frame_bytes: usize,
+
+    #[arg(long, value_enum, default_value_t = WireCodec::Bincode)]
+    gossip_codec: WireCodec,
+
+    #[arg(long, default_value_t = 16 * 1024 * 1024)]
+    gossip_max_payload_bytes: usize,
+
+    #[arg(long, default_value_t = 10_000)]
+    gossip_max_messages: usize,
+
+    #[arg(long, default_value_t = 10)]
+    gossip_batch_window_ms: u64,
+
+    #[arg(long, default_value_t = 128, value_parser = clap::value_parser!(u64).range(1..))]
+    gossip_batch_max_messages: u64,
+
+    #[arg(long, default_value_t = 3)]
+    gossip_fanout: usize,
+
+    #[arg(long, value_enum, default_value_t = Network::Local)]
+    gossip_network: Network,
+
+    #[arg(long)]
+    gossip_probing_interval_ms: Option<u64>,
+
+    #[arg(long)]
+    gossip_ack_timeout_ms: Option<u64>,
+
+    #[arg(long)]
+    gossip_indirect_ack_timeout_ms: Option<u64>,
+
+    #[arg(long)]
+    gossip_suspicious_timeout_ms: Option<u64>,
+
+    #[arg(long, default_value_t = 3600)]
+    gossip_hint_ttl_secs: u64,
+
+    #[arg(long, default_value_t = 10_000)]
+    gossip_hint_capacity: usize,
+
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u32).range(1..))]
+    gossip_retry_max_attempts: u32,
+
+    #[arg(long, default_value_t = 10_000)]
+    gossip_retry_queue_capacity: usize,
+
+    #[arg(long, default_value_t = 1000)]
+    gossip_receive_queue_capacity: usize,
+
+    #[arg(long, value_enum, default_value_t = OverflowPolicy::Block)]
+    gossip_receive_queue_overflow: OverflowPolicy,
+
+    #[arg(long)]
+    cluster_secret: Option<ClusterSecret>,
+
+    #[arg(long, default_value_t = 100)]
+    http_replication_queue_capacity: usize,
+
+    #[arg(long, value_enum, default_value_t = OverflowPolicy::Block)]
+    http_replication_queue_overflow: OverflowPolicy,
+
+    #[arg(long, default_value_t = 500)]
+    session_wait_ms: u64,
+
+    #[arg(long)]
+    acl_token: Vec<AclToken>,
+
+    #[arg(long, default_value_t = 30)]
+    http_request_timeout: u64,
+
+    #[arg(long)]
+    http_route_timeout: Vec<RouteTimeout>,
+
+    #[arg(long, default_value_t = 1024)]
+    http_max_in_flight: usize,
+
+    #[arg(long, default_value_t = 2 * 1024 * 1024)]
+    http_max_body_bytes: usize,
+
+    #[arg(long, value_enum, default_value_t = HttpVersion::Auto)]
+    http_version: HttpVersion,
+
+    #[arg(long, default_value_t = 60)]
+    http_keep_alive_timeout_secs: u64,
+
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+    http_header_read_timeout_secs: u64,
+
+    #[arg(long, default_value_t = 10_000)]
+    http_max_connections: usize,
+
+    #[arg(long, default_value_t = 1024)]
+    http_backlog: u32,
+
+    #[arg(long)]
+    admin_addr: Option<String>,
+
+    #[arg(long, value_delimiter = ',')]
+    cors_allow_origin: Vec<String>,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "GET,POST,PUT,PATCH,DELETE"
+    )]
+    cors_allow_methods: Vec<String>,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = http_server::DEFAULT_CORS_HEADERS
+    )]
+    cors_allow_headers: Vec<String>,
+
+    #[arg(long, default_value_t = 1024)]
+    max_key_bytes: usize,
+
+    #[arg(long, default_value_t = 1024 * 1024)]
+    max_value_bytes: usize,
+
+    #[arg(long, value_enum, default_value_t = KeyPolicy::Printable)]
+    key_policy: KeyPolicy,
+
+    #[arg(long, default_value_t = 300)]
+    idempotency_window_secs: u64,
+
+    #[arg(long, default_value_t = 10_000)]
+    idempotency_capacity: usize,
+
+    #[arg(long, default_value_t = 10_000)]
+    key_stats_capacity: usize,
+
+    #[arg(long, default_value_t = 1000.0)]
+    hot_key_threshold: f64,
+
+    #[arg(long, default_value_t = 10)]
+    hot_key_window_secs: u64,
+
+    #[arg(long, default_value_t = 0)]
+    hot_key_cache_ttl_ms: u64,
+
+    #[arg(long, default_value = "snapshots")]
+    snapshot_dir: PathBuf,
+
+    #[arg(long)]
+    snapshot_interval_secs: Option<u64>,
+
+    #[arg(long)]
+    wal_dir: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = FsyncPolicy::Always)]
+    wal_fsync: FsyncPolicy,
+
+    #[arg(long, default_value_t = 64 * 1024 * 1024)]
+    wal_segment_bytes: u64,
+
+    #[arg(long)]
+    preload_file: Option<PathBuf>,
+
+    #[arg(long, value_enum)]
+    preload_format: Option<PreloadFormat>,
+
+    #[arg(long, default_value_t = false)]
+    preload_gossip: bool,
+
+    #[arg(long)]
+    cold_tier_bucket: Option<String>,
+
+    #[arg(long, default_value = "kv")]
+    cold_tier_prefix: String,
+
+    #[arg(long, value_enum, default_value_t = KeyLayout::Flat)]
+    cold_tier_layout: KeyLayout,
+
+    #[arg(long)]
+    cold_tier_endpoint: Option<String>,
+
+    #[arg(long)]
+    cold_tier_region: Option<String>,
+
+    #[arg(long, default_value_t = 8)]
+    cold_tier_concurrency: usize,
+
+    #[arg(long)]
+    redis_url: Option<String>,
+
+    #[arg(long, default_value_t = 4)]
+    redis_pool_size: usize,
+
+    #[arg(long, default_value_t = 1000)]
+    redis_connect_timeout_ms: u64,
+
+    #[arg(long, default_value_t = 500)]
+    redis_response_timeout_ms: u64,
+
+    #[arg(long, default_value = "")]
+    redis_key_prefix: String,
+
+    #[arg(long)]
+    write_behind_url: Option<String>,
+
+    #[arg(long, default_value_t = 10_000)]
+    write_behind_queue_capacity: usize,
+
+    #[arg(long, default_value_t = 100)]
+    write_behind_batch_size: usize,
+
+    #[arg(long, default_value_t = 100)]
+    write_behind_flush_interval_ms: u64,
+
+    #[arg(long, default_value_t = 5)]
+    write_behind_max_attempts: u32,
+
+    #[arg(long, default_value_t = 0)]
+    negative_cache_ttl_ms: u64,
+
+    #[arg(long)]
+    negative_cache_namespace: Vec<NamespaceTtl>,
+
+    #[arg(long, default_value_t = 10_000)]
+    negative_cache_capacity: usize,
+
+    #[arg(long)]
+    namespace_quota: Vec<NamespaceQuota>,
+
+    #[arg(long, default_value_t = 0.0)]
+    chaos_drop_percent: f64,
+
+    #[arg(long, default_value_t = 0.0)]
+    chaos_duplicate_percent: f64,
+
+    #[arg(long, default_value_t = 0.0)]
+    chaos_delay_percent: f64,
+
+    #[arg(long, default_value_t = 0.0)]
+    chaos_reorder_percent: f64,
+
+    #[arg(long, default_value_t = 500)]
+    chaos_max_delay_ms: u64,
+
+    #[arg(long, default_value_t = 0.0)]
+    chaos_stall_percent: f64,
+
+    #[arg(long, default_value_t = 200)]
+    chaos_max_stall_ms: u64,
+
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = LogRotation::Daily)]
+    log_rotation: LogRotation,
+
+    #[arg(long)]
+    log_max_files: Option<usize>,
+
+    #[arg(long, default_value_t = 100)]
+    slow_request_threshold_ms: u64,
+
+    #[arg(long, default_value_t = 50)]
+    slow_gossip_apply_threshold_ms: u64,
+
+    #[arg(long, default_value_t = 128)]
+    slowlog_capacity: usize,
+
+    #[arg(long)]
+    audit_log: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = ValueRedaction::Omit)]
+    audit_redaction: ValueRedaction,
+
+    #[arg(long, default_value_t = 10.0)]
+    anomaly_factor: f64,
+
+    #[arg(long, default_value_t = 100.0)]
+    anomaly_min_rate: f64,
+
+    #[arg(long)]
+    otlp_endpoint: Option<String>,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initializing the log and parsing parameters
-    log::setup_tracing();
-    let args = Args::parse();
+    // Parsing parameters and initializing the log
+    let args = match Cli::parse() {
+        Cli {
+            command: Some(Command::Bench(options)),
+            ..
+        } => {
+            println!("{}", bench::run(options).await?);
+            return Ok(());
+        }
+        Cli {
+            node: Some(args), ..
+        } => args,
+        Cli { .. } => Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "the arguments of a node are required without a subcommand",
+            )
+            .exit(),
+    };
+    let _log_guard = log::setup_tracing(&LogConfig {
+        format: args.log_format,
+        file: args.log_file.clone(),
+        rotation: args.log_rotation,
+        max_files: args.log_max_files,
+        otlp_endpoint: args.otlp_endpoint.clone(),
+        service_name: args.name.clone(),
+    })?;
     info!("Starting application with arguments: {:?}", args);
 
+    // Opening the audit log
+    let audit = Arc::new(match &args.audit_log {
+        Some(path) => AuditLog::open(path, args.audit_redaction).await?,
+        None => AuditLog::disabled(),
+    });
+
+    // Creating a Cache, which publishes the expirations of its entries
+    let events = KeyEvents::new(args.events_capacity);
+    let cache_config = CacheConfig {
+        capacity: args.cache_capacity,
+        capacity_bytes: args.cache_capacity_bytes,
+        time_to_idle: args.tti.map(Duration::from_secs),
+        events: Some(events.clone()),
+    };
+    let mut cache: Box<dyn BCache> = match args.cache_backend {
+        CacheBackend::Foyer => Box::new(FoyerCache::from_config(&cache_config).await),
+        CacheBackend::Moka => Box::new(MokaCache::from_config(&cache_config).await),
+        CacheBackend::Sled => Box::new(SledCache::open(&args.sled_path)?),
+    };
+    if let Some(bucket) = &args.cold_tier_bucket {
+        cache = Box::new(ColdTierCache::new(
+            cache,
+            ColdTierOptions {
+                prefix: args.cold_tier_prefix.clone(),
+                layout: args.cold_tier_layout,
+                endpoint: args.cold_tier_endpoint.clone(),
+                region: args.cold_tier_region.clone(),
+                concurrency: args.cold_tier_concurrency,
+                ..ColdTierOptions::new(bucket.clone())
+            },
+        )?);
+    }
+    if let Some(url) = &args.redis_url {
+        cache = Box::new(
+            RedisCache::connect(
+                cache,
+                RedisOptions {
+                    pool_size: args.redis_pool_size,
+                    connection_timeout: Duration::from_millis(args.redis_connect_timeout_ms),
+                    response_timeout: Duration::from_millis(args.redis_response_timeout_ms),
+                    key_prefix: args.redis_key_prefix.clone(),
+                    ..RedisOptions::new(url.clone())
+                },
+            )
+            .await?,
+        );
+    }
+    if let Some(url) = &args.write_behind_url {
+        cache = Box::new(WriteBehindCache::new(
+            cache,
+            write_behind::open_backing_store(url).await?,
+            WriteBehindOptions {
+                queue_capacity: args.write_behind_queue_capacity,
+                batch_size: args.write_behind_batch_size,
+                flush_interval: Duration::from_millis(args.write_behind_flush_interval_ms),
+                max_attempts: args.write_behind_max_attempts,
+                ..WriteBehindOptions::default()
+            },
+        ));
+    }
+    let negative_cache = NegativeCacheOptions {
+        ttl: Duration::from_millis(args.negative_cache_ttl_ms),
+        namespaces: args.negative_cache_namespace.clone(),
+        capacity: args.negative_cache_capacity,
+    };
+    if negative_cache.is_enabled() {
+        cache = Box::new(NegativeCache::new(cache, negative_cache));
+    }
+    let quotas = Arc::new(Quotas::new(args.namespace_quota.clone()));
+    if quotas.is_enabled() {
+        cache = Box::new(QuotaCache::new(
+            cache,
+            quotas.clone(),
+            Some(events.clone()),
+            audit.clone(),
+        ));
+    }
+
+    // Injecting faults, when enabled by flag or through /admin/chaos
+    let chaos = Arc::new(Chaos::new(ChaosOptions {
+        drop_percent: args.chaos_drop_percent,
+        duplicate_percent: args.chaos_duplicate_percent,
+        delay_percent: args.chaos_delay_percent,
+        reorder_percent: args.chaos_reorder_percent,
+        max_delay_ms: args.chaos_max_delay_ms,
+        stall_percent: args.chaos_stall_percent,
+        max_stall_ms: args.chaos_max_stall_ms,
+    })?);
+    cache = Box::new(ChaosCache::new(cache, chaos.clone()));
+    let bcache: Arc<Mutex<Box<dyn BCache>>> = Arc::new(Mutex::new(cache));
+    let tags = Arc::new(TagIndex::default());
+
+    // Opening the write-ahead log
+    let wal = Arc::new(match &args.wal_dir {
+        Some(dir) => {
+            Wal::open(WalOptions {
+                segment_bytes: args.wal_segment_bytes,
+                fsync: args.wal_fsync,
+                ..WalOptions::new(dir.clone())
+            })
+            .await?
+        }
+        None => Wal::disabled(),
+    });
+
+    // Warm restart from the latest periodic snapshot and the write-ahead log, before joining the cluster
+    let snapshots = SnapshotStore::new(args.snapshot_dir.clone());
+    let mut wal_lsn = 0;
+    if let Some(snapshot) = snapshots.load_if_exists(PERIODIC_SNAPSHOT).await? {
+        wal_lsn = snapshot.metadata.wal_lsn;
+        let entries = snapshot.load_into(&bcache, &tags).await;
+        info!("Loaded {} entries from {}", entries, PERIODIC_SNAPSHOT);
+    }
+    let replayed = wal.replay(wal_lsn).await?;
+    if !replayed.is_empty() {
+        info!("Replaying {} write-ahead log records", replayed.len());
+    }
+    for message in replayed {
+        if let Err(e) = wal::apply(message, &bcache, &tags).await {
+            warn!("Failed to replay write-ahead log record: {:?}", e);
+        }
+    }
+
+    // Preloading reference data, keeping the keys restored above
+    let limits = Limits {
+        max_key_bytes: args.max_key_bytes,
+        max_value_bytes: args.max_value_bytes,
+        key_policy: args.key_policy,
+    };
+    let preloaded = match &args.preload_file {
+        Some(path) => {
+            let entries = preload::read(path, args.preload_format).await?;
+            let count = entries.len();
+            let loaded = preload::load_into(entries, &bcache, &tags, &limits).await;
+            info!(
+                "Preloaded {} of the {} entries of {}",
+                loaded.len(),
+                count,
+                path.display()
+            );
+            loaded
+        }
+        None => Vec::new(),
+    };
+    if let Some(interval) = args.snapshot_interval_secs {
+        tokio::spawn(snapshot::run_periodic(
+            snapshots,
+            bcache.clone(),
+            tags.clone(),
+            wal.clone(),
+            Duration::from_secs(interval),
+        ));
+    }
+
+    let topology = Arc::new(Topology::new(
+        args.name.clone(),
+        NodeInfo {
+            http_addr: Some(
+                args.advertise_http_addr
+                    .clone()
+                    .unwrap_or_else(|| args.http_addr.clone()),
+            ),
+            vnodes: args.vnodes,
+        },
+    ));
+    let sessions = Arc::new(Sessions::new(args.name.clone()));
+    let staleness = Arc::new(Staleness::default());
+
     // Starting a GossipNode
-    let (gossip, gossip_receiver) = GossipNode::start(GossipodConfig::new(
-        args.name,
-        args.gossip_addr,
-        args.gossip_join_addr,
-    ))
-    .await?;
-
-    // Creating a Cache
-    let bcache: Arc<Mutex<Box<dyn BCache>>> = Arc::new(Mutex::new(Box::new(
-        FoyerCache::new(args.cache_capacity).await,
-    )));
+    let mut gossip_config = GossipodConfig::new(args.name, args.gossip_addr, args.gossip_join_addr);
+    gossip_config.wire = WireOptions {
+        version: args.gossip_wire_version,
+        compression: args.gossip_compression,
+        compression_threshold: args.gossip_compression_threshold,
+        max_frame_bytes: args.gossip_max_frame_bytes,
+        codec: args.gossip_codec,
+        limits: DecodeLimits {
+            max_payload_bytes: args.gossip_max_payload_bytes,
+            max_messages: args.gossip_max_messages,
+            ..DecodeLimits::default()
+        },
+    };
+    gossip_config.batch = BatchOptions {
+        window: Duration::from_millis(args.gossip_batch_window_ms),
+        max_messages: args.gossip_batch_max_messages as usize,
+    };
+    gossip_config.fanout = args.gossip_fanout;
+    let protocol = ProtocolOptions::for_network(args.gossip_network);
+    gossip_config.protocol = ProtocolOptions {
+        probing_interval: args
+            .gossip_probing_interval_ms
+            .map_or(protocol.probing_interval, Duration::from_millis),
+        ack_timeout: args
+            .gossip_ack_timeout_ms
+            .map_or(protocol.ack_timeout, Duration::from_millis),
+        indirect_ack_timeout: args
+            .gossip_indirect_ack_timeout_ms
+            .map_or(protocol.indirect_ack_timeout, Duration::from_millis),
+        suspicious_timeout: args
+            .gossip_suspicious_timeout_ms
+            .map_or(protocol.suspicious_timeout, Duration::from_millis),
+        ..protocol
+    };
+    gossip_config.hints = HintOptions {
+        ttl: Duration::from_secs(args.gossip_hint_ttl_secs),
+        capacity: args.gossip_hint_capacity,
+    };
+    gossip_config.chaos = Some(chaos.clone());
+    gossip_config.retry = RetryOptions {
+        max_attempts: args.gossip_retry_max_attempts,
+        capacity: args.gossip_retry_queue_capacity,
+        ..RetryOptions::default()
+    };
+    gossip_config.receive_queue = QueueOptions {
+        capacity: args.gossip_receive_queue_capacity,
+        overflow: args.gossip_receive_queue_overflow,
+    };
+    // Authenticating gossip frames, when the cluster has a secret
+    let cluster_auth = Arc::new(ClusterAuth::new(
+        gossip_config.name.clone(),
+        args.cluster_secret.clone(),
+    ));
+    gossip_config.auth = cluster_auth.clone();
+    let (gossip, gossip_receiver) = GossipNode::start(gossip_config).await?;
+    if args.preload_gossip {
+        preload::replicate(&gossip, preloaded).await;
+    }
+
+    // Creating the slow log shared by the HTTP server and the gossip sync loop
+    let slowlog = Arc::new(SlowLog::new(
+        Duration::from_millis(args.slow_request_threshold_ms),
+        Duration::from_millis(args.slow_gossip_apply_threshold_ms),
+        args.slowlog_capacity,
+    ));
+
+    // Creating the anomaly detector for write and delete rates
+    let anomalies = Arc::new(AnomalyDetector::new(AnomalyConfig {
+        factor: args.anomaly_factor,
+        min_rate: args.anomaly_min_rate,
+        ..AnomalyConfig::default()
+    }));
 
     // Starting the HTTP server
-    let http_receiver = http_server::start(args.http_addr.clone(), bcache.clone()).await?;
+    let mut http_config = HttpServerConfig {
+        request_timeout: Duration::from_secs(args.http_request_timeout),
+        max_in_flight: args.http_max_in_flight,
+        max_body_bytes: args.http_max_body_bytes,
+        snapshot_dir: args.snapshot_dir.clone(),
+        http_version: args.http_version,
+        keep_alive_timeout: (args.http_keep_alive_timeout_secs > 0)
+            .then(|| Duration::from_secs(args.http_keep_alive_timeout_secs)),
+        header_read_timeout: Duration::from_secs(args.http_header_read_timeout_secs),
+        max_connections: args.http_max_connections,
+        backlog: args.http_backlog,
+        admin_addr: args.admin_addr.clone(),
+        cors_origins: args.cors_allow_origin.clone(),
+        cors_methods: args.cors_allow_methods.clone(),
+        cors_headers: args.cors_allow_headers.clone(),
+        limits: limits.clone(),
+        idempotency: IdempotencyOptions {
+            window: Duration::from_secs(args.idempotency_window_secs),
+            capacity: args.idempotency_capacity,
+        },
+        key_stats_capacity: args.key_stats_capacity,
+        hot_keys: HotKeyOptions {
+            threshold: args.hot_key_threshold,
+            window: Duration::from_secs(args.hot_key_window_secs.max(1)),
+            cache_ttl: Duration::from_millis(args.hot_key_cache_ttl_ms),
+            ..HotKeyOptions::default()
+        },
+        replication_queue: QueueOptions {
+            capacity: args.http_replication_queue_capacity,
+            overflow: args.http_replication_queue_overflow,
+        },
+        session_wait: Duration::from_millis(args.session_wait_ms),
+        acl_tokens: args.acl_token.clone(),
+        ..HttpServerConfig::new(args.http_addr.clone())
+    };
+    for route in &args.http_route_timeout {
+        http_config.set_route_timeout(route.clone());
+    }
+    let deps = ServerDeps {
+        bcache: bcache.clone(),
+        tags: tags.clone(),
+        slowlog: slowlog.clone(),
+        audit: audit.clone(),
+        anomalies: anomalies.clone(),
+        wal: wal.clone(),
+        events: events.clone(),
+        chaos,
+        gossip_queue: gossip_receiver.counters(),
+        cluster_auth,
+        topology: topology.clone(),
+        sessions: sessions.clone(),
+        staleness: staleness.clone(),
+        quotas,
+    };
+    let http_receiver = http_server::start(http_config, deps).await?;
     info!("HTTP server started on {}", args.http_addr);
 
     // Synchronize Gossip and HTTP data
-    sync_data(bcache, gossip, gossip_receiver, http_receiver).await?;
+    let ctx = SyncContext {
+        bcache,
+        tags,
+        slowlog,
+        audit,
+        anomalies,
+        wal,
+        limits,
+        events,
+        gossip_expirations: args.gossip_expirations,
+        topology,
+        sessions,
+        staleness,
+    };
+    sync_data(ctx, gossip, gossip_receiver, http_receiver).await?;
 
     Ok(())
 }
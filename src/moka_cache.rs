@@ -1,8 +1,13 @@
 use async_trait::async_trait;
 use moka::future::Cache;
+use moka::notification::RemovalCause;
+use std::sync::Arc;
 
-use crate::cache_trait::BCache;
+use crate::cache_trait::{entry_weight, BCache, CacheConfig};
+use crate::error::Error;
+use crate::events::{ExpirationCause, KeyEvent};
 use anyhow::Result;
+use tracing::info;
 
 /// `MokaCache` is an implementation of the `BCache` trait using the `moka` asynchronous cache.
 ///
@@ -40,9 +45,60 @@ impl MokaCache {
     /// let cache = MokaCache::new(10).await;
     /// ```
     pub async fn new(cache_capacity: usize) -> Self {
-        let cache = Cache::new(cache_capacity as u64);
+        Self::from_config(&CacheConfig::new(cache_capacity)).await
+    }
+
+    /// Creates a new `MokaCache` from a `CacheConfig`.
+    ///
+    /// When `capacity_bytes` is set, each entry is weighted by `entry_weight`, so memory usage
+    /// stays bounded by the size of the stored keys and values rather than by the number of entries.
+    /// When `time_to_idle` is set, entries that have not been read within the window expire,
+    /// and are published to `events` when moka drops them.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The capacity and expiration settings for the cache.
+    ///
+    /// # Returns
+    ///
+    /// * A new instance of `MokaCache`.
+    pub async fn from_config(config: &CacheConfig) -> Self {
+        let events = config.events.clone();
+        let mut builder =
+            Cache::builder().eviction_listener(move |key: Arc<String>, value, cause| {
+                if let (Some(events), RemovalCause::Expired) = (&events, cause) {
+                    events.publish(KeyEvent::expired(
+                        key.to_string(),
+                        ExpirationCause::TimeToIdle,
+                    ));
+                }
+                log_eviction(key, value, cause);
+            });
+        builder = match config.capacity_bytes {
+            Some(capacity_bytes) => builder.max_capacity(capacity_bytes as u64).weigher(
+                |key: &String, value: &String| {
+                    u32::try_from(entry_weight(key, value)).unwrap_or(u32::MAX)
+                },
+            ),
+            None => builder.max_capacity(config.capacity as u64),
+        };
+        if let Some(time_to_idle) = config.time_to_idle {
+            builder = builder.time_to_idle(time_to_idle);
+        }
 
-        Self { cc: cache }
+        Self {
+            cc: builder.build(),
+        }
+    }
+}
+
+/// Eviction listener that logs entries dropped by the cache itself.
+///
+/// Explicit removals and replacements are ignored, so only capacity evictions and
+/// expirations show up in the log. Otherwise these are indistinguishable from lost data.
+fn log_eviction(key: Arc<String>, _value: String, cause: RemovalCause) {
+    if cause.was_evicted() {
+        info!("Key {} evicted from moka cache: {:?}", key, cause);
     }
 }
 
@@ -88,7 +144,7 @@ impl BCache for MokaCache {
         let value = match self.cc.get(&key).await {
             Some(e) => e,
             None => {
-                return Err(anyhow::anyhow!("key not found"));
+                return Err(Error::NotFound.into());
             }
         };
 
@@ -109,11 +165,24 @@ impl BCache for MokaCache {
     async fn remove(&mut self, key: String) {
         self.cc.remove(&key).await;
     }
+
+    /// Asynchronously returns every unexpired entry of the cache.
+    ///
+    /// # Returns
+    ///
+    /// * The key-value pairs of the cache, in no particular order.
+    async fn entries(&mut self) -> Vec<(String, String)> {
+        self.cc
+            .iter()
+            .map(|(key, value)| (key.as_ref().clone(), value))
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Duration;
 
     /// Unit test for `MokaCache`.
     ///
@@ -128,4 +197,55 @@ mod tests {
             "world".to_string()
         );
     }
+
+    /// Unit test for listing the entries of a `MokaCache`.
+    #[tokio::test]
+    async fn test_moka_cache_entries() {
+        let mut cache = MokaCache::new(4).await;
+        cache.insert("hello".to_string(), "world".to_string()).await;
+        cache.insert("foo".to_string(), "bar".to_string()).await;
+        cache.remove("foo".to_string()).await;
+        assert_eq!(
+            cache.entries().await,
+            vec![("hello".to_string(), "world".to_string())]
+        );
+    }
+
+    /// Unit test for a byte-weighted `MokaCache`.
+    ///
+    /// An entry heavier than the whole capacity must not be retained.
+    #[tokio::test]
+    async fn test_moka_cache_with_capacity_bytes() {
+        let config = CacheConfig {
+            capacity_bytes: Some(16),
+            ..CacheConfig::new(2)
+        };
+        let mut cache = MokaCache::from_config(&config).await;
+        cache.insert("hello".to_string(), "world".to_string()).await;
+        assert_eq!(
+            cache.get("hello".to_string()).await.unwrap(),
+            "world".to_string()
+        );
+
+        cache.insert("big".to_string(), "x".repeat(64)).await;
+        cache.cc.run_pending_tasks().await;
+        assert!(cache.get("big".to_string()).await.is_err());
+    }
+
+    /// Unit test for time-to-idle expiration in `MokaCache`.
+    #[tokio::test]
+    async fn test_moka_cache_time_to_idle() {
+        let config = CacheConfig {
+            time_to_idle: Some(Duration::from_millis(50)),
+            ..CacheConfig::new(2)
+        };
+        let mut cache = MokaCache::from_config(&config).await;
+        cache.insert("hello".to_string(), "world".to_string()).await;
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(cache.get("hello".to_string()).await.is_ok());
+
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert!(cache.get("hello".to_string()).await.is_err());
+    }
 }
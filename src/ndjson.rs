@@ -0,0 +1,284 @@
+use crate::cache_trait::BCache;
+use crate::error::Error;
+use crate::snapshot::SnapshotEntry;
+use crate::tags::TagIndex;
+use axum::body::Bytes;
+use futures::Stream;
+use std::io;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// The number of entries read from the cache, and written to the client, at a time by `export`.
+pub const EXPORT_PAGE: usize = 500;
+
+/// The number of entries `/import` applies to the cache at a time.
+pub const IMPORT_BATCH: usize = 500;
+
+/// Where an export resumes.
+enum Cursor {
+    /// The next page of an ordered backend starts at this key, or at the first key.
+    Range(Option<String>),
+    /// The remaining entries of a backend that does not keep its keys ordered.
+    Buffered(std::vec::IntoIter<(String, String)>),
+    /// Every entry was exported.
+    Done,
+}
+
+/// Streams every entry of the cache, with its tags, as newline-delimited JSON: one
+/// `SnapshotEntry` per line, in the format `/import` accepts.
+///
+/// Pages of `EXPORT_PAGE` entries are read only when the previous one was consumed, so a slow
+/// client slows the export down instead of making the node buffer the keyspace. Ordered
+/// backends are paged through with range queries, releasing the cache between pages; the
+/// entries of the others are copied once, then serialized a page at a time.
+///
+/// # Arguments
+///
+/// * `bcache` - The cache to export.
+/// * `tags` - The tag index the tags of the entries are looked up in.
+///
+/// # Returns
+///
+/// * A stream of chunks of lines, ending with an error if the backend fails mid-export.
+///
+/// # Example
+///
+/// ```rust
+/// let body = Body::from_stream(ndjson::export(bcache, tags));
+/// ```
+pub fn export(
+    bcache: Arc<Mutex<Box<dyn BCache>>>,
+    tags: Arc<TagIndex>,
+) -> impl Stream<Item = io::Result<Bytes>> {
+    futures::stream::unfold(Cursor::Range(None), move |cursor| {
+        let bcache = bcache.clone();
+        let tags = tags.clone();
+        async move {
+            let (page, cursor) = match cursor {
+                Cursor::Done => return None,
+                Cursor::Range(start) => {
+                    let page = bcache.lock().await.range(start, None, EXPORT_PAGE).await;
+                    match page {
+                        Ok(page) => {
+                            // The smallest key after the last one exported.
+                            let next = (page.len() == EXPORT_PAGE)
+                                .then(|| page.last().map(|(key, _)| format!("{}\0", key)))
+                                .flatten();
+                            (
+                                page,
+                                next.map_or(Cursor::Done, |next| Cursor::Range(Some(next))),
+                            )
+                        }
+                        Err(e)
+                            if matches!(e.downcast_ref::<Error>(), Some(Error::Unsupported(_))) =>
+                        {
+                            let mut entries = bcache.lock().await.entries().await;
+                            entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+                            let mut entries = entries.into_iter();
+                            let page = entries.by_ref().take(EXPORT_PAGE).collect();
+                            (page, Cursor::Buffered(entries))
+                        }
+                        Err(e) => {
+                            return Some((Err(io::Error::other(format!("{:#}", e))), Cursor::Done))
+                        }
+                    }
+                }
+                Cursor::Buffered(mut entries) => {
+                    let page: Vec<_> = entries.by_ref().take(EXPORT_PAGE).collect();
+                    if page.is_empty() {
+                        return None;
+                    }
+                    (page, Cursor::Buffered(entries))
+                }
+            };
+
+            let mut chunk = Vec::new();
+            for (key, value) in page {
+                let entry = SnapshotEntry {
+                    tags: tags.tags_of(&key),
+                    key,
+                    value,
+                };
+                if let Err(e) = serde_json::to_writer(&mut chunk, &entry) {
+                    return Some((Err(e.into()), Cursor::Done));
+                }
+                chunk.push(b'\n');
+            }
+            Some((Ok(Bytes::from(chunk)), cursor))
+        }
+    })
+}
+
+/// Splits the chunks of a newline-delimited JSON body into `SnapshotEntry`s, whatever the
+/// boundaries of the chunks. Blank lines are skipped.
+///
+/// # Fields
+///
+/// - `buffer`: The start of a line whose end has not been received yet.
+/// - `line`: The number of lines decoded so far.
+/// - `max_line_bytes`: The maximum length of a line, bounding the buffer.
+///
+/// # Example
+///
+/// ```rust
+/// let mut decoder = NdjsonDecoder::new(1024);
+/// let mut entries = decoder.feed(b"{\"key\":\"a\",\"value\":\"1\"}\n{\"key\"")?;
+/// entries.extend(decoder.feed(b":\"b\",\"value\":\"2\"}")?);
+/// entries.extend(decoder.finish()?);
+/// ```
+pub struct NdjsonDecoder {
+    buffer: Vec<u8>,
+    line: usize,
+    max_line_bytes: usize,
+}
+
+impl NdjsonDecoder {
+    /// Creates a decoder accepting lines of up to `max_line_bytes` bytes.
+    pub fn new(max_line_bytes: usize) -> Self {
+        Self {
+            buffer: Vec::new(),
+            line: 0,
+            max_line_bytes,
+        }
+    }
+
+    /// Returns the number of the last line decoded, counting from 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Decodes the lines a chunk completes, keeping the start of an incomplete last line.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidRequest` error naming the line if a line is not a valid entry, or is
+    /// longer than `max_line_bytes`.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<SnapshotEntry>, Error> {
+        let mut entries = Vec::new();
+        let mut start = 0;
+        let mut scanned = self.buffer.len();
+        self.buffer.extend_from_slice(chunk);
+        while let Some(position) = self.buffer[scanned..].iter().position(|b| *b == b'\n') {
+            let end = scanned + position;
+            self.line += 1;
+            if let Some(entry) =
+                decode_line(&self.buffer[start..end], self.line, self.max_line_bytes)?
+            {
+                entries.push(entry);
+            }
+            start = end + 1;
+            scanned = start;
+        }
+        self.buffer.drain(..start);
+        if self.buffer.len() > self.max_line_bytes {
+            return Err(line_too_long(self.line + 1, self.max_line_bytes));
+        }
+        Ok(entries)
+    }
+
+    /// Decodes the last line of the body, which may not end with a newline.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidRequest` error if the last line is not a valid entry.
+    pub fn finish(&mut self) -> Result<Option<SnapshotEntry>, Error> {
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
+        self.line += 1;
+        let buffer = std::mem::take(&mut self.buffer);
+        decode_line(&buffer, self.line, self.max_line_bytes)
+    }
+}
+
+/// Decodes a line of a newline-delimited JSON body, or `None` if it is blank.
+fn decode_line(
+    line: &[u8],
+    number: usize,
+    max_line_bytes: usize,
+) -> Result<Option<SnapshotEntry>, Error> {
+    if line.len() > max_line_bytes {
+        return Err(line_too_long(number, max_line_bytes));
+    }
+    if line.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+    serde_json::from_slice(line)
+        .map(Some)
+        .map_err(|e| Error::InvalidRequest(format!("Invalid entry on line {}: {}", number, e)))
+}
+
+/// Returns the error of a line longer than `max_line_bytes`.
+fn line_too_long(number: usize, max_line_bytes: usize) -> Error {
+    Error::InvalidRequest(format!(
+        "Line {} is longer than {} bytes",
+        number, max_line_bytes
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moka_cache::MokaCache;
+    use futures::StreamExt;
+
+    /// Unit test for `export` and `NdjsonDecoder`.
+    ///
+    /// Every entry is exported with its tags, in pages, and decodes back to the same entries
+    /// whatever the chunk boundaries; malformed and oversized lines are reported by number.
+    #[tokio::test]
+    async fn test_export_import() {
+        let bcache: Arc<Mutex<Box<dyn BCache>>> =
+            Arc::new(Mutex::new(Box::new(MokaCache::new(2 * EXPORT_PAGE).await)));
+        let tags = Arc::new(TagIndex::default());
+        for i in 0..EXPORT_PAGE + 1 {
+            let key = format!("key{:04}", i);
+            bcache.lock().await.insert(key.clone(), i.to_string()).await;
+            if i == 0 {
+                tags.set_tags(&key, &["sale".to_string()]);
+            }
+        }
+
+        let chunks: Vec<Bytes> = export(bcache, tags)
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+        assert_eq!(chunks.len(), 2);
+        let body: Vec<u8> = chunks.concat();
+
+        let mut decoder = NdjsonDecoder::new(1024);
+        let mut entries = Vec::new();
+        for chunk in body.chunks(7) {
+            entries.extend(decoder.feed(chunk).unwrap());
+        }
+        entries.extend(decoder.finish().unwrap());
+        assert_eq!(entries.len(), EXPORT_PAGE + 1);
+        assert_eq!(
+            entries[0],
+            SnapshotEntry {
+                key: "key0000".to_string(),
+                value: "0".to_string(),
+                tags: vec!["sale".to_string()],
+            }
+        );
+        assert!(entries.windows(2).all(|pair| pair[0].key < pair[1].key));
+
+        let mut decoder = NdjsonDecoder::new(1024);
+        let entries = decoder
+            .feed(b"{\"key\":\"a\",\"value\":\"1\"}\n\n{\"key\":\"b\",\"value\":\"2\"}")
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(decoder.finish().unwrap().unwrap().key, "b");
+        assert_eq!(decoder.line(), 3);
+
+        let mut decoder = NdjsonDecoder::new(1024);
+        let error = decoder
+            .feed(b"{\"key\":\"a\",\"value\":\"1\"}\nnot json\n")
+            .unwrap_err();
+        assert!(error.to_string().starts_with("Invalid entry on line 2"));
+
+        let mut decoder = NdjsonDecoder::new(8);
+        let error = decoder.feed(b"{\"key\":\"long\"").unwrap_err();
+        assert_eq!(error.to_string(), "Line 1 is longer than 8 bytes");
+    }
+}
@@ -0,0 +1,271 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::cache_trait::BCache;
+use crate::error::Error;
+use anyhow::{anyhow, Result};
+
+/// A time-to-live for the misses of the keys starting with a prefix, parsed from
+/// `<prefix>=<milliseconds>`, such as `session:=2000`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamespaceTtl {
+    pub prefix: String,
+    pub ttl: Duration,
+}
+
+impl FromStr for NamespaceTtl {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (prefix, ttl_ms) = s
+            .rsplit_once('=')
+            .ok_or_else(|| anyhow!("Expected <prefix>=<milliseconds>, got '{}'", s))?;
+        let ttl_ms: u64 = ttl_ms
+            .parse()
+            .map_err(|_| anyhow!("Invalid time-to-live '{}' for prefix '{}'", ttl_ms, prefix))?;
+        Ok(Self {
+            prefix: prefix.to_string(),
+            ttl: Duration::from_millis(ttl_ms),
+        })
+    }
+}
+
+/// Configures the negative cache.
+///
+/// # Fields
+///
+/// - `ttl`: How long a miss is remembered, for keys in no namespace. Zero disables it.
+/// - `namespaces`: The time-to-live of the misses of the keys starting with each prefix. The
+///   longest matching prefix applies, and a zero time-to-live disables it for its keys.
+/// - `capacity`: The maximum number of misses remembered.
+#[derive(Debug, Clone)]
+pub struct NegativeCacheOptions {
+    pub ttl: Duration,
+    pub namespaces: Vec<NamespaceTtl>,
+    pub capacity: usize,
+}
+
+impl Default for NegativeCacheOptions {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::ZERO,
+            namespaces: Vec::new(),
+            capacity: 10_000,
+        }
+    }
+}
+
+impl NegativeCacheOptions {
+    /// Returns whether any miss is remembered.
+    pub fn is_enabled(&self) -> bool {
+        !self.ttl.is_zero() || self.namespaces.iter().any(|ns| !ns.ttl.is_zero())
+    }
+}
+
+/// `NegativeCache` wraps a `BCache` and remembers, for a short time, the keys it does not hold.
+///
+/// Repeated reads of a missing key are answered without reaching the wrapped cache, so they do
+/// not cost a round trip to Redis or the cold tier each time. Writing a key through this cache
+/// forgets its miss, and removing one remembers it, so misses only go stale when another writer
+/// updates the remote store directly, for at most their time-to-live.
+///
+/// # Example
+///
+/// ```rust
+/// let inner: Box<dyn BCache> = Box::new(ColdTierCache::new(local, cold_tier_options)?);
+/// let mut cache = NegativeCache::new(inner, NegativeCacheOptions {
+///     ttl: Duration::from_millis(500),
+///     ..NegativeCacheOptions::default()
+/// });
+/// assert!(cache.get("missing".to_string()).await.is_err());
+/// ```
+pub struct NegativeCache {
+    inner: Box<dyn BCache>,
+    options: NegativeCacheOptions,
+    /// The keys known to be missing, with the instant their miss is forgotten.
+    misses: HashMap<String, Instant>,
+}
+
+impl NegativeCache {
+    /// Creates a new `NegativeCache` wrapping `inner`.
+    pub fn new(inner: Box<dyn BCache>, options: NegativeCacheOptions) -> Self {
+        Self {
+            inner,
+            options,
+            misses: HashMap::new(),
+        }
+    }
+
+    /// Returns how long a miss of `key` is remembered.
+    fn ttl(&self, key: &str) -> Duration {
+        self.options
+            .namespaces
+            .iter()
+            .filter(|ns| key.starts_with(&ns.prefix))
+            .max_by_key(|ns| ns.prefix.len())
+            .map_or(self.options.ttl, |ns| ns.ttl)
+    }
+
+    /// Remembers that `key` is missing, unless misses of its namespace are not remembered or
+    /// the capacity is reached by misses that have not expired yet.
+    fn remember(&mut self, key: String) {
+        let ttl = self.ttl(&key);
+        if ttl.is_zero() {
+            return;
+        }
+        let now = Instant::now();
+        if self.misses.len() >= self.options.capacity {
+            self.misses.retain(|_, expires_at| *expires_at > now);
+            if self.misses.len() >= self.options.capacity {
+                return;
+            }
+        }
+        self.misses.insert(key, now + ttl);
+    }
+}
+
+#[async_trait]
+impl BCache for NegativeCache {
+    /// Asynchronously inserts a key-value pair into the wrapped cache, forgetting its miss.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A `String` representing the key.
+    /// * `val` - A `String` representing the value associated with the key.
+    async fn insert(&mut self, key: String, val: String) {
+        self.misses.remove(&key);
+        self.inner.insert(key, val).await;
+    }
+
+    /// Asynchronously retrieves the value of a key from the wrapped cache, unless it is known
+    /// to be missing.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A `String` representing the key to retrieve.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key is known to be missing, or the wrapped cache fails to return it.
+    async fn get(&mut self, key: String) -> Result<String> {
+        if let Some(expires_at) = self.misses.get(&key) {
+            if *expires_at > Instant::now() {
+                return Err(Error::NotFound.into());
+            }
+            self.misses.remove(&key);
+        }
+
+        match self.inner.get(key.clone()).await {
+            Ok(value) => Ok(value),
+            Err(e) => {
+                if Error::is_not_found(&e) {
+                    self.remember(key);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Asynchronously removes a key from the wrapped cache, remembering its miss.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A `String` representing the key to remove.
+    async fn remove(&mut self, key: String) {
+        self.inner.remove(key.clone()).await;
+        self.remember(key);
+    }
+
+    /// Asynchronously returns the entries of the wrapped cache.
+    async fn entries(&mut self) -> Vec<(String, String)> {
+        self.inner.entries().await
+    }
+
+    /// Asynchronously returns a range of the entries of the wrapped cache.
+    async fn range(
+        &mut self,
+        start: Option<String>,
+        end: Option<String>,
+        limit: usize,
+    ) -> Result<Vec<(String, String)>> {
+        self.inner.range(start, end, limit).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moka_cache::MokaCache;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// A cache counting the reads that reach it.
+    struct CountingCache {
+        inner: MokaCache,
+        gets: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl BCache for CountingCache {
+        async fn insert(&mut self, key: String, val: String) {
+            self.inner.insert(key, val).await;
+        }
+
+        async fn get(&mut self, key: String) -> Result<String> {
+            self.gets.fetch_add(1, Ordering::SeqCst);
+            self.inner.get(key).await
+        }
+
+        async fn remove(&mut self, key: String) {
+            self.inner.remove(key).await;
+        }
+
+        async fn entries(&mut self) -> Vec<(String, String)> {
+            self.inner.entries().await
+        }
+    }
+
+    /// Unit test for `NegativeCache`.
+    ///
+    /// Repeated misses reach the wrapped cache once per time-to-live, except in namespaces that
+    /// do not remember misses, and writes are read back right away.
+    #[tokio::test]
+    async fn test_negative_cache() {
+        let gets = Arc::new(AtomicUsize::new(0));
+        let mut cache = NegativeCache::new(
+            Box::new(CountingCache {
+                inner: MokaCache::new(16).await,
+                gets: gets.clone(),
+            }),
+            NegativeCacheOptions {
+                ttl: Duration::from_millis(50),
+                namespaces: vec!["live:=0".parse().unwrap()],
+                ..NegativeCacheOptions::default()
+            },
+        );
+
+        for _ in 0..3 {
+            assert!(cache.get("missing".to_string()).await.is_err());
+        }
+        assert_eq!(gets.load(Ordering::SeqCst), 1);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(cache.get("missing".to_string()).await.is_err());
+        assert_eq!(gets.load(Ordering::SeqCst), 2);
+
+        cache
+            .insert("missing".to_string(), "found".to_string())
+            .await;
+        assert_eq!(cache.get("missing".to_string()).await.unwrap(), "found");
+        cache.remove("missing".to_string()).await;
+        assert!(cache.get("missing".to_string()).await.is_err());
+        assert_eq!(gets.load(Ordering::SeqCst), 3);
+
+        for _ in 0..2 {
+            assert!(cache.get("live:1".to_string()).await.is_err());
+        }
+        assert_eq!(gets.load(Ordering::SeqCst), 5);
+        assert!("live".parse::<NamespaceTtl>().is_err());
+    }
+}
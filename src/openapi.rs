@@ -0,0 +1,656 @@
+use serde_json::{json, Map, Value};
+
+/// The page served at `/docs`: Swagger UI, loaded from a CDN, browsing `/openapi.json`.
+pub const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>http-distributed-kv API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##;
+
+/// The machine-readable codes of `crate::error::Error`, carried in the `error` field of responses.
+const ERROR_CODES: [&str; 16] = [
+    "not_found",
+    "conflict",
+    "precondition_failed",
+    "invalid_request",
+    "unauthorized",
+    "forbidden",
+    "limit_exceeded",
+    "unprocessable",
+    "timeout",
+    "replication_failed",
+    "backend_error",
+    "unavailable",
+    "unsupported",
+    "stale",
+    "quota_exceeded",
+    "internal",
+];
+
+/// Returns a reference to a schema of the document.
+fn schema(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+/// Returns a JSON request body of the given schema.
+fn body(name: &str) -> Value {
+    json!({
+        "required": true,
+        "content": { "application/json": { "schema": schema(name) } }
+    })
+}
+
+/// Returns a parameter, described by reference to the components of the document.
+fn param(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/parameters/{}", name) })
+}
+
+/// Returns the responses of an operation: `200` with the given schema, and the listed error
+/// statuses with the error envelope.
+fn responses(ok: &str, errors: &[u16]) -> Value {
+    let mut responses = Map::new();
+    responses.insert(
+        "200".to_string(),
+        json!({
+            "description": "Success",
+            "content": { "application/json": { "schema": schema(ok) } }
+        }),
+    );
+    for status in errors {
+        let description = axum::http::StatusCode::from_u16(*status)
+            .ok()
+            .and_then(|status| status.canonical_reason())
+            .unwrap_or("Error");
+        responses.insert(
+            status.to_string(),
+            json!({
+                "description": description,
+                "content": { "application/json": { "schema": schema("Response") } }
+            }),
+        );
+    }
+    Value::Object(responses)
+}
+
+/// Returns an operation of the document.
+///
+/// # Arguments
+///
+/// * `tag` - The group of the operation in the docs.
+/// * `summary` - What the operation does, in one line.
+/// * `params` - The names of the parameters of the operation.
+/// * `request` - The schema of the JSON body of the request, if any.
+/// * `responses` - The responses of the operation.
+fn operation(
+    tag: &str,
+    summary: &str,
+    params: &[&str],
+    request: Option<&str>,
+    responses: Value,
+) -> Value {
+    let mut operation = json!({
+        "tags": [tag],
+        "summary": summary,
+        "parameters": params.iter().map(|name| param(name)).collect::<Vec<_>>(),
+        "responses": responses,
+    });
+    if let Some(request) = request {
+        operation["requestBody"] = body(request);
+    }
+    operation
+}
+
+/// Adds the redirect of reads whose value may be staler than their `max_staleness` allows to an
+/// operation.
+fn redirects_stale_reads(mut operation: Value) -> Value {
+    operation["responses"]["307"] = json!({
+        "description": "The value may be staler than max_staleness allows on this node; Location is the same request on the owner of the key",
+        "headers": { "Location": { "schema": { "type": "string" } } }
+    });
+    operation
+}
+
+/// The status codes of the errors of a write: invalid parameters, a missing token or grant,
+/// failed preconditions and limits, a full replication queue, failures of the backend or the
+/// replication, and an exceeded namespace quota.
+const WRITE_ERRORS: [u16; 11] = [400, 401, 403, 409, 412, 413, 422, 500, 502, 503, 507];
+
+/// The status codes of the errors of a read: a missing key, token or grant, failures of the
+/// backend, and a session the node could not catch up with.
+const READ_ERRORS: [u16; 7] = [400, 401, 403, 404, 502, 503, 504];
+
+/// Builds the OpenAPI 3 document of the HTTP API, served at `/openapi.json`.
+///
+/// The routes and types are described by hand, next to the handlers in `http_server.rs`; a
+/// route added there is added here too.
+pub fn document() -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "http-distributed-kv",
+            "description": "A distributed key-value cache replicated over gossip. Every response carries an `X-Request-Id` header, successful writes an `X-Session-Token` header to pass on later reads to read them back, and errors are answered with their HTTP status and a machine-readable `error` code.",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths(),
+        "security": [{ "bearerAuth": [] }, {}],
+        "components": {
+            "parameters": parameters(),
+            "schemas": schemas(),
+            "securitySchemes": {
+                "bearerAuth": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "Required when the node is started with --acl-token; each token is granted read, write or admin access to key prefixes.",
+                },
+            },
+        },
+    })
+}
+
+/// Returns the routes of the document, and their operations.
+fn paths() -> Value {
+    let key_write = ["Key", "ReplicateTo", "IfMatch", "IdempotencyKey"];
+    let mut put_key = operation(
+        "keys",
+        "Write a key; the body is the value",
+        &[
+            "Key",
+            "ReplicateTo",
+            "Nx",
+            "Tags",
+            "IfMatch",
+            "IdempotencyKey",
+        ],
+        None,
+        responses("Response", &WRITE_ERRORS),
+    );
+    put_key["requestBody"] = json!({
+        "required": true,
+        "content": { "text/plain": { "schema": { "type": "string" } } }
+    });
+    json!({
+        "/query": {
+            "get": redirects_stale_reads(operation("keys", "Read the value of a key", &["KeyQuery", "MaxStaleness", "IfNoneMatch", "SessionToken"], None, responses("Response", &READ_ERRORS))),
+        },
+        "/add": {
+            "post": operation("keys", "Write a key, or insert it only if absent with nx", &["ReplicateTo", "Nx", "IfMatch", "IdempotencyKey"], Some("AddRequest"), responses("Response", &WRITE_ERRORS)),
+        },
+        "/delete": {
+            "delete": operation("keys", "Remove a key", &["ReplicateTo", "IfMatch", "IdempotencyKey"], Some("RemoveRequest"), responses("Response", &WRITE_ERRORS)),
+        },
+        "/get_or_set": {
+            "post": operation("keys", "Read a key, or insert a default value if it is absent", &["ReplicateTo"], Some("AddRequest"), responses("Response", &WRITE_ERRORS)),
+        },
+        "/append": {
+            "post": operation("keys", "Append a suffix to the value of a key", &["ReplicateTo", "IfMatch", "IdempotencyKey"], Some("AppendRequest"), responses("Response", &WRITE_ERRORS)),
+        },
+        "/txn": {
+            "post": operation("keys", "Apply several writes and removals atomically, if every check holds", &["ReplicateTo", "IdempotencyKey"], Some("TxnRequest"), responses("Response", &WRITE_ERRORS)),
+        },
+        "/range": {
+            "get": operation("keys", "List the entries of a range of keys, in lexicographic order", &["Start", "End", "Limit", "SessionToken"], None, responses("RangeResponse", &[501, 502, 503])),
+        },
+        "/export": {
+            "get": {
+                "tags": ["keys"],
+                "summary": "Stream every entry, with its tags, as newline-delimited JSON",
+                "parameters": [],
+                "responses": {
+                    "200": {
+                        "description": "Success",
+                        "content": { "application/x-ndjson": { "schema": schema("SnapshotEntry") } }
+                    }
+                },
+            },
+        },
+        "/import": {
+            "post": {
+                "tags": ["keys"],
+                "summary": "Write the entries of a newline-delimited JSON body, in the format of /export",
+                "parameters": [param("ReplicateTo")],
+                "requestBody": {
+                    "required": true,
+                    "content": { "application/x-ndjson": { "schema": schema("SnapshotEntry") } }
+                },
+                "responses": responses("Response", &[400, 401, 403, 413, 422, 500, 503, 507]),
+            },
+        },
+        "/v1/keys/{key}": {
+            "get": redirects_stale_reads(operation("keys", "Read the value of a key", &["Key", "MaxStaleness", "IfNoneMatch", "SessionToken"], None, responses("Response", &READ_ERRORS))),
+            "put": put_key,
+            "delete": operation("keys", "Remove a key", &key_write, None, responses("Response", &WRITE_ERRORS)),
+            "patch": operation("documents", "Patch a JSON document, with a merge patch or a JSON patch", &key_write, Some("Patch"), responses("Response", &WRITE_ERRORS)),
+        },
+        "/keys/{key}": {
+            "get": redirects_stale_reads(operation("documents", "Read a JSON document, or one of its fields", &["Key", "Path", "MaxStaleness", "SessionToken"], None, responses("Response", &[400, 404, 422, 502, 503, 504]))),
+            "patch": operation("documents", "Patch a JSON document, with a merge patch or a JSON patch", &key_write, Some("Patch"), responses("Response", &WRITE_ERRORS)),
+        },
+        "/keys/{key}/ops": {
+            "post": operation("keys", "Apply a type-aware operation to a list, set, integer or string", &key_write, Some("ValueOp"), responses("Response", &WRITE_ERRORS)),
+        },
+        "/tags/{tag}": {
+            "delete": operation("tags", "Remove every entry carrying a tag", &["Tag", "ReplicateTo"], None, responses("Response", &[400, 500, 503])),
+        },
+        "/tags/{tag}/expire": {
+            "post": operation("tags", "Expire every entry carrying a tag at a point in time", &["Tag", "ReplicateTo"], Some("ExpireTagRequest"), responses("Response", &[400, 500, 503])),
+        },
+        "/events": {
+            "get": {
+                "tags": ["keys"],
+                "summary": "Stream key events, such as expirations and evictions, as server-sent events",
+                "parameters": [param("Prefix")],
+                "responses": {
+                    "200": {
+                        "description": "Success",
+                        "content": { "text/event-stream": { "schema": { "type": "string" } } }
+                    }
+                },
+            },
+        },
+        "/topology": {
+            "get": operation("cluster", "Describe the hash ring: the members, their addresses and tokens, the topology version and the elected coordinator", &[], None, responses("Topology", &[])),
+        },
+        "/debug/slowlog": {
+            "get": operation("admin", "List the most recent slow requests and gossip applications", &[], None, responses("SlowLog", &[])),
+        },
+        "/cluster/alerts": {
+            "get": operation("admin", "List the anomaly alerts of this node", &[], None, responses("Alerts", &[])),
+        },
+        "/cluster/auth": {
+            "get": operation("admin", "Report the members admitted by the cluster secret and the gossip frames rejected", &[], None, responses("ClusterAuthStats", &[])),
+        },
+        "/stats/hotkeys": {
+            "get": operation("admin", "List the keys read at or above the hot-key threshold", &[], None, responses("HotKeys", &[])),
+        },
+        "/stats/keys": {
+            "get": operation("admin", "List the most accessed keys (requires the key-stats feature)", &["Top"], None, responses("KeyStats", &[])),
+        },
+        "/stats/queues": {
+            "get": operation("admin", "Report the occupancy and overflows of the queues between tasks", &[], None, responses("QueuesStats", &[])),
+        },
+        "/stats/namespaces": {
+            "get": operation("admin", "Report the usage, quota, rejections and evictions of each namespace with a quota", &[], None, responses("NamespacesStats", &[])),
+        },
+        "/admin/snapshot": {
+            "post": {
+                "tags": ["admin"],
+                "summary": "Write a snapshot of every entry of this node",
+                "requestBody": {
+                    "required": false,
+                    "content": { "application/json": { "schema": schema("SnapshotRequest") } }
+                },
+                "responses": responses("Response", &[500]),
+            },
+        },
+        "/admin/restore": {
+            "post": operation("admin", "Load a snapshot and replicate its entries", &["ReplicateTo"], Some("SnapshotRequest"), responses("Response", &[400, 500, 503])),
+        },
+        "/admin/chaos": {
+            "get": operation("admin", "Read the faults injected by this node", &[], None, responses("ChaosOptions", &[])),
+            "post": operation("admin", "Replace the faults injected by this node", &[], Some("ChaosOptions"), responses("ChaosOptions", &[422])),
+        },
+    })
+}
+
+/// Returns the parameters shared by the operations of the document.
+fn parameters() -> Value {
+    json!({
+            "Key": { "name": "key", "in": "path", "required": true, "schema": { "type": "string" } },
+            "KeyQuery": { "name": "key", "in": "query", "required": true, "schema": { "type": "string" } },
+            "Tag": { "name": "tag", "in": "path", "required": true, "schema": { "type": "string" } },
+            "ReplicateTo": {
+                "name": "replicate_to", "in": "query",
+                "description": "`local-only`, or a comma-separated list of node names. Writes are replicated to every node when absent.",
+                "schema": { "type": "string" }
+            },
+            "Nx": {
+                "name": "nx", "in": "query",
+                "description": "Only insert the key if it does not exist, answering 409 otherwise.",
+                "schema": { "type": "boolean", "default": false }
+            },
+            "Tags": {
+                "name": "tags", "in": "query",
+                "description": "The comma-separated tags of the entry.",
+                "schema": { "type": "string" }
+            },
+            "IfMatch": {
+                "name": "If-Match", "in": "header",
+                "description": "Only apply the write if the current value has one of these ETags, answering 412 otherwise.",
+                "schema": { "type": "string" }
+            },
+            "IfNoneMatch": {
+                "name": "If-None-Match", "in": "header",
+                "description": "Answer 304 without a body if the value still has one of these ETags.",
+                "schema": { "type": "string" }
+            },
+            "IdempotencyKey": {
+                "name": "Idempotency-Key", "in": "header",
+                "description": "Retries carrying the same key and bearer token get the response of the first request; the key reused with another query or body gets 422.",
+                "schema": { "type": "string" }
+            },
+            "MaxStaleness": {
+                "name": "max_staleness", "in": "query",
+                "description": "How stale the value may be, such as `500ms`, `2s` or `1m`. Reads are redirected to the owner of the key when this node may be further behind it, or answered with 504 if its address is not known. Reads report when the key was last modified on the node, as a Unix time in milliseconds, in the `X-Last-Modified` header, and how far behind the owner the node may be, in milliseconds, in the `X-Replica-Lag` header.",
+                "schema": { "type": "string" }
+            },
+            "SessionToken": {
+                "name": "X-Session-Token", "in": "header",
+                "description": "The session token returned by earlier writes, as `node:position` pairs. The read waits until this node applied the writes it covers, or is proxied to the node that accepted them.",
+                "schema": { "type": "string" }
+            },
+            "Path": {
+                "name": "path", "in": "query",
+                "description": "The field to return, as a JSONPath (`$.user.name`) or a JSON pointer (`/user/name`).",
+                "schema": { "type": "string" }
+            },
+            "Start": { "name": "start", "in": "query", "description": "The first key of the range, inclusive.", "schema": { "type": "string" } },
+            "End": { "name": "end", "in": "query", "description": "The key the range stops before, exclusive.", "schema": { "type": "string" } },
+            "Limit": { "name": "limit", "in": "query", "schema": { "type": "integer", "minimum": 1, "maximum": 1000, "default": 100 } },
+            "Prefix": { "name": "prefix", "in": "query", "description": "Only stream the events of keys starting with this prefix.", "schema": { "type": "string" } },
+            "Top": { "name": "top", "in": "query", "schema": { "type": "integer", "default": 50 } },
+    })
+}
+
+/// Returns the schemas of the request and response bodies of the document.
+fn schemas() -> Value {
+    json!({
+            "Response": {
+                "type": "object",
+                "required": ["code", "message"],
+                "properties": {
+                    "code": { "type": "integer", "description": "The HTTP status of the response." },
+                    "data": { "type": "object", "nullable": true, "additionalProperties": { "type": "string" } },
+                    "message": { "type": "string" },
+                    "error": { "type": "string", "enum": ERROR_CODES, "description": "The machine-readable code of the error, absent on success." },
+                    "request_id": { "type": "string" },
+                },
+            },
+            "RangeResponse": {
+                "type": "object",
+                "properties": {
+                    "code": { "type": "integer" },
+                    "data": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": { "key": { "type": "string" }, "value": { "type": "string" } },
+                        },
+                    },
+                    "next": { "type": "string", "description": "The start of the next page, when the limit was reached." },
+                    "message": { "type": "string" },
+                    "request_id": { "type": "string" },
+                },
+            },
+            "AddRequest": {
+                "type": "object",
+                "required": ["key", "value"],
+                "properties": {
+                    "key": { "type": "string" },
+                    "value": { "type": "string" },
+                    "tags": { "type": "array", "items": { "type": "string" } },
+                },
+            },
+            "SnapshotEntry": {
+                "type": "object",
+                "required": ["key", "value"],
+                "properties": {
+                    "key": { "type": "string" },
+                    "value": { "type": "string" },
+                    "tags": { "type": "array", "items": { "type": "string" } },
+                },
+            },
+            "AppendRequest": {
+                "type": "object",
+                "required": ["key", "value"],
+                "properties": { "key": { "type": "string" }, "value": { "type": "string" } },
+            },
+            "RemoveRequest": {
+                "type": "object",
+                "required": ["key"],
+                "properties": { "key": { "type": "string" } },
+            },
+            "TxnRequest": {
+                "type": "object",
+                "required": ["ops"],
+                "properties": {
+                    "checks": { "type": "array", "items": schema("TxnCheck") },
+                    "ops": { "type": "array", "items": schema("TxnOp") },
+                },
+            },
+            "TxnCheck": {
+                "type": "object",
+                "required": ["key"],
+                "properties": {
+                    "key": { "type": "string" },
+                    "exists": { "type": "boolean" },
+                    "value": { "type": "string" },
+                    "etag": { "type": "string" },
+                },
+            },
+            "TxnOp": {
+                "type": "object",
+                "required": ["op", "key"],
+                "properties": {
+                    "op": { "type": "string", "enum": ["set", "delete"] },
+                    "key": { "type": "string" },
+                    "value": { "type": "string", "description": "The value of a `set`." },
+                    "tags": { "type": "array", "items": { "type": "string" } },
+                },
+            },
+            "ValueOp": {
+                "type": "object",
+                "required": ["op"],
+                "properties": {
+                    "op": { "type": "string", "enum": ["push", "set_add", "set_remove", "add", "append"] },
+                    "values": { "type": "array", "items": { "type": "string" }, "description": "The values of a `push`." },
+                    "front": { "type": "boolean", "description": "Whether a `push` prepends." },
+                    "members": { "type": "array", "items": { "type": "string" }, "description": "The members of a `set_add` or `set_remove`." },
+                    "delta": { "type": "integer", "format": "int64", "description": "The delta of an `add`." },
+                    "suffix": { "type": "string", "description": "The suffix of an `append`." },
+                },
+            },
+            "Patch": {
+                "description": "A JSON merge patch (`application/merge-patch+json` or `application/json`), or a JSON patch (`application/json-patch+json`).",
+            },
+            "ExpireTagRequest": {
+                "type": "object",
+                "required": ["expire_at_ms"],
+                "properties": { "expire_at_ms": { "type": "integer", "format": "int64", "description": "The Unix timestamp, in milliseconds, at which the tag expires." } },
+            },
+            "SnapshotRequest": {
+                "type": "object",
+                "properties": { "name": { "type": "string", "description": "The file name of the snapshot in the snapshot directory." } },
+            },
+            "ChaosOptions": {
+                "type": "object",
+                "properties": {
+                    "drop_percent": { "type": "number" },
+                    "duplicate_percent": { "type": "number" },
+                    "delay_percent": { "type": "number" },
+                    "reorder_percent": { "type": "number" },
+                    "max_delay_ms": { "type": "integer" },
+                    "stall_percent": { "type": "number" },
+                    "max_stall_ms": { "type": "integer" },
+                },
+            },
+            "Topology": {
+                "type": "object",
+                "properties": {
+                    "version": { "type": "integer", "description": "Changes whenever the topology changes; also sent in the X-Topology-Version header of every data response." },
+                    "replication_factor": { "type": "integer" },
+                    "members": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "name": { "type": "string" },
+                                "gossip_addr": { "type": "string" },
+                                "http_addr": { "type": "string", "nullable": true },
+                                "vnodes": { "type": "integer" },
+                                "tokens": { "type": "array", "items": { "type": "integer", "format": "int64" } },
+                            },
+                        },
+                    },
+                    "leader": { "type": "string", "nullable": true, "description": "The coordinator of cluster-wide tasks as elected by this node: the member with the lowest name. Each side of a partition elects its own." },
+                },
+            },
+            "SlowLog": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "kind": { "type": "string" },
+                        "operation": { "type": "string" },
+                        "key": { "type": "string", "nullable": true },
+                        "duration_ms": { "type": "integer" },
+                        "timestamp_ms": { "type": "integer" },
+                    },
+                },
+            },
+            "Alerts": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "kind": { "type": "string" },
+                        "rate": { "type": "number" },
+                        "baseline": { "type": "number" },
+                        "timestamp_ms": { "type": "integer" },
+                    },
+                },
+            },
+            "HotKeys": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": { "key": { "type": "string" }, "rate": { "type": "number" } },
+                },
+            },
+            "KeyStats": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "key": { "type": "string" },
+                        "reads": { "type": "integer" },
+                        "writes": { "type": "integer" },
+                        "last_access_ms": { "type": "integer" },
+                    },
+                },
+            },
+            "QueueStats": {
+                "type": "object",
+                "properties": {
+                    "capacity": { "type": "integer" },
+                    "overflow": { "type": "string", "enum": ["block", "drop_oldest", "reject"] },
+                    "len": { "type": "integer" },
+                    "dropped": { "type": "integer" },
+                    "rejected": { "type": "integer" },
+                },
+            },
+            "QueuesStats": {
+                "type": "object",
+                "properties": {
+                    "replication": schema("QueueStats"),
+                    "gossip": schema("QueueStats"),
+                },
+            },
+            "NamespaceStats": {
+                "type": "object",
+                "properties": {
+                    "prefix": { "type": "string" },
+                    "entries": { "type": "integer" },
+                    "bytes": { "type": "integer" },
+                    "max_entries": { "type": "integer", "nullable": true },
+                    "max_bytes": { "type": "integer", "nullable": true },
+                    "policy": { "type": "string", "enum": ["reject", "evict"] },
+                    "rejected": { "type": "integer" },
+                    "evicted": { "type": "integer" },
+                },
+            },
+            "NamespacesStats": { "type": "array", "items": schema("NamespaceStats") },
+            "ClusterAuthStats": {
+                "type": "object",
+                "properties": {
+                    "enabled": { "type": "boolean" },
+                    "verified_members": { "type": "array", "items": { "type": "string" } },
+                    "pending_members": { "type": "array", "items": { "type": "string" } },
+                    "rejected_frames": { "type": "integer" },
+                    "rejected_peers": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "addr": { "type": "string" },
+                                "frames": { "type": "integer" },
+                                "last_rejected_ms": { "type": "integer" },
+                            },
+                        },
+                    },
+                },
+            },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Collects the targets of the `$ref`s found in a value.
+    fn refs(value: &Value, found: &mut Vec<String>) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::String(target)) = map.get("$ref") {
+                    found.push(target.clone());
+                }
+                map.values().for_each(|value| refs(value, found));
+            }
+            Value::Array(values) => values.iter().for_each(|value| refs(value, found)),
+            _ => {}
+        }
+    }
+
+    /// Unit test for `document`.
+    ///
+    /// Every reference resolves to a component of the document, and every operation answers
+    /// with at least one response.
+    #[test]
+    fn test_document() {
+        let document = document();
+        let mut found = Vec::new();
+        refs(&document, &mut found);
+        assert!(!found.is_empty());
+        for target in found {
+            let pointer = target.trim_start_matches('#');
+            assert!(
+                document.pointer(pointer).is_some(),
+                "{} does not resolve",
+                target
+            );
+        }
+
+        let paths = document["paths"].as_object().unwrap();
+        assert!(paths.contains_key("/v1/keys/{key}"));
+        for (path, operations) in paths {
+            for (method, operation) in operations.as_object().unwrap() {
+                assert!(
+                    !operation["responses"].as_object().unwrap().is_empty(),
+                    "{} {} has no responses",
+                    method,
+                    path
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use serde_json::Value;
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time;
+use tracing::{info, warn};
+
+use crate::cache_trait::BCache;
+use crate::gossip::{Command, GossipNode, Message, Replication};
+use crate::limits::Limits;
+use crate::ndjson::{NdjsonDecoder, IMPORT_BATCH};
+use crate::snapshot::SnapshotEntry;
+use crate::tags::TagIndex;
+use crate::wire;
+
+/// How long a node gossiping its preloaded entries waits for another member to join.
+const PRELOAD_JOIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The format of a preload file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PreloadFormat {
+    /// A JSON object of keys and values, or an array of `{"key", "value", "tags"}` entries as in
+    /// snapshots. Values that are not strings are stored as their JSON text.
+    Json,
+    /// One `{"key", "value", "tags"}` entry per line, as served by `/export`.
+    Ndjson,
+    /// One `key,value[,tags]` record per line, with the tags separated by `;`, and fields quoted
+    /// with `"` when they hold commas, quotes or newlines. A `key,value` header is skipped.
+    Csv,
+}
+
+impl PreloadFormat {
+    /// Returns the format of a file from its extension: `.json`, `.ndjson` or `.jsonl`, or `.csv`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the extension is none of those.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Ok(PreloadFormat::Json),
+            Some("ndjson" | "jsonl") => Ok(PreloadFormat::Ndjson),
+            Some("csv") => Ok(PreloadFormat::Csv),
+            _ => Err(anyhow!(
+                "Cannot tell the format of {} from its extension, set --preload-format",
+                path.display()
+            )),
+        }
+    }
+
+    /// Parses the entries of a preload file, in the order they appear.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the entry, or the line, that is malformed.
+    pub fn parse(self, data: &[u8]) -> Result<Vec<SnapshotEntry>> {
+        match self {
+            PreloadFormat::Json => parse_json(data),
+            PreloadFormat::Ndjson => {
+                let mut decoder = NdjsonDecoder::new(data.len());
+                let mut entries = decoder.feed(data)?;
+                entries.extend(decoder.finish()?);
+                Ok(entries)
+            }
+            PreloadFormat::Csv => parse_csv(std::str::from_utf8(data)?),
+        }
+    }
+}
+
+/// Parses a JSON object of keys and values, or an array of entries.
+fn parse_json(data: &[u8]) -> Result<Vec<SnapshotEntry>> {
+    match serde_json::from_slice(data)? {
+        Value::Object(map) => Ok(map
+            .into_iter()
+            .map(|(key, value)| SnapshotEntry {
+                key,
+                value: match value {
+                    Value::String(value) => value,
+                    value => value.to_string(),
+                },
+                tags: Vec::new(),
+            })
+            .collect()),
+        Value::Array(entries) => entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                serde_json::from_value(entry).map_err(|e| anyhow!("Invalid entry {}: {}", i, e))
+            })
+            .collect(),
+        _ => Err(anyhow!(
+            "Expected an object of keys and values, or an array of entries"
+        )),
+    }
+}
+
+/// Parses `key,value[,tags]` records.
+fn parse_csv(text: &str) -> Result<Vec<SnapshotEntry>> {
+    let mut entries = Vec::new();
+    for (i, record) in csv_records(text)?.into_iter().enumerate() {
+        let line = i + 1;
+        if i == 0 && record.len() >= 2 && record[0] == "key" && record[1] == "value" {
+            continue;
+        }
+        let mut fields = record.into_iter();
+        let (Some(key), Some(value), tags, None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(anyhow!("Expected key,value[,tags] on record {}", line));
+        };
+        entries.push(SnapshotEntry {
+            key,
+            value,
+            tags: tags
+                .iter()
+                .flat_map(|tags| tags.split(';'))
+                .filter(|tag| !tag.is_empty())
+                .map(str::to_string)
+                .collect(),
+        });
+    }
+    Ok(entries)
+}
+
+/// Splits CSV text into records of fields. Blank lines are skipped.
+fn csv_records(text: &str) -> Result<Vec<Vec<String>>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if field.is_empty() => quoted = true,
+            (',', false) => record.push(std::mem::take(&mut field)),
+            ('\r', false) if chars.peek() == Some(&'\n') => {}
+            ('\n', false) => {
+                if !record.is_empty() || !field.is_empty() {
+                    record.push(std::mem::take(&mut field));
+                    records.push(std::mem::take(&mut record));
+                }
+            }
+            (c, _) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(anyhow!(
+            "Unterminated quoted field on record {}",
+            records.len() + 1
+        ));
+    }
+    if !record.is_empty() || !field.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Reads the entries of a preload file.
+///
+/// # Arguments
+///
+/// * `path` - The file to read.
+/// * `format` - The format of the file, or `None` to tell it from its extension.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read, its format cannot be told, or it is malformed.
+pub async fn read(path: &Path, format: Option<PreloadFormat>) -> Result<Vec<SnapshotEntry>> {
+    let format = match format {
+        Some(format) => format,
+        None => PreloadFormat::from_path(path)?,
+    };
+    let data = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("Failed to read preload file {}", path.display()))?;
+    format
+        .parse(&data)
+        .with_context(|| format!("Invalid preload file {}", path.display()))
+}
+
+/// Loads entries into the cache of this node, and their tags into the tag index.
+///
+/// Keys the cache already holds, such as those restored from a snapshot or the write-ahead log,
+/// keep their value, so preloading the same file on every start never overwrites newer writes.
+/// Entries exceeding the limits of the node are logged and skipped.
+///
+/// # Returns
+///
+/// * The entries loaded, in the order they were given.
+pub async fn load_into(
+    entries: Vec<SnapshotEntry>,
+    bcache: &Mutex<Box<dyn BCache>>,
+    tags: &TagIndex,
+    limits: &Limits,
+) -> Vec<SnapshotEntry> {
+    let mut bcache = bcache.lock().await;
+    let mut loaded = Vec::with_capacity(entries.len());
+    for entry in entries {
+        if let Err(violation) = limits.check_entry(&entry.key, &entry.value) {
+            warn!("Skipping preloaded key {}: {}", entry.key, violation);
+            continue;
+        }
+        if bcache.get(entry.key.clone()).await.is_ok() {
+            continue;
+        }
+        tags.set_tags(&entry.key, &entry.tags);
+        bcache.insert(entry.key.clone(), entry.value.clone()).await;
+        loaded.push(entry);
+    }
+    loaded
+}
+
+/// Gossips preloaded entries to the other members, once one joined or `PRELOAD_JOIN_TIMEOUT`
+/// passed. They are sent as `InsertIfAbsent` messages in batches of `IMPORT_BATCH`, so members
+/// already holding a key keep their value.
+///
+/// # Arguments
+///
+/// * `gossip` - The gossip node of this node, before it is handed to `sync_data`.
+/// * `entries` - The entries loaded by `load_into`.
+pub async fn replicate(gossip: &GossipNode, entries: Vec<SnapshotEntry>) {
+    if entries.is_empty() {
+        return;
+    }
+    if gossip.wire().version < wire::min_version(&Command::InsertIfAbsent) {
+        warn!(
+            "Not gossiping preloaded entries: wire version {} predates InsertIfAbsent",
+            gossip.wire().version
+        );
+        return;
+    }
+    let waited = time::timeout(PRELOAD_JOIN_TIMEOUT, async {
+        while gossip.members().await.len() < 2 {
+            time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await;
+    if waited.is_err() {
+        warn!(
+            "No member joined within {:?}, not gossiping {} preloaded entries",
+            PRELOAD_JOIN_TIMEOUT,
+            entries.len()
+        );
+        return;
+    }
+
+    info!("Gossiping {} preloaded entries", entries.len());
+    let mut messages = entries.into_iter().map(|entry| {
+        let msg =
+            Message::new(Command::InsertIfAbsent, entry.key, entry.value).with_tags(entry.tags);
+        (msg, Replication::All)
+    });
+    loop {
+        let batch: Vec<_> = messages.by_ref().take(IMPORT_BATCH).collect();
+        if batch.is_empty() {
+            break;
+        }
+        gossip.send_batch(batch).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moka_cache::MokaCache;
+
+    fn entry(key: &str, value: &str, tags: &[&str]) -> SnapshotEntry {
+        SnapshotEntry {
+            key: key.to_string(),
+            value: value.to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        }
+    }
+
+    /// Unit test for `PreloadFormat::parse` and `load_into`.
+    ///
+    /// Each format parses to the same entries; keys already in the cache, and entries exceeding
+    /// the limits, are not loaded.
+    #[tokio::test]
+    async fn test_preload() {
+        let expected = vec![
+            entry("country:fr", "France", &["countries"]),
+            entry("motto", "a, \"b\"\nc", &[]),
+        ];
+        let json = br#"[{"key":"country:fr","value":"France","tags":["countries"]},
+            {"key":"motto","value":"a, \"b\"\nc"}]"#;
+        let ndjson = b"{\"key\":\"country:fr\",\"value\":\"France\",\"tags\":[\"countries\"]}\n\n\
+            {\"key\":\"motto\",\"value\":\"a, \\\"b\\\"\\nc\"}";
+        let csv =
+            b"key,value,tags\r\ncountry:fr,France,countries\n\n\"motto\",\"a, \"\"b\"\"\nc\"\n";
+        assert_eq!(PreloadFormat::Json.parse(json).unwrap(), expected);
+        assert_eq!(PreloadFormat::Ndjson.parse(ndjson).unwrap(), expected);
+        assert_eq!(PreloadFormat::Csv.parse(csv).unwrap(), expected);
+        assert_eq!(
+            PreloadFormat::Json
+                .parse(br#"{"limit": 10, "name": "x"}"#)
+                .unwrap(),
+            vec![entry("limit", "10", &[]), entry("name", "x", &[])]
+        );
+        assert!(PreloadFormat::Csv.parse(b"a,b,c,d").is_err());
+        assert!(PreloadFormat::Csv.parse(b"a,\"b").is_err());
+        assert!(PreloadFormat::Json.parse(b"[{\"key\":\"a\"}]").is_err());
+        assert_eq!(
+            PreloadFormat::from_path(Path::new("seed.jsonl")).unwrap(),
+            PreloadFormat::Ndjson
+        );
+        assert!(PreloadFormat::from_path(Path::new("seed.txt")).is_err());
+
+        let bcache: Mutex<Box<dyn BCache>> = Mutex::new(Box::new(MokaCache::new(16).await));
+        bcache
+            .lock()
+            .await
+            .insert("motto".to_string(), "newer".to_string())
+            .await;
+        let tags = TagIndex::default();
+        let limits = Limits {
+            max_value_bytes: 8,
+            ..Limits::default()
+        };
+        let mut entries = expected.clone();
+        entries.push(entry("too-large", "123456789", &[]));
+        let loaded = load_into(entries, &bcache, &tags, &limits).await;
+        assert_eq!(loaded, expected[..1]);
+        let mut bcache = bcache.lock().await;
+        assert_eq!(
+            bcache.get("country:fr".to_string()).await.unwrap(),
+            "France"
+        );
+        assert_eq!(bcache.get("motto".to_string()).await.unwrap(), "newer");
+        assert!(bcache.get("too-large".to_string()).await.is_err());
+        assert_eq!(tags.tags_of("country:fr"), vec!["countries".to_string()]);
+    }
+}
@@ -0,0 +1,404 @@
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tracing::warn;
+
+/// What happens to an item pushed to a full queue.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// The producer waits for room, slowing down writers or the membership protocol.
+    #[default]
+    Block,
+    /// The oldest item is dropped to make room, and counted.
+    DropOldest,
+    /// The new item is refused, and counted. HTTP writes are answered with
+    /// `503 Service Unavailable` before they are applied.
+    Reject,
+}
+
+/// Configures a queue between two tasks.
+///
+/// # Fields
+///
+/// - `capacity`: The number of items buffered until the consumer reads them.
+/// - `overflow`: What happens to items pushed while the queue is full.
+#[derive(Clone, Debug)]
+pub struct QueueOptions {
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+}
+
+impl QueueOptions {
+    /// Creates options for a queue of `capacity` items, whose producers wait while it is full.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            overflow: OverflowPolicy::default(),
+        }
+    }
+}
+
+/// The occupancy and overflows of a queue, as served at `/stats/queues`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QueueStats {
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+    pub len: usize,
+    /// The items dropped to make room for newer ones.
+    pub dropped: u64,
+    /// The items refused because the queue was full.
+    pub rejected: u64,
+}
+
+/// The counters of a queue, shared with whoever reports them.
+#[derive(Debug)]
+pub struct QueueCounters {
+    name: &'static str,
+    options: QueueOptions,
+    len: AtomicUsize,
+    dropped: AtomicU64,
+    rejected: AtomicU64,
+}
+
+impl QueueCounters {
+    /// Returns the current occupancy and overflows of the queue.
+    pub fn stats(&self) -> QueueStats {
+        QueueStats {
+            capacity: self.options.capacity,
+            overflow: self.options.overflow,
+            len: self.len.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct Shared<T> {
+    items: Mutex<VecDeque<T>>,
+    counters: Arc<QueueCounters>,
+    /// Notified when an item is pushed, or the last sender is dropped.
+    readable: Notify,
+    /// Notified when an item is popped, or the receiver is dropped.
+    writable: Notify,
+    senders: AtomicUsize,
+    closed: AtomicBool,
+}
+
+impl<T> Shared<T> {
+    fn capacity(&self) -> usize {
+        self.counters.options.capacity.max(1)
+    }
+
+    /// Pushes an item if there is room, or returns it.
+    fn try_push(&self, item: T) -> std::result::Result<(), T> {
+        let mut items = self.items.lock().unwrap();
+        if items.len() >= self.capacity() {
+            return Err(item);
+        }
+        items.push_back(item);
+        self.counters.len.store(items.len(), Ordering::Relaxed);
+        drop(items);
+        self.readable.notify_one();
+        Ok(())
+    }
+
+    /// Pushes an item, dropping the oldest ones to make room for it.
+    fn push_evicting(&self, item: T) {
+        let mut items = self.items.lock().unwrap();
+        let mut dropped = 0;
+        while items.len() >= self.capacity() {
+            items.pop_front();
+            dropped += 1;
+        }
+        items.push_back(item);
+        self.counters.len.store(items.len(), Ordering::Relaxed);
+        drop(items);
+        if dropped > 0 {
+            let total = self.counters.dropped.fetch_add(dropped, Ordering::Relaxed) + dropped;
+            warn!(
+                "The {} queue is full, dropped its oldest item ({} dropped so far)",
+                self.counters.name, total
+            );
+        }
+        self.readable.notify_one();
+    }
+
+    fn pop(&self) -> Option<T> {
+        let mut items = self.items.lock().unwrap();
+        let item = items.pop_front();
+        self.counters.len.store(items.len(), Ordering::Relaxed);
+        drop(items);
+        if item.is_some() {
+            self.writable.notify_waiters();
+        }
+        item
+    }
+
+    fn count_rejected(&self) {
+        let total = self.counters.rejected.fetch_add(1, Ordering::Relaxed) + 1;
+        warn!(
+            "The {} queue is full, rejected an item ({} rejected so far)",
+            self.counters.name, total
+        );
+    }
+}
+
+/// Creates a bounded queue between producers and a single consumer, which handles overflows
+/// as configured by `options` instead of always applying backpressure.
+///
+/// # Arguments
+///
+/// * `name` - The name of the queue in logs.
+/// * `options` - The capacity and overflow policy of the queue.
+///
+/// # Example
+///
+/// ```rust
+/// let (sender, mut receiver) = queue::<Message>("replication", QueueOptions {
+///     capacity: 100,
+///     overflow: OverflowPolicy::DropOldest,
+/// });
+/// sender.send(msg).await?;
+/// println!("{:?}", sender.counters().stats());
+/// ```
+pub fn queue<T>(name: &'static str, options: QueueOptions) -> (QueueSender<T>, QueueReceiver<T>) {
+    let shared = Arc::new(Shared {
+        items: Mutex::new(VecDeque::new()),
+        counters: Arc::new(QueueCounters {
+            name,
+            options,
+            len: AtomicUsize::new(0),
+            dropped: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        }),
+        readable: Notify::new(),
+        writable: Notify::new(),
+        senders: AtomicUsize::new(1),
+        closed: AtomicBool::new(false),
+    });
+    (
+        QueueSender {
+            shared: shared.clone(),
+        },
+        QueueReceiver { shared },
+    )
+}
+
+/// The producing side of a queue created by `queue`.
+pub struct QueueSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> QueueSender<T> {
+    /// Pushes an item. While the queue is full, waits for room under the `Block` and `Reject`
+    /// policies, and drops the oldest item under `DropOldest`.
+    ///
+    /// Producers that can still refuse the item check `admit` first under `Reject`, or use
+    /// `try_send`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the receiver was dropped.
+    pub async fn send(&self, item: T) -> Result<()> {
+        if self.shared.counters.options.overflow == OverflowPolicy::DropOldest {
+            self.ensure_open()?;
+            self.shared.push_evicting(item);
+            return Ok(());
+        }
+
+        let mut item = item;
+        loop {
+            let writable = self.shared.writable.notified();
+            tokio::pin!(writable);
+            writable.as_mut().enable();
+            self.ensure_open()?;
+            match self.shared.try_push(item) {
+                Ok(()) => return Ok(()),
+                Err(rejected) => item = rejected,
+            }
+            writable.await;
+        }
+    }
+
+    /// Pushes an item applying the overflow policy without waiting: under `Block` and
+    /// `Reject`, an item that does not fit is refused and counted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the queue is full, or the receiver was dropped.
+    pub fn try_send(&self, item: T) -> Result<()> {
+        self.ensure_open()?;
+        if self.shared.counters.options.overflow == OverflowPolicy::DropOldest {
+            self.shared.push_evicting(item);
+            return Ok(());
+        }
+        self.shared.try_push(item).map_err(|_| {
+            self.shared.count_rejected();
+            anyhow!("The {} queue is full", self.shared.counters.name)
+        })
+    }
+
+    /// Pushes an item applying the overflow policy, waiting for room only under `Block`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the item is rejected, or the receiver was dropped.
+    pub async fn offer(&self, item: T) -> Result<()> {
+        match self.shared.counters.options.overflow {
+            OverflowPolicy::Block => self.send(item).await,
+            _ => self.try_send(item),
+        }
+    }
+
+    /// Checks, under the `Reject` policy, that an item produced now would fit, counting a
+    /// rejection if not. Items always fit under the other policies.
+    pub fn admit(&self) -> bool {
+        if self.shared.counters.options.overflow != OverflowPolicy::Reject {
+            return true;
+        }
+        let full = self.shared.items.lock().unwrap().len() >= self.shared.capacity();
+        if full {
+            self.shared.count_rejected();
+        }
+        !full
+    }
+
+    /// Returns the counters of the queue.
+    pub fn counters(&self) -> Arc<QueueCounters> {
+        self.shared.counters.clone()
+    }
+
+    fn ensure_open(&self) -> Result<()> {
+        if self.shared.closed.load(Ordering::Acquire) {
+            return Err(anyhow!(
+                "The {} queue is no longer read",
+                self.shared.counters.name
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<T> Clone for QueueSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::AcqRel);
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for QueueSender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.readable.notify_one();
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for QueueSender<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QueueSender")
+            .field("counters", &self.shared.counters)
+            .finish()
+    }
+}
+
+/// The consuming side of a queue created by `queue`.
+pub struct QueueReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> QueueReceiver<T> {
+    /// Receives the oldest item, waiting for one. Cancel-safe, so it can be used in `select!`.
+    ///
+    /// # Returns
+    ///
+    /// * `None` once every sender was dropped and the queue is empty.
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            let readable = self.shared.readable.notified();
+            tokio::pin!(readable);
+            readable.as_mut().enable();
+            if let Some(item) = self.shared.pop() {
+                return Some(item);
+            }
+            if self.shared.senders.load(Ordering::Acquire) == 0 {
+                return None;
+            }
+            readable.await;
+        }
+    }
+
+    /// Returns the counters of the queue.
+    pub fn counters(&self) -> Arc<QueueCounters> {
+        self.shared.counters.clone()
+    }
+}
+
+impl<T> Drop for QueueReceiver<T> {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Release);
+        self.shared.writable.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Unit test for `queue`.
+    ///
+    /// Each overflow policy blocks, drops the oldest item or rejects the new one, and counts
+    /// what it drops and rejects; the receiver sees the queue close.
+    #[tokio::test]
+    async fn test_queue() {
+        let options = |overflow| QueueOptions {
+            capacity: 2,
+            overflow,
+        };
+
+        let (sender, mut receiver) = queue("block", options(OverflowPolicy::Block));
+        sender.send(1).await.unwrap();
+        sender.send(2).await.unwrap();
+        assert!(sender.try_send(3).is_err());
+        let blocked = tokio::spawn({
+            let sender = sender.clone();
+            async move { sender.send(3).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!blocked.is_finished());
+        assert_eq!(receiver.recv().await, Some(1));
+        blocked.await.unwrap().unwrap();
+        assert_eq!(receiver.recv().await, Some(2));
+        assert_eq!(receiver.recv().await, Some(3));
+        assert_eq!(sender.counters().stats().rejected, 1);
+        drop(sender);
+        assert_eq!(receiver.recv().await, None);
+
+        let (sender, mut receiver) = queue("drop", options(OverflowPolicy::DropOldest));
+        for i in 1..=5 {
+            sender.offer(i).await.unwrap();
+        }
+        let stats = receiver.counters().stats();
+        assert_eq!((stats.len, stats.dropped), (2, 3));
+        assert_eq!(receiver.recv().await, Some(4));
+        assert_eq!(receiver.recv().await, Some(5));
+
+        let (sender, receiver) = queue("reject", options(OverflowPolicy::Reject));
+        sender.offer(1).await.unwrap();
+        assert!(sender.admit());
+        sender.offer(2).await.unwrap();
+        assert!(!sender.admit());
+        assert!(sender.offer(3).await.is_err());
+        assert_eq!(sender.counters().stats().rejected, 2);
+        drop(receiver);
+        assert!(sender.send(4).await.is_err());
+    }
+}
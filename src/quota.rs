@@ -0,0 +1,634 @@
+use async_trait::async_trait;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::audit::{AuditLog, AuditOperation, AuditOrigin};
+use crate::cache_trait::{entry_weight, BCache};
+use crate::error::Error;
+use crate::events::{EvictionCause, KeyEvent, KeyEvents};
+use anyhow::{anyhow, Result};
+
+/// The minimum time between two recounts of the usage of the namespaces from the entries of
+/// the cache.
+const RECOUNT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What happens to a write that would take a namespace over its quota.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaPolicy {
+    /// The write is rejected with `507 Insufficient Storage`.
+    #[default]
+    Reject,
+    /// The write is accepted, and the entries of the namespace written longest ago are evicted
+    /// to make room for it.
+    ///
+    /// Evictions are not replicated: each node evicts from the entries it holds, in the order it
+    /// applied their writes, so nodes that applied writes in different orders, or missed some,
+    /// may evict different keys and hold different entries of the namespace.
+    Evict,
+}
+
+/// The quota of the keys starting with a prefix, parsed from
+/// `<prefix>=entries=<count>,bytes=<bytes>,policy=<reject|evict>`, such as
+/// `tenant-a:=entries=1000,bytes=1048576,policy=evict`. Each of the settings may be left out,
+/// and the policy defaults to `reject`.
+///
+/// # Fields
+///
+/// - `prefix`: The prefix of the keys of the namespace.
+/// - `max_entries`: The maximum number of entries of the namespace.
+/// - `max_bytes`: The maximum combined weight of the entries of the namespace, as computed by
+///   `entry_weight`.
+/// - `policy`: What happens to a write that would exceed them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamespaceQuota {
+    pub prefix: String,
+    pub max_entries: Option<usize>,
+    pub max_bytes: Option<usize>,
+    pub policy: QuotaPolicy,
+}
+
+impl NamespaceQuota {
+    /// Returns whether a namespace holding `entries` entries weighing `bytes` exceeds the quota.
+    fn exceeded_by(&self, entries: usize, bytes: usize) -> bool {
+        self.max_entries.is_some_and(|max| entries > max)
+            || self.max_bytes.is_some_and(|max| bytes > max)
+    }
+}
+
+impl FromStr for NamespaceQuota {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (prefix, settings) = s.split_once('=').ok_or_else(|| {
+            anyhow!(
+                "Expected <prefix>=entries=<count>,bytes=<bytes>,policy=<reject|evict>, got '{}'",
+                s
+            )
+        })?;
+        let mut quota = Self {
+            prefix: prefix.to_string(),
+            max_entries: None,
+            max_bytes: None,
+            policy: QuotaPolicy::default(),
+        };
+        for setting in settings.split(',').filter(|setting| !setting.is_empty()) {
+            let (name, value) = setting
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Expected <setting>=<value>, got '{}'", setting))?;
+            let invalid = || anyhow!("Invalid {} '{}' for prefix '{}'", name, value, prefix);
+            match name {
+                "entries" => quota.max_entries = Some(value.parse().map_err(|_| invalid())?),
+                "bytes" => quota.max_bytes = Some(value.parse().map_err(|_| invalid())?),
+                "policy" => {
+                    quota.policy = QuotaPolicy::from_str(value, true).map_err(|_| invalid())?
+                }
+                _ => return Err(anyhow!("Unknown quota setting '{}'", name)),
+            }
+        }
+        if quota.max_entries.is_none() && quota.max_bytes.is_none() {
+            return Err(anyhow!("The quota of prefix '{}' sets no limit", prefix));
+        }
+        Ok(quota)
+    }
+}
+
+/// The entries of a namespace, in the order they were written.
+#[derive(Debug, Default)]
+struct Usage {
+    /// The sequence number of the last write of each key, and the weight of its entry.
+    keys: HashMap<String, (u64, usize)>,
+    /// The keys, by the sequence number of their last write.
+    order: BTreeMap<u64, String>,
+    bytes: usize,
+    next: u64,
+    rejected: u64,
+    evicted: u64,
+}
+
+impl Usage {
+    /// Records a write of a key.
+    fn insert(&mut self, key: String, weight: usize) {
+        self.remove(&key);
+        self.next += 1;
+        self.order.insert(self.next, key.clone());
+        self.keys.insert(key, (self.next, weight));
+        self.bytes += weight;
+    }
+
+    /// Records the removal of a key.
+    fn remove(&mut self, key: &str) {
+        if let Some((seq, weight)) = self.keys.remove(key) {
+            self.order.remove(&seq);
+            self.bytes -= weight;
+        }
+    }
+
+    /// Returns the number of entries and their weight once an entry is written.
+    fn with(&self, key: &str, weight: usize) -> (usize, usize) {
+        match self.keys.get(key) {
+            Some((_, old)) => (self.keys.len(), self.bytes - old + weight),
+            None => (self.keys.len() + 1, self.bytes + weight),
+        }
+    }
+}
+
+/// The usage of a namespace, as served at `/stats/namespaces`.
+///
+/// # Fields
+///
+/// - `prefix`: The prefix of the keys of the namespace.
+/// - `entries`: The number of entries of the namespace held by this node.
+/// - `bytes`: Their combined weight.
+/// - `max_entries`: The maximum number of entries, if limited.
+/// - `max_bytes`: The maximum combined weight, if limited.
+/// - `policy`: What happens to the writes that would exceed the quota.
+/// - `rejected`: The writes rejected since this node started.
+/// - `evicted`: The entries evicted to make room for writes since this node started.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NamespaceStats {
+    pub prefix: String,
+    pub entries: usize,
+    pub bytes: usize,
+    pub max_entries: Option<usize>,
+    pub max_bytes: Option<usize>,
+    pub policy: QuotaPolicy,
+    pub rejected: u64,
+    pub evicted: u64,
+}
+
+/// Tracks the entries of each namespace with a quota, so a single tenant cannot evict the data
+/// of the others by flooding the shared cache.
+///
+/// A key belongs to the namespace with the longest prefix it starts with. Writes through the
+/// HTTP API are checked with `check` before they are applied, and every write applied by the
+/// `QuotaCache`, including those replicated from other members, is accounted. Replicated writes
+/// are never rejected, since the member that accepted them checked them already.
+///
+/// Entries the backend evicts or expires on its own are still counted until the next recount
+/// from the entries of the cache, which happens when a namespace looks full, at most once per
+/// `RECOUNT_INTERVAL`.
+///
+/// # Example
+///
+/// ```rust
+/// let quotas = Arc::new(Quotas::new(vec!["tenant-a:=entries=1000".parse()?]));
+/// let mut bcache: Box<dyn BCache> =
+///     Box::new(QuotaCache::new(inner, quotas.clone(), None, Arc::new(AuditLog::disabled())));
+/// quotas.check(&mut bcache, &[("tenant-a:1", Some("value"))]).await?;
+/// bcache.insert("tenant-a:1".to_string(), "value".to_string()).await;
+/// ```
+#[derive(Debug)]
+pub struct Quotas {
+    namespaces: Vec<NamespaceQuota>,
+    usage: Mutex<HashMap<String, Usage>>,
+    recounted_at: Mutex<Option<Instant>>,
+}
+
+impl Quotas {
+    /// Creates the quotas of the namespaces, none of which holds any entry yet.
+    pub fn new(namespaces: Vec<NamespaceQuota>) -> Self {
+        Self {
+            namespaces,
+            usage: Mutex::new(HashMap::new()),
+            recounted_at: Mutex::new(None),
+        }
+    }
+
+    /// Returns whether any namespace has a quota.
+    pub fn is_enabled(&self) -> bool {
+        !self.namespaces.is_empty()
+    }
+
+    /// Returns the quota of the namespace of a key, if it has one.
+    fn namespace(&self, key: &str) -> Option<&NamespaceQuota> {
+        self.namespaces
+            .iter()
+            .filter(|ns| key.starts_with(&ns.prefix))
+            .max_by_key(|ns| ns.prefix.len())
+    }
+
+    /// Returns the first of a batch of writes, applied in order, that would take its
+    /// namespace over its quota, with the quota. Writes to namespaces that evict are only
+    /// refused when their entry alone weighs more than the namespace may hold.
+    fn refused<'a>(
+        &'a self,
+        writes: &[(&str, Option<&str>)],
+    ) -> Option<(&'a NamespaceQuota, String)> {
+        let usage = self.usage.lock().unwrap();
+        // The weight of the keys of the batch written so far, by key, or `None` once removed.
+        let mut written: HashMap<&str, Option<usize>> = HashMap::new();
+        let mut totals: HashMap<&str, (usize, usize)> = HashMap::new();
+        for (key, value) in writes {
+            let Some(quota) = self.namespace(key) else {
+                continue;
+            };
+            let weight = value.map(|value| entry_weight(key, value));
+            if let (Some(max), Some(weight)) = (quota.max_bytes, weight) {
+                if weight > max {
+                    return Some((quota, key.to_string()));
+                }
+            }
+            if quota.policy == QuotaPolicy::Evict {
+                continue;
+            }
+            let namespace = usage.get(&quota.prefix);
+            let (entries, bytes) = totals.entry(&quota.prefix).or_insert_with(|| {
+                namespace.map_or((0, 0), |usage| (usage.keys.len(), usage.bytes))
+            });
+            let previous = written.insert(key, weight).unwrap_or_else(|| {
+                namespace.and_then(|usage| usage.keys.get(*key).map(|(_, weight)| *weight))
+            });
+            if let Some(previous) = previous {
+                *entries -= 1;
+                *bytes -= previous;
+            }
+            let Some(weight) = weight else {
+                continue;
+            };
+            *entries += 1;
+            *bytes += weight;
+            if quota.exceeded_by(*entries, *bytes) {
+                return Some((quota, key.to_string()));
+            }
+        }
+        None
+    }
+
+    /// Checks that a batch of writes, applied in order, keeps every namespace within its quota,
+    /// recounting the usage from the entries of the cache first if it looks exceeded.
+    ///
+    /// # Arguments
+    ///
+    /// * `bcache` - The locked cache the writes are applied to.
+    /// * `writes` - The keys written with their values, or removed with `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `QuotaExceeded` error naming the namespace if a write would exceed its quota.
+    pub async fn check(
+        &self,
+        bcache: &mut Box<dyn BCache>,
+        writes: &[(&str, Option<&str>)],
+    ) -> Result<(), Error> {
+        if !self.is_enabled() || self.refused(writes).is_none() {
+            return Ok(());
+        }
+        if self.recount_due() {
+            self.recount(bcache.entries().await);
+        }
+        let Some((quota, key)) = self.refused(writes) else {
+            return Ok(());
+        };
+        self.usage
+            .lock()
+            .unwrap()
+            .entry(quota.prefix.clone())
+            .or_default()
+            .rejected += 1;
+        Err(Error::QuotaExceeded(format!(
+            "Writing {} would exceed the quota of namespace '{}'",
+            key, quota.prefix
+        )))
+    }
+
+    /// Records a write of a key, and evicts the entries of its namespace written longest ago if
+    /// it exceeds its quota and evicts.
+    ///
+    /// # Returns
+    ///
+    /// * The keys to evict from the cache.
+    fn admit(&self, key: &str, weight: usize) -> Vec<String> {
+        let Some(quota) = self.namespace(key) else {
+            return Vec::new();
+        };
+        let mut usage = self.usage.lock().unwrap();
+        let usage = usage.entry(quota.prefix.clone()).or_default();
+        usage.insert(key.to_string(), weight);
+        let mut evicted = Vec::new();
+        if quota.policy == QuotaPolicy::Evict {
+            while quota.exceeded_by(usage.keys.len(), usage.bytes) && usage.keys.len() > 1 {
+                let Some((_, oldest)) = usage.order.pop_first() else {
+                    break;
+                };
+                if let Some((_, weight)) = usage.keys.remove(&oldest) {
+                    usage.bytes -= weight;
+                }
+                evicted.push(oldest);
+            }
+            usage.evicted += evicted.len() as u64;
+        }
+        evicted
+    }
+
+    /// Returns whether recording a write of a key would take its namespace over its quota.
+    fn would_exceed(&self, key: &str, weight: usize) -> bool {
+        let Some(quota) = self.namespace(key) else {
+            return false;
+        };
+        let usage = self.usage.lock().unwrap();
+        let (entries, bytes) = usage
+            .get(&quota.prefix)
+            .map_or((1, weight), |usage| usage.with(key, weight));
+        quota.exceeded_by(entries, bytes)
+    }
+
+    /// Records the removal of a key.
+    fn removed(&self, key: &str) {
+        if let Some(quota) = self.namespace(key) {
+            if let Some(usage) = self.usage.lock().unwrap().get_mut(&quota.prefix) {
+                usage.remove(key);
+            }
+        }
+    }
+
+    /// Returns whether the usage was not recounted within the last `RECOUNT_INTERVAL`, and
+    /// starts a new interval if so.
+    fn recount_due(&self) -> bool {
+        let mut recounted_at = self.recounted_at.lock().unwrap();
+        if recounted_at.is_some_and(|at| at.elapsed() < RECOUNT_INTERVAL) {
+            return false;
+        }
+        *recounted_at = Some(Instant::now());
+        true
+    }
+
+    /// Replaces the usage of the namespaces with the entries the cache holds. Keys still held
+    /// keep their place in the order of writes; the others are placed after them.
+    fn recount(&self, entries: Vec<(String, String)>) {
+        let mut held: HashMap<String, BTreeMap<u64, (String, usize)>> = HashMap::new();
+        let mut usage = self.usage.lock().unwrap();
+        for (key, value) in entries {
+            let Some(quota) = self.namespace(&key) else {
+                continue;
+            };
+            let seq = usage
+                .get(&quota.prefix)
+                .and_then(|usage| usage.keys.get(&key))
+                .map_or(u64::MAX, |(seq, _)| *seq);
+            let keys = held.entry(quota.prefix.clone()).or_default();
+            // Keys that were not seen written are ordered after the others, in any order.
+            let seq = if seq == u64::MAX {
+                u64::MAX - keys.len() as u64
+            } else {
+                seq
+            };
+            let weight = entry_weight(&key, &value);
+            keys.insert(seq, (key, weight));
+        }
+        for quota in &self.namespaces {
+            let previous = usage.remove(&quota.prefix).unwrap_or_default();
+            let mut recounted = Usage {
+                rejected: previous.rejected,
+                evicted: previous.evicted,
+                ..Usage::default()
+            };
+            for (_, (key, weight)) in held.remove(&quota.prefix).unwrap_or_default() {
+                recounted.insert(key, weight);
+            }
+            usage.insert(quota.prefix.clone(), recounted);
+        }
+    }
+
+    /// Returns the usage of every namespace with a quota, sorted by prefix.
+    pub fn stats(&self) -> Vec<NamespaceStats> {
+        let usage = self.usage.lock().unwrap();
+        let mut stats: Vec<NamespaceStats> = self
+            .namespaces
+            .iter()
+            .map(|quota| {
+                let usage = usage.get(&quota.prefix);
+                NamespaceStats {
+                    prefix: quota.prefix.clone(),
+                    entries: usage.map_or(0, |usage| usage.keys.len()),
+                    bytes: usage.map_or(0, |usage| usage.bytes),
+                    max_entries: quota.max_entries,
+                    max_bytes: quota.max_bytes,
+                    policy: quota.policy,
+                    rejected: usage.map_or(0, |usage| usage.rejected),
+                    evicted: usage.map_or(0, |usage| usage.evicted),
+                }
+            })
+            .collect();
+        stats.sort_by(|a, b| a.prefix.cmp(&b.prefix));
+        stats
+    }
+}
+
+/// `QuotaCache` wraps a `BCache` and accounts the entries written to and removed from it in the
+/// namespaces of `Quotas`, evicting the entries of the namespaces that evict when they exceed
+/// their quota.
+///
+/// Evicted keys are published to the subscribers of `/events` and recorded in the audit log.
+/// They are neither appended to the write-ahead log nor replicated; replaying the log applies
+/// the same writes, which evict the same keys again.
+///
+/// # Example
+///
+/// ```rust
+/// let quotas = Arc::new(Quotas::new(args.namespace_quota.clone()));
+/// if quotas.is_enabled() {
+///     cache = Box::new(QuotaCache::new(cache, quotas.clone(), Some(events.clone()), audit.clone()));
+/// }
+/// ```
+pub struct QuotaCache {
+    inner: Box<dyn BCache>,
+    quotas: Arc<Quotas>,
+    events: Option<KeyEvents>,
+    audit: Arc<AuditLog>,
+}
+
+impl QuotaCache {
+    /// Creates a new `QuotaCache` wrapping `inner`.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The cache holding the entries.
+    /// * `quotas` - The quotas of the namespaces, and their usage.
+    /// * `events` - Where evicted keys are published, if anywhere.
+    /// * `audit` - The audit log evicted keys are recorded in.
+    pub fn new(
+        inner: Box<dyn BCache>,
+        quotas: Arc<Quotas>,
+        events: Option<KeyEvents>,
+        audit: Arc<AuditLog>,
+    ) -> Self {
+        Self {
+            inner,
+            quotas,
+            events,
+            audit,
+        }
+    }
+}
+
+#[async_trait]
+impl BCache for QuotaCache {
+    /// Asynchronously inserts a key-value pair into the wrapped cache, then evicts the entries
+    /// its namespace has to give up, publishing and auditing each of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A `String` representing the key.
+    /// * `val` - A `String` representing the value associated with the key.
+    async fn insert(&mut self, key: String, val: String) {
+        let weight = entry_weight(&key, &val);
+        if self.quotas.would_exceed(&key, weight) && self.quotas.recount_due() {
+            self.quotas.recount(self.inner.entries().await);
+        }
+        let evicted = self.quotas.admit(&key, weight);
+        self.inner.insert(key, val).await;
+        for key in evicted {
+            self.inner.remove(key.clone()).await;
+            self.audit
+                .record(AuditOperation::Remove, &key, None, AuditOrigin::Eviction)
+                .await;
+            if let Some(events) = &self.events {
+                events.publish(KeyEvent::evicted(key, EvictionCause::Quota));
+            }
+        }
+    }
+
+    /// Asynchronously retrieves the value of a key from the wrapped cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A `String` representing the key to retrieve.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the wrapped cache fails to return the key.
+    async fn get(&mut self, key: String) -> Result<String> {
+        self.inner.get(key).await
+    }
+
+    /// Asynchronously removes a key from the wrapped cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A `String` representing the key to remove.
+    async fn remove(&mut self, key: String) {
+        self.quotas.removed(&key);
+        self.inner.remove(key).await;
+    }
+
+    /// Asynchronously returns the entries of the wrapped cache.
+    async fn entries(&mut self) -> Vec<(String, String)> {
+        self.inner.entries().await
+    }
+
+    /// Asynchronously returns a range of the entries of the wrapped cache.
+    async fn range(
+        &mut self,
+        start: Option<String>,
+        end: Option<String>,
+        limit: usize,
+    ) -> Result<Vec<(String, String)>> {
+        self.inner.range(start, end, limit).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moka_cache::MokaCache;
+
+    /// Unit test for `Quotas` and `QuotaCache`.
+    ///
+    /// Writes that would take a rejecting namespace over its quota are refused, counting the
+    /// replaced entries and the entries removed by the backend, while namespaces that evict
+    /// give up the entries written longest ago, published as evicted, and keys in no namespace
+    /// are not limited.
+    #[tokio::test]
+    async fn test_quotas() {
+        let quotas = Arc::new(Quotas::new(vec![
+            "a:=entries=2".parse().unwrap(),
+            "a:big:=bytes=30,policy=evict".parse().unwrap(),
+        ]));
+        let events = KeyEvents::new(8);
+        let mut evictions = events.subscribe();
+        let mut cache: Box<dyn BCache> = Box::new(QuotaCache::new(
+            Box::new(MokaCache::new(64).await),
+            quotas.clone(),
+            Some(events),
+            Arc::new(AuditLog::disabled()),
+        ));
+        for key in ["a:1", "a:2"] {
+            quotas.check(&mut cache, &[(key, Some("x"))]).await.unwrap();
+            cache.insert(key.to_string(), "x".to_string()).await;
+        }
+        assert!(quotas
+            .check(&mut cache, &[("a:1", Some("y"))])
+            .await
+            .is_ok());
+        assert!(matches!(
+            quotas.check(&mut cache, &[("a:3", Some("x"))]).await,
+            Err(Error::QuotaExceeded(_))
+        ));
+        assert!(quotas
+            .check(
+                &mut cache,
+                &[("other", Some("x")), ("a:1", Some("z")), ("a:1", Some("w"))]
+            )
+            .await
+            .is_ok());
+        cache.remove("a:1".to_string()).await;
+        assert!(quotas
+            .check(&mut cache, &[("a:3", Some("x")), ("a:4", Some("x"))])
+            .await
+            .is_err());
+        assert!(quotas
+            .check(&mut cache, &[("a:3", Some("x"))])
+            .await
+            .is_ok());
+        assert!(quotas
+            .check(
+                &mut cache,
+                &[("a:3", Some("x")), ("a:4", Some("x")), ("a:2", None)]
+            )
+            .await
+            .is_err());
+        assert!(quotas
+            .check(
+                &mut cache,
+                &[("a:2", None), ("a:3", Some("x")), ("a:4", Some("x"))]
+            )
+            .await
+            .is_ok());
+
+        for key in ["a:big:1", "a:big:2", "a:big:3"] {
+            cache.insert(key.to_string(), "0123456".to_string()).await;
+        }
+        assert!(cache.get("a:big:1".to_string()).await.is_err());
+        assert!(cache.get("a:big:3".to_string()).await.is_ok());
+        let eviction = evictions.try_recv().unwrap();
+        assert_eq!(eviction.key, "a:big:1");
+        assert_eq!(eviction.name(), "evicted");
+        assert!(evictions.try_recv().is_err());
+        assert!(quotas
+            .check(
+                &mut cache,
+                &[("a:big:4", Some("012345678901234567890123456789"))]
+            )
+            .await
+            .is_err());
+
+        let stats = quotas.stats();
+        assert_eq!(
+            stats
+                .iter()
+                .map(|ns| (ns.prefix.as_str(), ns.entries, ns.rejected, ns.evicted))
+                .collect::<Vec<_>>(),
+            vec![("a:", 1, 3, 0), ("a:big:", 2, 1, 1)]
+        );
+        assert_eq!(stats[1].bytes, 28);
+
+        assert!("a:".parse::<NamespaceQuota>().is_err());
+        assert!("a:=entries=x".parse::<NamespaceQuota>().is_err());
+        assert!("a:=policy=evict".parse::<NamespaceQuota>().is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use async_trait::async_trait;
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use redis::AsyncCommands;
+use std::time::Duration;
+use tracing::{debug, error};
+
+use crate::cache_trait::BCache;
+use crate::error::Error;
+use anyhow::Result;
+
+/// Configures the connection to Redis.
+///
+/// # Fields
+///
+/// - `url`: The URL of the Redis or Valkey server, such as `redis://127.0.0.1:6379`.
+/// - `pool_size`: The number of connections requests are spread over.
+/// - `connection_timeout`: The maximum time to establish a connection.
+/// - `response_timeout`: The maximum time to wait for the reply to a command.
+/// - `key_prefix`: A prefix added to every key stored in Redis, to share a server with other data.
+#[derive(Debug, Clone)]
+pub struct RedisOptions {
+    pub url: String,
+    pub pool_size: usize,
+    pub connection_timeout: Duration,
+    pub response_timeout: Duration,
+    pub key_prefix: String,
+}
+
+impl RedisOptions {
+    /// Creates `RedisOptions` for a server, with 4 connections, a 1 second connection timeout,
+    /// a 500 millisecond response timeout and no key prefix.
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            pool_size: 4,
+            connection_timeout: Duration::from_secs(1),
+            response_timeout: Duration::from_millis(500),
+            key_prefix: String::new(),
+        }
+    }
+}
+
+/// `RedisCache` wraps a `BCache` as a near-cache in front of a Redis or Valkey server.
+///
+/// Writes and removals go through to Redis, then to the wrapped cache, which acts as the L1.
+/// Reads are served from the L1, and misses are read through from Redis and populate the L1.
+/// Commands are spread round-robin over a pool of connections, each reconnecting on failure.
+///
+/// # Example
+///
+/// ```rust
+/// let local: Box<dyn BCache> = Box::new(MokaCache::new(1000).await);
+/// let mut cache = RedisCache::connect(local, RedisOptions::new("redis://127.0.0.1/".to_string())).await?;
+/// cache.insert("key".to_string(), "value".to_string()).await;
+/// ```
+pub struct RedisCache {
+    /// The in-memory cache reads are served from.
+    local: Box<dyn BCache>,
+    pool: Vec<ConnectionManager>,
+    /// The connection the next command is sent on.
+    next: usize,
+    key_prefix: String,
+}
+
+impl RedisCache {
+    /// Connects to the Redis server of `options`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the URL is invalid or a connection cannot be established.
+    pub async fn connect(local: Box<dyn BCache>, options: RedisOptions) -> Result<Self> {
+        let client = redis::Client::open(options.url.as_str())?;
+        let config = ConnectionManagerConfig::new()
+            .set_connection_timeout(options.connection_timeout)
+            .set_response_timeout(options.response_timeout);
+        let mut pool = Vec::with_capacity(options.pool_size.max(1));
+        for _ in 0..options.pool_size.max(1) {
+            pool.push(ConnectionManager::new_with_config(client.clone(), config.clone()).await?);
+        }
+
+        Ok(Self {
+            local,
+            pool,
+            next: 0,
+            key_prefix: options.key_prefix,
+        })
+    }
+
+    /// Returns the next connection of the pool.
+    fn connection(&mut self) -> ConnectionManager {
+        self.next = (self.next + 1) % self.pool.len();
+        self.pool[self.next].clone()
+    }
+
+    /// Returns the key an entry is stored under in Redis.
+    fn redis_key(&self, key: &str) -> String {
+        format!("{}{}", self.key_prefix, key)
+    }
+}
+
+#[async_trait]
+impl BCache for RedisCache {
+    /// Asynchronously writes a key-value pair to Redis, then to the local cache.
+    ///
+    /// A failed write to Redis is logged, and the local cache is still updated.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A `String` representing the key.
+    /// * `val` - A `String` representing the value associated with the key.
+    async fn insert(&mut self, key: String, val: String) {
+        let redis_key = self.redis_key(&key);
+        let result: redis::RedisResult<()> = self.connection().set(&redis_key, &val).await;
+        if let Err(e) = result {
+            error!("Failed to write key {} through to Redis: {:?}", key, e);
+        }
+        self.local.insert(key, val).await;
+    }
+
+    /// Asynchronously retrieves the value of a key from the local cache, or from Redis on a miss.
+    ///
+    /// A value read from Redis is inserted into the local cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A `String` representing the key to retrieve.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` if the key is in neither cache, and `Timeout` or `Backend` if Redis
+    /// cannot be read.
+    async fn get(&mut self, key: String) -> Result<String> {
+        if let Ok(value) = self.local.get(key.clone()).await {
+            return Ok(value);
+        }
+
+        let redis_key = self.redis_key(&key);
+        let value: Option<String> = self.connection().get(&redis_key).await.map_err(|e| {
+            if e.is_timeout() {
+                Error::Timeout(e.to_string())
+            } else {
+                Error::Backend(e.to_string())
+            }
+        })?;
+        let value = value.ok_or(Error::NotFound)?;
+        debug!("Read key {} through from Redis", key);
+        self.local.insert(key, value.clone()).await;
+        Ok(value)
+    }
+
+    /// Asynchronously removes a key from Redis, then from the local cache.
+    ///
+    /// A failed removal from Redis is logged, and the key is still removed locally.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A `String` representing the key to remove.
+    async fn remove(&mut self, key: String) {
+        let redis_key = self.redis_key(&key);
+        let result: redis::RedisResult<()> = self.connection().del(&redis_key).await;
+        if let Err(e) = result {
+            error!("Failed to remove key {} from Redis: {:?}", key, e);
+        }
+        self.local.remove(key).await;
+    }
+
+    /// Asynchronously returns the entries of the local cache. Entries only held in Redis are
+    /// not listed.
+    async fn entries(&mut self) -> Vec<(String, String)> {
+        self.local.entries().await
+    }
+
+    /// Asynchronously returns a range of the entries of the local cache. Entries only held in
+    /// Redis are not listed.
+    async fn range(
+        &mut self,
+        start: Option<String>,
+        end: Option<String>,
+        limit: usize,
+    ) -> Result<Vec<(String, String)>> {
+        self.local.range(start, end, limit).await
+    }
+}
@@ -0,0 +1,268 @@
+use crate::wire::Envelope;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::error;
+
+/// Configuration of the retry queue for unacknowledged gossip frames.
+///
+/// # Fields
+///
+/// - `initial_backoff`: The delay before a frame is first resent. It doubles with every attempt.
+/// - `max_backoff`: The maximum delay between two attempts.
+/// - `max_attempts`: The number of times a frame is sent, including the first one, before it is dead-lettered.
+/// - `capacity`: The maximum number of unacknowledged frames tracked; further frames are dead-lettered.
+#[derive(Debug, Clone)]
+pub struct RetryOptions {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub max_attempts: u32,
+    pub capacity: usize,
+}
+
+impl Default for RetryOptions {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            max_attempts: 8,
+            capacity: 10_000,
+        }
+    }
+}
+
+/// A frame that is due to be resent.
+#[derive(Debug, Clone)]
+pub struct Retry {
+    pub id: u64,
+    pub node: String,
+    pub target: SocketAddr,
+    /// The encoded frames, possibly chunks, of the envelope.
+    pub frames: Vec<Vec<u8>>,
+    /// The envelope, when it is a rumor the receiver relays on to the members it names.
+    pub rumor: Option<Envelope>,
+}
+
+#[derive(Debug)]
+struct Pending {
+    retry: Retry,
+    attempts: u32,
+    next_attempt: Instant,
+}
+
+/// Tracks gossip frames until their receivers acknowledge them, and schedules resends
+/// with exponential backoff.
+///
+/// Frames that are still unacknowledged after `max_attempts`, or that do not fit in the
+/// queue, are dropped and counted as dead-lettered.
+///
+/// # Example
+///
+/// ```rust
+/// let retries = RetryQueue::new(RetryOptions::default());
+/// retries.track(Retry { id, node, target, frames, rumor: None });
+/// for retry in retries.due(Instant::now()) {
+///     // resend retry.frames to retry.target
+/// }
+/// retries.acknowledge(id);
+/// ```
+#[derive(Debug)]
+pub struct RetryQueue {
+    options: RetryOptions,
+    pending: Mutex<HashMap<u64, Pending>>,
+    dead_lettered: AtomicU64,
+}
+
+impl RetryQueue {
+    /// Creates a new, empty `RetryQueue`.
+    pub fn new(options: RetryOptions) -> Self {
+        Self {
+            options,
+            pending: Mutex::new(HashMap::new()),
+            dead_lettered: AtomicU64::new(0),
+        }
+    }
+
+    /// Tracks a frame that was just sent for the first time.
+    ///
+    /// # Returns
+    ///
+    /// * `false` if the queue is full and the frame was dead-lettered instead.
+    pub fn track(&self, retry: Retry) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.len() >= self.options.capacity {
+            drop(pending);
+            self.dead_letter(&retry, "the retry queue is full");
+            return false;
+        }
+
+        pending.insert(
+            retry.id,
+            Pending {
+                retry,
+                attempts: 1,
+                next_attempt: Instant::now() + self.backoff(1),
+            },
+        );
+        true
+    }
+
+    /// Stops tracking an acknowledged frame.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the frame was still pending.
+    pub fn acknowledge(&self, id: u64) -> bool {
+        self.pending.lock().unwrap().remove(&id).is_some()
+    }
+
+    /// Returns the frames whose next attempt is due, and schedules the attempt after it.
+    ///
+    /// Frames that have used up their attempts are dead-lettered instead.
+    pub fn due(&self, now: Instant) -> Vec<Retry> {
+        let mut pending = self.pending.lock().unwrap();
+        let due: Vec<u64> = pending
+            .iter()
+            .filter(|(_, p)| p.next_attempt <= now)
+            .map(|(id, _)| *id)
+            .collect();
+
+        let mut retries = Vec::new();
+        for id in due {
+            let Some(p) = pending.get_mut(&id) else {
+                continue;
+            };
+            if p.attempts >= self.options.max_attempts {
+                if let Some(p) = pending.remove(&id) {
+                    self.dead_letter(&p.retry, "it was not acknowledged");
+                }
+                continue;
+            }
+
+            p.attempts += 1;
+            p.next_attempt = now + self.backoff(p.attempts);
+            retries.push(p.retry.clone());
+        }
+
+        retries
+    }
+
+    /// Makes every frame addressed to a node due for its next attempt now.
+    ///
+    /// # Returns
+    ///
+    /// * The number of frames made due.
+    pub fn expedite(&self, node: &str, now: Instant) -> usize {
+        let mut pending = self.pending.lock().unwrap();
+        let mut expedited = 0;
+        for p in pending.values_mut().filter(|p| p.retry.node == node) {
+            p.next_attempt = now;
+            expedited += 1;
+        }
+        expedited
+    }
+
+    /// Returns the number of frames currently awaiting acknowledgement.
+    pub fn len(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    /// Returns whether no frame is awaiting acknowledgement.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of frames dropped without being acknowledged.
+    pub fn dead_lettered(&self) -> u64 {
+        self.dead_lettered.load(Ordering::Relaxed)
+    }
+
+    /// Returns the delay after the given number of attempts.
+    fn backoff(&self, attempts: u32) -> Duration {
+        self.options
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+            .min(self.options.max_backoff)
+    }
+
+    fn dead_letter(&self, retry: &Retry, reason: &str) {
+        let total = self.dead_lettered.fetch_add(1, Ordering::Relaxed) + 1;
+        error!(
+            "Dropping gossip frame {} to {} ({}) because {}; {} frames dead-lettered so far",
+            retry.id, retry.node, retry.target, reason, total
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn retry(id: u64) -> Retry {
+        Retry {
+            id,
+            node: "node-2".to_string(),
+            target: "127.0.0.1:4002".parse().unwrap(),
+            frames: vec![vec![id as u8]],
+            rumor: None,
+        }
+    }
+
+    /// Unit test for resending unacknowledged frames with exponential backoff.
+    #[test]
+    fn test_retry_backoff() {
+        let retries = RetryQueue::new(RetryOptions {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(3),
+            max_attempts: 4,
+            capacity: 8,
+        });
+        assert!(retries.track(retry(1)));
+        assert!(retries.track(retry(2)));
+        let start = Instant::now();
+        assert!(retries.acknowledge(2));
+        assert!(!retries.acknowledge(2));
+
+        assert!(retries.due(start + Duration::from_millis(500)).is_empty());
+        // Attempt 2 after 1s, attempt 3 after 2s more, attempt 4 after 3s more (capped).
+        let t = start + Duration::from_secs(1);
+        assert_eq!(retries.due(t).len(), 1);
+        assert!(retries.due(t + Duration::from_millis(1999)).is_empty());
+        let t = t + Duration::from_secs(2);
+        assert_eq!(retries.due(t).len(), 1);
+        let t = t + Duration::from_secs(3);
+        assert_eq!(retries.due(t)[0].id, 1);
+
+        // All attempts are used up.
+        assert!(retries.due(t + Duration::from_secs(3)).is_empty());
+        assert!(retries.is_empty());
+        assert_eq!(retries.dead_lettered(), 1);
+    }
+
+    /// Unit test for resending the frames addressed to a node right away.
+    #[test]
+    fn test_retry_expedite() {
+        let retries = RetryQueue::new(RetryOptions::default());
+        retries.track(retry(1));
+        let now = Instant::now();
+        assert!(retries.due(now).is_empty());
+        assert_eq!(retries.expedite("node-3", now), 0);
+        assert_eq!(retries.expedite("node-2", now), 1);
+        assert_eq!(retries.due(now)[0].id, 1);
+    }
+
+    /// Unit test for dead-lettering frames that do not fit in the queue.
+    #[test]
+    fn test_retry_capacity() {
+        let retries = RetryQueue::new(RetryOptions {
+            capacity: 1,
+            ..RetryOptions::default()
+        });
+        assert!(retries.track(retry(1)));
+        assert!(!retries.track(retry(2)));
+        assert_eq!(retries.len(), 1);
+        assert_eq!(retries.dead_lettered(), 1);
+    }
+}
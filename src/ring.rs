@@ -0,0 +1,120 @@
+use crate::utils::fnv1a;
+
+/// Places the members of the cluster, and the keys, on a consistent hash ring.
+///
+/// Each member takes `vnodes` tokens on the ring. A key is owned by the member holding the
+/// first token at or after the token of the key, wrapping around to the first token of the
+/// ring. Adding or removing a member only moves the keys of the tokens it takes or gives up.
+///
+/// Tokens are stable across processes and builds, so clients can compute the owner of a key
+/// from the members and tokens served at `/topology`: the token of a key is `token(key)`, and
+/// the `i`-th token of a member is `token("{name}#{i}")`.
+///
+/// # Example
+///
+/// ```rust
+/// let ring = HashRing::new([("node1", 1), ("node2", 1)]);
+/// let owner = ring.owner("hello").unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct HashRing {
+    /// The tokens of the ring, sorted, and the member holding each.
+    tokens: Vec<(u64, String)>,
+}
+
+/// Returns the position of a key, or of a token of a member, on the ring: the 64-bit FNV-1a
+/// hash of its bytes, mixed by the SplitMix64 finalizer so that similar names spread over the
+/// whole ring.
+pub fn token(key: &str) -> u64 {
+    let mut hash = fnv1a(key.as_bytes());
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^ (hash >> 31)
+}
+
+/// Returns the tokens a member named `name` takes on the ring with `vnodes` virtual nodes.
+pub fn member_tokens(name: &str, vnodes: usize) -> Vec<u64> {
+    (0..vnodes)
+        .map(|i| token(&format!("{}#{}", name, i)))
+        .collect()
+}
+
+impl HashRing {
+    /// Builds the ring of some members.
+    ///
+    /// # Arguments
+    ///
+    /// * `members` - The name of each member, and the number of tokens it takes.
+    pub fn new<'a>(members: impl IntoIterator<Item = (&'a str, usize)>) -> Self {
+        let mut tokens: Vec<(u64, String)> = members
+            .into_iter()
+            .flat_map(|(name, vnodes)| {
+                member_tokens(name, vnodes)
+                    .into_iter()
+                    .map(move |token| (token, name.to_string()))
+            })
+            .collect();
+        tokens.sort_unstable();
+        Self { tokens }
+    }
+
+    /// Returns the member owning a key, or `None` if the ring is empty.
+    pub fn owner(&self, key: &str) -> Option<&str> {
+        if self.tokens.is_empty() {
+            return None;
+        }
+        let position = token(key);
+        let index = self.tokens.partition_point(|(token, _)| *token < position);
+        let (_, owner) = &self.tokens[index % self.tokens.len()];
+        Some(owner)
+    }
+
+    /// Returns the tokens held by a member, sorted.
+    pub fn tokens_of(&self, name: &str) -> Vec<u64> {
+        self.tokens
+            .iter()
+            .filter(|(_, member)| member == name)
+            .map(|(token, _)| *token)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit test for `HashRing`.
+    ///
+    /// Keys are owned by the member holding the next token, wrapping around the ring, and
+    /// removing a member only moves the keys it owned.
+    #[test]
+    fn test_hash_ring() {
+        assert_eq!(HashRing::default().owner("key"), None);
+
+        let ring = HashRing::new([("node1", 1), ("node2", 1), ("node3", 1)]);
+        let mut tokens: Vec<(u64, &str)> = ["node1", "node2", "node3"]
+            .into_iter()
+            .map(|name| (ring.tokens_of(name)[0], name))
+            .collect();
+        tokens.sort_unstable();
+        assert_eq!(ring.tokens_of("node1"), member_tokens("node1", 1));
+
+        let keys: Vec<String> = (0..1000).map(|i| format!("key{}", i)).collect();
+        for key in &keys {
+            let position = token(key);
+            let expected = tokens
+                .iter()
+                .find(|(token, _)| *token >= position)
+                .unwrap_or(&tokens[0])
+                .1;
+            assert_eq!(ring.owner(key), Some(expected));
+        }
+
+        let smaller = HashRing::new([("node1", 1), ("node3", 1)]);
+        for key in &keys {
+            if ring.owner(key) != Some("node2") {
+                assert_eq!(smaller.owner(key), ring.owner(key));
+            }
+        }
+    }
+}
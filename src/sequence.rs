@@ -0,0 +1,283 @@
+use crate::gossip::Sequence;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+/// Configuration of the ordering of sequenced gossip messages.
+///
+/// # Fields
+///
+/// - `gap_timeout`: How long messages following a missing one are held back before the
+///   missing one is given up on.
+/// - `max_held`: The maximum number of messages held back per origin; once exceeded, the
+///   missing messages are given up on.
+#[derive(Debug, Clone)]
+pub struct SequenceOptions {
+    pub gap_timeout: Duration,
+    pub max_held: usize,
+}
+
+impl Default for SequenceOptions {
+    fn default() -> Self {
+        Self {
+            gap_timeout: Duration::from_secs(5),
+            max_held: 1024,
+        }
+    }
+}
+
+/// The outcome of accepting a sequenced message.
+///
+/// # Fields
+///
+/// - `ready`: The messages that can be applied now, in order. Empty if the message is a
+///   duplicate or is held back until a missing one arrives.
+/// - `duplicate`: Whether the message was already received.
+/// - `missing`: The first missing sequence number, when the message revealed a new gap.
+#[derive(Debug)]
+pub struct Accepted<T> {
+    pub ready: Vec<T>,
+    pub duplicate: bool,
+    pub missing: Option<u64>,
+}
+
+/// The messages received from one incarnation of an origin.
+#[derive(Debug)]
+struct Stream<T> {
+    incarnation: u64,
+    /// The sequence number of the next message to apply.
+    next: u64,
+    /// Messages received ahead of `next`.
+    held: BTreeMap<u64, T>,
+    /// When the oldest unresolved gap was detected.
+    gap_since: Option<Instant>,
+}
+
+impl<T> Stream<T> {
+    fn new(incarnation: u64, next: u64) -> Self {
+        Self {
+            incarnation,
+            next,
+            held: BTreeMap::new(),
+            gap_since: None,
+        }
+    }
+
+    /// Moves the contiguous held messages starting at `next` into `ready`.
+    fn drain_contiguous(&mut self, ready: &mut Vec<T>) {
+        while let Some(item) = self.held.remove(&self.next) {
+            ready.push(item);
+            self.next += 1;
+        }
+    }
+
+    /// Gives up on the missing messages, moving every held message into `ready`.
+    fn skip_gap(&mut self, ready: &mut Vec<T>) {
+        if let Some(last) = self.held.keys().next_back().copied() {
+            ready.extend(std::mem::take(&mut self.held).into_values());
+            self.next = last + 1;
+        }
+        self.gap_since = None;
+    }
+}
+
+/// Puts the sequenced messages received from each origin back in order, dropping duplicates.
+///
+/// Messages are released in sequence order. A message arriving ahead of a missing one is held
+/// back until the missing one arrives, or until it is given up on after
+/// `SequenceOptions::gap_timeout`. The first message received from an origin, or from a new
+/// incarnation of it, starts its sequence.
+///
+/// # Example
+///
+/// ```rust
+/// let mut tracker = SequenceTracker::new(SequenceOptions::default());
+/// let accepted = tracker.accept(msg.sequence.as_ref().unwrap(), msg, Instant::now());
+/// for msg in accepted.ready {
+///     // apply msg
+/// }
+/// ```
+#[derive(Debug)]
+pub struct SequenceTracker<T> {
+    options: SequenceOptions,
+    streams: HashMap<String, Stream<T>>,
+}
+
+impl<T> SequenceTracker<T> {
+    /// Creates a new `SequenceTracker` that has not received any message.
+    pub fn new(options: SequenceOptions) -> Self {
+        Self {
+            options,
+            streams: HashMap::new(),
+        }
+    }
+
+    /// Accepts a message carrying the given sequence.
+    ///
+    /// # Arguments
+    ///
+    /// * `sequence` - The sequence of the message.
+    /// * `item` - The message.
+    /// * `now` - The current time, from which a newly detected gap is timed.
+    pub fn accept(&mut self, sequence: &Sequence, item: T, now: Instant) -> Accepted<T> {
+        let mut accepted = Accepted {
+            ready: Vec::new(),
+            duplicate: false,
+            missing: None,
+        };
+
+        let stream = self
+            .streams
+            .entry(sequence.origin.clone())
+            .or_insert_with(|| Stream::new(sequence.incarnation, sequence.seq));
+        if sequence.incarnation > stream.incarnation {
+            *stream = Stream::new(sequence.incarnation, sequence.seq);
+        }
+        if sequence.incarnation < stream.incarnation
+            || sequence.seq < stream.next
+            || stream.held.contains_key(&sequence.seq)
+        {
+            accepted.duplicate = true;
+            return accepted;
+        }
+
+        if sequence.seq == stream.next {
+            accepted.ready.push(item);
+            stream.next += 1;
+            stream.drain_contiguous(&mut accepted.ready);
+            if stream.held.is_empty() {
+                stream.gap_since = None;
+            }
+            return accepted;
+        }
+
+        stream.held.insert(sequence.seq, item);
+        if stream.gap_since.is_none() {
+            stream.gap_since = Some(now);
+            accepted.missing = Some(stream.next);
+        }
+        if stream.held.len() > self.options.max_held {
+            stream.skip_gap(&mut accepted.ready);
+        }
+        accepted
+    }
+
+    /// Takes back a released message that could not be applied, so that it is accepted again
+    /// when its sender resends it, holding back the messages released after it.
+    ///
+    /// # Arguments
+    ///
+    /// * `sequence` - The sequence of the message that could not be applied.
+    /// * `rest` - The messages released after it by the same origin, with their sequence
+    ///   numbers, which were not applied either.
+    /// * `now` - The current time, from which the gap left by the message is timed.
+    pub fn rewind(&mut self, sequence: &Sequence, rest: Vec<(u64, T)>, now: Instant) {
+        let Some(stream) = self.streams.get_mut(&sequence.origin) else {
+            return;
+        };
+        if stream.incarnation != sequence.incarnation || sequence.seq >= stream.next {
+            return;
+        }
+        stream.next = sequence.seq;
+        stream.held.extend(rest);
+        if !stream.held.is_empty() {
+            stream.gap_since = Some(now);
+        }
+    }
+
+    /// Gives up on messages missing for longer than the gap timeout.
+    ///
+    /// # Returns
+    ///
+    /// * The messages held back behind them, which can be applied now, in order.
+    pub fn release_stalled(&mut self, now: Instant) -> Vec<T> {
+        let mut ready = Vec::new();
+        for stream in self.streams.values_mut() {
+            if let Some(since) = stream.gap_since {
+                if now.duration_since(since) >= self.options.gap_timeout {
+                    stream.skip_gap(&mut ready);
+                }
+            }
+        }
+        ready
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequence(incarnation: u64, seq: u64) -> Sequence {
+        Sequence {
+            origin: "node-1".to_string(),
+            incarnation,
+            seq,
+        }
+    }
+
+    /// Unit test for dropping duplicates and releasing reordered messages in order.
+    #[test]
+    fn test_sequence_order() {
+        let now = Instant::now();
+        let mut tracker = SequenceTracker::new(SequenceOptions::default());
+
+        assert_eq!(tracker.accept(&sequence(1, 5), 5, now).ready, vec![5]);
+        assert!(tracker.accept(&sequence(1, 5), 5, now).duplicate);
+
+        let accepted = tracker.accept(&sequence(1, 8), 8, now);
+        assert!(accepted.ready.is_empty());
+        assert_eq!(accepted.missing, Some(6));
+        let accepted = tracker.accept(&sequence(1, 7), 7, now);
+        assert!(accepted.ready.is_empty());
+        assert_eq!(accepted.missing, None);
+        assert!(tracker.accept(&sequence(1, 7), 7, now).duplicate);
+        assert_eq!(tracker.accept(&sequence(1, 6), 6, now).ready, vec![6, 7, 8]);
+
+        // A restarted origin starts a new sequence, and the old one is stale.
+        assert_eq!(tracker.accept(&sequence(2, 1), 1, now).ready, vec![1]);
+        assert!(tracker.accept(&sequence(1, 9), 9, now).duplicate);
+    }
+
+    /// Unit test for taking back released messages that could not be applied.
+    ///
+    /// The message is accepted again when resent, releasing those held back behind it.
+    #[test]
+    fn test_sequence_rewind() {
+        let now = Instant::now();
+        let mut tracker = SequenceTracker::new(SequenceOptions::default());
+        tracker.accept(&sequence(1, 1), 1, now);
+        tracker.accept(&sequence(1, 3), 3, now);
+        assert_eq!(tracker.accept(&sequence(1, 2), 2, now).ready, vec![2, 3]);
+
+        tracker.rewind(&sequence(1, 2), vec![(3, 3)], now);
+        assert!(tracker.accept(&sequence(1, 3), 3, now).duplicate);
+        assert_eq!(tracker.accept(&sequence(1, 2), 2, now).ready, vec![2, 3]);
+        tracker.rewind(&sequence(1, 5), Vec::new(), now);
+        assert_eq!(tracker.accept(&sequence(1, 4), 4, now).ready, vec![4]);
+    }
+
+    /// Unit test for giving up on missing messages after the gap timeout.
+    #[test]
+    fn test_sequence_gap_timeout() {
+        let now = Instant::now();
+        let mut tracker = SequenceTracker::new(SequenceOptions {
+            gap_timeout: Duration::from_secs(5),
+            max_held: 2,
+        });
+        tracker.accept(&sequence(1, 1), 1, now);
+        tracker.accept(&sequence(1, 3), 3, now);
+        tracker.accept(&sequence(1, 4), 4, now);
+
+        assert!(tracker.release_stalled(now).is_empty());
+        assert_eq!(
+            tracker.release_stalled(now + Duration::from_secs(5)),
+            vec![3, 4]
+        );
+        assert!(tracker.accept(&sequence(1, 2), 2, now).duplicate);
+        assert_eq!(tracker.accept(&sequence(1, 5), 5, now).ready, vec![5]);
+
+        // Holding back more than `max_held` messages gives up on the missing ones too.
+        tracker.accept(&sequence(1, 7), 7, now);
+        tracker.accept(&sequence(1, 8), 8, now);
+        assert_eq!(tracker.accept(&sequence(1, 9), 9, now).ready, vec![7, 8, 9]);
+    }
+}
@@ -0,0 +1,254 @@
+use crate::error::Error;
+use crate::gossip::{Command, Message};
+use crate::utils::unix_time_ms;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+/// The writes a client made, or observed, through a session: for each node that accepted one,
+/// the position of the last of them.
+///
+/// Sent in the `X-Session-Token` header as `node1:42,node2:17`, sorted by node name.
+///
+/// # Example
+///
+/// ```rust
+/// let mut token = SessionToken::parse("node1:42")?;
+/// token.merge(&sessions.token());
+/// let header = token.to_string();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionToken(BTreeMap<String, u64>);
+
+impl SessionToken {
+    /// Parses a token, as formatted by `to_string`. Blank tokens are empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidRequest` error if an origin has no position, or a position is not a
+    /// number.
+    pub fn parse(token: &str) -> Result<Self, Error> {
+        let mut positions = BTreeMap::new();
+        for part in token
+            .split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+        {
+            let (origin, position) = part
+                .rsplit_once(':')
+                .filter(|(origin, _)| !origin.is_empty())
+                .ok_or_else(|| Error::InvalidRequest(format!("Invalid session token: {}", part)))?;
+            let position = position
+                .parse::<u64>()
+                .map_err(|e| Error::InvalidRequest(format!("Invalid session token: {}", e)))?;
+            let current: &mut u64 = positions.entry(origin.to_string()).or_default();
+            *current = (*current).max(position);
+        }
+        Ok(Self(positions))
+    }
+
+    /// Returns whether the token covers no write.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Adds the writes covered by another token to this one.
+    pub fn merge(&mut self, other: &SessionToken) {
+        for (origin, position) in &other.0 {
+            let current = self.0.entry(origin.clone()).or_default();
+            *current = (*current).max(*position);
+        }
+    }
+}
+
+impl fmt::Display for SessionToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut separator = "";
+        for (origin, position) in &self.0 {
+            write!(f, "{}{}:{}", separator, origin, position)?;
+            separator = ",";
+        }
+        Ok(())
+    }
+}
+
+/// Tracks how far this node has caught up with the writes of every node, so reads carrying a
+/// `SessionToken` see the writes it covers.
+///
+/// Every replicated write accepted by this node advances its position, and is followed in the
+/// replication queue by a `Checkpoint` message carrying it. Replicas apply messages in the
+/// order they were sent, so once they applied a checkpoint, they applied the writes before it.
+///
+/// Positions start from the time the node started, in microseconds, so they keep increasing
+/// when it restarts.
+///
+/// # Fields
+///
+/// - `name`: The name of this node.
+/// - `start`: The position of this node before it accepted any write.
+/// - `position`: The position of the last write accepted by this node.
+/// - `applied`: The position of the last checkpoint applied from each other node.
+/// - `changed`: Wakes the reads waiting for a checkpoint.
+///
+/// # Example
+///
+/// ```rust
+/// sender.send((msg, replication.clone())).await?;
+/// sender.send((sessions.advance(), replication)).await?;
+///
+/// // On a replica, when applying the checkpoint.
+/// sessions.record(&msg.key, msg.value.parse()?);
+/// let behind = sessions.wait(&token, Duration::from_millis(500)).await;
+/// ```
+#[derive(Debug)]
+pub struct Sessions {
+    name: String,
+    start: u64,
+    position: AtomicU64,
+    applied: Mutex<HashMap<String, u64>>,
+    changed: Notify,
+}
+
+impl Sessions {
+    /// Creates the sessions of a node that has not accepted or applied any write yet.
+    pub fn new(name: String) -> Self {
+        let start = unix_time_ms() * 1000;
+        Self {
+            name,
+            start,
+            position: AtomicU64::new(start),
+            applied: Mutex::new(HashMap::new()),
+            changed: Notify::new(),
+        }
+    }
+
+    /// Returns the name of this node.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Advances the position of this node past a replicated write it accepted.
+    ///
+    /// # Returns
+    ///
+    /// * The `Checkpoint` message to replicate after the write.
+    pub fn advance(&self) -> Message {
+        let position = self.position.fetch_add(1, Ordering::SeqCst) + 1;
+        Message::new(Command::Checkpoint, self.name.clone(), position.to_string())
+    }
+
+    /// Returns the token covering the writes accepted by this node so far, which is empty
+    /// until it accepted one.
+    pub fn token(&self) -> SessionToken {
+        let position = self.position.load(Ordering::SeqCst);
+        let mut token = SessionToken::default();
+        if position > self.start {
+            token.0.insert(self.name.clone(), position);
+        }
+        token
+    }
+
+    /// Records that the writes of a node up to a position were applied, waking the reads
+    /// waiting for them.
+    ///
+    /// # Arguments
+    ///
+    /// * `origin` - The name of the node that accepted the writes, from the `key` of the checkpoint.
+    /// * `position` - The position of the checkpoint, from its `value`.
+    pub fn record(&self, origin: &str, position: u64) {
+        let mut applied = self.applied.lock().unwrap();
+        let current = applied.entry(origin.to_string()).or_default();
+        if position > *current {
+            *current = position;
+            self.changed.notify_waiters();
+        }
+    }
+
+    /// Returns the nodes whose writes covered by a token were not all applied by this node,
+    /// sorted by name. The writes of this node are always applied.
+    pub fn behind(&self, token: &SessionToken) -> Vec<String> {
+        let applied = self.applied.lock().unwrap();
+        token
+            .0
+            .iter()
+            .filter(|(origin, position)| {
+                **origin != self.name && applied.get(*origin).copied().unwrap_or(0) < **position
+            })
+            .map(|(origin, _)| origin.clone())
+            .collect()
+    }
+
+    /// Waits until this node has applied the writes covered by a token, or the timeout elapses.
+    ///
+    /// # Returns
+    ///
+    /// * The nodes this node is still behind on, empty once it caught up.
+    pub async fn wait(&self, token: &SessionToken, timeout: Duration) -> Vec<String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            // Registered before checking, so a checkpoint recorded in between still wakes it.
+            let changed = self.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+
+            let behind = self.behind(token);
+            if behind.is_empty() || tokio::time::timeout_at(deadline, changed).await.is_err() {
+                return behind;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Unit test for `SessionToken` and `Sessions`.
+    ///
+    /// Tokens round-trip and merge by keeping the highest position of each node; reads wait
+    /// until the checkpoints of the writes their token covers are recorded, and give up after
+    /// the timeout.
+    #[tokio::test]
+    async fn test_sessions() {
+        let mut token = SessionToken::parse("node2:7, node1:3,node1:2").unwrap();
+        assert_eq!(token.to_string(), "node1:3,node2:7");
+        token.merge(&SessionToken::parse("node1:5,node3:1").unwrap());
+        assert_eq!(token.to_string(), "node1:5,node2:7,node3:1");
+        assert!(SessionToken::parse("").unwrap().is_empty());
+        assert!(SessionToken::parse("node1").is_err());
+        assert!(SessionToken::parse(":1").is_err());
+        assert!(SessionToken::parse("node1:x").is_err());
+
+        let origin = Sessions::new("node1".to_string());
+        assert!(origin.token().is_empty());
+        let checkpoint = origin.advance();
+        assert_eq!(checkpoint.cmd, Command::Checkpoint);
+        let token = origin.token();
+        assert_eq!(origin.behind(&token), Vec::<String>::new());
+
+        let replica = Arc::new(Sessions::new("node2".to_string()));
+        assert_eq!(replica.behind(&token), vec!["node1".to_string()]);
+        assert_eq!(
+            replica.wait(&token, Duration::from_millis(10)).await,
+            vec!["node1".to_string()]
+        );
+
+        let position: u64 = checkpoint.value.parse().unwrap();
+        let recorder = replica.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            recorder.record("node1", position - 1);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            recorder.record("node1", position);
+        });
+        assert!(replica
+            .wait(&token, Duration::from_secs(5))
+            .await
+            .is_empty());
+    }
+}
@@ -0,0 +1,231 @@
+use async_trait::async_trait;
+use std::ops::Bound;
+use std::path::Path;
+
+use crate::cache_trait::BCache;
+use crate::error::Error;
+use anyhow::Result;
+use tracing::error;
+
+/// `SledCache` is an implementation of the `BCache` trait backed by the embedded `sled` database.
+///
+/// Entries are stored on disk in a single directory and survive restarts. Keys are kept in
+/// byte order, so entries are listed sorted by key and key ranges can be queried. There is no
+/// capacity or expiration: entries stay until they are removed.
+///
+/// # Example
+///
+/// ```rust
+/// let mut cache = SledCache::open("data.sled")?;
+/// cache.insert("key".to_string(), "value".to_string()).await;
+/// assert_eq!(cache.get("key".to_string()).await.unwrap(), "value");
+/// ```
+#[derive(Debug, Clone)]
+pub struct SledCache {
+    /// The `sled` database holding the entries.
+    db: sled::Db,
+}
+
+impl SledCache {
+    /// Opens (or creates) the `sled` database at `path`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The directory of the database.
+    ///
+    /// # Returns
+    ///
+    /// * A new `SledCache` instance.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be opened, for example because another process holds it.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            db: sled::open(path)?,
+        })
+    }
+}
+
+#[async_trait]
+impl BCache for SledCache {
+    /// Asynchronously inserts a key-value pair into the database.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A `String` representing the key.
+    /// * `val` - A `String` representing the value associated with the key.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// cache.insert("key".to_string(), "value".to_string()).await;
+    /// ```
+    async fn insert(&mut self, key: String, val: String) {
+        if let Err(e) = self.db.insert(key.as_bytes(), val.as_bytes()) {
+            error!("Failed to insert key {} into sled: {:?}", key, e);
+        }
+    }
+
+    /// Asynchronously retrieves the value associated with the given key from the database.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A `String` representing the key to retrieve.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<String>` containing the value if found, or an error if the key is not found.
+    ///
+    /// # Errors
+    ///
+    /// If the key does not exist, or the database cannot be read, an `anyhow::Error` is returned.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let value = cache.get("key".to_string()).await.unwrap();
+    /// assert_eq!(value, "value".to_string());
+    /// ```
+    async fn get(&mut self, key: String) -> Result<String> {
+        match self
+            .db
+            .get(key.as_bytes())
+            .map_err(|e| Error::Backend(e.to_string()))?
+        {
+            Some(value) => Ok(String::from_utf8(value.to_vec())?),
+            None => Err(Error::NotFound.into()),
+        }
+    }
+
+    /// Asynchronously removes the key-value pair from the database, if it exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A `String` representing the key to remove.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// cache.remove("key".to_string()).await;
+    /// ```
+    async fn remove(&mut self, key: String) {
+        if let Err(e) = self.db.remove(key.as_bytes()) {
+            error!("Failed to remove key {} from sled: {:?}", key, e);
+        }
+    }
+
+    /// Asynchronously returns every entry of the database.
+    ///
+    /// # Returns
+    ///
+    /// * The key-value pairs of the database, sorted by key.
+    async fn entries(&mut self) -> Vec<(String, String)> {
+        self.db
+            .iter()
+            .filter_map(|entry| match entry {
+                Ok((key, value)) => Some((
+                    String::from_utf8_lossy(&key).into_owned(),
+                    String::from_utf8_lossy(&value).into_owned(),
+                )),
+                Err(e) => {
+                    error!("Failed to read an entry from sled: {:?}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Asynchronously returns the entries whose keys are between `start` (inclusive) and `end`
+    /// (exclusive), sorted by key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an entry cannot be read.
+    async fn range(
+        &mut self,
+        start: Option<String>,
+        end: Option<String>,
+        limit: usize,
+    ) -> Result<Vec<(String, String)>> {
+        if let (Some(start), Some(end)) = (&start, &end) {
+            if start >= end {
+                return Ok(Vec::new());
+            }
+        }
+        let bounds = (
+            start.map_or(Bound::Unbounded, |start| {
+                Bound::Included(start.into_bytes())
+            }),
+            end.map_or(Bound::Unbounded, |end| Bound::Excluded(end.into_bytes())),
+        );
+
+        self.db
+            .range::<Vec<u8>, _>(bounds)
+            .take(limit)
+            .map(|entry| {
+                let (key, value) = entry?;
+                Ok((
+                    String::from_utf8_lossy(&key).into_owned(),
+                    String::from_utf8_lossy(&value).into_owned(),
+                ))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::unix_time_ms;
+
+    /// Unit test for `SledCache`.
+    ///
+    /// Entries are listed in key order, can be queried by range and survive reopening the database.
+    #[tokio::test]
+    async fn test_sled_cache() {
+        let path = std::env::temp_dir().join(format!("sled-test-{}", unix_time_ms()));
+        let mut cache = SledCache::open(&path).unwrap();
+        cache.insert("hello".to_string(), "world".to_string()).await;
+        cache.insert("foo".to_string(), "bar".to_string()).await;
+        cache.insert("gone".to_string(), "soon".to_string()).await;
+        cache.remove("gone".to_string()).await;
+        cache.db.flush().unwrap();
+        drop(cache);
+
+        // sled's background flusher may hold the database lock briefly after the drop.
+        let mut cache = loop {
+            match SledCache::open(&path) {
+                Ok(cache) => break cache,
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+            }
+        };
+        assert_eq!(cache.get("hello".to_string()).await.unwrap(), "world");
+        assert!(cache.get("gone".to_string()).await.is_err());
+        assert_eq!(
+            cache.entries().await,
+            vec![
+                ("foo".to_string(), "bar".to_string()),
+                ("hello".to_string(), "world".to_string()),
+            ]
+        );
+        assert_eq!(
+            cache
+                .range(Some("f".to_string()), Some("h".to_string()), 10)
+                .await
+                .unwrap(),
+            vec![("foo".to_string(), "bar".to_string())]
+        );
+        assert_eq!(
+            cache.range(None, None, 1).await.unwrap(),
+            vec![("foo".to_string(), "bar".to_string())]
+        );
+        assert!(cache
+            .range(Some("z".to_string()), Some("a".to_string()), 10)
+            .await
+            .unwrap()
+            .is_empty());
+        drop(cache);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
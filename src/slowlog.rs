@@ -0,0 +1,212 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// The kind of operation recorded in the slow log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SlowLogKind {
+    /// An HTTP handler.
+    Http,
+    /// The application of a gossip message in `handle_gossip_message`.
+    Gossip,
+}
+
+/// An operation that exceeded its slow log threshold.
+#[derive(Debug, Clone, Serialize)]
+pub struct SlowLogEntry {
+    pub kind: SlowLogKind,
+    /// The HTTP route, or the gossip command.
+    pub operation: String,
+    pub key: Option<String>,
+    pub duration_ms: u64,
+    /// Unix timestamp, in milliseconds, at which the operation completed.
+    pub timestamp_ms: u64,
+}
+
+/// Records HTTP requests and gossip applications that exceed configurable thresholds.
+///
+/// Slow operations are logged with their key, route (or command) and duration, and the most
+/// recent ones are kept in a bounded ring buffer exposed at `/debug/slowlog`.
+///
+/// # Example
+///
+/// ```rust
+/// let slowlog = SlowLog::new(Duration::from_millis(100), Duration::from_millis(50), 128);
+/// {
+///     let _timer = slowlog.start(SlowLogKind::Http, "/add", Some("key".to_string()));
+///     // handle the request
+/// }
+/// println!("{:?}", slowlog.entries());
+/// ```
+#[derive(Debug)]
+pub struct SlowLog {
+    http_threshold: Duration,
+    gossip_threshold: Duration,
+    capacity: usize,
+    entries: Mutex<VecDeque<SlowLogEntry>>,
+}
+
+impl SlowLog {
+    /// Creates a new `SlowLog`.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_threshold` - HTTP handlers taking longer than this are recorded.
+    /// * `gossip_threshold` - Gossip message applications taking longer than this are recorded.
+    /// * `capacity` - The maximum number of entries kept; the oldest entries are dropped first.
+    pub fn new(http_threshold: Duration, gossip_threshold: Duration, capacity: usize) -> Self {
+        Self {
+            http_threshold,
+            gossip_threshold,
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Starts timing an operation; it is recorded when the returned timer is dropped.
+    pub fn start(
+        &self,
+        kind: SlowLogKind,
+        operation: impl Into<String>,
+        key: Option<String>,
+    ) -> SlowLogTimer<'_> {
+        SlowLogTimer {
+            slowlog: self,
+            kind,
+            operation: operation.into(),
+            key,
+            start: Instant::now(),
+        }
+    }
+
+    /// Records an operation if its duration exceeds the threshold for its kind.
+    pub fn record(
+        &self,
+        kind: SlowLogKind,
+        operation: String,
+        key: Option<String>,
+        duration: Duration,
+    ) {
+        let threshold = match kind {
+            SlowLogKind::Http => self.http_threshold,
+            SlowLogKind::Gossip => self.gossip_threshold,
+        };
+        if duration <= threshold || self.capacity == 0 {
+            return;
+        }
+
+        warn!(
+            "Slow {:?} operation: operation={} key={:?} duration={:?}",
+            kind, operation, key, duration
+        );
+
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(SlowLogEntry {
+            kind,
+            operation,
+            key,
+            duration_ms: duration.as_millis() as u64,
+            timestamp_ms,
+        });
+    }
+
+    /// Returns the recorded entries, oldest first.
+    pub fn entries(&self) -> Vec<SlowLogEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Times an operation and records it in the `SlowLog` when dropped.
+pub struct SlowLogTimer<'a> {
+    slowlog: &'a SlowLog,
+    kind: SlowLogKind,
+    operation: String,
+    key: Option<String>,
+    start: Instant,
+}
+
+impl Drop for SlowLogTimer<'_> {
+    fn drop(&mut self) {
+        self.slowlog.record(
+            self.kind,
+            std::mem::take(&mut self.operation),
+            self.key.take(),
+            self.start.elapsed(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit test for `SlowLog` thresholds.
+    ///
+    /// Only operations slower than the threshold of their kind are recorded.
+    #[test]
+    fn test_slowlog_threshold() {
+        let slowlog = SlowLog::new(Duration::from_millis(100), Duration::from_millis(10), 8);
+        slowlog.record(
+            SlowLogKind::Http,
+            "/add".to_string(),
+            Some("fast".to_string()),
+            Duration::from_millis(50),
+        );
+        slowlog.record(
+            SlowLogKind::Gossip,
+            "Insert".to_string(),
+            Some("slow".to_string()),
+            Duration::from_millis(50),
+        );
+
+        let entries = slowlog.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].kind, SlowLogKind::Gossip);
+        assert_eq!(entries[0].key.as_deref(), Some("slow"));
+        assert_eq!(entries[0].duration_ms, 50);
+    }
+
+    /// Unit test for the `SlowLog` ring buffer.
+    ///
+    /// Once full, the oldest entries are dropped first.
+    #[test]
+    fn test_slowlog_capacity() {
+        let slowlog = SlowLog::new(Duration::ZERO, Duration::ZERO, 2);
+        for key in ["a", "b", "c"] {
+            slowlog.record(
+                SlowLogKind::Http,
+                "/query".to_string(),
+                Some(key.to_string()),
+                Duration::from_millis(1),
+            );
+        }
+
+        let keys: Vec<_> = slowlog
+            .entries()
+            .into_iter()
+            .filter_map(|e| e.key)
+            .collect();
+        assert_eq!(keys, vec!["b".to_string(), "c".to_string()]);
+    }
+
+    /// Unit test for `SlowLogTimer`.
+    #[test]
+    fn test_slowlog_timer() {
+        let slowlog = SlowLog::new(Duration::ZERO, Duration::ZERO, 2);
+        {
+            let _timer = slowlog.start(SlowLogKind::Http, "/add", None);
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        assert_eq!(slowlog.entries()[0].operation, "/add");
+    }
+}
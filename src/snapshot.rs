@@ -0,0 +1,331 @@
+use crate::cache_trait::BCache;
+use crate::tags::TagIndex;
+use crate::utils::{fnv1a, unix_time_ms};
+use crate::wal::Wal;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time;
+use tracing::{error, info};
+
+/// The version of the snapshot file format written by this release.
+const FORMAT_VERSION: u32 = 1;
+
+/// The file name periodic snapshots are written to and warm restarts load from.
+pub const PERIODIC_SNAPSHOT: &str = "latest.json";
+
+/// A point-in-time copy of the keyspace of a node.
+///
+/// Snapshot files are JSON documents holding the `SnapshotMetadata` and the entries. The
+/// metadata carries a checksum of the entries, verified when the snapshot is read back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub metadata: SnapshotMetadata,
+    pub entries: Vec<SnapshotEntry>,
+}
+
+/// Describes a snapshot.
+///
+/// # Fields
+///
+/// - `format_version`: The version of the file format.
+/// - `created_at_ms`: The Unix timestamp, in milliseconds, at which the snapshot was taken.
+/// - `entry_count`: The number of entries in the snapshot.
+/// - `checksum`: The FNV-1a hash of the serialized entries, in hexadecimal.
+/// - `wal_lsn`: The last write-ahead log record the snapshot covers, or `0`. Only later records
+///   are replayed on top of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotMetadata {
+    pub format_version: u32,
+    pub created_at_ms: u64,
+    pub entry_count: usize,
+    pub checksum: String,
+    #[serde(default)]
+    pub wal_lsn: u64,
+}
+
+/// A key-value pair of a snapshot, and its tags.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    pub key: String,
+    pub value: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl Snapshot {
+    /// Takes a snapshot of every entry of the cache, and its tags.
+    ///
+    /// # Arguments
+    ///
+    /// * `bcache` - The cache to copy.
+    /// * `tags` - The tag index the tags of the entries are looked up in.
+    pub async fn capture(bcache: &Mutex<Box<dyn BCache>>, tags: &TagIndex) -> Result<Self> {
+        let mut entries: Vec<SnapshotEntry> = bcache
+            .lock()
+            .await
+            .entries()
+            .await
+            .into_iter()
+            .map(|(key, value)| SnapshotEntry {
+                tags: tags.tags_of(&key),
+                key,
+                value,
+            })
+            .collect();
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+
+        Ok(Self {
+            metadata: SnapshotMetadata {
+                format_version: FORMAT_VERSION,
+                created_at_ms: unix_time_ms(),
+                entry_count: entries.len(),
+                checksum: checksum(&entries)?,
+                wal_lsn: 0,
+            },
+            entries,
+        })
+    }
+
+    /// Checks that the snapshot has a supported format and that its entries match its checksum.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the format version is unsupported, or the entries were altered.
+    pub fn verify(&self) -> Result<()> {
+        if self.metadata.format_version != FORMAT_VERSION {
+            return Err(anyhow!(
+                "Unsupported snapshot format version {}",
+                self.metadata.format_version
+            ));
+        }
+        if self.metadata.entry_count != self.entries.len()
+            || self.metadata.checksum != checksum(&self.entries)?
+        {
+            return Err(anyhow!("Snapshot checksum mismatch"));
+        }
+        Ok(())
+    }
+
+    /// Loads every entry of the snapshot, and its tags, into the cache of this node only.
+    ///
+    /// # Arguments
+    ///
+    /// * `bcache` - The cache the entries are inserted into.
+    /// * `tags` - The tag index the tags of the entries are recorded in.
+    ///
+    /// # Returns
+    ///
+    /// * The number of entries loaded.
+    pub async fn load_into(self, bcache: &Mutex<Box<dyn BCache>>, tags: &TagIndex) -> usize {
+        let mut bcache = bcache.lock().await;
+        let count = self.entries.len();
+        for entry in self.entries {
+            tags.set_tags(&entry.key, &entry.tags);
+            bcache.insert(entry.key, entry.value).await;
+        }
+        count
+    }
+}
+
+/// Computes the checksum of the entries of a snapshot.
+fn checksum(entries: &[SnapshotEntry]) -> Result<String> {
+    Ok(format!("{:016x}", fnv1a(&serde_json::to_vec(entries)?)))
+}
+
+/// The directory snapshots are written to and read from, by file name.
+///
+/// Only plain file names are accepted, so requests cannot read or write files outside the
+/// directory.
+#[derive(Debug, Clone)]
+pub struct SnapshotStore {
+    dir: PathBuf,
+}
+
+impl SnapshotStore {
+    /// Creates a new `SnapshotStore` over a directory, which is created on the first write.
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Returns the path of the snapshot with the given file name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name is empty or is not a plain file name.
+    pub fn path(&self, name: &str) -> Result<PathBuf> {
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            return Err(anyhow!("Invalid snapshot name {:?}", name));
+        }
+        Ok(self.dir.join(name))
+    }
+
+    /// Writes a snapshot under the given file name, replacing any previous one atomically.
+    ///
+    /// # Returns
+    ///
+    /// * The path the snapshot was written to.
+    pub async fn save(&self, snapshot: &Snapshot, name: &str) -> Result<PathBuf> {
+        let path = self.path(name)?;
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, serde_json::to_vec(snapshot)?)
+            .await
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        tokio::fs::rename(&tmp, &path)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Reads and verifies the snapshot with the given file name.
+    pub async fn load(&self, name: &str) -> Result<Snapshot> {
+        read(&self.path(name)?).await
+    }
+
+    /// Reads and verifies the snapshot with the given file name, if it exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot exists but cannot be read or fails verification.
+    pub async fn load_if_exists(&self, name: &str) -> Result<Option<Snapshot>> {
+        let path = self.path(name)?;
+        if !tokio::fs::try_exists(&path).await? {
+            return Ok(None);
+        }
+        read(&path).await.map(Some)
+    }
+}
+
+/// Writes a snapshot of the cache to `PERIODIC_SNAPSHOT` every `interval`, forever.
+///
+/// Each snapshot records the last write-ahead log record it covers, and the log segments it
+/// covers are deleted once it is written. Failures are logged and retried on the next tick,
+/// so a full disk does not stop the node.
+///
+/// # Arguments
+///
+/// * `store` - The directory the snapshots are written to.
+/// * `bcache` - The cache to copy.
+/// * `tags` - The tag index the tags of the entries are looked up in.
+/// * `wal` - The write-ahead log truncated after each snapshot.
+/// * `interval` - The time between two snapshots.
+pub async fn run_periodic(
+    store: SnapshotStore,
+    bcache: Arc<Mutex<Box<dyn BCache>>>,
+    tags: Arc<TagIndex>,
+    wal: Arc<Wal>,
+    interval: Duration,
+) {
+    let mut ticker = time::interval(interval);
+    // The first tick completes immediately, and there is nothing new to save at startup.
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        // Records appended while the cache is copied are replayed again on restart, which
+        // applies them in the same order and yields the same state.
+        let wal_lsn = wal.last_lsn().await;
+        let result = match Snapshot::capture(&bcache, &tags).await {
+            Ok(mut snapshot) => {
+                snapshot.metadata.wal_lsn = wal_lsn;
+                store
+                    .save(&snapshot, PERIODIC_SNAPSHOT)
+                    .await
+                    .map(|path| (path, snapshot.metadata.entry_count))
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok((path, entries)) => {
+                info!("Wrote {} entries to {}", entries, path.display());
+                if let Err(e) = wal.truncate(wal_lsn).await {
+                    error!("Failed to truncate the write-ahead log: {:?}", e);
+                }
+            }
+            Err(e) => error!("Failed to write periodic snapshot: {:?}", e),
+        }
+    }
+}
+
+/// Reads and verifies the snapshot at a path.
+async fn read(path: &Path) -> Result<Snapshot> {
+    let bytes = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let snapshot: Snapshot = serde_json::from_slice(&bytes)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    snapshot.verify()?;
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moka_cache::MokaCache;
+
+    /// Unit test for writing a snapshot and reading it back, rejecting altered ones.
+    #[tokio::test]
+    async fn test_snapshot_round_trip() {
+        let bcache: Mutex<Box<dyn BCache>> = Mutex::new(Box::new(MokaCache::new(16).await));
+        let tags = TagIndex::default();
+        bcache
+            .lock()
+            .await
+            .insert("hello".to_string(), "world".to_string())
+            .await;
+        tags.set_tags("hello", &["greeting".to_string()]);
+
+        let dir = std::env::temp_dir().join(format!("snapshot-test-{}", unix_time_ms()));
+        let store = SnapshotStore::new(dir.clone());
+        let snapshot = Snapshot::capture(&bcache, &tags).await.unwrap();
+        store.save(&snapshot, "backup.json").await.unwrap();
+
+        let loaded = store.load("backup.json").await.unwrap();
+        assert_eq!(
+            loaded.entries,
+            vec![SnapshotEntry {
+                key: "hello".to_string(),
+                value: "world".to_string(),
+                tags: vec!["greeting".to_string()],
+            }]
+        );
+
+        let mut altered = loaded.clone();
+        altered.entries[0].value = "moon".to_string();
+        assert!(altered.verify().is_err());
+
+        let restored: Mutex<Box<dyn BCache>> = Mutex::new(Box::new(MokaCache::new(16).await));
+        let restored_tags = TagIndex::default();
+        assert_eq!(loaded.load_into(&restored, &restored_tags).await, 1);
+        assert_eq!(
+            restored
+                .lock()
+                .await
+                .get("hello".to_string())
+                .await
+                .unwrap(),
+            "world"
+        );
+        assert_eq!(restored_tags.tags_of("hello"), vec!["greeting".to_string()]);
+        assert!(store
+            .load_if_exists("missing.json")
+            .await
+            .unwrap()
+            .is_none());
+
+        assert!(store.path("../backup.json").is_err());
+        assert!(store.path("").is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
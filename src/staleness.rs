@@ -0,0 +1,119 @@
+use crate::gossip::{Command, Message};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Tracks how up to date the data of this node is: when each key was last modified on it, and
+/// when it last applied a message from each other member.
+///
+/// Keys are modified by the writes made through the HTTP API and those applied from gossip.
+/// Keys evicted or expired by the cache, or removed by a tag invalidation, keep their time
+/// until they are written or removed again.
+///
+/// # Fields
+///
+/// - `modified`: The Unix time, in milliseconds, at which each key was last modified.
+/// - `heard`: The Unix time, in milliseconds, at which a message from each member was last applied.
+///
+/// # Example
+///
+/// ```rust
+/// let staleness = Staleness::default();
+/// staleness.record(&msg, unix_time_ms());
+/// staleness.heard_from("node2", unix_time_ms());
+/// let lag = staleness.lag_ms("node2", unix_time_ms());
+/// ```
+#[derive(Debug, Default)]
+pub struct Staleness {
+    modified: Mutex<HashMap<String, u64>>,
+    heard: Mutex<HashMap<String, u64>>,
+}
+
+impl Staleness {
+    /// Records the keys a mutation modified or removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `msg` - The mutation, applied on this node. Messages that are not mutations are ignored.
+    /// * `at_ms` - When it was applied, as a Unix time in milliseconds.
+    pub fn record(&self, msg: &Message, at_ms: u64) {
+        let ops = match msg.cmd {
+            Command::Txn => msg.txn_messages().unwrap_or_default(),
+            _ => vec![msg.clone()],
+        };
+        let mut modified = self.modified.lock().unwrap();
+        for op in ops {
+            match op.cmd {
+                Command::Insert | Command::InsertIfAbsent | Command::Patch | Command::Update => {
+                    modified.insert(op.key, at_ms);
+                }
+                Command::Remove | Command::Expire => {
+                    modified.remove(&op.key);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns when a key was last modified on this node, as a Unix time in milliseconds.
+    pub fn last_modified(&self, key: &str) -> Option<u64> {
+        self.modified.lock().unwrap().get(key).copied()
+    }
+
+    /// Records that a message from a member was applied.
+    pub fn heard_from(&self, member: &str, at_ms: u64) {
+        let mut heard = self.heard.lock().unwrap();
+        let last = heard.entry(member.to_string()).or_default();
+        *last = (*last).max(at_ms);
+    }
+
+    /// Returns how long ago a message from a member was last applied, in milliseconds: how far
+    /// behind it the data of this node may be.
+    ///
+    /// # Returns
+    ///
+    /// * The lag, or `None` if no message from the member was applied yet.
+    pub fn lag_ms(&self, member: &str, now_ms: u64) -> Option<u64> {
+        self.heard
+            .lock()
+            .unwrap()
+            .get(member)
+            .map(|last| now_ms.saturating_sub(*last))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit test for `Staleness`.
+    ///
+    /// Writes, including those of transactions, set the modification time of their keys and
+    /// removals clear it; the lag behind a member is the time since its last message.
+    #[test]
+    fn test_staleness() {
+        let staleness = Staleness::default();
+        let insert = |key: &str| Message::new(Command::Insert, key.to_string(), "1".to_string());
+        staleness.record(&insert("a"), 1000);
+        staleness.record(
+            &Message::txn(vec![
+                insert("b"),
+                Message::new(Command::Remove, "a".to_string(), String::new()),
+            ])
+            .unwrap(),
+            2000,
+        );
+        staleness.record(
+            &Message::new(Command::Ping, "c".to_string(), String::new()),
+            3000,
+        );
+        assert_eq!(staleness.last_modified("a"), None);
+        assert_eq!(staleness.last_modified("b"), Some(2000));
+        assert_eq!(staleness.last_modified("c"), None);
+
+        assert_eq!(staleness.lag_ms("node2", 5000), None);
+        staleness.heard_from("node2", 4000);
+        staleness.heard_from("node2", 3500);
+        assert_eq!(staleness.lag_ms("node2", 5000), Some(1000));
+        assert_eq!(staleness.lag_ms("node2", 3000), Some(0));
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+/// An index from tags to the keys carrying them, used for tag-based invalidation.
+///
+/// Every node maintains its own index from the tags attached to local and replicated
+/// writes, so invalidating a tag only needs to replicate the tag itself. Keys evicted or
+/// expired by the cache stay indexed until they are removed, overwritten or invalidated.
+///
+/// Tags can also be scheduled to expire at an absolute time; the schedule is replicated
+/// once and every node invalidates the tag when it is due.
+///
+/// # Example
+///
+/// ```rust
+/// let tags = TagIndex::default();
+/// tags.set_tags("product:1", &["sale".to_string()]);
+/// assert_eq!(tags.take_tag("sale"), vec!["product:1".to_string()]);
+/// ```
+#[derive(Debug, Default)]
+pub struct TagIndex {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    keys_by_tag: HashMap<String, HashSet<String>>,
+    tags_by_key: HashMap<String, HashSet<String>>,
+    /// Unix timestamps, in milliseconds, at which tags expire.
+    expirations: HashMap<String, u64>,
+}
+
+impl Inner {
+    fn remove_key(&mut self, key: &str) {
+        for tag in self.tags_by_key.remove(key).unwrap_or_default() {
+            if let Some(keys) = self.keys_by_tag.get_mut(&tag) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.keys_by_tag.remove(&tag);
+                }
+            }
+        }
+    }
+}
+
+impl TagIndex {
+    /// Replaces the tags of a key. An empty list removes the key from the index.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key that was written.
+    /// * `tags` - The tags attached to the write.
+    pub fn set_tags(&self, key: &str, tags: &[String]) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove_key(key);
+        if tags.is_empty() {
+            return;
+        }
+
+        for tag in tags {
+            inner
+                .keys_by_tag
+                .entry(tag.clone())
+                .or_default()
+                .insert(key.to_string());
+        }
+        inner
+            .tags_by_key
+            .insert(key.to_string(), tags.iter().cloned().collect());
+    }
+
+    /// Returns the tags of a key, sorted.
+    pub fn tags_of(&self, key: &str) -> Vec<String> {
+        let mut tags: Vec<String> = self
+            .inner
+            .lock()
+            .unwrap()
+            .tags_by_key
+            .get(key)
+            .map(|tags| tags.iter().cloned().collect())
+            .unwrap_or_default();
+        tags.sort();
+        tags
+    }
+
+    /// Removes a key, and its tags, from the index.
+    pub fn remove_key(&self, key: &str) {
+        self.inner.lock().unwrap().remove_key(key);
+    }
+
+    /// Removes a tag from the index, returning the keys that carried it.
+    ///
+    /// The returned keys are removed from the index as well, so the caller is expected to
+    /// remove them from the cache.
+    pub fn take_tag(&self, tag: &str) -> Vec<String> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut keys: Vec<String> = inner
+            .keys_by_tag
+            .get(tag)
+            .map(|keys| keys.iter().cloned().collect())
+            .unwrap_or_default();
+        keys.sort();
+
+        for key in &keys {
+            inner.remove_key(key);
+        }
+
+        keys
+    }
+
+    /// Schedules a tag to expire at the given time, replacing any earlier schedule.
+    ///
+    /// # Arguments
+    ///
+    /// * `tag` - The tag to expire.
+    /// * `expire_at_ms` - The Unix timestamp, in milliseconds, at which the tag expires.
+    pub fn expire_at(&self, tag: &str, expire_at_ms: u64) {
+        self.inner
+            .lock()
+            .unwrap()
+            .expirations
+            .insert(tag.to_string(), expire_at_ms);
+    }
+
+    /// Removes and returns the tags whose scheduled expiration is at or before `now_ms`.
+    pub fn take_expired(&self, now_ms: u64) -> Vec<String> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut expired: Vec<String> = inner
+            .expirations
+            .iter()
+            .filter(|(_, expire_at_ms)| **expire_at_ms <= now_ms)
+            .map(|(tag, _)| tag.clone())
+            .collect();
+        expired.sort();
+
+        for tag in &expired {
+            inner.expirations.remove(tag);
+        }
+
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit test for indexing, retagging and invalidating keys.
+    #[test]
+    fn test_tag_index() {
+        let tags = TagIndex::default();
+        tags.set_tags("a", &["sale".to_string(), "shoes".to_string()]);
+        tags.set_tags("b", &["sale".to_string()]);
+        tags.set_tags("c", &["shoes".to_string()]);
+
+        // Rewriting a key replaces its tags.
+        tags.set_tags("c", &["hats".to_string()]);
+        tags.remove_key("b");
+
+        assert_eq!(tags.take_tag("sale"), vec!["a".to_string()]);
+        // "a" was dropped entirely, including from "shoes".
+        assert!(tags.take_tag("shoes").is_empty());
+        assert_eq!(tags.take_tag("hats"), vec!["c".to_string()]);
+        assert!(tags.take_tag("hats").is_empty());
+    }
+
+    /// Unit test for scheduling tag expirations.
+    #[test]
+    fn test_tag_expiration() {
+        let tags = TagIndex::default();
+        tags.expire_at("sale", 1_000);
+        tags.expire_at("launch", 5_000);
+        tags.expire_at("launch", 2_000);
+
+        assert!(tags.take_expired(999).is_empty());
+        assert_eq!(tags.take_expired(1_500), vec!["sale".to_string()]);
+        assert_eq!(tags.take_expired(3_000), vec!["launch".to_string()]);
+        assert!(tags.take_expired(10_000).is_empty());
+    }
+}
@@ -0,0 +1,372 @@
+use crate::gossip::{Command, Message};
+use crate::ring::HashRing;
+use crate::transport::Member;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+/// The number of tokens a member takes on the hash ring unless it announces otherwise.
+pub const DEFAULT_VNODES: usize = 64;
+
+/// The maximum number of tokens a member may take on the hash ring.
+pub const MAX_VNODES: usize = 1024;
+
+/// What a node tells the other members about itself, JSON-encoded in the `value` of the `Ping`
+/// messages it sends, whose `key` is its name.
+///
+/// # Fields
+///
+/// - `http_addr`: The address clients reach the HTTP API of the node at.
+/// - `vnodes`: The number of tokens the node takes on the hash ring, so that larger machines
+///   can own proportionally more keys. Capped at `MAX_VNODES`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeInfo {
+    #[serde(default)]
+    pub http_addr: Option<String>,
+    #[serde(default = "default_vnodes")]
+    pub vnodes: usize,
+}
+
+fn default_vnodes() -> usize {
+    DEFAULT_VNODES
+}
+
+impl Default for NodeInfo {
+    fn default() -> Self {
+        Self {
+            http_addr: None,
+            vnodes: DEFAULT_VNODES,
+        }
+    }
+}
+
+/// A member of the cluster, as served at `/topology`.
+///
+/// # Fields
+///
+/// - `name`: The name of the member.
+/// - `gossip_addr`: The address the member replicates over.
+/// - `http_addr`: The address of the HTTP API of the member, once it announced it.
+/// - `vnodes`: The number of tokens the member takes on the hash ring, `DEFAULT_VNODES` until it
+///   announced it.
+/// - `tokens`: The tokens the member holds on the hash ring, sorted.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MemberTopology {
+    pub name: String,
+    pub gossip_addr: SocketAddr,
+    pub http_addr: Option<String>,
+    pub vnodes: usize,
+    pub tokens: Vec<u64>,
+}
+
+/// The hash ring of the cluster, as served at `/topology`, so clients can send each key to the
+/// member owning it.
+///
+/// # Fields
+///
+/// - `version`: Changes whenever the members, their addresses or their vnodes change, as seen
+///   by the node serving it. Clients refresh the topology when it differs from theirs.
+/// - `replication_factor`: The number of members holding each key. Every member replicates
+///   every key, so it is the number of members.
+/// - `members`: The members of the cluster, sorted by name.
+/// - `leader`: The coordinator of cluster-wide tasks as last elected by the node serving it,
+///   `None` until it held an election. Each side of a partition elects its own coordinator, so
+///   two nodes may report different ones until the partition heals.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TopologyView {
+    pub version: u64,
+    pub replication_factor: usize,
+    pub members: Vec<MemberTopology>,
+    pub leader: Option<String>,
+}
+
+/// The members of the cluster and what they announced about themselves.
+#[derive(Debug, Default)]
+struct State {
+    version: u64,
+    /// The members, sorted by name.
+    members: Vec<Member>,
+    infos: HashMap<String, NodeInfo>,
+    /// The hash ring of the members, rebuilt whenever the version changes.
+    ring: HashRing,
+    /// The coordinator of cluster-wide tasks, as last elected by the gossip node.
+    leader: Option<String>,
+}
+
+impl State {
+    /// Returns the number of tokens a member takes on the ring.
+    fn vnodes(&self, name: &str) -> usize {
+        self.infos
+            .get(name)
+            .map_or(DEFAULT_VNODES, |info| info.vnodes.min(MAX_VNODES))
+    }
+
+    /// Moves to the next version, rebuilding the ring.
+    fn changed(&mut self) {
+        self.ring = HashRing::new(
+            self.members
+                .iter()
+                .map(|member| (member.name.as_str(), self.vnodes(&member.name))),
+        );
+        self.version += 1;
+    }
+}
+
+/// Tracks the topology of the cluster as seen by this node: its members, from the membership
+/// of the gossip node, and what they announce about themselves in their pings.
+///
+/// The gossip task updates it, and the HTTP server serves it at `/topology`.
+///
+/// # Example
+///
+/// ```rust
+/// let topology = Topology::new("node1".to_string(), NodeInfo { http_addr: Some(addr) });
+/// gossip.send_msg_to_all(topology.announcement()).await;
+/// topology.set_members(gossip.members().await);
+/// ```
+#[derive(Debug)]
+pub struct Topology {
+    name: String,
+    info: NodeInfo,
+    state: Mutex<State>,
+}
+
+impl Topology {
+    /// Creates the topology of a node, which only knows about itself until it learns of the
+    /// members of the cluster.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of this node.
+    /// * `info` - What this node announces about itself.
+    pub fn new(name: String, info: NodeInfo) -> Self {
+        let mut state = State::default();
+        state.infos.insert(name.clone(), info.clone());
+        Self {
+            name,
+            info,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Returns the `Ping` message announcing this node to the other members.
+    pub fn announcement(&self) -> Message {
+        Message::new(
+            Command::Ping,
+            self.name.clone(),
+            serde_json::to_string(&self.info).unwrap_or_default(),
+        )
+    }
+
+    /// Records the current members of the cluster, this node included.
+    ///
+    /// # Returns
+    ///
+    /// * Whether the members changed, which changes the version of the topology.
+    pub fn set_members(&self, mut members: Vec<Member>) -> bool {
+        members.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut state = self.state.lock().unwrap();
+        if state.members == members {
+            return false;
+        }
+        state
+            .infos
+            .retain(|name, _| *name == self.name || members.iter().any(|m| &m.name == name));
+        state.members = members;
+        state.changed();
+        true
+    }
+
+    /// Records what a member announced about itself in a `Ping` message. Pings of nodes that
+    /// do not announce themselves are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the member, from the `key` of the ping.
+    /// * `info` - The JSON-encoded `NodeInfo` of the member, from the `value` of the ping.
+    ///
+    /// # Returns
+    ///
+    /// * Whether the announcement changed the topology.
+    pub fn announced(&self, name: &str, info: &str) -> bool {
+        let Ok(info) = serde_json::from_str::<NodeInfo>(info) else {
+            return false;
+        };
+        if name.is_empty() || name == self.name {
+            return false;
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.infos.get(name) == Some(&info) {
+            return false;
+        }
+        let known = state.members.iter().any(|member| member.name == name);
+        state.infos.insert(name.to_string(), info);
+        if known {
+            state.changed();
+        }
+        known
+    }
+
+    /// Records the coordinator of cluster-wide tasks elected by the gossip node. It follows from
+    /// the members, so it does not change the version of the topology.
+    ///
+    /// # Returns
+    ///
+    /// * Whether the coordinator changed.
+    pub fn set_leader(&self, leader: String) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.leader.as_ref() == Some(&leader) {
+            return false;
+        }
+        state.leader = Some(leader);
+        true
+    }
+
+    /// Returns the coordinator of cluster-wide tasks, or `None` until it was elected.
+    pub fn leader(&self) -> Option<String> {
+        self.state.lock().unwrap().leader.clone()
+    }
+
+    /// Returns the version of the topology.
+    pub fn version(&self) -> u64 {
+        self.state.lock().unwrap().version
+    }
+
+    /// Returns the name of this node.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the member owning a key on the hash ring, or `None` until the members are known.
+    pub fn owner(&self, key: &str) -> Option<String> {
+        self.state
+            .lock()
+            .unwrap()
+            .ring
+            .owner(key)
+            .map(str::to_string)
+    }
+
+    /// Returns the address of the HTTP API of a member, once it announced it.
+    pub fn http_addr(&self, name: &str) -> Option<String> {
+        self.state
+            .lock()
+            .unwrap()
+            .infos
+            .get(name)
+            .and_then(|info| info.http_addr.clone())
+    }
+
+    /// Returns the topology, with the tokens of every member on the hash ring.
+    pub fn view(&self) -> TopologyView {
+        let state = self.state.lock().unwrap();
+        TopologyView {
+            version: state.version,
+            replication_factor: state.members.len(),
+            members: state
+                .members
+                .iter()
+                .map(|member| MemberTopology {
+                    name: member.name.clone(),
+                    gossip_addr: member.addr,
+                    http_addr: state
+                        .infos
+                        .get(&member.name)
+                        .and_then(|info| info.http_addr.clone()),
+                    vnodes: state.vnodes(&member.name),
+                    tokens: state.ring.tokens_of(&member.name),
+                })
+                .collect(),
+            leader: state.leader.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(name: &str, port: u16) -> Member {
+        Member {
+            name: name.to_string(),
+            addr: SocketAddr::from(([127, 0, 0, 1], port)),
+        }
+    }
+
+    /// Unit test for `Topology`.
+    ///
+    /// The version changes when the members or what they announced change, and only then;
+    /// announcements of members that are not known yet are kept until they are, and each member
+    /// takes the number of tokens it announced.
+    #[test]
+    fn test_topology() {
+        let topology = Topology::new(
+            "node1".to_string(),
+            NodeInfo {
+                http_addr: Some("127.0.0.1:3001".to_string()),
+                vnodes: 8,
+            },
+        );
+        assert_eq!(topology.version(), 0);
+        assert_eq!(topology.owner("hello"), None);
+
+        let announcement = topology.announcement();
+        assert_eq!(announcement.cmd, Command::Ping);
+        let other = Topology::new("node2".to_string(), NodeInfo::default());
+        assert!(!other.announced(&announcement.key, &announcement.value));
+
+        assert!(topology.set_members(vec![member("node2", 9002), member("node1", 9001)]));
+        assert!(!topology.set_members(vec![member("node1", 9001), member("node2", 9002)]));
+        assert_eq!(topology.version(), 1);
+
+        assert!(!topology.announced("node2", ""));
+        assert!(topology.announced("node2", r#"{"http_addr":"127.0.0.1:3002"}"#));
+        assert!(!topology.announced("node2", r#"{"http_addr":"127.0.0.1:3002"}"#));
+        assert!(!topology.announced("node3", r#"{"http_addr":"127.0.0.1:3003"}"#));
+        assert_eq!(topology.version(), 2);
+
+        let view = topology.view();
+        assert_eq!(view.version, 2);
+        assert_eq!(view.replication_factor, 2);
+        assert_eq!(
+            view.members
+                .iter()
+                .map(|member| (member.name.as_str(), member.http_addr.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("node1", Some("127.0.0.1:3001")),
+                ("node2", Some("127.0.0.1:3002"))
+            ]
+        );
+        assert_eq!(view.members[0].tokens.len(), 8);
+        assert_eq!(view.members[1].vnodes, DEFAULT_VNODES);
+        assert_eq!(view.members[1].tokens.len(), DEFAULT_VNODES);
+        let owner = topology.owner("hello").unwrap();
+        assert_eq!(
+            topology.http_addr(&owner).unwrap(),
+            format!("127.0.0.1:300{}", &owner[4..])
+        );
+
+        assert!(topology.announced("node2", r#"{"http_addr":"127.0.0.1:3002","vnodes":16}"#));
+        assert_eq!(topology.version(), 3);
+        assert_eq!(topology.view().members[1].tokens.len(), 16);
+        assert!(!topology.announced("node3", r#"{"vnodes":1000000}"#));
+
+        assert!(topology.set_members(vec![
+            member("node1", 9001),
+            member("node2", 9002),
+            member("node3", 9003)
+        ]));
+        let view = topology.view();
+        assert_eq!(view.members[2].http_addr, None);
+        assert_eq!(view.members[2].tokens.len(), MAX_VNODES);
+        assert_eq!(view.leader, None);
+
+        let version = topology.version();
+        assert!(topology.set_leader("node1".to_string()));
+        assert!(!topology.set_leader("node1".to_string()));
+        assert_eq!(topology.version(), version);
+        assert_eq!(topology.view().leader.as_deref(), Some("node1"));
+    }
+}
@@ -0,0 +1,565 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::info;
+
+use crate::cluster_auth::ClusterAuth;
+use crate::gossip::{GossipNode, GossipodConfig, Membership};
+use crate::queue::{queue, QueueOptions, QueueReceiver, QueueSender};
+
+/// A member of the cluster, as seen by a transport.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    pub name: String,
+    pub addr: SocketAddr,
+}
+
+/// Carries the replication frames of a `GossipNode` between the members of the cluster, and
+/// tracks who the members are.
+///
+/// Frames received from other members, and changes of membership, are reported to the
+/// `TransportEvents` the transport was created with.
+///
+/// # Example
+///
+/// ```rust
+/// let (events, receiver) = TransportEvents::new(&config.receive_queue, config.auth.clone());
+/// let transport: Arc<dyn Transport> = Arc::new(network.attach("node-1", addr, events.clone()));
+/// let gossip = GossipNode::with_transport(config, transport, &events);
+/// tokio::spawn(sync_data(ctx, gossip, receiver, http_receiver));
+/// ```
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Returns the current members of the cluster, this node included.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the membership cannot be read.
+    async fn members(&self) -> Result<Vec<Member>>;
+
+    /// Sends a frame to the member at `target`. Frames may be lost, like datagrams.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the frame cannot be sent.
+    async fn send(&self, target: SocketAddr, frame: &[u8]) -> Result<()>;
+
+    /// Joins the cluster through the member at `addr`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the member cannot be reached.
+    async fn join(&self, addr: SocketAddr) -> Result<()>;
+}
+
+/// Where a transport reports received frames and membership changes to its `GossipNode`.
+///
+/// When the cluster authenticates its frames, received frames are checked against its secret,
+/// and members are only recorded once they authenticated.
+#[derive(Debug, Clone)]
+pub struct TransportEvents {
+    sender: QueueSender<(SocketAddr, Vec<u8>)>,
+    membership: Arc<Mutex<Membership>>,
+    auth: Arc<ClusterAuth>,
+}
+
+impl TransportEvents {
+    /// Creates the events of a node, whose received frames are buffered as configured by
+    /// `options` until the sync task reads them, and authenticated by `auth`.
+    ///
+    /// # Returns
+    ///
+    /// * The events, and the receiver of the frames received by the node, which is handed to `sync_data`.
+    pub fn new(
+        options: &QueueOptions,
+        auth: Arc<ClusterAuth>,
+    ) -> (Self, QueueReceiver<(SocketAddr, Vec<u8>)>) {
+        let (sender, receiver) = queue("gossip", options.clone());
+        let events = Self {
+            sender,
+            membership: Arc::new(Mutex::new(Membership::default())),
+            auth,
+        };
+        (events, receiver)
+    }
+
+    /// Reports that a member joined the cluster. A member that has not authenticated yet is
+    /// only recorded once it does.
+    pub fn joined(&self, name: &str, addr: SocketAddr) {
+        if !self.auth.joined(name, addr) {
+            info!("Node {} has joined, awaiting its authentication", name);
+            return;
+        }
+        info!("Node {} has joined the cluster", name);
+        self.membership.lock().unwrap().joined(name, addr);
+    }
+
+    /// Reports that a member was detected as dead.
+    pub fn died(&self, name: &str) {
+        if !self.auth.is_admitted(name) {
+            return;
+        }
+        info!("Node {} detected as dead", name);
+        self.membership.lock().unwrap().died(name, Instant::now());
+    }
+
+    /// Reports that a member left the cluster.
+    pub fn left(&self, name: &str) {
+        info!("Node {} is leaving the cluster", name);
+        self.auth.left(name);
+        self.membership.lock().unwrap().left(name);
+    }
+
+    /// Checks the authentication of a frame received from `from`, recording its sender as a
+    /// member the first time it authenticates.
+    ///
+    /// # Returns
+    ///
+    /// * The frame to hand to the node, or `None` if it was rejected or only greeted this node.
+    fn authenticate(&self, from: SocketAddr, frame: Vec<u8>) -> Option<Vec<u8>> {
+        let (frame, admitted) = self.auth.verify(from, frame)?;
+        if let Some(name) = admitted {
+            info!("Node {} has joined the cluster", name);
+            self.membership.lock().unwrap().joined(&name, from);
+        }
+        (!frame.is_empty()).then_some(frame)
+    }
+
+    /// Hands a frame received from `from` to the node, applying the overflow policy of its
+    /// buffer: waiting for room, dropping the oldest frame, or dropping this one. Frames that
+    /// fail authentication are dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the frame was dropped or the node stopped reading frames.
+    pub async fn received(&self, from: SocketAddr, frame: Vec<u8>) -> Result<()> {
+        let Some(frame) = self.authenticate(from, frame) else {
+            return Ok(());
+        };
+        self.sender
+            .offer((from, frame))
+            .await
+            .map_err(|e| anyhow!("Failed to deliver a frame from {}: {}", from, e))
+    }
+
+    /// Hands a frame received from `from` to the node without waiting for room in its buffer.
+    /// Frames that fail authentication are dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer is full or the node stopped reading frames.
+    pub fn try_received(&self, from: SocketAddr, frame: Vec<u8>) -> Result<()> {
+        let Some(frame) = self.authenticate(from, frame) else {
+            return Ok(());
+        };
+        self.sender
+            .try_send((from, frame))
+            .map_err(|e| anyhow!("Failed to deliver a frame from {}: {}", from, e))
+    }
+
+    /// Returns the membership the events are recorded in.
+    pub(crate) fn membership(&self) -> Arc<Mutex<Membership>> {
+        self.membership.clone()
+    }
+}
+
+/// A node attached to a `LoopbackNetwork`.
+struct Peer {
+    name: String,
+    events: TransportEvents,
+    /// Whether the node joined the cluster, or was joined through.
+    joined: bool,
+    /// Whether the node can send and receive frames.
+    connected: bool,
+}
+
+/// An in-memory network connecting the nodes of a cluster that run in a single process.
+///
+/// Frames are handed directly to their receivers, so tests can run any number of virtual nodes
+/// and assert that they converge, without sockets. Nodes can be disconnected and reconnected to
+/// simulate partitions: the other members see a disconnected node die, and frames to and from
+/// it are lost.
+///
+/// # Example
+///
+/// ```rust
+/// let network = LoopbackNetwork::default();
+/// let (gossip, receiver) = network
+///     .start_node(GossipodConfig::new("node-1".to_string(), "127.0.0.1:4001".to_string(), vec![]))
+///     .await?;
+/// network.disconnect("127.0.0.1:4001".parse()?);
+/// ```
+#[derive(Clone, Default)]
+pub struct LoopbackNetwork {
+    peers: Arc<Mutex<HashMap<SocketAddr, Peer>>>,
+}
+
+impl LoopbackNetwork {
+    /// Attaches a node to the network at `addr`.
+    ///
+    /// The node is not a member of the cluster until it joins it, or another node joins through it.
+    pub fn attach(
+        &self,
+        name: &str,
+        addr: SocketAddr,
+        events: TransportEvents,
+    ) -> LoopbackTransport {
+        self.peers.lock().unwrap().insert(
+            addr,
+            Peer {
+                name: name.to_string(),
+                events,
+                joined: false,
+                connected: true,
+            },
+        );
+        LoopbackTransport {
+            network: self.clone(),
+            addr,
+        }
+    }
+
+    /// Starts a `GossipNode` attached to the network at the address of `config`, which joins
+    /// the cluster through the seeds of `config`.
+    ///
+    /// # Returns
+    ///
+    /// * The node, and the receiver of the frames it receives, which is handed to `sync_data`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address of `config` is invalid.
+    pub async fn start_node(
+        &self,
+        config: GossipodConfig,
+    ) -> Result<(GossipNode, QueueReceiver<(SocketAddr, Vec<u8>)>)> {
+        let addr: SocketAddr = format!("{}:{}", config.ip, config.port).parse()?;
+        let (events, receiver) = TransportEvents::new(&config.receive_queue, config.auth.clone());
+        let transport = Arc::new(self.attach(&config.name, addr, events.clone()));
+        Ok((
+            GossipNode::with_transport(config, transport, &events),
+            receiver,
+        ))
+    }
+
+    /// Cuts the node at `addr` off the network. The other members see it die, and it sees
+    /// them die.
+    pub fn disconnect(&self, addr: SocketAddr) {
+        let mut peers = self.peers.lock().unwrap();
+        let Some(peer) = peers.get_mut(&addr) else {
+            return;
+        };
+        peer.connected = false;
+        let name = peer.name.clone();
+        for (other, peer) in peers.iter() {
+            if *other != addr && peer.joined && peer.connected {
+                peer.events.died(&name);
+                peers[&addr].events.died(&peer.name);
+            }
+        }
+    }
+
+    /// Connects the node at `addr` back to the network. If it was a member, the other members
+    /// see it join again, and it sees them join.
+    pub fn reconnect(&self, addr: SocketAddr) {
+        let mut peers = self.peers.lock().unwrap();
+        let Some(peer) = peers.get_mut(&addr) else {
+            return;
+        };
+        peer.connected = true;
+        if peer.joined {
+            announce(&peers, addr);
+        }
+    }
+}
+
+/// Tells the connected members and the node at `addr` about each other.
+fn announce(peers: &HashMap<SocketAddr, Peer>, addr: SocketAddr) {
+    let peer = &peers[&addr];
+    for (other_addr, other) in peers.iter() {
+        if *other_addr != addr && other.joined && other.connected {
+            other.events.joined(&peer.name, addr);
+            peer.events.joined(&other.name, *other_addr);
+        }
+    }
+}
+
+/// The `Transport` of a node attached to a `LoopbackNetwork`.
+pub struct LoopbackTransport {
+    network: LoopbackNetwork,
+    addr: SocketAddr,
+}
+
+#[async_trait]
+impl Transport for LoopbackTransport {
+    async fn members(&self) -> Result<Vec<Member>> {
+        let peers = self.network.peers.lock().unwrap();
+        let me = &peers[&self.addr];
+        Ok(peers
+            .iter()
+            .filter(|(addr, peer)| {
+                **addr == self.addr || (me.joined && me.connected && peer.joined && peer.connected)
+            })
+            .map(|(addr, peer)| Member {
+                name: peer.name.clone(),
+                addr: *addr,
+            })
+            .collect())
+    }
+
+    async fn send(&self, target: SocketAddr, frame: &[u8]) -> Result<()> {
+        let peers = self.network.peers.lock().unwrap();
+        match peers.get(&target) {
+            Some(peer) if peer.connected && peers[&self.addr].connected => {
+                peer.events.try_received(self.addr, frame.to_vec())
+            }
+            _ => Err(anyhow!("{} is unreachable from {}", target, self.addr)),
+        }
+    }
+
+    async fn join(&self, addr: SocketAddr) -> Result<()> {
+        let mut peers = self.network.peers.lock().unwrap();
+        if !peers[&self.addr].connected || !peers.get(&addr).is_some_and(|peer| peer.connected) {
+            return Err(anyhow!("{} is unreachable from {}", addr, self.addr));
+        }
+        for joining in [addr, self.addr] {
+            let newly_joined = peers
+                .get_mut(&joining)
+                .is_some_and(|peer| !std::mem::replace(&mut peer.joined, true));
+            if newly_joined {
+                announce(&peers, joining);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anomaly::AnomalyDetector;
+    use crate::audit::AuditLog;
+    use crate::cache_trait::{sync_data, BCache, SyncContext};
+    use crate::events::KeyEvents;
+    use crate::gossip::{Command, Message, Replication};
+    use crate::limits::Limits;
+    use crate::moka_cache::MokaCache;
+    use crate::session::Sessions;
+    use crate::slowlog::SlowLog;
+    use crate::staleness::Staleness;
+    use crate::tags::TagIndex;
+    use crate::topology::{NodeInfo, Topology};
+    use crate::wal::Wal;
+    use std::time::Duration;
+    use tokio::sync::Mutex as AsyncMutex;
+
+    /// A virtual node: its cache, the sender of the writes it replicates, and its topology.
+    struct TestNode {
+        bcache: Arc<AsyncMutex<Box<dyn BCache>>>,
+        writes: QueueSender<(Message, Replication)>,
+        topology: Arc<Topology>,
+    }
+
+    impl TestNode {
+        /// Applies an insert or a remove locally, then replicates it, as the HTTP server does.
+        async fn write(&self, msg: Message) {
+            let mut bcache = self.bcache.lock().await;
+            match msg.cmd {
+                Command::Insert => bcache.insert(msg.key.clone(), msg.value.clone()).await,
+                _ => bcache.remove(msg.key.clone()).await,
+            }
+            self.writes.send((msg, Replication::All)).await.unwrap();
+        }
+    }
+
+    async fn start_node(network: &LoopbackNetwork, i: u16) -> TestNode {
+        let seeds = (i > 1)
+            .then(|| "127.0.0.1:4001".to_string())
+            .into_iter()
+            .collect();
+        let config = GossipodConfig::new(
+            format!("node-{}", i),
+            format!("127.0.0.1:{}", 4000 + i),
+            seeds,
+        );
+        let (gossip, receiver) = network.start_node(config).await.unwrap();
+        let bcache: Arc<AsyncMutex<Box<dyn BCache>>> =
+            Arc::new(AsyncMutex::new(Box::new(MokaCache::new(16).await)));
+        let (writes, http_receiver) = queue("replication", QueueOptions::new(16));
+        let topology = Arc::new(Topology::new(format!("node-{}", i), NodeInfo::default()));
+        let ctx = SyncContext {
+            bcache: bcache.clone(),
+            tags: Arc::new(TagIndex::default()),
+            slowlog: Arc::new(SlowLog::new(
+                Duration::from_secs(1),
+                Duration::from_secs(1),
+                8,
+            )),
+            audit: Arc::new(AuditLog::disabled()),
+            anomalies: Arc::new(AnomalyDetector::new(Default::default())),
+            wal: Arc::new(Wal::disabled()),
+            limits: Limits::default(),
+            events: KeyEvents::default(),
+            gossip_expirations: false,
+            topology: topology.clone(),
+            sessions: Arc::new(Sessions::new(format!("node-{}", i))),
+            staleness: Arc::new(Staleness::default()),
+        };
+        tokio::spawn(sync_data(ctx, gossip, receiver, http_receiver));
+        TestNode {
+            bcache,
+            writes,
+            topology,
+        }
+    }
+
+    /// Waits until every node holds `value` for `key`, or `None` for no value, on the paused clock.
+    async fn converge(nodes: &[TestNode], key: &str, value: Option<&str>) {
+        for _ in 0..1000 {
+            let mut converged = true;
+            for node in nodes {
+                let current = node.bcache.lock().await.get(key.to_string()).await.ok();
+                converged &= current.as_deref() == value;
+            }
+            if converged {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("The nodes did not converge on {} = {:?}", key, value);
+    }
+
+    /// Waits until each node elected the coordinator at the same index of `leaders`, on the
+    /// paused clock.
+    async fn elected(nodes: &[TestNode], leaders: &[&str]) {
+        for _ in 0..1000 {
+            let current: Vec<_> = nodes.iter().map(|node| node.topology.leader()).collect();
+            if current
+                .iter()
+                .zip(leaders)
+                .all(|(current, leader)| current.as_deref() == Some(*leader))
+            {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("The nodes did not elect {:?}", leaders);
+    }
+
+    /// Waits until every node attached to the network joined the cluster.
+    async fn joined(network: &LoopbackNetwork) {
+        while !network
+            .peers
+            .lock()
+            .unwrap()
+            .values()
+            .all(|peer| peer.joined)
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Unit test for replicating writes between virtual nodes on a `LoopbackNetwork`.
+    ///
+    /// A write reaches every node, and a write made while a node is disconnected reaches it
+    /// once it is reconnected, from the hints kept for it.
+    #[tokio::test(start_paused = true)]
+    async fn test_loopback_network() {
+        let network = LoopbackNetwork::default();
+        let mut nodes = Vec::new();
+        for i in 1..=3 {
+            nodes.push(start_node(&network, i).await);
+        }
+        joined(&network).await;
+
+        nodes[0]
+            .write(Message::new(
+                Command::Insert,
+                "key".to_string(),
+                "value".to_string(),
+            ))
+            .await;
+        converge(&nodes, "key", Some("value")).await;
+
+        let node_3: SocketAddr = "127.0.0.1:4003".parse().unwrap();
+        network.disconnect(node_3);
+        nodes[0]
+            .write(Message::new(
+                Command::Remove,
+                "key".to_string(),
+                String::new(),
+            ))
+            .await;
+        converge(&nodes[..2], "key", None).await;
+        converge(&nodes[2..], "key", Some("value")).await;
+
+        network.reconnect(node_3);
+        converge(&nodes, "key", None).await;
+    }
+
+    /// Unit test for disseminating writes as rumors through a cluster larger than the fanout.
+    ///
+    /// A write reaches the nodes that are only relayed it, and a write made while one of the
+    /// relaying nodes is disconnected reaches the rest of its share and, once it is
+    /// reconnected, the node itself.
+    #[tokio::test(start_paused = true)]
+    async fn test_rumor_relay() {
+        let network = LoopbackNetwork::default();
+        let mut nodes = Vec::new();
+        for i in 1..=7 {
+            nodes.push(start_node(&network, i).await);
+        }
+        joined(&network).await;
+
+        for (i, node) in nodes.iter().enumerate() {
+            node.write(Message::new(
+                Command::Insert,
+                "key".to_string(),
+                format!("value-{}", i),
+            ))
+            .await;
+            converge(&nodes, "key", Some(&format!("value-{}", i))).await;
+        }
+
+        let node_2: SocketAddr = "127.0.0.1:4002".parse().unwrap();
+        network.disconnect(node_2);
+        nodes[0]
+            .write(Message::new(
+                Command::Remove,
+                "key".to_string(),
+                String::new(),
+            ))
+            .await;
+        converge(&nodes[2..], "key", None).await;
+
+        network.reconnect(node_2);
+        converge(&nodes, "key", None).await;
+    }
+
+    /// Unit test for failing over the coordinator of cluster-wide tasks.
+    ///
+    /// Every node elects the member with the lowest name. When it is cut off, the other nodes
+    /// elect the next lowest one while it keeps electing itself, as there is no quorum, and
+    /// they agree again once it is reconnected.
+    #[tokio::test(start_paused = true)]
+    async fn test_leader_failover() {
+        let network = LoopbackNetwork::default();
+        let mut nodes = Vec::new();
+        for i in 1..=3 {
+            nodes.push(start_node(&network, i).await);
+        }
+        joined(&network).await;
+        elected(&nodes, &["node-1", "node-1", "node-1"]).await;
+
+        let node_1: SocketAddr = "127.0.0.1:4001".parse().unwrap();
+        network.disconnect(node_1);
+        elected(&nodes, &["node-1", "node-2", "node-2"]).await;
+        assert_eq!(nodes[1].topology.view().leader.as_deref(), Some("node-2"));
+
+        network.reconnect(node_1);
+        elected(&nodes, &["node-1", "node-1", "node-1"]).await;
+    }
+}
@@ -0,0 +1,213 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// A value with a type, which type-aware operations can update in place.
+///
+/// Strings are stored in the cache as they are, so values written with `/add` are strings. The
+/// other types are stored as their JSON encoding, such as `{"type":"integer","value":42}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum TypedValue {
+    String(String),
+    Integer(i64),
+    List(Vec<String>),
+    Set(BTreeSet<String>),
+}
+
+impl TypedValue {
+    /// Decodes a value stored in the cache. Values that are not the encoding of a typed value
+    /// are strings.
+    pub fn decode(value: &str) -> Self {
+        serde_json::from_str(value).unwrap_or_else(|_| TypedValue::String(value.to_string()))
+    }
+
+    /// Encodes the value to be stored in the cache.
+    pub fn encode(&self) -> String {
+        match self {
+            TypedValue::String(value) => value.clone(),
+            typed => serde_json::to_string(typed).expect("typed values are serializable"),
+        }
+    }
+
+    /// Returns the name of the type of the value.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            TypedValue::String(_) => "string",
+            TypedValue::Integer(_) => "integer",
+            TypedValue::List(_) => "list",
+            TypedValue::Set(_) => "set",
+        }
+    }
+}
+
+/// A type-aware operation on a value, replicated in place of the updated value so concurrent
+/// updates of the same key on different nodes do not overwrite each other.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ValueOp {
+    /// Appends values to a list, or prepends them like `LPUSH` when `front` is set, in which
+    /// case the last value ends up first.
+    Push {
+        values: Vec<String>,
+        #[serde(default)]
+        front: bool,
+    },
+    /// Adds members to a set.
+    SetAdd { members: Vec<String> },
+    /// Removes members from a set.
+    SetRemove { members: Vec<String> },
+    /// Adds `delta` to an integer. Strings holding an integer are updated as integers.
+    Add { delta: i64 },
+    /// Appends `suffix` to a string.
+    Append { suffix: String },
+}
+
+impl ValueOp {
+    /// Applies the operation to a value.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The current value of the key, or `None` if it is absent, in which case the
+    ///   operation is applied to an empty list, set or string, or to zero.
+    ///
+    /// # Returns
+    ///
+    /// * The updated value, encoded to be stored in the cache.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value has a type the operation does not apply to, or an
+    /// addition overflows.
+    pub fn apply(&self, value: Option<&str>) -> Result<String> {
+        let value = value.map(TypedValue::decode);
+        let updated = match (self, value) {
+            (ValueOp::Push { values, front }, value) => {
+                let mut list = match value {
+                    Some(TypedValue::List(list)) => list,
+                    None => Vec::new(),
+                    Some(other) => return Err(wrong_type(self, &other)),
+                };
+                if *front {
+                    for value in values {
+                        list.insert(0, value.clone());
+                    }
+                } else {
+                    list.extend(values.iter().cloned());
+                }
+                TypedValue::List(list)
+            }
+            (ValueOp::SetAdd { members } | ValueOp::SetRemove { members }, value) => {
+                let mut set = match value {
+                    Some(TypedValue::Set(set)) => set,
+                    None => BTreeSet::new(),
+                    Some(other) => return Err(wrong_type(self, &other)),
+                };
+                for member in members {
+                    if matches!(self, ValueOp::SetAdd { .. }) {
+                        set.insert(member.clone());
+                    } else {
+                        set.remove(member);
+                    }
+                }
+                TypedValue::Set(set)
+            }
+            (ValueOp::Add { delta }, value) => {
+                let integer = match value {
+                    Some(TypedValue::Integer(integer)) => integer,
+                    Some(TypedValue::String(string)) => string
+                        .parse()
+                        .map_err(|_| anyhow!("The value '{}' is not an integer", string))?,
+                    None => 0,
+                    Some(other) => return Err(wrong_type(self, &other)),
+                };
+                TypedValue::Integer(
+                    integer
+                        .checked_add(*delta)
+                        .ok_or_else(|| anyhow!("Adding {} to {} overflows", delta, integer))?,
+                )
+            }
+            (ValueOp::Append { suffix }, value) => match value {
+                Some(TypedValue::String(string)) => TypedValue::String(string + suffix),
+                None => TypedValue::String(suffix.clone()),
+                Some(other) => return Err(wrong_type(self, &other)),
+            },
+        };
+        Ok(updated.encode())
+    }
+}
+
+fn wrong_type(op: &ValueOp, value: &TypedValue) -> anyhow::Error {
+    anyhow!(
+        "{:?} does not apply to a value of type {}",
+        op,
+        value.type_name()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit test for applying `ValueOp`s to absent, typed and string values.
+    #[test]
+    fn test_apply_value_op() {
+        let push = |values: &[&str], front| ValueOp::Push {
+            values: values.iter().map(|v| v.to_string()).collect(),
+            front,
+        };
+        let list = push(&["a", "b"], false).apply(None).unwrap();
+        assert_eq!(list, r#"{"type":"list","value":["a","b"]}"#);
+        let list = push(&["c", "d"], true).apply(Some(&list)).unwrap();
+        assert_eq!(
+            TypedValue::decode(&list),
+            TypedValue::List(["d", "c", "a", "b"].map(str::to_string).to_vec())
+        );
+
+        let set = ValueOp::SetAdd {
+            members: vec!["x".to_string(), "y".to_string(), "x".to_string()],
+        }
+        .apply(None)
+        .unwrap();
+        let set = ValueOp::SetRemove {
+            members: vec!["y".to_string()],
+        }
+        .apply(Some(&set))
+        .unwrap();
+        assert_eq!(
+            TypedValue::decode(&set),
+            TypedValue::Set(BTreeSet::from(["x".to_string()]))
+        );
+
+        let add = |delta| ValueOp::Add { delta };
+        assert_eq!(
+            TypedValue::decode(&add(5).apply(Some("37")).unwrap()),
+            TypedValue::Integer(42)
+        );
+        assert_eq!(
+            TypedValue::decode(&add(-1).apply(None).unwrap()),
+            TypedValue::Integer(-1)
+        );
+        assert!(add(1).apply(Some("hello")).is_err());
+        assert!(add(1)
+            .apply(Some(&add(i64::MAX).apply(None).unwrap()))
+            .is_err());
+        assert!(add(1).apply(Some(&list)).is_err());
+        assert!(push(&["a"], false).apply(Some("hello")).is_err());
+        assert!(ValueOp::SetAdd { members: vec![] }
+            .apply(Some(&list))
+            .is_err());
+
+        let append = |suffix: &str| ValueOp::Append {
+            suffix: suffix.to_string(),
+        };
+        let log = append("login\n").apply(None).unwrap();
+        assert_eq!(
+            append("logout\n").apply(Some(&log)).unwrap(),
+            "login\nlogout\n"
+        );
+        assert!(append("x").apply(Some(&list)).is_err());
+
+        assert_eq!(TypedValue::decode("hello").encode(), "hello");
+    }
+}
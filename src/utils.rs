@@ -1,5 +1,6 @@
 use anyhow::{anyhow, Result};
 use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Parses an optional string into a `SocketAddr`.
 ///
@@ -53,3 +54,128 @@ pub fn parse_address(addr: Option<String>) -> Result<SocketAddr> {
         None => Err(anyhow!("No address provided")),
     }
 }
+
+/// Computes the HTTP entity tag of a value.
+///
+/// The tag is a strong validator derived from a 64-bit FNV-1a hash of the value. The hash is
+/// stable across processes and builds, so every node returns the same tag for the same value.
+///
+/// # Arguments
+///
+/// * `value` - The value to compute the tag for.
+///
+/// # Returns
+///
+/// * The quoted entity tag, e.g. `"af63bd4c8601b7df"`.
+pub fn etag(value: &str) -> String {
+    format!("\"{:016x}\"", fnv1a(value.as_bytes()))
+}
+
+/// Computes the 64-bit FNV-1a hash of some bytes.
+///
+/// The hash is stable across processes and builds. It detects accidental changes, not
+/// tampering.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Checks whether an `If-Match` / `If-None-Match` header value matches an entity tag.
+///
+/// The header may contain `*` or a comma-separated list of tags. Weak tags (`W/"..."`)
+/// are compared by their opaque part.
+///
+/// # Arguments
+///
+/// * `header` - The raw header value.
+/// * `etag` - The entity tag of the current value, as returned by `etag`.
+///
+/// # Returns
+///
+/// * `true` if the header is `*` or lists the given tag.
+pub fn etag_matches(header: &str, etag: &str) -> bool {
+    header.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag
+    })
+}
+
+/// Parses a duration such as `500ms`, `2s` or `1m`. A bare number is a number of milliseconds.
+///
+/// # Errors
+///
+/// Returns an error if the number or the unit is not valid.
+///
+/// # Example
+///
+/// ```rust
+/// assert_eq!(parse_duration("500ms")?, Duration::from_millis(500));
+/// ```
+pub fn parse_duration(duration: &str) -> Result<Duration> {
+    let duration = duration.trim();
+    let split = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration.len());
+    let (number, unit) = duration.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| anyhow!("Invalid duration {:?}", duration))?;
+    match unit.trim() {
+        "" | "ms" => Ok(Duration::from_millis(number)),
+        "s" => Ok(Duration::from_secs(number)),
+        "m" => Ok(Duration::from_secs(number.saturating_mul(60))),
+        unit => Err(anyhow!(
+            "Invalid unit {:?} in duration {:?}, expected ms, s or m",
+            unit,
+            duration
+        )),
+    }
+}
+
+/// Returns the current Unix time in milliseconds.
+pub fn unix_time_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit test for `etag`.
+    ///
+    /// Equal values get equal tags and different values get different ones.
+    #[test]
+    fn test_etag() {
+        assert_eq!(etag("world"), etag("world"));
+        assert_ne!(etag("world"), etag("world!"));
+        assert!(etag("").starts_with('"') && etag("").ends_with('"'));
+    }
+
+    /// Unit test for `etag_matches`.
+    #[test]
+    fn test_etag_matches() {
+        let tag = etag("world");
+        assert!(etag_matches(&tag, &tag));
+        assert!(etag_matches("*", &tag));
+        assert!(etag_matches(&format!("\"other\", W/{}", tag), &tag));
+        assert!(!etag_matches("\"other\"", &tag));
+    }
+
+    /// Unit test for `parse_duration`.
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("250").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse_duration("2s").unwrap(), Duration::from_secs(2));
+        assert_eq!(parse_duration("1m").unwrap(), Duration::from_secs(60));
+        assert!(parse_duration("ms").is_err());
+        assert!(parse_duration("1h").is_err());
+        assert!(parse_duration("-1s").is_err());
+    }
+}
@@ -0,0 +1,453 @@
+use crate::cache_trait::BCache;
+use crate::gossip::{Command, Message};
+use crate::tags::TagIndex;
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+/// The file extension of write-ahead log segments.
+const SEGMENT_EXTENSION: &str = "wal";
+
+/// When appended records are flushed to stable storage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum FsyncPolicy {
+    /// Every record is synced before the mutation is acknowledged.
+    #[default]
+    Always,
+    /// Records are synced once per housekeeping tick, so a crash loses at most about a second of writes.
+    Interval,
+    /// Syncing is left to the operating system.
+    Never,
+}
+
+/// Configures the write-ahead log.
+///
+/// # Fields
+///
+/// - `dir`: The directory the log segments are written to.
+/// - `segment_bytes`: The size after which a new segment is started. Only whole segments are
+///   truncated, so smaller segments free disk space sooner after a snapshot.
+/// - `fsync`: When appended records are flushed to stable storage.
+#[derive(Debug, Clone)]
+pub struct WalOptions {
+    pub dir: PathBuf,
+    pub segment_bytes: u64,
+    pub fsync: FsyncPolicy,
+}
+
+impl WalOptions {
+    /// Creates `WalOptions` writing to `dir`, with 64 MiB segments synced on every record.
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            segment_bytes: 64 * 1024 * 1024,
+            fsync: FsyncPolicy::default(),
+        }
+    }
+}
+
+/// A single line of a log segment.
+#[derive(Debug, Serialize, Deserialize)]
+struct WalRecord {
+    lsn: u64,
+    message: Message,
+}
+
+/// The segment records are currently appended to.
+#[derive(Debug)]
+struct Segment {
+    file: File,
+    len: u64,
+    /// Whether records were appended since the segment was last synced.
+    dirty: bool,
+}
+
+/// The append side of the log, guarded by a single lock so records reach disk in order.
+#[derive(Debug)]
+struct WalState {
+    segment: Segment,
+    /// The log sequence number of the last appended record, or `0` for an empty log.
+    last_lsn: u64,
+}
+
+/// An optional segmented write-ahead log of every mutation accepted by this node.
+///
+/// Each mutation, whether it came from an HTTP client or a gossip peer, is appended as a JSON
+/// line holding its log sequence number (LSN) and the replicated `Message`. On startup the
+/// records written after the latest snapshot are replayed, and segments whose records are all
+/// covered by a snapshot are deleted. A disabled log ignores all records.
+///
+/// # Example
+///
+/// ```rust
+/// let wal = Wal::open(WalOptions::new("wal".into())).await?;
+/// let lsn = wal.append(&Message::new(Command::Insert, key, value)).await?;
+/// ```
+#[derive(Debug)]
+pub struct Wal {
+    state: Option<Mutex<WalState>>,
+    options: WalOptions,
+}
+
+impl Wal {
+    /// Opens the write-ahead log in `options.dir`, creating the directory if needed.
+    ///
+    /// Appends continue after the last record found on disk, in a new segment.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory or the segments cannot be read, or the new segment
+    /// cannot be created.
+    pub async fn open(options: WalOptions) -> Result<Self> {
+        tokio::fs::create_dir_all(&options.dir)
+            .await
+            .with_context(|| format!("Failed to create {}", options.dir.display()))?;
+        // Every record may have been truncated, leaving only an empty segment named after
+        // the next log sequence number.
+        let next_segment_lsn = list_segments(&options)
+            .await?
+            .last()
+            .map_or(0, |(first_lsn, _)| first_lsn.saturating_sub(1));
+        let last_lsn = read_records(&options, 0)
+            .await?
+            .last()
+            .map_or(next_segment_lsn, |record| record.lsn.max(next_segment_lsn));
+        let segment = open_segment(&options, last_lsn + 1).await?;
+
+        Ok(Self {
+            state: Some(Mutex::new(WalState { segment, last_lsn })),
+            options,
+        })
+    }
+
+    /// Creates a disabled write-ahead log that records nothing.
+    pub fn disabled() -> Self {
+        Self {
+            state: None,
+            options: WalOptions::new(PathBuf::new()),
+        }
+    }
+
+    /// Appends a mutation to the log, syncing it according to the `FsyncPolicy`.
+    ///
+    /// # Returns
+    ///
+    /// * The log sequence number of the record, or `0` if the log is disabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the record cannot be written or synced. The mutation must then not
+    /// be acknowledged, as it would not survive a crash.
+    pub async fn append(&self, message: &Message) -> Result<u64> {
+        let Some(state) = &self.state else {
+            return Ok(0);
+        };
+        let mut state = state.lock().await;
+
+        let lsn = state.last_lsn + 1;
+        let mut line = serde_json::to_vec(&WalRecord {
+            lsn,
+            message: message.clone(),
+        })?;
+        line.push(b'\n');
+        if state.segment.len > 0
+            && state.segment.len + line.len() as u64 > self.options.segment_bytes
+        {
+            self.rotate(&mut state, lsn).await?;
+        }
+
+        let segment = &mut state.segment;
+        segment.file.write_all(&line).await?;
+        segment.file.flush().await?;
+        segment.len += line.len() as u64;
+        segment.dirty = true;
+        if self.options.fsync == FsyncPolicy::Always {
+            segment.file.sync_data().await?;
+            segment.dirty = false;
+        }
+        state.last_lsn = lsn;
+        Ok(lsn)
+    }
+
+    /// Syncs the records appended since the last sync. Called periodically under `FsyncPolicy::Interval`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the segment cannot be synced.
+    pub async fn sync(&self) -> Result<()> {
+        let Some(state) = &self.state else {
+            return Ok(());
+        };
+        let mut state = state.lock().await;
+        if state.segment.dirty {
+            state.segment.file.sync_data().await?;
+            state.segment.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Returns whether records must be synced periodically, as `FsyncPolicy::Interval` requires.
+    pub fn needs_periodic_sync(&self) -> bool {
+        self.state.is_some() && self.options.fsync == FsyncPolicy::Interval
+    }
+
+    /// Returns the log sequence number of the last appended record, or `0` for an empty or disabled log.
+    pub async fn last_lsn(&self) -> u64 {
+        match &self.state {
+            Some(state) => state.lock().await.last_lsn,
+            None => 0,
+        }
+    }
+
+    /// Returns the messages of the records appended after `after_lsn`, in order.
+    ///
+    /// A torn last line, left by a crash in the middle of an append, is skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a segment cannot be read.
+    pub async fn replay(&self, after_lsn: u64) -> Result<Vec<Message>> {
+        if self.state.is_none() {
+            return Ok(Vec::new());
+        }
+        Ok(read_records(&self.options, after_lsn)
+            .await?
+            .into_iter()
+            .map(|record| record.message)
+            .collect())
+    }
+
+    /// Deletes the segments whose records all have a log sequence number of at most `lsn`,
+    /// once a snapshot covering them has been written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be listed or a segment cannot be deleted.
+    pub async fn truncate(&self, lsn: u64) -> Result<()> {
+        let Some(state) = &self.state else {
+            return Ok(());
+        };
+        let mut state = state.lock().await;
+        if state.segment.len > 0 && state.last_lsn <= lsn {
+            let next_lsn = state.last_lsn + 1;
+            self.rotate(&mut state, next_lsn).await?;
+        }
+
+        let segments = list_segments(&self.options).await?;
+        let mut removed = 0;
+        for pair in segments.windows(2) {
+            let ((_, path), (next_first_lsn, _)) = (&pair[0], &pair[1]);
+            if *next_first_lsn > lsn + 1 {
+                break;
+            }
+            tokio::fs::remove_file(path)
+                .await
+                .with_context(|| format!("Failed to delete {}", path.display()))?;
+            removed += 1;
+        }
+        if removed > 0 {
+            info!(
+                "Deleted {} write-ahead log segments up to LSN {}",
+                removed, lsn
+            );
+        }
+        Ok(())
+    }
+
+    /// Syncs the current segment and starts a new one whose first record is `first_lsn`.
+    async fn rotate(&self, state: &mut WalState, first_lsn: u64) -> Result<()> {
+        state.segment.file.sync_data().await?;
+        state.segment = open_segment(&self.options, first_lsn).await?;
+        Ok(())
+    }
+}
+
+/// Opens (or creates) the segment whose first record is `first_lsn`.
+async fn open_segment(options: &WalOptions, first_lsn: u64) -> Result<Segment> {
+    let path = options
+        .dir
+        .join(format!("{:020}.{}", first_lsn, SEGMENT_EXTENSION));
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let len = file.metadata().await?.len();
+
+    Ok(Segment {
+        file,
+        len,
+        dirty: false,
+    })
+}
+
+/// Lists the segments of the log with the log sequence number of their first record, in order.
+async fn list_segments(options: &WalOptions) -> Result<Vec<(u64, PathBuf)>> {
+    let mut segments = Vec::new();
+    let mut dir = tokio::fs::read_dir(&options.dir)
+        .await
+        .with_context(|| format!("Failed to list {}", options.dir.display()))?;
+    while let Some(entry) = dir.next_entry().await? {
+        let path = entry.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(SEGMENT_EXTENSION) {
+            continue;
+        }
+        if let Some(first_lsn) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse().ok())
+        {
+            segments.push((first_lsn, path));
+        }
+    }
+    segments.sort();
+    Ok(segments)
+}
+
+/// Reads the records of every segment with a log sequence number above `after_lsn`, in order.
+async fn read_records(options: &WalOptions, after_lsn: u64) -> Result<Vec<WalRecord>> {
+    let mut records = Vec::new();
+    for (_, path) in list_segments(options).await? {
+        let contents = tokio::fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        for line in contents.lines() {
+            match serde_json::from_str::<WalRecord>(line) {
+                Ok(record) if record.lsn > after_lsn => records.push(record),
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("Skipping torn record in {}: {:?}", path.display(), e);
+                    break;
+                }
+            }
+        }
+    }
+    Ok(records)
+}
+
+/// Applies a replayed mutation to the cache of this node only. The messages of a transaction
+/// are applied under a single lock of the cache, as are patches and updates with the read of
+/// their value.
+///
+/// # Arguments
+///
+/// * `message` - The mutation read back from the log.
+/// * `bcache` - The cache the mutation is applied to.
+/// * `tags` - The tag index the mutation is applied to.
+///
+/// # Errors
+///
+/// Returns an error if the message is not a mutation or is malformed.
+pub async fn apply(
+    message: Message,
+    bcache: &Mutex<Box<dyn BCache>>,
+    tags: &TagIndex,
+) -> Result<()> {
+    let messages = match message.cmd {
+        Command::Txn => message.txn_messages()?,
+        _ => vec![message],
+    };
+    let mut bcache = bcache.lock().await;
+    for message in messages {
+        match message.cmd {
+            Command::Insert => {
+                tags.set_tags(&message.key, &message.tags);
+                bcache.insert(message.key, message.value).await;
+            }
+            Command::Remove | Command::Expire => {
+                tags.remove_key(&message.key);
+                bcache.remove(message.key).await;
+            }
+            Command::InvalidateTag => {
+                for key in tags.take_tag(&message.key) {
+                    bcache.remove(key).await;
+                }
+            }
+            Command::ExpireTag => {
+                let expire_at_ms = message
+                    .value
+                    .parse()
+                    .map_err(|e| anyhow!("Invalid expiration of tag {}: {:?}", message.key, e))?;
+                tags.expire_at(&message.key, expire_at_ms);
+            }
+            Command::InsertIfAbsent => {
+                if bcache.get(message.key.clone()).await.is_err() {
+                    tags.set_tags(&message.key, &message.tags);
+                    bcache.insert(message.key, message.value).await;
+                }
+            }
+            Command::Patch | Command::Update => {
+                let current = bcache.get(message.key.clone()).await.ok();
+                let value = message.updated_value(current.as_deref())?;
+                bcache.insert(message.key, value).await;
+            }
+            Command::Ping | Command::Ack | Command::Resync | Command::Txn | Command::Checkpoint => {
+                return Err(anyhow!("{:?} is not a mutation", message.cmd));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::unix_time_ms;
+
+    /// Unit test for appending to a `Wal`, replaying it after a reopen and truncating it.
+    #[tokio::test]
+    async fn test_wal_replay_and_truncate() {
+        let dir = std::env::temp_dir().join(format!("wal-test-{}", unix_time_ms()));
+        let options = WalOptions {
+            segment_bytes: 1,
+            ..WalOptions::new(dir.clone())
+        };
+
+        let wal = Wal::open(options.clone()).await.unwrap();
+        for key in ["a", "b", "c"] {
+            wal.append(&Message::new(
+                Command::Insert,
+                key.to_string(),
+                "value".to_string(),
+            ))
+            .await
+            .unwrap();
+        }
+        drop(wal);
+
+        let wal = Wal::open(options.clone()).await.unwrap();
+        assert_eq!(wal.last_lsn().await, 3);
+        let keys: Vec<String> = wal
+            .replay(1)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|message| message.key)
+            .collect();
+        assert_eq!(keys, vec!["b".to_string(), "c".to_string()]);
+
+        wal.truncate(2).await.unwrap();
+        let keys: Vec<String> = wal
+            .replay(0)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|message| message.key)
+            .collect();
+        assert_eq!(keys, vec!["c".to_string()]);
+
+        wal.truncate(3).await.unwrap();
+        drop(wal);
+        let wal = Wal::open(options).await.unwrap();
+        assert!(wal.replay(0).await.unwrap().is_empty());
+        assert_eq!(wal.last_lsn().await, 3);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
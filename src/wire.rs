@@ -0,0 +1,1209 @@
+use crate::codec::{deserialize_bincode, DecodeLimits, WireCodec};
+use crate::gossip::{Command, Message};
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// The first byte of every versioned frame.
+///
+/// Unversioned (v1) frames are a bare bincode-encoded message, which starts with the
+/// little-endian variant index of its `Command`, so they never begin with this byte.
+const FRAME_MAGIC: u8 = 0xB7;
+
+/// The protocol version emitted by this release.
+pub const PROTOCOL_VERSION: u8 = 18;
+
+/// The oldest protocol version this release can still decode and emit.
+pub const MIN_PROTOCOL_VERSION: u8 = 1;
+
+/// The compression level used for zstd-compressed frames.
+const ZSTD_LEVEL: i32 = 3;
+
+/// Marks a v7 or later frame as a chunk, in place of the compression flag.
+const CHUNK_MARKER: u8 = 0xFF;
+
+/// An upper bound on the serialized overhead of a chunk frame besides its data.
+const CHUNK_OVERHEAD: usize = 32;
+
+/// The maximum number of chunks a frame may be split into, bounding reassembly buffers.
+const MAX_CHUNKS: u32 = 65536;
+
+/// The envelope wrapped around the messages of versioned frames.
+///
+/// Frames are laid out as:
+///
+/// - v2: `[FRAME_MAGIC, 2, bincode(EnvelopeV2)]`
+/// - v3: `[FRAME_MAGIC, 3, compression, payload]`, where the payload is `bincode(EnvelopeV2)`
+///   compressed with the algorithm flagged by the `compression` byte.
+/// - v4: laid out like v3, with messages carrying tags.
+/// - v5: laid out like v4, adding the `ExpireTag` command.
+/// - v6: laid out like v5 with an `Envelope`, which batches several messages into one frame.
+/// - v7: laid out like v6. Frames larger than `WireOptions::max_frame_bytes` are split into
+///   chunk frames, `[FRAME_MAGIC, version, CHUNK_MARKER, bincode(Chunk)]`, which are reassembled by
+///   a `Reassembler` into the original frame.
+/// - v8: laid out like v7 with an `Envelope` carrying an ID, which the receiver acknowledges
+///   with an `Ack` message, and adding the `Ack` command.
+/// - v9: laid out like v8, with messages carrying a `Sequence`, and adding the `Resync` command.
+/// - v10: laid out like v9 with an `Envelope` that may carry a `Rumor`, which receivers relay.
+/// - v11: laid out like v10, adding the `Txn` command.
+/// - v12: laid out like v11, adding the `Patch` command.
+/// - v13: laid out like v12, adding the `Update` command.
+/// - v14: laid out like v13, adding the `InsertIfAbsent` command.
+/// - v15: laid out like v14, adding the `Expire` command.
+/// - v16: `[FRAME_MAGIC, 16, compression, codec, payload]`, where the payload is the `Envelope`
+///   encoded with the `WireCodec` flagged by the `codec` byte, then compressed like in v3.
+/// - v17: laid out like v16, adding the `Checkpoint` command.
+/// - v18: laid out like v17 with a `Rumor` naming the members its receiver relays it to.
+///
+/// v2 and v3 envelopes wrap the message layout of `MessageV2`, v4 and v5 envelopes that of
+/// `MessageV4`. v6 and v7 frames are `EnvelopeV6`, v8 frames `EnvelopeV8`, v9 frames `EnvelopeV9`,
+/// and v10 to v17 frames encoded with bincode `EnvelopeV10`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Envelope {
+    /// Identifies the frame among those sent by this node so that the receiver can
+    /// acknowledge it. `0` means no acknowledgement is requested.
+    pub id: u64,
+    /// The messages of the frame, applied in order.
+    pub messages: Vec<Message>,
+    /// Set when the envelope is disseminated epidemically rather than sent to every member.
+    pub rumor: Option<Rumor>,
+}
+
+/// Identifies an envelope relayed from member to member, so that each member applies and
+/// relays it once.
+///
+/// # Fields
+///
+/// - `origin`: The name of the node that started the rumor.
+/// - `id`: Identifies the rumor among those started by the origin.
+/// - `hops`: How many more times the envelope is to be relayed to random members, when it names
+///   no `members`.
+/// - `members`: The members the receiver relays the envelope on to, from v18 on.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Rumor {
+    pub origin: String,
+    pub id: u64,
+    pub hops: u32,
+    #[serde(default)]
+    pub members: Vec<String>,
+}
+
+/// The envelope of v10 to v17 frames encoded with bincode, whose rumors predate member lists.
+#[derive(Debug, Serialize, Deserialize)]
+struct EnvelopeV10 {
+    id: u64,
+    messages: Vec<Message>,
+    rumor: Option<RumorV10>,
+}
+
+/// The rumor of v10 to v17 frames, which is only relayed to random members.
+#[derive(Debug, Serialize, Deserialize)]
+struct RumorV10 {
+    origin: String,
+    id: u64,
+    hops: u32,
+}
+
+impl From<&Envelope> for EnvelopeV10 {
+    fn from(envelope: &Envelope) -> Self {
+        Self {
+            id: envelope.id,
+            messages: envelope.messages.clone(),
+            rumor: envelope.rumor.as_ref().map(|rumor| RumorV10 {
+                origin: rumor.origin.clone(),
+                id: rumor.id,
+                hops: rumor.hops,
+            }),
+        }
+    }
+}
+
+impl From<EnvelopeV10> for Envelope {
+    fn from(envelope: EnvelopeV10) -> Self {
+        Self {
+            id: envelope.id,
+            messages: envelope.messages,
+            rumor: envelope.rumor.map(|rumor| Rumor {
+                origin: rumor.origin,
+                id: rumor.id,
+                hops: rumor.hops,
+                members: Vec::new(),
+            }),
+        }
+    }
+}
+
+/// The envelope of v9 frames, which predates rumors.
+#[derive(Debug, Serialize, Deserialize)]
+struct EnvelopeV9 {
+    id: u64,
+    messages: Vec<Message>,
+}
+
+/// The envelope of v6 and v7 frames, which predates acknowledgements.
+#[derive(Debug, Serialize, Deserialize)]
+struct EnvelopeV6 {
+    messages: Vec<MessageV4>,
+}
+
+/// The envelope of v8 frames, which predates sequence numbers.
+#[derive(Debug, Serialize, Deserialize)]
+struct EnvelopeV8 {
+    id: u64,
+    messages: Vec<MessageV4>,
+}
+
+/// A numbered piece of a frame that was too large to be sent at once.
+#[derive(Debug, Serialize, Deserialize)]
+struct Chunk {
+    /// Identifies the frame among those split by the sender.
+    id: u64,
+    index: u32,
+    count: u32,
+    data: Vec<u8>,
+}
+
+/// The envelope of v2 to v5 frames, carrying a single message.
+#[derive(Debug, Serialize, Deserialize)]
+struct EnvelopeV2<M> {
+    message: M,
+}
+
+/// The message layout of protocol version 1, sent as a bare bincode frame.
+#[derive(Debug, Serialize, Deserialize)]
+struct MessageV1 {
+    cmd: Command,
+    key: String,
+    value: String,
+}
+
+/// The message layout of protocol versions 2 and 3, which predates tags.
+#[derive(Debug, Serialize, Deserialize)]
+struct MessageV2 {
+    cmd: Command,
+    key: String,
+    value: String,
+    trace_context: HashMap<String, String>,
+}
+
+impl From<MessageV2> for Message {
+    fn from(msg: MessageV2) -> Self {
+        Message {
+            cmd: msg.cmd,
+            key: msg.key,
+            value: msg.value,
+            trace_context: msg.trace_context,
+            tags: Vec::new(),
+            sequence: None,
+        }
+    }
+}
+
+/// The message layout of protocol versions 4 to 8, which predates sequence numbers.
+#[derive(Debug, Serialize, Deserialize)]
+struct MessageV4 {
+    cmd: Command,
+    key: String,
+    value: String,
+    trace_context: HashMap<String, String>,
+    tags: Vec<String>,
+}
+
+impl From<&Message> for MessageV4 {
+    fn from(msg: &Message) -> Self {
+        MessageV4 {
+            cmd: msg.cmd.clone(),
+            key: msg.key.clone(),
+            value: msg.value.clone(),
+            trace_context: msg.trace_context.clone(),
+            tags: msg.tags.clone(),
+        }
+    }
+}
+
+impl From<MessageV4> for Message {
+    fn from(msg: MessageV4) -> Self {
+        Message {
+            cmd: msg.cmd,
+            key: msg.key,
+            value: msg.value,
+            trace_context: msg.trace_context,
+            tags: msg.tags,
+            sequence: None,
+        }
+    }
+}
+
+/// The compression applied to gossip payloads, flagged in the frame header from v3 on.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum Compression {
+    None,
+    #[default]
+    Lz4,
+    Zstd,
+}
+
+impl Compression {
+    fn flag(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Lz4 => 1,
+            Compression::Zstd => 2,
+        }
+    }
+
+    fn from_flag(flag: u8) -> Result<Self> {
+        match flag {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Lz4),
+            2 => Ok(Compression::Zstd),
+            f => Err(anyhow!("Unknown gossip compression flag {}", f)),
+        }
+    }
+
+    fn compress(self, payload: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(payload),
+            Compression::Lz4 => Ok(lz4_flex::compress_prepend_size(&payload)),
+            Compression::Zstd => Ok(zstd::encode_all(payload.as_slice(), ZSTD_LEVEL)?),
+        }
+    }
+
+    /// Decompresses a payload, failing rather than inflating it beyond `max_bytes`.
+    fn decompress(self, payload: &[u8], max_bytes: usize) -> Result<Vec<u8>> {
+        let too_large = || anyhow!("Decompressed payload is larger than {} bytes", max_bytes);
+        match self {
+            Compression::None => Ok(payload.to_vec()),
+            Compression::Lz4 => {
+                let (size, _) = lz4_flex::block::uncompressed_size(payload)
+                    .map_err(|e| anyhow!("Failed to decompress lz4 payload: {:?}", e))?;
+                if size > max_bytes {
+                    return Err(too_large());
+                }
+                lz4_flex::decompress_size_prepended(payload)
+                    .map_err(|e| anyhow!("Failed to decompress lz4 payload: {:?}", e))
+            }
+            Compression::Zstd => {
+                let mut decompressed = Vec::new();
+                zstd::Decoder::new(payload)?
+                    .take(max_bytes as u64 + 1)
+                    .read_to_end(&mut decompressed)
+                    .map_err(|e| anyhow!("Failed to decompress zstd payload: {:?}", e))?;
+                if decompressed.len() > max_bytes {
+                    return Err(too_large());
+                }
+                Ok(decompressed)
+            }
+        }
+    }
+}
+
+/// Options controlling how outgoing gossip frames are encoded, and incoming ones decoded.
+///
+/// # Fields
+///
+/// - `version`: The protocol version to emit, between `MIN_PROTOCOL_VERSION` and `PROTOCOL_VERSION`.
+/// - `compression`: The algorithm used for payloads of at least `compression_threshold` bytes.
+///   Only v3 and later frames can be compressed.
+/// - `compression_threshold`: The serialized size in bytes from which payloads are compressed.
+/// - `max_frame_bytes`: The size in bytes above which frames are split into chunks.
+///   Only v7 and later frames can be chunked.
+/// - `codec`: The serialization of the envelopes. Only v16 and later frames use another codec
+///   than bincode.
+/// - `limits`: The bounds on the frames received from peers.
+#[derive(Clone, Debug)]
+pub struct WireOptions {
+    pub version: u8,
+    pub compression: Compression,
+    pub compression_threshold: usize,
+    pub max_frame_bytes: usize,
+    pub codec: WireCodec,
+    pub limits: DecodeLimits,
+}
+
+impl Default for WireOptions {
+    fn default() -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            compression: Compression::default(),
+            compression_threshold: 1024,
+            max_frame_bytes: 1400,
+            codec: WireCodec::default(),
+            limits: DecodeLimits::default(),
+        }
+    }
+}
+
+/// Encodes a message into a gossip frame.
+///
+/// Fields that do not exist in an older version (such as the trace context in v1) are dropped.
+///
+/// # Arguments
+///
+/// * `msg` - The message to encode.
+/// * `options` - The protocol version and compression to encode with.
+///
+/// # Errors
+///
+/// Returns an error if the version is not supported or cannot carry the command, or
+/// serialization or compression fails.
+pub fn encode(msg: &Message, options: &WireOptions) -> Result<Vec<u8>> {
+    if options.version < min_version(&msg.cmd) {
+        return Err(anyhow!(
+            "{:?} requires gossip protocol version {}",
+            msg.cmd,
+            min_version(&msg.cmd)
+        ));
+    }
+
+    match options.version {
+        1 => Ok(bincode::serialize(&MessageV1 {
+            cmd: msg.cmd.clone(),
+            key: msg.key.clone(),
+            value: msg.value.clone(),
+        })?),
+        2 => {
+            let mut frame = vec![FRAME_MAGIC, 2];
+            bincode::serialize_into(&mut frame, &message_v2(msg))?;
+            Ok(frame)
+        }
+        3 => compressed_frame(3, bincode::serialize(&message_v2(msg))?, options),
+        v @ (4 | 5) => compressed_frame(
+            v,
+            bincode::serialize(&EnvelopeV2 {
+                message: MessageV4::from(msg),
+            })?,
+            options,
+        ),
+        _ => encode_envelope(
+            &Envelope {
+                messages: vec![msg.clone()],
+                ..Envelope::default()
+            },
+            options,
+        )?
+        .pop()
+        .ok_or_else(|| anyhow!("No frame encoded")),
+    }
+}
+
+/// Encodes an envelope into gossip frames.
+///
+/// From v6 on the envelope is encoded into a single frame; older versions get one frame per
+/// message. The envelope ID is only sent from v8 on, message sequences from v9 on, rumors
+/// from v10 on, the codec is only honored from v16 on and the members of rumors are only sent
+/// from v18 on.
+///
+/// # Arguments
+///
+/// * `envelope` - The messages to encode, in the order they are to be applied, and their frame ID.
+/// * `options` - The protocol version and compression to encode with.
+///
+/// # Errors
+///
+/// Returns an error if any message cannot be encoded.
+pub fn encode_envelope(envelope: &Envelope, options: &WireOptions) -> Result<Vec<Vec<u8>>> {
+    if let Some(msg) = envelope
+        .messages
+        .iter()
+        .find(|msg| options.version < min_version(&msg.cmd))
+    {
+        return Err(anyhow!(
+            "{:?} requires gossip protocol version {}",
+            msg.cmd,
+            min_version(&msg.cmd)
+        ));
+    }
+
+    match options.version {
+        v @ (6 | 7) => Ok(vec![compressed_frame(
+            v,
+            bincode::serialize(&EnvelopeV6 {
+                messages: envelope.messages.iter().map(MessageV4::from).collect(),
+            })?,
+            options,
+        )?]),
+        8 => Ok(vec![compressed_frame(
+            8,
+            bincode::serialize(&EnvelopeV8 {
+                id: envelope.id,
+                messages: envelope.messages.iter().map(MessageV4::from).collect(),
+            })?,
+            options,
+        )?]),
+        9 => Ok(vec![compressed_frame(
+            9,
+            bincode::serialize(&EnvelopeV9 {
+                id: envelope.id,
+                messages: envelope.messages.clone(),
+            })?,
+            options,
+        )?]),
+        v @ 10..=15 => Ok(vec![compressed_frame(
+            v,
+            bincode::serialize(&EnvelopeV10::from(envelope))?,
+            options,
+        )?]),
+        v @ (16 | 17) => {
+            let payload = match options.codec {
+                WireCodec::Bincode => bincode::serialize(&EnvelopeV10::from(envelope))?,
+                codec => codec.codec().encode(&Envelope {
+                    rumor: envelope.rumor.clone().map(|rumor| Rumor {
+                        members: Vec::new(),
+                        ..rumor
+                    }),
+                    ..envelope.clone()
+                })?,
+            };
+            let mut frame = compressed_frame(v, payload, options)?;
+            frame.insert(3, options.codec.flag());
+            Ok(vec![frame])
+        }
+        v @ RELAY_VERSION..=PROTOCOL_VERSION => {
+            let mut frame = compressed_frame(v, options.codec.codec().encode(envelope)?, options)?;
+            frame.insert(3, options.codec.flag());
+            Ok(vec![frame])
+        }
+        v if v < 6 => envelope
+            .messages
+            .iter()
+            .map(|msg| encode(msg, options))
+            .collect(),
+        v => Err(unsupported_version(v)),
+    }
+}
+
+/// Decodes a gossip frame of any supported protocol version into its envelope, within the
+/// default `DecodeLimits`.
+///
+/// # Errors
+///
+/// Returns an error if `decode_with_limits` does.
+pub fn decode(frame: &[u8]) -> Result<Envelope> {
+    decode_with_limits(frame, &DecodeLimits::default())
+}
+
+/// Decodes a gossip frame of any supported protocol version into its envelope, and checks it.
+///
+/// Frames come from untrusted peers: they are rejected when they, or their decompressed
+/// payload, exceed `limits.max_payload_bytes`, when they carry more messages, tags or trace
+/// context entries than `limits` allows, or commands their protocol version predates. Frames of
+/// versions that predate envelope IDs are decoded with an ID of `0`.
+///
+/// # Errors
+///
+/// Returns an error if the frame announces an unsupported protocol version, compression or
+/// codec, its payload cannot be decompressed or deserialized, or it exceeds `limits`.
+pub fn decode_with_limits(frame: &[u8], limits: &DecodeLimits) -> Result<Envelope> {
+    if frame.len() > limits.max_payload_bytes {
+        return Err(anyhow!(
+            "Frame of {} bytes is larger than {} bytes",
+            frame.len(),
+            limits.max_payload_bytes
+        ));
+    }
+    let envelope = decode_frame(frame, limits.max_payload_bytes)?;
+    let version = match frame {
+        [FRAME_MAGIC, version, ..] => *version,
+        _ => 1,
+    };
+    validate(&envelope, version, limits)?;
+    Ok(envelope)
+}
+
+/// Checks the contents of an envelope decoded from a frame of `version`.
+fn validate(envelope: &Envelope, version: u8, limits: &DecodeLimits) -> Result<()> {
+    if envelope.messages.len() > limits.max_messages {
+        return Err(anyhow!(
+            "Frame carries {} messages, more than the maximum of {}",
+            envelope.messages.len(),
+            limits.max_messages
+        ));
+    }
+    for msg in &envelope.messages {
+        if min_version(&msg.cmd) > version {
+            return Err(anyhow!(
+                "{:?} cannot be carried by a version {} frame",
+                msg.cmd,
+                version
+            ));
+        }
+        if msg.tags.len() > limits.max_tags {
+            return Err(anyhow!(
+                "Message for key {} carries {} tags, more than the maximum of {}",
+                msg.key,
+                msg.tags.len(),
+                limits.max_tags
+            ));
+        }
+        if msg.trace_context.len() > limits.max_trace_context {
+            return Err(anyhow!(
+                "Message for key {} carries {} trace context entries, more than the maximum of {}",
+                msg.key,
+                msg.trace_context.len(),
+                limits.max_trace_context
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn decode_frame(frame: &[u8], max_bytes: usize) -> Result<Envelope> {
+    let messages = match frame {
+        [FRAME_MAGIC, 2, payload @ ..] => vec![deserialize_v2(payload)?],
+        [FRAME_MAGIC, 3, compression, payload @ ..] => {
+            let payload = Compression::from_flag(*compression)?.decompress(payload, max_bytes)?;
+            vec![deserialize_v2(&payload)?]
+        }
+        [FRAME_MAGIC, 4 | 5, compression, payload @ ..] => {
+            let payload = Compression::from_flag(*compression)?.decompress(payload, max_bytes)?;
+            vec![deserialize_bincode::<EnvelopeV2<MessageV4>>(&payload)
+                .map_err(|e| anyhow!("Failed to deserialize v4 envelope: {:?}", e))?
+                .message
+                .into()]
+        }
+        [FRAME_MAGIC, 7..=PROTOCOL_VERSION, CHUNK_MARKER, ..] => {
+            return Err(anyhow!("Chunk frames must be reassembled before decoding"))
+        }
+        [FRAME_MAGIC, 6 | 7, compression, payload @ ..] => {
+            let payload = Compression::from_flag(*compression)?.decompress(payload, max_bytes)?;
+            deserialize_bincode::<EnvelopeV6>(&payload)
+                .map_err(|e| anyhow!("Failed to deserialize v6 envelope: {:?}", e))?
+                .messages
+                .into_iter()
+                .map(Message::from)
+                .collect()
+        }
+        [FRAME_MAGIC, 8, compression, payload @ ..] => {
+            let payload = Compression::from_flag(*compression)?.decompress(payload, max_bytes)?;
+            let envelope = deserialize_bincode::<EnvelopeV8>(&payload)
+                .map_err(|e| anyhow!("Failed to deserialize v8 envelope: {:?}", e))?;
+            return Ok(Envelope {
+                id: envelope.id,
+                messages: envelope.messages.into_iter().map(Message::from).collect(),
+                rumor: None,
+            });
+        }
+        [FRAME_MAGIC, 9, compression, payload @ ..] => {
+            let payload = Compression::from_flag(*compression)?.decompress(payload, max_bytes)?;
+            let envelope = deserialize_bincode::<EnvelopeV9>(&payload)
+                .map_err(|e| anyhow!("Failed to deserialize v9 envelope: {:?}", e))?;
+            return Ok(Envelope {
+                id: envelope.id,
+                messages: envelope.messages,
+                rumor: None,
+            });
+        }
+        [FRAME_MAGIC, 10..=15, compression, payload @ ..] => {
+            let payload = Compression::from_flag(*compression)?.decompress(payload, max_bytes)?;
+            return deserialize_bincode::<EnvelopeV10>(&payload)
+                .map(Envelope::from)
+                .map_err(|e| anyhow!("Failed to deserialize envelope: {:?}", e));
+        }
+        [FRAME_MAGIC, 16 | 17, compression, codec, payload @ ..] => {
+            let codec = WireCodec::from_flag(*codec)?;
+            let payload = Compression::from_flag(*compression)?.decompress(payload, max_bytes)?;
+            return match codec {
+                WireCodec::Bincode => deserialize_bincode::<EnvelopeV10>(&payload)
+                    .map(Envelope::from)
+                    .map_err(|e| anyhow!("Failed to deserialize bincode envelope: {:?}", e)),
+                codec => codec.codec().decode(&payload),
+            };
+        }
+        [FRAME_MAGIC, RELAY_VERSION..=PROTOCOL_VERSION, compression, codec, payload @ ..] => {
+            let codec = WireCodec::from_flag(*codec)?;
+            let payload = Compression::from_flag(*compression)?.decompress(payload, max_bytes)?;
+            return codec.codec().decode(&payload);
+        }
+        [FRAME_MAGIC, version, ..] => return Err(unsupported_version(*version)),
+        _ => {
+            let msg: MessageV1 = deserialize_bincode(frame)
+                .map_err(|e| anyhow!("Failed to deserialize v1 message: {:?}", e))?;
+            vec![Message {
+                cmd: msg.cmd,
+                key: msg.key,
+                value: msg.value,
+                trace_context: HashMap::new(),
+                tags: Vec::new(),
+                sequence: None,
+            }]
+        }
+    };
+
+    Ok(Envelope {
+        id: 0,
+        messages,
+        rumor: None,
+    })
+}
+
+/// Splits a frame larger than `options.max_frame_bytes` into chunk frames.
+///
+/// Smaller frames, and frames of versions that predate chunking, are returned unchanged.
+///
+/// # Arguments
+///
+/// * `frame` - The encoded frame.
+/// * `id` - An identifier of the frame, unique among the frames recently split by this node.
+/// * `options` - The protocol version and maximum frame size to encode with.
+///
+/// # Errors
+///
+/// Returns an error if the frame needs more than `MAX_CHUNKS` chunks, or serialization fails.
+pub fn split_frame(frame: Vec<u8>, id: u64, options: &WireOptions) -> Result<Vec<Vec<u8>>> {
+    if options.version < 7 || frame.len() <= options.max_frame_bytes {
+        return Ok(vec![frame]);
+    }
+
+    let chunk_size = options
+        .max_frame_bytes
+        .saturating_sub(CHUNK_OVERHEAD)
+        .max(1);
+    let count = frame.len().div_ceil(chunk_size);
+    if count > MAX_CHUNKS as usize {
+        return Err(anyhow!(
+            "Frame of {} bytes needs {} chunks, more than the maximum of {}",
+            frame.len(),
+            count,
+            MAX_CHUNKS
+        ));
+    }
+
+    frame
+        .chunks(chunk_size)
+        .enumerate()
+        .map(|(index, data)| {
+            let mut chunk_frame = vec![FRAME_MAGIC, options.version, CHUNK_MARKER];
+            bincode::serialize_into(
+                &mut chunk_frame,
+                &Chunk {
+                    id,
+                    index: index as u32,
+                    count: count as u32,
+                    data: data.to_vec(),
+                },
+            )?;
+            Ok(chunk_frame)
+        })
+        .collect()
+}
+
+/// A frame being reassembled from its chunks.
+struct PartialFrame {
+    started: Instant,
+    chunks: Vec<Option<Vec<u8>>>,
+    received: u32,
+}
+
+/// Reassembles chunked frames received from peers.
+///
+/// Frames whose chunks do not all arrive within the timeout are dropped by `evict_expired`.
+///
+/// # Example
+///
+/// ```rust
+/// let mut reassembler = Reassembler::new(Duration::from_secs(10));
+/// if let Some(frame) = reassembler.accept(from, frame)? {
+///     let msgs = wire::decode(&frame)?;
+/// }
+/// ```
+pub struct Reassembler {
+    timeout: Duration,
+    partial: HashMap<(SocketAddr, u64), PartialFrame>,
+}
+
+impl Reassembler {
+    /// Creates a new `Reassembler` dropping incomplete frames after `timeout`.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            partial: HashMap::new(),
+        }
+    }
+
+    /// Accepts a frame received from a peer.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The address of the peer that sent the frame.
+    /// * `frame` - The received frame.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(frame)` - A frame that is ready to be decoded: the received frame itself if it
+    ///   is not a chunk, or the reassembled frame once its last chunk has arrived.
+    /// * `None` - The frame is a chunk of a frame that is still incomplete.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the chunk cannot be deserialized or is inconsistent with the chunks
+    /// received before it.
+    pub fn accept(&mut self, from: SocketAddr, frame: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        let chunk: Chunk = match frame.as_slice() {
+            [FRAME_MAGIC, 7..=PROTOCOL_VERSION, CHUNK_MARKER, payload @ ..] => {
+                deserialize_bincode(payload)
+                    .map_err(|e| anyhow!("Failed to deserialize chunk: {:?}", e))?
+            }
+            _ => return Ok(Some(frame)),
+        };
+        if chunk.count == 0 || chunk.count > MAX_CHUNKS || chunk.index >= chunk.count {
+            return Err(anyhow!(
+                "Invalid chunk {} of {} of frame {}",
+                chunk.index,
+                chunk.count,
+                chunk.id
+            ));
+        }
+
+        let key = (from, chunk.id);
+        let partial = self.partial.entry(key).or_insert_with(|| PartialFrame {
+            started: Instant::now(),
+            chunks: vec![None; chunk.count as usize],
+            received: 0,
+        });
+        if partial.chunks.len() != chunk.count as usize {
+            self.partial.remove(&key);
+            return Err(anyhow!(
+                "Chunk count of frame {} changed to {}",
+                chunk.id,
+                chunk.count
+            ));
+        }
+
+        let slot = &mut partial.chunks[chunk.index as usize];
+        if slot.is_none() {
+            *slot = Some(chunk.data);
+            partial.received += 1;
+        }
+        if partial.received < chunk.count {
+            return Ok(None);
+        }
+
+        let chunks = std::mem::take(&mut partial.chunks);
+        self.partial.remove(&key);
+        Ok(Some(chunks.into_iter().flatten().flatten().collect()))
+    }
+
+    /// Drops incomplete frames whose first chunk arrived longer than the timeout ago.
+    ///
+    /// # Returns
+    ///
+    /// * The number of frames dropped.
+    pub fn evict_expired(&mut self) -> usize {
+        let before = self.partial.len();
+        let timeout = self.timeout;
+        self.partial
+            .retain(|_, partial| partial.started.elapsed() < timeout);
+        before - self.partial.len()
+    }
+}
+
+/// The first protocol version whose envelopes can carry a `Rumor`.
+pub const RUMOR_VERSION: u8 = 10;
+
+/// The first protocol version whose rumors name the members they are relayed to.
+pub const RELAY_VERSION: u8 = 18;
+
+/// Returns the first protocol version that can carry a command.
+pub fn min_version(cmd: &Command) -> u8 {
+    match cmd {
+        Command::Ping | Command::Insert | Command::Remove => 1,
+        Command::InvalidateTag => 4,
+        Command::ExpireTag => 5,
+        Command::Ack => 8,
+        Command::Resync => 9,
+        Command::Txn => 11,
+        Command::Patch => 12,
+        Command::Update => 13,
+        Command::InsertIfAbsent => 14,
+        Command::Expire => 15,
+        Command::Checkpoint => 17,
+    }
+}
+
+/// Builds a v3 or later frame, compressing the payload if it reaches the threshold.
+fn compressed_frame(version: u8, payload: Vec<u8>, options: &WireOptions) -> Result<Vec<u8>> {
+    let compression = if payload.len() >= options.compression_threshold {
+        options.compression
+    } else {
+        Compression::None
+    };
+
+    let mut frame = vec![FRAME_MAGIC, version, compression.flag()];
+    frame.extend(compression.compress(payload)?);
+    Ok(frame)
+}
+
+fn message_v2(msg: &Message) -> EnvelopeV2<MessageV2> {
+    EnvelopeV2 {
+        message: MessageV2 {
+            cmd: msg.cmd.clone(),
+            key: msg.key.clone(),
+            value: msg.value.clone(),
+            trace_context: msg.trace_context.clone(),
+        },
+    }
+}
+
+fn deserialize_v2(payload: &[u8]) -> Result<Message> {
+    Ok(deserialize_bincode::<EnvelopeV2<MessageV2>>(payload)
+        .map_err(|e| anyhow!("Failed to deserialize v2 envelope: {:?}", e))?
+        .message
+        .into())
+}
+
+fn unsupported_version(version: u8) -> anyhow::Error {
+    anyhow!(
+        "Unsupported gossip protocol version {} (supported: {}..={})",
+        version,
+        MIN_PROTOCOL_VERSION,
+        PROTOCOL_VERSION
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gossip::Sequence;
+
+    fn message(value: &str) -> Message {
+        Message {
+            cmd: Command::Insert,
+            key: "hello".to_string(),
+            value: value.to_string(),
+            trace_context: HashMap::from([(
+                "traceparent".to_string(),
+                "00-abc-def-01".to_string(),
+            )]),
+            tags: vec!["sale".to_string()],
+            sequence: Some(Sequence {
+                origin: "node-1".to_string(),
+                incarnation: 7,
+                seq: 3,
+            }),
+        }
+    }
+
+    fn decode_one(frame: &[u8]) -> Result<Message> {
+        let mut msgs = decode(frame)?.messages;
+        assert_eq!(msgs.len(), 1);
+        Ok(msgs.remove(0))
+    }
+
+    fn options(version: u8, compression: Compression) -> WireOptions {
+        WireOptions {
+            version,
+            compression,
+            ..WireOptions::default()
+        }
+    }
+
+    /// Unit test for the compatibility matrix: every version this release can emit decodes.
+    ///
+    /// Fields introduced after the emitted version are lost, everything else round-trips.
+    #[test]
+    fn test_encode_decode_supported_versions() {
+        for version in MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION {
+            let msg = message("world");
+            let decoded =
+                decode_one(&encode(&msg, &options(version, Compression::None)).unwrap()).unwrap();
+            assert_eq!(decoded.cmd, Command::Insert);
+            assert_eq!(decoded.key, "hello");
+            assert_eq!(decoded.value, "world");
+            if version >= 2 {
+                assert_eq!(decoded.trace_context, msg.trace_context);
+            } else {
+                assert!(decoded.trace_context.is_empty());
+            }
+            if version >= 4 {
+                assert_eq!(decoded.tags, msg.tags);
+            } else {
+                assert!(decoded.tags.is_empty());
+            }
+            if version >= 9 {
+                assert_eq!(decoded.sequence, msg.sequence);
+            } else {
+                assert!(decoded.sequence.is_none());
+            }
+        }
+    }
+
+    /// Unit test for refusing to encode commands that older versions cannot represent.
+    #[test]
+    fn test_encode_newer_commands() {
+        for cmd in [
+            Command::InvalidateTag,
+            Command::ExpireTag,
+            Command::Ack,
+            Command::Resync,
+            Command::Txn,
+            Command::Patch,
+            Command::Update,
+            Command::InsertIfAbsent,
+            Command::Expire,
+            Command::Checkpoint,
+        ] {
+            let msg = Message::new(cmd.clone(), "sale".to_string(), "".to_string());
+            for version in MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION {
+                let frame = encode(&msg, &options(version, Compression::None));
+                if version < min_version(&cmd) {
+                    assert!(frame.is_err());
+                } else {
+                    assert_eq!(decode_one(&frame.unwrap()).unwrap().cmd, cmd);
+                }
+            }
+        }
+    }
+
+    /// Unit test for decoding frames sent by v1 nodes, which predate the envelope.
+    #[test]
+    fn test_decode_v1_frame() {
+        for cmd in [Command::Ping, Command::Insert, Command::Remove] {
+            let frame = bincode::serialize(&MessageV1 {
+                cmd: cmd.clone(),
+                key: "hello".to_string(),
+                value: "".to_string(),
+            })
+            .unwrap();
+            assert_ne!(frame[0], FRAME_MAGIC);
+
+            let decoded = decode_one(&frame).unwrap();
+            assert_eq!(decoded.cmd, cmd);
+            assert_eq!(decoded.key, "hello");
+        }
+    }
+
+    /// Unit test for rejecting frames from newer releases rather than misparsing them.
+    #[test]
+    fn test_reject_unsupported_versions() {
+        let mut frame = encode(&message("world"), &WireOptions::default()).unwrap();
+        frame[1] = PROTOCOL_VERSION + 1;
+        let err = decode_one(&frame).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Unsupported gossip protocol version"));
+
+        assert!(encode(
+            &message("world"),
+            &options(PROTOCOL_VERSION + 1, Compression::None)
+        )
+        .is_err());
+        assert!(encode(&message("world"), &options(0, Compression::None)).is_err());
+    }
+
+    /// Unit test for compressing payloads above the threshold with each algorithm.
+    #[test]
+    fn test_compression() {
+        let large = "a".repeat(4096);
+        for (version, compression) in (3..=PROTOCOL_VERSION).flat_map(|version| {
+            [Compression::Lz4, Compression::Zstd].map(|compression| (version, compression))
+        }) {
+            let frame = encode(&message(&large), &options(version, compression)).unwrap();
+            assert_eq!(frame[2], compression.flag());
+            assert!(frame.len() < large.len());
+            assert_eq!(decode_one(&frame).unwrap().value, large);
+
+            let frame = encode(&message("small"), &options(version, compression)).unwrap();
+            assert_eq!(frame[2], Compression::None.flag());
+            assert_eq!(decode_one(&frame).unwrap().value, "small");
+        }
+
+        let mut frame = encode(&message("small"), &WireOptions::default()).unwrap();
+        frame[2] = 0xFF;
+        assert!(decode_one(&frame).is_err());
+    }
+
+    /// Unit test for batching messages into one frame, and falling back to one frame per
+    /// message for versions that predate batching.
+    #[test]
+    fn test_encode_batch() {
+        let envelope = Envelope {
+            id: 42,
+            messages: vec![
+                message("1"),
+                Message::new(Command::Remove, "hello".to_string(), "".to_string()),
+                message("2"),
+            ],
+            rumor: Some(Rumor {
+                origin: "node-1".to_string(),
+                id: 7,
+                hops: 2,
+                members: vec!["node-3".to_string()],
+            }),
+        };
+
+        let frames = encode_envelope(&envelope, &WireOptions::default()).unwrap();
+        assert_eq!(frames.len(), 1);
+        let decoded = decode(&frames[0]).unwrap();
+        assert_eq!(decoded.id, 42);
+        let cmds: Vec<Command> = decoded.messages.iter().map(|msg| msg.cmd.clone()).collect();
+        assert_eq!(
+            cmds,
+            vec![Command::Insert, Command::Remove, Command::Insert]
+        );
+        assert_eq!(decoded.messages[2].value, "2");
+        assert_eq!(decoded.rumor, envelope.rumor);
+
+        // Versions that predate member lists keep the rest of the rumor, whatever the codec.
+        for codec in [WireCodec::Bincode, WireCodec::Json, WireCodec::Protobuf] {
+            for version in [15, 17] {
+                let options = WireOptions {
+                    codec,
+                    ..options(version, Compression::None)
+                };
+                let frames = encode_envelope(&envelope, &options).unwrap();
+                let rumor = decode(&frames[0]).unwrap().rumor.unwrap();
+                assert_eq!(rumor.id, 7);
+                assert_eq!(rumor.hops, 2);
+                assert!(rumor.members.is_empty());
+            }
+        }
+
+        // Versions that predate rumors or envelope IDs drop them.
+        let frames = encode_envelope(&envelope, &options(9, Compression::None)).unwrap();
+        let decoded = decode(&frames[0]).unwrap();
+        assert_eq!(decoded.id, 42);
+        assert!(decoded.rumor.is_none());
+        let frames = encode_envelope(&envelope, &options(7, Compression::None)).unwrap();
+        assert_eq!(decode(&frames[0]).unwrap().id, 0);
+
+        let frames = encode_envelope(&envelope, &options(5, Compression::None)).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(decode_one(&frames[2]).unwrap().value, "2");
+    }
+
+    /// Unit test for splitting a large frame into chunks and reassembling it, out of order.
+    #[test]
+    fn test_chunked_frame() {
+        let from: SocketAddr = "127.0.0.1:4001".parse().unwrap();
+        let options = WireOptions {
+            compression: Compression::None,
+            max_frame_bytes: 256,
+            ..WireOptions::default()
+        };
+        let large = "a".repeat(2000);
+        let frame = encode(&message(&large), &options).unwrap();
+
+        let mut chunks = split_frame(frame.clone(), 1, &options).unwrap();
+        assert!(chunks.len() > 1);
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.len() <= options.max_frame_bytes));
+        assert!(decode(&chunks[0]).is_err());
+
+        chunks.reverse();
+        let mut reassembler = Reassembler::new(Duration::from_secs(10));
+        let last = chunks.pop().unwrap();
+        for chunk in chunks {
+            assert!(reassembler.accept(from, chunk).unwrap().is_none());
+        }
+        let reassembled = reassembler.accept(from, last).unwrap().unwrap();
+        assert_eq!(reassembled, frame);
+        assert_eq!(decode_one(&reassembled).unwrap().value, large);
+
+        // Small frames and frames of older versions are not chunked.
+        let small = encode(&message("small"), &options).unwrap();
+        assert_eq!(
+            split_frame(small.clone(), 2, &options).unwrap(),
+            vec![small.clone()]
+        );
+        assert_eq!(
+            reassembler.accept(from, small.clone()).unwrap(),
+            Some(small)
+        );
+        let v6 = WireOptions {
+            version: 6,
+            ..options.clone()
+        };
+        assert_eq!(split_frame(frame.clone(), 3, &v6).unwrap().len(), 1);
+    }
+
+    /// Unit test for dropping frames whose chunks do not all arrive in time.
+    #[test]
+    fn test_reassembly_timeout() {
+        let from: SocketAddr = "127.0.0.1:4001".parse().unwrap();
+        let options = WireOptions {
+            compression: Compression::None,
+            max_frame_bytes: 256,
+            ..WireOptions::default()
+        };
+        let frame = encode(&message(&"a".repeat(2000)), &options).unwrap();
+        let mut chunks = split_frame(frame, 1, &options).unwrap();
+
+        let mut reassembler = Reassembler::new(Duration::ZERO);
+        assert!(reassembler
+            .accept(from, chunks.remove(0))
+            .unwrap()
+            .is_none());
+        assert_eq!(reassembler.evict_expired(), 1);
+        assert_eq!(reassembler.evict_expired(), 0);
+    }
+
+    /// Unit test for encoding v16 frames with each codec, and rejecting frames that exceed
+    /// the decode limits or carry commands their version predates.
+    #[test]
+    fn test_decode_limits() {
+        for codec in [WireCodec::Bincode, WireCodec::Json, WireCodec::Protobuf] {
+            let options = WireOptions {
+                codec,
+                ..options(PROTOCOL_VERSION, Compression::Zstd)
+            };
+            let frame = encode(&message(&"a".repeat(4096)), &options).unwrap();
+            assert_eq!(frame[3], codec.flag());
+            assert_eq!(decode_one(&frame).unwrap().value, "a".repeat(4096));
+
+            let limits = DecodeLimits {
+                max_payload_bytes: 1024,
+                ..DecodeLimits::default()
+            };
+            let err = decode_with_limits(&frame, &limits).unwrap_err();
+            assert!(err.to_string().contains("larger than 1024 bytes"));
+        }
+
+        // A small frame inflating to a large payload is not decompressed past the limit.
+        let limits = DecodeLimits {
+            max_payload_bytes: 64 * 1024,
+            ..DecodeLimits::default()
+        };
+        for compression in [Compression::Lz4, Compression::Zstd] {
+            let frame = encode(
+                &message(&"a".repeat(1024 * 1024)),
+                &options(PROTOCOL_VERSION, compression),
+            )
+            .unwrap();
+            assert!(frame.len() < limits.max_payload_bytes);
+            assert!(decode_with_limits(&frame, &limits).is_err());
+        }
+
+        let envelope = Envelope {
+            messages: vec![message("1"), message("2")],
+            ..Envelope::default()
+        };
+        let frame = encode_envelope(&envelope, &WireOptions::default())
+            .unwrap()
+            .remove(0);
+        let limits = DecodeLimits {
+            max_messages: 1,
+            ..DecodeLimits::default()
+        };
+        assert!(decode_with_limits(&frame, &limits).is_err());
+        let limits = DecodeLimits {
+            max_tags: 0,
+            ..DecodeLimits::default()
+        };
+        assert!(decode_with_limits(&frame, &limits).is_err());
+
+        // A v1 frame cannot carry a command introduced in v4.
+        let frame = bincode::serialize(&MessageV1 {
+            cmd: Command::InvalidateTag,
+            key: "sale".to_string(),
+            value: "".to_string(),
+        })
+        .unwrap();
+        assert!(decode(&frame).is_err());
+    }
+}
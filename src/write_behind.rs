@@ -0,0 +1,442 @@
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use redis::aio::ConnectionManager;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::time::{self, Instant};
+use tracing::{debug, error, warn};
+
+use crate::cache_trait::BCache;
+use anyhow::{anyhow, Result};
+
+/// A mutation flushed to the backing store: the new value of a key, or `None` for a removal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteOp {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+/// An external store that `WriteBehindCache` persists mutations to in batches.
+#[async_trait]
+pub trait BackingStore: Send + Sync {
+    /// Applies a batch of mutations. Each key appears at most once in a batch.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the batch could not be fully applied. The whole batch is then retried,
+    /// so applying a mutation must be idempotent.
+    async fn write_batch(&self, ops: &[WriteOp]) -> Result<()>;
+}
+
+/// A `BackingStore` writing each batch to Redis or Valkey in a single atomic pipeline.
+pub struct RedisStore {
+    conn: ConnectionManager,
+    key_prefix: String,
+}
+
+impl RedisStore {
+    /// Connects to the Redis server at `url`. Keys are stored with `key_prefix` prepended.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the URL is invalid or the server cannot be reached.
+    pub async fn connect(url: &str, key_prefix: String) -> Result<Self> {
+        let client = redis::Client::open(url)?;
+        Ok(Self {
+            conn: ConnectionManager::new(client).await?,
+            key_prefix,
+        })
+    }
+}
+
+#[async_trait]
+impl BackingStore for RedisStore {
+    async fn write_batch(&self, ops: &[WriteOp]) -> Result<()> {
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for op in ops {
+            let key = format!("{}{}", self.key_prefix, op.key);
+            match &op.value {
+                Some(value) => pipe.set(key, value).ignore(),
+                None => pipe.del(key).ignore(),
+            };
+        }
+        pipe.query_async::<()>(&mut self.conn.clone()).await?;
+        Ok(())
+    }
+}
+
+/// A `BackingStore` writing each entry as an object of an S3-compatible bucket.
+pub struct ObjectStoreBackend {
+    store: Arc<dyn ObjectStore>,
+    prefix: Path,
+    concurrency: usize,
+}
+
+impl ObjectStoreBackend {
+    /// Creates a backend writing objects under `prefix` in `store`, with up to `concurrency`
+    /// requests in flight.
+    pub fn new(store: Arc<dyn ObjectStore>, prefix: &str, concurrency: usize) -> Self {
+        Self {
+            store,
+            prefix: Path::from(prefix),
+            concurrency: concurrency.max(1),
+        }
+    }
+
+    /// Creates a backend for an `s3://<bucket>/<prefix>` URL, with credentials and region read
+    /// from the `AWS_*` environment variables.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the URL is invalid or the S3 client cannot be configured.
+    pub fn from_url(url: &str, concurrency: usize) -> Result<Self> {
+        let store = AmazonS3Builder::from_env().with_url(url).build()?;
+        let prefix = url
+            .strip_prefix("s3://")
+            .and_then(|rest| rest.split_once('/'))
+            .map_or("", |(_, prefix)| prefix);
+        Ok(Self::new(Arc::new(store), prefix, concurrency))
+    }
+}
+
+#[async_trait]
+impl BackingStore for ObjectStoreBackend {
+    async fn write_batch(&self, ops: &[WriteOp]) -> Result<()> {
+        let writes: Vec<_> = ops
+            .iter()
+            .map(|op| write_object(self.store.as_ref(), &self.prefix, op))
+            .collect();
+        stream::iter(writes)
+            .buffer_unordered(self.concurrency)
+            .try_collect::<()>()
+            .await?;
+        Ok(())
+    }
+}
+
+/// Writes a single mutation as an object under `prefix`.
+async fn write_object(
+    store: &dyn ObjectStore,
+    prefix: &Path,
+    op: &WriteOp,
+) -> object_store::Result<()> {
+    let path = prefix.child(op.key.as_str());
+    match &op.value {
+        Some(value) => store.put(&path, value.clone().into()).await.map(|_| ()),
+        None => match store.delete(&path).await {
+            Err(object_store::Error::NotFound { .. }) => Ok(()),
+            result => result,
+        },
+    }
+}
+
+/// Opens the backing store for a URL: `redis://` and `rediss://` URLs select Redis, and
+/// `s3://<bucket>/<prefix>` URLs select an S3-compatible bucket.
+///
+/// # Errors
+///
+/// Returns an error if the scheme is not supported or the store cannot be opened.
+pub async fn open_backing_store(url: &str) -> Result<Arc<dyn BackingStore>> {
+    if url.starts_with("redis://") || url.starts_with("rediss://") {
+        Ok(Arc::new(RedisStore::connect(url, String::new()).await?))
+    } else if url.starts_with("s3://") {
+        Ok(Arc::new(ObjectStoreBackend::from_url(url, 8)?))
+    } else {
+        Err(anyhow!("Unsupported write-behind store {}", url))
+    }
+}
+
+/// Configures the write-behind queue.
+///
+/// # Fields
+///
+/// - `queue_capacity`: The number of mutations waiting to be flushed. Writers wait for room when
+///   it is full, which slows clients down rather than growing memory without bound.
+/// - `batch_size`: The maximum number of mutations flushed in one batch.
+/// - `flush_interval`: The maximum time a mutation waits before its batch is flushed.
+/// - `max_attempts`: The number of times a batch is written, including the first one, before it is dropped.
+/// - `initial_backoff`: The delay before a failed batch is retried. It doubles with every attempt.
+/// - `max_backoff`: The maximum delay between two attempts.
+#[derive(Debug, Clone)]
+pub struct WriteBehindOptions {
+    pub queue_capacity: usize,
+    pub batch_size: usize,
+    pub flush_interval: Duration,
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for WriteBehindOptions {
+    fn default() -> Self {
+        Self {
+            queue_capacity: 10_000,
+            batch_size: 100,
+            flush_interval: Duration::from_millis(100),
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+/// `WriteBehindCache` wraps a `BCache` and persists its mutations to a `BackingStore` in the background.
+///
+/// Writes and removals are applied to the wrapped cache and queued; a background task flushes
+/// the queue in batches, keeping only the last mutation of each key in a batch, and retries
+/// failed batches with exponential backoff. Clients therefore only wait for the local write,
+/// unless the queue is full. Reads are served from the wrapped cache only.
+///
+/// # Example
+///
+/// ```rust
+/// let local: Box<dyn BCache> = Box::new(MokaCache::new(1000).await);
+/// let store = open_backing_store("redis://127.0.0.1/").await?;
+/// let mut cache = WriteBehindCache::new(local, store, WriteBehindOptions::default());
+/// cache.insert("key".to_string(), "value".to_string()).await;
+/// ```
+pub struct WriteBehindCache {
+    /// The cache reads are served from.
+    local: Box<dyn BCache>,
+    queue: Sender<WriteOp>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl WriteBehindCache {
+    /// Creates a new `WriteBehindCache` and spawns the task flushing its queue to `store`.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn new(
+        local: Box<dyn BCache>,
+        store: Arc<dyn BackingStore>,
+        options: WriteBehindOptions,
+    ) -> Self {
+        let (queue, receiver) = mpsc::channel(options.queue_capacity.max(1));
+        let dropped = Arc::new(AtomicU64::new(0));
+        tokio::spawn(flush(store, receiver, options, dropped.clone()));
+
+        Self {
+            local,
+            queue,
+            dropped,
+        }
+    }
+
+    /// Returns the number of mutations dropped after their batch failed `max_attempts` times.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Queues a mutation, waiting for room in the queue if it is full.
+    async fn enqueue(&self, op: WriteOp) {
+        if self.queue.send(op).await.is_err() {
+            error!("Write-behind flusher stopped, mutation not persisted");
+        }
+    }
+}
+
+/// Collects queued mutations into batches and writes them to the backing store until the
+/// queue is closed.
+async fn flush(
+    store: Arc<dyn BackingStore>,
+    mut receiver: Receiver<WriteOp>,
+    options: WriteBehindOptions,
+    dropped: Arc<AtomicU64>,
+) {
+    let batch_size = options.batch_size.max(1);
+    while let Some(first) = receiver.recv().await {
+        let mut batch = vec![first];
+        let deadline = Instant::now() + options.flush_interval;
+        while batch.len() < batch_size {
+            match time::timeout_at(deadline, receiver.recv()).await {
+                Ok(Some(op)) => batch.push(op),
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        let ops = coalesce(batch);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match store.write_batch(&ops).await {
+                Ok(()) => {
+                    debug!("Flushed {} mutations to the write-behind store", ops.len());
+                    break;
+                }
+                Err(e) if attempts >= options.max_attempts => {
+                    let total =
+                        dropped.fetch_add(ops.len() as u64, Ordering::Relaxed) + ops.len() as u64;
+                    error!(
+                        "Dropping {} mutations after {} failed flushes: {:?}; {} dropped so far",
+                        ops.len(),
+                        attempts,
+                        e,
+                        total
+                    );
+                    break;
+                }
+                Err(e) => {
+                    let backoff = options
+                        .initial_backoff
+                        .saturating_mul(2u32.saturating_pow(attempts - 1))
+                        .min(options.max_backoff);
+                    warn!(
+                        "Failed to flush {} mutations, retrying in {:?}: {:?}",
+                        ops.len(),
+                        backoff,
+                        e
+                    );
+                    time::sleep(backoff).await;
+                }
+            }
+        }
+    }
+}
+
+/// Keeps the last mutation of each key, in the order of their first occurrence.
+fn coalesce(batch: Vec<WriteOp>) -> Vec<WriteOp> {
+    let mut positions = HashMap::new();
+    let mut ops: Vec<WriteOp> = Vec::with_capacity(batch.len());
+    for op in batch {
+        match positions.get(&op.key) {
+            Some(&position) => ops[position] = op,
+            None => {
+                positions.insert(op.key.clone(), ops.len());
+                ops.push(op);
+            }
+        }
+    }
+    ops
+}
+
+#[async_trait]
+impl BCache for WriteBehindCache {
+    /// Asynchronously inserts a key-value pair into the local cache and queues it for the backing store.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A `String` representing the key.
+    /// * `val` - A `String` representing the value associated with the key.
+    async fn insert(&mut self, key: String, val: String) {
+        self.local.insert(key.clone(), val.clone()).await;
+        self.enqueue(WriteOp {
+            key,
+            value: Some(val),
+        })
+        .await;
+    }
+
+    /// Asynchronously retrieves the value associated with the given key from the local cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A `String` representing the key to retrieve.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key is not in the local cache.
+    async fn get(&mut self, key: String) -> Result<String> {
+        self.local.get(key).await
+    }
+
+    /// Asynchronously removes a key from the local cache and queues its removal from the backing store.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A `String` representing the key to remove.
+    async fn remove(&mut self, key: String) {
+        self.local.remove(key.clone()).await;
+        self.enqueue(WriteOp { key, value: None }).await;
+    }
+
+    /// Asynchronously returns the entries of the local cache.
+    async fn entries(&mut self) -> Vec<(String, String)> {
+        self.local.entries().await
+    }
+
+    /// Asynchronously returns a range of the entries of the local cache.
+    async fn range(
+        &mut self,
+        start: Option<String>,
+        end: Option<String>,
+        limit: usize,
+    ) -> Result<Vec<(String, String)>> {
+        self.local.range(start, end, limit).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moka_cache::MokaCache;
+    use std::sync::Mutex;
+
+    /// A `BackingStore` that records its batches, failing the first `failures` of them.
+    #[derive(Default)]
+    struct RecordingStore {
+        batches: Mutex<Vec<Vec<WriteOp>>>,
+        failures: AtomicU64,
+    }
+
+    #[async_trait]
+    impl BackingStore for RecordingStore {
+        async fn write_batch(&self, ops: &[WriteOp]) -> Result<()> {
+            if self.failures.load(Ordering::Relaxed) > 0 {
+                self.failures.fetch_sub(1, Ordering::Relaxed);
+                return Err(anyhow!("unavailable"));
+            }
+            self.batches.lock().unwrap().push(ops.to_vec());
+            Ok(())
+        }
+    }
+
+    /// Unit test for `WriteBehindCache`.
+    ///
+    /// Mutations queued within the flush interval are coalesced into one batch, which is
+    /// retried until the store accepts it.
+    #[tokio::test]
+    async fn test_write_behind_cache() {
+        let store = Arc::new(RecordingStore {
+            failures: AtomicU64::new(1),
+            ..RecordingStore::default()
+        });
+        let options = WriteBehindOptions {
+            flush_interval: Duration::from_millis(50),
+            initial_backoff: Duration::from_millis(10),
+            ..WriteBehindOptions::default()
+        };
+        let mut cache =
+            WriteBehindCache::new(Box::new(MokaCache::new(16).await), store.clone(), options);
+
+        cache.insert("a".to_string(), "1".to_string()).await;
+        cache.insert("b".to_string(), "2".to_string()).await;
+        cache.insert("a".to_string(), "3".to_string()).await;
+        cache.remove("b".to_string()).await;
+        assert_eq!(cache.get("a".to_string()).await.unwrap(), "3");
+
+        while store.batches.lock().unwrap().is_empty() {
+            time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(
+            store.batches.lock().unwrap()[0],
+            vec![
+                WriteOp {
+                    key: "a".to_string(),
+                    value: Some("3".to_string()),
+                },
+                WriteOp {
+                    key: "b".to_string(),
+                    value: None,
+                },
+            ]
+        );
+        assert_eq!(cache.dropped(), 0);
+    }
+}